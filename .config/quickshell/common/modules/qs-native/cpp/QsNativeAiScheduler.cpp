@@ -0,0 +1,84 @@
+#include "QsNativeAiScheduler.h"
+#include "QsNativeGlue.h"
+
+#include <QJsonDocument>
+#include <QJsonObject>
+
+QsNativeAiScheduler::QsNativeAiScheduler(QObject* parent)
+    : QObject(parent), m_handle(QsNative_AiScheduler_New()) {}
+
+QsNativeAiScheduler::~QsNativeAiScheduler() {
+  QsNative_AiScheduler_Delete(m_handle);
+}
+
+void QsNativeAiScheduler::setProviderConfig(const QVariantMap& value) {
+  if (value == m_providerConfig) {
+    return;
+  }
+  m_providerConfig = value;
+  emit provider_configChanged();
+  // The worker holds its own copy, so pick up new keys and endpoints.
+  if (m_running) {
+    start();
+  }
+}
+
+void QsNativeAiScheduler::start() {
+  setError(QString());
+  const QByteArray providerConfigCbor = qsn::toCbor(m_providerConfig);
+  QsNative_AiScheduler_Start(m_handle,
+                             reinterpret_cast<const uint8_t*>(providerConfigCbor.constData()),
+                             static_cast<size_t>(providerConfigCbor.size()), this,
+                             &QsNativeAiScheduler::eventCallback);
+  setRunning(true);
+}
+
+void QsNativeAiScheduler::stop() {
+  QsNative_AiScheduler_Stop(m_handle);
+  setRunning(false);
+}
+
+void QsNativeAiScheduler::cancelJob() {
+  if (m_jobSessionId >= 0) {
+    QsNative_AiChat_Cancel(m_jobSessionId);
+  }
+}
+
+void QsNativeAiScheduler::eventCallback(void* ctx, const char* json) {
+  auto* self = static_cast<QsNativeAiScheduler*>(ctx);
+  const QString payload = (json != nullptr) ? QString::fromUtf8(json) : QString();
+  qsn::postToObject(self, [self, payload]() { self->applyEvent(payload); });
+}
+
+void QsNativeAiScheduler::applyEvent(const QString& json) {
+  const QJsonDocument doc = QJsonDocument::fromJson(json.toUtf8());
+  if (!doc.isObject()) {
+    return;
+  }
+  const QJsonObject object = doc.object();
+  const QString kind = object.value(QStringLiteral("kind")).toString();
+  if (kind == QStringLiteral("error")) {
+    setError(object.value(QStringLiteral("error")).toString());
+  } else if (kind == QStringLiteral("job_started")) {
+    m_jobSessionId = object.value(QStringLiteral("session_id")).toInt(-1);
+    emit jobStarted(object.toVariantMap());
+  } else if (kind == QStringLiteral("job_finished")) {
+    m_jobSessionId = -1;
+    setError(QString());
+    emit jobFinished(object.toVariantMap());
+  }
+}
+
+void QsNativeAiScheduler::setRunning(bool value) {
+  if (value != m_running) {
+    m_running = value;
+    emit runningChanged();
+  }
+}
+
+void QsNativeAiScheduler::setError(const QString& value) {
+  if (value != m_error) {
+    m_error = value;
+    emit errorChanged();
+  }
+}
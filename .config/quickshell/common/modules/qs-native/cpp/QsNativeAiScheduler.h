@@ -0,0 +1,58 @@
+#pragma once
+
+#include <QObject>
+#include <QString>
+#include <QVariantMap>
+
+struct AiSchedulerHandle;
+
+// Scheduled background agent jobs. Rust re-reads `[[schedule.jobs]]` from
+// config.toml on a worker thread, runs each job headless when it comes due and
+// saves it as a chat conversation tagged `scheduled`. This QObject forwards the
+// provider config the jobs stream with (restarting the worker when it changes)
+// and re-emits each run as `jobStarted` ({job_id, label, session_id}) and
+// `jobFinished` ({job_id, label, conversation_id, title, error}). `cancelJob`
+// stops the run in progress; what it produced so far is still saved.
+class QsNativeAiScheduler : public QObject {
+  Q_OBJECT
+
+  Q_PROPERTY(bool running READ running NOTIFY runningChanged)
+  Q_PROPERTY(QString error READ error NOTIFY errorChanged)
+  Q_PROPERTY(QVariantMap provider_config READ providerConfig WRITE setProviderConfig NOTIFY
+                 provider_configChanged)
+
+public:
+  explicit QsNativeAiScheduler(QObject* parent = nullptr);
+  ~QsNativeAiScheduler() override;
+
+  [[nodiscard]] auto running() const -> bool { return m_running; }
+  [[nodiscard]] auto error() const -> QString { return m_error; }
+  [[nodiscard]] auto providerConfig() const -> QVariantMap { return m_providerConfig; }
+
+  void setProviderConfig(const QVariantMap& value);
+
+  Q_INVOKABLE void start();
+  Q_INVOKABLE void stop();
+  Q_INVOKABLE void cancelJob();
+
+signals:
+  void runningChanged();
+  void errorChanged();
+  void provider_configChanged();
+  void jobStarted(const QVariantMap& job);
+  void jobFinished(const QVariantMap& job);
+
+private:
+  static void eventCallback(void* ctx, const char* json);
+  void applyEvent(const QString& json);
+
+  void setRunning(bool value);
+  void setError(const QString& value);
+
+  AiSchedulerHandle* m_handle;
+
+  bool m_running = false;
+  int m_jobSessionId = -1;
+  QString m_error;
+  QVariantMap m_providerConfig;
+};
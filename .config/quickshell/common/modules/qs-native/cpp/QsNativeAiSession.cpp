@@ -13,6 +13,7 @@
 #include <QJsonObject>
 #include <QMimeData>
 #include <QPalette>
+#include <QSet>
 #include <QThreadPool>
 #include <QUuid>
 #include <algorithm>
@@ -82,6 +83,12 @@ auto QsNativeAiSession::data(const QModelIndex& index, int role) const -> QVaria
       return msg.tool;
     case ShowHeaderRole:
       return msg.showHeader;
+    case ReasoningRole:
+      return msg.reasoning;
+    case CitationsRole:
+      return msg.citations;
+    case VariantCountRole:
+      return msg.variantCount;
     default:
       return {};
   }
@@ -91,7 +98,8 @@ auto QsNativeAiSession::roleNames() const -> QHash<int, QByteArray> {
   return {
       {IdRole, "messageId"}, {SenderRole, "sender"},         {BodyRole, "body"},
       {KindRole, "kind"},    {MetricsRole, "metrics"},       {AttachmentsRole, "attachments"},
-      {ToolRole, "tool"},    {ShowHeaderRole, "showHeader"},
+      {ToolRole, "tool"},    {ShowHeaderRole, "showHeader"},   {ReasoningRole, "reasoning"},
+      {CitationsRole, "citations"}, {VariantCountRole, "variantCount"},
   };
 }
 
@@ -125,6 +133,20 @@ void QsNativeAiSession::setDisabledToolServers(const QVariantList& v) {
   }
 }
 
+void QsNativeAiSession::setResponseSchema(const QVariantMap& v) {
+  if (v != m_responseSchema) {
+    m_responseSchema = v;
+    emit responseSchemaChanged();
+  }
+}
+
+void QsNativeAiSession::setFocusedWindow(const QString& v) {
+  if (v != m_focusedWindow) {
+    m_focusedWindow = v;
+    emit focusedWindowChanged();
+  }
+}
+
 void QsNativeAiSession::setBusy(bool v) {
   if (v != m_busy) {
     m_busy = v;
@@ -155,6 +177,16 @@ void QsNativeAiSession::submitInput(const QString& text) {
   }
 
   if (trimmed.startsWith('/')) {
+    // Parameter values (stop sequences) and search terms keep their case.
+    const QString command = trimmed.section(' ', 0, 0).toLower();
+    if (command == QStringLiteral("/params")) {
+      handleParamsCommand(trimmed.section(' ', 1).trimmed());
+      return;
+    }
+    if (command == QStringLiteral("/search")) {
+      handleSearchCommand(trimmed.section(' ', 1).trimmed());
+      return;
+    }
     handleSlashCommand(trimmed.toLower());
     return;
   }
@@ -172,18 +204,36 @@ void QsNativeAiSession::submitInputWithAttachments(const QString& text,
   startStream(text.trimmed(), attachments);
 }
 
-void QsNativeAiSession::startStream(const QString& text, const QVariantList& attachments) {
+void QsNativeAiSession::submitInputWithContext(const QString& text,
+                                               const QVariantList& attachments,
+                                               const QVariantList& context) {
+  if (m_busy) {
+    return;
+  }
+  startStream(text.trimmed(), attachments, context);
+}
+
+auto QsNativeAiSession::mcpPrompt(const QString& serverId, const QString& name,
+                                  const QVariantMap& arguments) -> QVariantMap {
+  const QByteArray argumentsJson =
+      QJsonDocument::fromVariant(arguments).toJson(QJsonDocument::Compact);
+  return qsn::takeCborObject(QsNative_AiMcp_GetPrompt(serverId.toUtf8().constData(),
+                                                      name.toUtf8().constData(),
+                                                      argumentsJson.constData()));
+}
+
+auto QsNativeAiSession::mcpResource(const QString& serverId, const QString& uri) -> QVariantMap {
+  return qsn::takeCborObject(
+      QsNative_AiMcp_ReadResource(serverId.toUtf8().constData(), uri.toUtf8().constData()));
+}
+
+void QsNativeAiSession::startStream(const QString& text, const QVariantList& attachments,
+                                    const QVariantList& context) {
   if (!ensureHistoryConversation()) {
     setError(QStringLiteral("Failed to open conversation history"));
     return;
   }
 
-  const QByteArray providerConfigCbor = buildProviderConfigCbor();
-  const QByteArray attachmentsJson =
-      QJsonDocument::fromVariant(attachments).toJson(QJsonDocument::Compact);
-  const QByteArray disabledToolServersJson =
-      QJsonDocument::fromVariant(m_disabledToolServers).toJson(QJsonDocument::Compact);
-
   // Append user message.
   const int userRow = rowCountAsInt(m_messages.size());
   beginInsertRows({}, userRow, userRow);
@@ -193,8 +243,29 @@ void QsNativeAiSession::startStream(const QString& text, const QVariantList& att
   m_currentTurnId = m_messages.at(userRow).id;
   m_currentTurnOrdinal = userRow;
   m_nextReplayItemOrdinal = 0;
+  m_currentVariantOf.clear();
+  m_currentVariantIndex = 0;
   persistMessageAt(userRow, QStringLiteral("complete"), utcNow());
 
+  streamReply(text, attachments, context);
+}
+
+void QsNativeAiSession::streamReply(const QString& text, const QVariantList& attachments,
+                                    const QVariantList& context) {
+  const QByteArray providerConfigCbor = buildProviderConfigCbor();
+  const QByteArray attachmentsJson =
+      QJsonDocument::fromVariant(attachments).toJson(QJsonDocument::Compact);
+  const QByteArray disabledToolServersJson =
+      QJsonDocument::fromVariant(m_disabledToolServers).toJson(QJsonDocument::Compact);
+  const QByteArray contextJson = QJsonDocument::fromVariant(context).toJson(QJsonDocument::Compact);
+  const QByteArray responseSchemaJson =
+      m_responseSchema.isEmpty()
+          ? QByteArray()
+          : QJsonDocument::fromVariant(m_responseSchema).toJson(QJsonDocument::Compact);
+  const QByteArray promptVarsJson =
+      QJsonDocument(QJsonObject{{QStringLiteral("focused_window"), m_focusedWindow}})
+          .toJson(QJsonDocument::Compact);
+
   // Append empty assistant message (filled by tokens).
   const int asstRow = rowCountAsInt(m_messages.size());
   beginInsertRows({}, asstRow, asstRow);
@@ -214,8 +285,10 @@ void QsNativeAiSession::startStream(const QString& text, const QVariantList& att
       reinterpret_cast<const uint8_t*>(providerConfigCbor.constData()),
       static_cast<size_t>(providerConfigCbor.size()), m_systemPrompt.toUtf8().constData(),
       m_conversationId.toUtf8().constData(), text.toUtf8().constData(),
-      attachmentsJson.constData(), disabledToolServersJson.constData(),
-      &QsNativeAiSession::tokenCallback, this);
+      attachmentsJson.constData(), disabledToolServersJson.constData(), contextJson.constData(),
+      responseSchemaJson.constData(), promptVarsJson.constData(), &QsNativeAiSession::tokenCallback,
+      this);
+  m_lastSessionId = m_sessionId;
 }
 
 void QsNativeAiSession::cancel() {
@@ -223,6 +296,8 @@ void QsNativeAiSession::cancel() {
     QsNative_AiChat_Cancel(m_sessionId);
     m_sessionId = -1;
   }
+  clearPendingApprovals();
+  finishVariantRows();
   m_currentTurnId.clear();
   m_currentTurnOrdinal = -1;
   m_nextReplayItemOrdinal = 0;
@@ -234,6 +309,30 @@ void QsNativeAiSession::cancel() {
   setStatus(QStringLiteral("Cancelled"));
 }
 
+auto QsNativeAiSession::resolveApproval(const QString& toolCallId, bool allow) -> bool {
+  if (m_sessionId < 0 || toolCallId.isEmpty()) {
+    return false;
+  }
+  const bool resolved =
+      QsNative_AiChat_ResolveApproval(m_sessionId, toolCallId.toUtf8().constData(), allow);
+  for (qsizetype i = m_pendingApprovals.size() - 1; i >= 0; --i) {
+    if (m_pendingApprovals.at(i).toMap().value(QStringLiteral("tool_call_id")).toString() ==
+        toolCallId) {
+      m_pendingApprovals.removeAt(i);
+    }
+  }
+  emit pendingApprovalsChanged();
+  return resolved;
+}
+
+void QsNativeAiSession::clearPendingApprovals() {
+  if (m_pendingApprovals.isEmpty()) {
+    return;
+  }
+  m_pendingApprovals.clear();
+  emit pendingApprovalsChanged();
+}
+
 void QsNativeAiSession::regenerate(const QString& messageId) {
   if (m_busy) {
     return;
@@ -257,18 +356,68 @@ void QsNativeAiSession::regenerate(const QString& messageId) {
     return;
   }
 
-  const QString userText = m_messages.at(userIdx).body;
-  const QVariantList userAttachments = m_messages.at(userIdx).attachments;
-  persistDeletedFromOrdinal(userIdx);
+  // The answer runs up to the next user message. Later turns were asked against
+  // it, so only the latest answer is replaced; editing an earlier message branches.
+  int answerEnd = userIdx + 1;
+  while (answerEnd < m_messages.size() &&
+         m_messages.at(answerEnd).sender != QStringLiteral("user")) {
+    ++answerEnd;
+  }
+  if (answerEnd < m_messages.size()) {
+    appendInfo(QStringLiteral("Only the latest answer can be regenerated; edit an earlier "
+                              "message to branch from it."));
+    return;
+  }
 
-  // Remove from userIdx onwards.
-  beginRemoveRows({}, userIdx, rowCountAsInt(m_messages.size()) - 1);
-  while (m_messages.size() > userIdx) {
-    m_messages.removeLast();
+  // The old answer is kept as a variant the user can switch back to.
+  const Message& user = m_messages.at(userIdx);
+  const QVariantMap result = qsn::takeCborObject(QsNative_AiHistory_StartVariant(
+      m_conversationId.toUtf8().constData(), user.id.toUtf8().constData()));
+  if (!result.value(QStringLiteral("ok")).toBool()) {
+    setError(result.value(QStringLiteral("error")).toString());
+    return;
   }
-  endRemoveRows();
+  const QVariantMap started = result.value(QStringLiteral("started_variant")).toMap();
+  m_currentTurnId = user.id;
+  m_currentTurnOrdinal = userIdx;
+  m_nextReplayItemOrdinal = started.value(QStringLiteral("next_item_ordinal")).toInt();
+  m_currentVariantOf = user.id;
+  m_currentVariantIndex = started.value(QStringLiteral("variant_index")).toInt();
+  const QString userText = user.body;
+  const QVariantList userAttachments = user.attachments;
+
+  if (answerEnd > userIdx + 1) {
+    beginRemoveRows({}, userIdx + 1, answerEnd - 1);
+    m_messages.remove(userIdx + 1, answerEnd - userIdx - 1);
+    endRemoveRows();
+  }
+
+  streamReply(userText, userAttachments, QVariantList{});
+}
 
-  startStream(userText, userAttachments);
+auto QsNativeAiSession::variants(const QString& messageId) -> QVariantList {
+  const QVariantMap result =
+      qsn::takeCborObject(QsNative_AiHistory_ListVariants(messageId.toUtf8().constData()));
+  return result.value(QStringLiteral("variants")).toList();
+}
+
+auto QsNativeAiSession::switchVariant(const QString& messageId, int index) -> bool {
+  if (m_busy) {
+    return false;
+  }
+  const QVariantMap result = qsn::takeCborObject(
+      QsNative_AiHistory_SwitchVariant(messageId.toUtf8().constData(), index));
+  if (!result.value(QStringLiteral("ok")).toBool()) {
+    setError(result.value(QStringLiteral("error")).toString());
+    return false;
+  }
+  if (!m_messages.isEmpty()) {
+    beginRemoveRows({}, 0, rowCountAsInt(m_messages.size()) - 1);
+    m_messages.clear();
+    endRemoveRows();
+  }
+  restoreMessages(result.value(QStringLiteral("messages")).toList());
+  return true;
 }
 
 void QsNativeAiSession::deleteMessage(const QString& messageId) {
@@ -302,6 +451,36 @@ void QsNativeAiSession::editMessage(const QString& messageId, const QString& new
   persistMessageAt(idx, QStringLiteral("complete"));
 }
 
+auto QsNativeAiSession::branchFrom(const QString& messageId, const QString& newBody) -> bool {
+  const int idx = indexOfMessage(messageId);
+  const QString text = newBody.trimmed();
+  if (m_busy || m_conversationId.isEmpty() || idx < 0 ||
+      m_messages.at(idx).sender != QStringLiteral("user") || text.isEmpty()) {
+    return false;
+  }
+  const QVariantList attachments = m_messages.at(idx).attachments;
+  const QVariantMap result = qsn::takeCborObject(QsNative_AiHistory_Fork(
+      m_conversationId.toUtf8().constData(), messageId.toUtf8().constData()));
+  if (!result.value(QStringLiteral("ok")).toBool()) {
+    setError(result.value(QStringLiteral("error")).toString());
+    return false;
+  }
+
+  beginRemoveRows({}, 0, rowCountAsInt(m_messages.size()) - 1);
+  m_messages.clear();
+  endRemoveRows();
+  m_conversationId =
+      result.value(QStringLiteral("conversation")).toMap().value(QStringLiteral("id")).toString();
+  m_currentTurnId.clear();
+  m_currentTurnOrdinal = -1;
+  m_nextReplayItemOrdinal = 0;
+  m_historyLoaded = true;
+  restoreMessages(result.value(QStringLiteral("messages")).toList());
+
+  startStream(text, attachments);
+  return true;
+}
+
 void QsNativeAiSession::resetForModelSwitch(const QString& newModelId) {
   closeHistoryConversation();
   if (!m_messages.isEmpty()) {
@@ -430,6 +609,11 @@ auto QsNativeAiSession::commands() -> QVariantList {
                   {QStringLiteral("description"), QStringLiteral("Change mood / persona")}},
       QVariantMap{{QStringLiteral("name"), QStringLiteral("/resume")},
                   {QStringLiteral("description"), QStringLiteral("Resume previous chat")}},
+      QVariantMap{
+          {QStringLiteral("name"), QStringLiteral("/branches")},
+          {QStringLiteral("description"), QStringLiteral("Switch between branches of this chat")}},
+      QVariantMap{{QStringLiteral("name"), QStringLiteral("/search")},
+                  {QStringLiteral("description"), QStringLiteral("Search all chat history")}},
       QVariantMap{{QStringLiteral("name"), QStringLiteral("/clear")},
                   {QStringLiteral("description"), QStringLiteral("Clear chat history")}},
       QVariantMap{
@@ -442,6 +626,11 @@ auto QsNativeAiSession::commands() -> QVariantList {
           {QStringLiteral("description"), QStringLiteral("Show MCP server and tool status")}},
       QVariantMap{{QStringLiteral("name"), QStringLiteral("/tools")},
                   {QStringLiteral("description"), QStringLiteral("Enable or disable tools")}},
+      QVariantMap{
+          {QStringLiteral("name"), QStringLiteral("/params")},
+          {QStringLiteral("description"), QStringLiteral("Set temperature, max tokens, reasoning")}},
+      QVariantMap{{QStringLiteral("name"), QStringLiteral("/usage")},
+                  {QStringLiteral("description"), QStringLiteral("Show token usage and spend")}},
       QVariantMap{
           {QStringLiteral("name"), QStringLiteral("/debug")},
           {QStringLiteral("description"), QStringLiteral("Show detailed session diagnostics")}},
@@ -476,6 +665,16 @@ void QsNativeAiSession::handleSlashCommand(const QString& cmd) {
     }
     refreshResumeConversations(QString());
     emit openResumePickerRequested();
+  } else if (cmd == QStringLiteral("/branches")) {
+    if (m_busy) {
+      appendInfo(QStringLiteral("Cannot switch branches while a response is streaming."));
+      return;
+    }
+    if (!refreshBranchConversations() || m_resumeConversations.isEmpty()) {
+      appendInfo(QStringLiteral("This chat has no other branches. Edit a message to start one."));
+      return;
+    }
+    emit openResumePickerRequested();
   } else if (cmd.startsWith(QStringLiteral("/copy"))) {
     const QString text = copyAllText();
     emit copyAllRequested(text);
@@ -487,11 +686,15 @@ void QsNativeAiSession::handleSlashCommand(const QString& cmd) {
                               "| `/providers` | Order provider priority |\n"
                               "| `/mood` | Change mood / persona |\n"
                               "| `/resume` | Resume previous chat |\n"
+                              "| `/branches` | Switch between branches of this chat |\n"
+                              "| `/search` | Search all chat history |\n"
                               "| `/clear` | Clear chat history |\n"
                               "| `/copy` | Copy all messages to clipboard |\n"
                               "| `/status` | Show model & connection info |\n"
                               "| `/mcp` | Show MCP server and tool status |\n"
                               "| `/tools` | Enable or disable tools |\n"
+                              "| `/params` | Set temperature, max tokens, reasoning |\n"
+                              "| `/usage` | Show token usage and spend |\n"
                               "| `/debug` | Show detailed session diagnostics |\n"
                               "| `/help` | Show this message |"));
   } else if (cmd == QStringLiteral("/mcp")) {
@@ -510,6 +713,39 @@ void QsNativeAiSession::handleSlashCommand(const QString& cmd) {
                    .arg(m_mcpError.isEmpty()
                             ? QString()
                             : QStringLiteral("\n- **Error:** %1").arg(m_mcpError)));
+  } else if (cmd == QStringLiteral("/usage")) {
+    const auto rows = [](const QVariantList& totals) -> QString {
+      QString out;
+      for (const QVariant& item : totals) {
+        const QVariantMap total = item.toMap();
+        const QString label = total.value(QStringLiteral("label")).toString();
+        const double cost = total.value(QStringLiteral("cost_usd")).toDouble();
+        const bool unpriced = total.value(QStringLiteral("unpriced_turns")).toInt() > 0;
+        // The label goes in last so a `%n` in a title is not substituted.
+        out += QStringLiteral("| %5 | %1 | %2 | %3 | %4 |\n")
+                   .arg(total.value(QStringLiteral("turns")).toInt())
+                   .arg(total.value(QStringLiteral("prompt_tokens")).toLongLong())
+                   .arg(total.value(QStringLiteral("output_tokens")).toLongLong())
+                   .arg(QStringLiteral("$%1%2")
+                            .arg(cost, 0, 'f', 4)
+                            .arg(unpriced ? QStringLiteral("+") : QString()))
+                   .arg(label.isEmpty() ? QStringLiteral("(untitled)") : label);
+      }
+      return out.isEmpty() ? QStringLiteral("| (none) | | | | |\n") : out;
+    };
+    const QString header =
+        QStringLiteral("| | Turns | Input | Output | Cost |\n|---|---|---|---|---|\n");
+    appendInfo(QStringLiteral("**Usage**\n\n"
+                              "This chat\n\n%1%2\n"
+                              "By model\n\n%1%3\n"
+                              "By month\n\n%1%4\n"
+                              "*`+` marks totals with turns on unpriced models.*")
+                   .arg(header,
+                        rows(m_conversationId.isEmpty()
+                                 ? QVariantList()
+                                 : usageTotals(QStringLiteral("conversation"), m_conversationId, 1)),
+                        rows(usageTotals(QStringLiteral("model"), QString(), 8)),
+                        rows(usageTotals(QStringLiteral("month"), QString(), 3))));
   } else if (cmd == QStringLiteral("/status")) {
     const QString providerId = activeProviderId();
     const QString provider = providerId.isEmpty()
@@ -578,10 +814,10 @@ void QsNativeAiSession::handleSlashCommand(const QString& cmd) {
                                 ? QStringLiteral("(default)")
                                 : activeConfig.value(QStringLiteral("base_url")).toString();
 
-    // Pull last-stream metrics from the native backend.
+    // Pull this session's last-stream metrics from the native backend.
     QString metricsSection = QStringLiteral("*(no stream yet)*");
     {
-      const QVariantMap o = qsn::takeCborObject(QsNative_AiChat_LastMetrics());
+      const QVariantMap o = qsn::takeCborObject(QsNative_AiChat_Metrics(m_lastSessionId));
       if (!o.isEmpty()) {
         const double ttf = o.value(QStringLiteral("ttf_ms"), -1).toDouble();
         const double total = o.value(QStringLiteral("total_ms"), 0).toDouble();
@@ -724,6 +960,33 @@ auto QsNativeAiSession::refreshResumeConversations(const QString& query) -> bool
   return true;
 }
 
+auto QsNativeAiSession::refreshBranchConversations() -> bool {
+  if (m_conversationId.isEmpty()) {
+    return false;
+  }
+  const QVariantMap result = qsn::takeCborObject(
+      QsNative_AiHistory_ListBranches(m_conversationId.toUtf8().constData()));
+  if (!result.value(QStringLiteral("ok")).toBool()) {
+    return false;
+  }
+
+  QVariantList options;
+  const QVariantList summaries = result.value(QStringLiteral("conversations")).toList();
+  for (const QVariant& item : summaries) {
+    const QVariantMap summary = item.toMap();
+    if (summary.value(QStringLiteral("id")).toString() == m_conversationId) {
+      continue;
+    }
+    const QVariantMap option = resumeOptionFromSummary(summary);
+    if (!option.isEmpty()) {
+      options.append(option);
+    }
+  }
+  m_resumeConversations = options;
+  emit resumeConversationsChanged();
+  return true;
+}
+
 auto QsNativeAiSession::resumeConversation(const QString& conversationId) -> bool {
   if (m_busy) {
     return false;
@@ -731,6 +994,185 @@ auto QsNativeAiSession::resumeConversation(const QString& conversationId) -> boo
   return resumeHistoryConversation(conversationId);
 }
 
+auto QsNativeAiSession::renameConversation(const QString& conversationId, const QString& title)
+    -> bool {
+  const QString trimmed = title.trimmed();
+  if (conversationId.isEmpty() || trimmed.isEmpty()) {
+    return false;
+  }
+  const QVariantMap result = qsn::takeCborObject(QsNative_AiHistory_Rename(
+      conversationId.toUtf8().constData(), trimmed.toUtf8().constData()));
+  if (!result.value(QStringLiteral("ok")).toBool()) {
+    setError(result.value(QStringLiteral("error")).toString());
+    return false;
+  }
+  for (QVariant& item : m_resumeConversations) {
+    QVariantMap option = item.toMap();
+    if (option.value(QStringLiteral("value")).toString() != conversationId) {
+      continue;
+    }
+    option.insert(QStringLiteral("label"), trimmed.length() > 48
+                                               ? trimmed.left(48) + QStringLiteral("...")
+                                               : trimmed);
+    item = option;
+    emit resumeConversationsChanged();
+    break;
+  }
+  return true;
+}
+
+auto QsNativeAiSession::generationParams() -> QVariantMap {
+  if (m_conversationId.isEmpty()) {
+    return {};
+  }
+  const QVariantMap result = qsn::takeCborObject(
+      QsNative_AiHistory_GetParams(m_conversationId.toUtf8().constData()));
+  return QJsonDocument::fromJson(result.value(QStringLiteral("params_json")).toString().toUtf8())
+      .object()
+      .toVariantMap();
+}
+
+auto QsNativeAiSession::setGenerationParams(const QVariantMap& params) -> bool {
+  if (!ensureHistoryConversation()) {
+    return false;
+  }
+  const QByteArray json =
+      QJsonDocument(QJsonObject::fromVariantMap(params)).toJson(QJsonDocument::Compact);
+  const QVariantMap result = qsn::takeCborObject(
+      QsNative_AiHistory_SetParams(m_conversationId.toUtf8().constData(),
+                                   m_modelId.toUtf8().constData(), json.constData()));
+  if (!result.value(QStringLiteral("ok")).toBool()) {
+    setError(result.value(QStringLiteral("error")).toString());
+    return false;
+  }
+  return true;
+}
+
+void QsNativeAiSession::handleParamsCommand(const QString& args) {
+  QVariantMap params = generationParams();
+  if (args.compare(QStringLiteral("reset"), Qt::CaseInsensitive) == 0) {
+    params.clear();
+  } else {
+    // `key=value` pairs; an empty value unsets the key, `stop` takes a
+    // comma-separated list.
+    for (const QString& pair : args.split(' ', Qt::SkipEmptyParts)) {
+      const QString key = pair.section('=', 0, 0).trimmed().toLower();
+      const QString value = pair.section('=', 1).trimmed();
+      if (key.isEmpty()) {
+        continue;
+      }
+      if (value.isEmpty()) {
+        params.remove(key);
+      } else if (key == QStringLiteral("stop")) {
+        params.insert(key, value.split(',', Qt::SkipEmptyParts));
+      } else if (key == QStringLiteral("reasoning_effort")) {
+        params.insert(key, value);
+      } else {
+        bool isNumber = false;
+        const double number = value.toDouble(&isNumber);
+        params.insert(key, isNumber ? QVariant(number) : QVariant(value));
+      }
+    }
+  }
+  if (!args.isEmpty() && !setGenerationParams(params)) {
+    appendInfo(QStringLiteral("**Parameters not changed:** %1").arg(m_error));
+    return;
+  }
+  QString lines;
+  for (auto it = params.cbegin(); it != params.cend(); ++it) {
+    const QString value = it.key() == QStringLiteral("stop")
+                              ? it.value().toStringList().join(QStringLiteral(", "))
+                              : it.value().toString();
+    lines += QStringLiteral("- **%1:** %2\n").arg(it.key(), value);
+  }
+  appendInfo(QStringLiteral("**Generation parameters**\n\n%1\n"
+                            "*Usage: `/params temperature=0.7 max_output_tokens=2048 "
+                            "reasoning_effort=low stop=END` · `key=` unsets · `/params reset`*")
+                 .arg(lines.isEmpty() ? QStringLiteral("- Provider defaults\n") : lines));
+}
+
+auto QsNativeAiSession::usageTotals(const QString& groupBy, const QString& conversationId,
+                                    int limit) -> QVariantList {
+  const QVariantMap result = qsn::takeCborObject(
+      QsNative_AiHistory_UsageTotals(groupBy.toUtf8().constData(),
+                                     conversationId.toUtf8().constData(), limit));
+  return result.value(QStringLiteral("usage")).toList();
+}
+
+auto QsNativeAiSession::searchHistory(const QString& query, int limit) -> QVariantList {
+  const QVariantMap result = qsn::takeCborObject(
+      QsNative_AiHistory_Search(query.toUtf8().constData(), limit));
+  QVariantList hits = result.value(QStringLiteral("search_hits")).toList();
+  for (QVariant& value : hits) {
+    QVariantMap hit = value.toMap();
+    // The store marks matches with STX/ETX, which survive HTML escaping.
+    const QString html = hit.value(QStringLiteral("snippet"))
+                             .toString()
+                             .toHtmlEscaped()
+                             .replace(QChar(0x02), QStringLiteral("<b>"))
+                             .replace(QChar(0x03), QStringLiteral("</b>"));
+    hit.insert(QStringLiteral("snippet_html"), html);
+    value = hit;
+  }
+  return hits;
+}
+
+void QsNativeAiSession::handleSearchCommand(const QString& query) {
+  if (query.isEmpty()) {
+    appendInfo(QStringLiteral("*Usage: `/search <terms>` · matches every word, the last as a "
+                              "prefix, across all models*"));
+    return;
+  }
+  if (m_busy) {
+    appendInfo(QStringLiteral("Cannot search while a response is streaming."));
+    return;
+  }
+  // Hits open in the resume picker rather than landing in the chat, so they
+  // are never saved (or indexed) as a message. Only closed chats can be
+  // resumed; each is listed once, at its best-ranked hit.
+  QVariantList options;
+  QSet<QString> seen;
+  const QVariantList hits = searchHistory(query, 30);
+  for (const QVariant& value : hits) {
+    const QVariantMap hit = value.toMap();
+    const QString id = hit.value(QStringLiteral("conversation_id")).toString();
+    if (id == m_conversationId ||
+        hit.value(QStringLiteral("status")).toString() != QStringLiteral("closed") ||
+        seen.contains(id)) {
+      continue;
+    }
+    seen.insert(id);
+    QString title = hit.value(QStringLiteral("title")).toString().trimmed();
+    if (title.isEmpty()) {
+      title = QStringLiteral("Untitled chat");
+    }
+    if (title.length() > 48) {
+      title = title.left(48) + QStringLiteral("...");
+    }
+    const QString snippet = hit.value(QStringLiteral("snippet"))
+                                .toString()
+                                .remove(QChar(0x02))
+                                .remove(QChar(0x03))
+                                .simplified();
+    options.append(QVariantMap{
+        {QStringLiteral("label"), title},
+        {QStringLiteral("value"), id},
+        {QStringLiteral("description"),
+         QStringLiteral("%1  •  %2  •  %3")
+             .arg(hit.value(QStringLiteral("model_id")).toString(),
+                  hit.value(QStringLiteral("source")).toString(), snippet)},
+        {QStringLiteral("icon"), QStringLiteral("\uf002")},
+    });
+  }
+  if (options.isEmpty()) {
+    appendInfo(QStringLiteral("No other chats match **%1**.").arg(query));
+    return;
+  }
+  m_resumeConversations = options;
+  emit resumeConversationsChanged();
+  emit openSearchPickerRequested();
+}
+
 auto QsNativeAiSession::resumeHistoryConversation(const QString& conversationId) -> bool {
   const QVariantMap result = qsn::takeCborObject(QsNative_AiHistory_Resume(
       m_modelId.toUtf8().constData(), activeProviderId().toUtf8().constData(),
@@ -791,6 +1233,13 @@ auto QsNativeAiSession::messageToHistoryMap(const Message& msg, int ordinal,
   if (!completedAt.isEmpty()) {
     out.insert(QStringLiteral("completed_at"), completedAt);
   }
+  // Rows a regenerated answer adds belong to its variant; Rust ignores these
+  // on later saves, so tagging only while the turn runs is enough.
+  if (!m_currentVariantOf.isEmpty() && m_currentTurnOrdinal >= 0 &&
+      ordinal > m_currentTurnOrdinal) {
+    out.insert(QStringLiteral("variant_of"), m_currentVariantOf);
+    out.insert(QStringLiteral("variant_index"), m_currentVariantIndex);
+  }
   if (status == QStringLiteral("complete") || status == QStringLiteral("error")) {
     out.insert(QStringLiteral("updated_at"), utcNow());
   }
@@ -872,6 +1321,7 @@ void QsNativeAiSession::persistResponseItems(const QJsonArray& items, const QStr
         {QStringLiteral("item_type"), itemType},
         {QStringLiteral("call_id"), callId},
         {QStringLiteral("raw"), raw.toVariantMap()},
+        {QStringLiteral("variant_index"), m_currentVariantIndex},
     });
   }
   if (apiItems.isEmpty()) {
@@ -888,18 +1338,19 @@ void QsNativeAiSession::persistResponseItems(const QJsonArray& items, const QStr
   }
 }
 
-void QsNativeAiSession::persistDeletedFromOrdinal(int ordinal) {
-  if (m_conversationId.isEmpty()) {
+// Gives the rows a regenerated answer streamed in their turn's variant count, which
+// restored rows get from Rust. Called before the turn state is cleared.
+void QsNativeAiSession::finishVariantRows() {
+  if (m_currentVariantOf.isEmpty() || m_currentTurnOrdinal < 0 ||
+      m_currentTurnOrdinal + 1 >= m_messages.size()) {
     return;
   }
-  const QVariantMap result = qsn::takeCborObject(
-      QsNative_AiHistory_DeleteFromOrdinal(m_conversationId.toUtf8().constData(), ordinal));
-  Q_UNUSED(result);
-  if (m_currentTurnOrdinal >= ordinal) {
-    m_currentTurnId.clear();
-    m_currentTurnOrdinal = -1;
-    m_nextReplayItemOrdinal = 0;
+  const int first = m_currentTurnOrdinal + 1;
+  const int last = rowCountAsInt(m_messages.size()) - 1;
+  for (int row = first; row <= last; ++row) {
+    m_messages[row].variantCount = m_currentVariantIndex + 1;
   }
+  emit dataChanged(index(first, 0), index(last, 0), {VariantCountRole});
 }
 
 auto QsNativeAiSession::extraForMessage(const Message& msg) -> QVariantMap {
@@ -907,6 +1358,12 @@ auto QsNativeAiSession::extraForMessage(const Message& msg) -> QVariantMap {
   if (!msg.attachments.isEmpty()) {
     out.insert(QStringLiteral("attachments"), msg.attachments);
   }
+  if (!msg.reasoning.isEmpty()) {
+    out.insert(QStringLiteral("reasoning"), msg.reasoning);
+  }
+  if (!msg.citations.isEmpty()) {
+    out.insert(QStringLiteral("citations"), msg.citations);
+  }
   return out;
 }
 
@@ -933,8 +1390,19 @@ void QsNativeAiSession::restoreMessages(const QVariantList& messages) {
     msg.kind = raw.value(QStringLiteral("kind")).toString();
     msg.body = raw.value(QStringLiteral("body")).toString();
     const QString status = raw.value(QStringLiteral("status")).toString();
+
+    const QJsonDocument extraDoc =
+        QJsonDocument::fromJson(raw.value(QStringLiteral("extra_json")).toString().toUtf8());
+    const QVariantMap extra =
+        extraDoc.isObject() ? extraDoc.object().toVariantMap() : QVariantMap{};
+    msg.attachments = extra.value(QStringLiteral("attachments")).toList();
+    msg.reasoning = extra.value(QStringLiteral("reasoning")).toString();
+    msg.citations = extra.value(QStringLiteral("citations")).toList();
+    msg.variantCount = raw.value(QStringLiteral("variant_count")).toInt();
+
     if (msg.kind == QStringLiteral("chat") && msg.sender == QStringLiteral("assistant") &&
-        status == QStringLiteral("streaming") && msg.body.trimmed().isEmpty()) {
+        status == QStringLiteral("streaming") && msg.body.trimmed().isEmpty() &&
+        msg.reasoning.isEmpty() && msg.attachments.isEmpty()) {
       continue;
     }
 
@@ -942,12 +1410,6 @@ void QsNativeAiSession::restoreMessages(const QVariantList& messages) {
         QJsonDocument::fromJson(raw.value(QStringLiteral("metrics_json")).toString().toUtf8());
     msg.metrics = metricsDoc.isObject() ? metricsDoc.object().toVariantMap() : QVariantMap{};
 
-    const QJsonDocument extraDoc =
-        QJsonDocument::fromJson(raw.value(QStringLiteral("extra_json")).toString().toUtf8());
-    const QVariantMap extra =
-        extraDoc.isObject() ? extraDoc.object().toVariantMap() : QVariantMap{};
-    msg.attachments = extra.value(QStringLiteral("attachments")).toList();
-
     const QVariantList toolCalls = raw.value(QStringLiteral("tool_calls")).toList();
     if (!toolCalls.isEmpty()) {
       const QVariantMap call = toolCalls.first().toMap();
@@ -964,7 +1426,8 @@ void QsNativeAiSession::restoreMessages(const QVariantList& messages) {
           showHeader = false;
         } else if (previous.kind == QStringLiteral("chat") &&
                    previous.sender == QStringLiteral("assistant")) {
-          showHeader = previous.body.trimmed().isEmpty();
+          showHeader = previous.body.trimmed().isEmpty() && previous.reasoning.isEmpty() &&
+                     previous.attachments.isEmpty();
         }
       } else if (msg.kind == QStringLiteral("chat") && msg.sender == QStringLiteral("assistant") &&
                  previous.kind == QStringLiteral("tool")) {
@@ -1024,6 +1487,8 @@ void QsNativeAiSession::refreshMcpStateAsync() {
       }
       m_mcpServers = obj.value(QStringLiteral("servers")).toList();
       m_mcpTools = obj.value(QStringLiteral("tools")).toList();
+      m_mcpPrompts = obj.value(QStringLiteral("prompts")).toList();
+      m_mcpResources = obj.value(QStringLiteral("resources")).toList();
       m_mcpStatus = obj.value(QStringLiteral("status")).toString();
       m_mcpError = obj.value(QStringLiteral("error")).toString();
       emit mcpStateChanged();
@@ -1072,6 +1537,13 @@ auto QsNativeAiSession::resumeOptionFromSummary(const QVariantMap& summary) -> Q
   if (!preview.isEmpty() && preview != title) {
     description += QStringLiteral("  •  %1").arg(preview);
   }
+  if (summary.value(QStringLiteral("tags")).toStringList().contains(QStringLiteral("scheduled"))) {
+    description.prepend(QStringLiteral("scheduled  •  "));
+  }
+  if (summary.contains(QStringLiteral("fork_ordinal"))) {
+    description.prepend(QStringLiteral("branch at message %1  •  ")
+                            .arg(summary.value(QStringLiteral("fork_ordinal")).toInt() + 1));
+  }
 
   return QVariantMap{
       {QStringLiteral("label"), title},
@@ -1114,6 +1586,23 @@ auto QsNativeAiSession::lastAssistantChatIndex() const -> int {
   return -1;
 }
 
+auto QsNativeAiSession::streamingAssistantRow() -> int {
+  int row = rowCountAsInt(m_messages.size()) - 1;
+  if (row >= 0 && m_messages.at(row).sender == QStringLiteral("assistant") &&
+      m_messages.at(row).kind == QStringLiteral("chat")) {
+    return row;
+  }
+  const bool showHeader = row < 0 || m_messages.at(row).kind != QStringLiteral("tool");
+  row = rowCountAsInt(m_messages.size());
+  beginInsertRows({}, row, row);
+  m_messages.append({QUuid::createUuid().toString(QUuid::WithoutBraces),
+                     QStringLiteral("assistant"), QString(), QStringLiteral("chat"), QVariantMap{},
+                     QVariantList{}, QVariantMap{}, showHeader});
+  endInsertRows();
+  persistMessageAt(row, QStringLiteral("streaming"));
+  return row;
+}
+
 void QsNativeAiSession::handleToolEventJson(const QString& json) {
   const QJsonDocument doc = QJsonDocument::fromJson(json.toUtf8());
   if (!doc.isObject()) {
@@ -1121,11 +1610,92 @@ void QsNativeAiSession::handleToolEventJson(const QString& json) {
   }
 
   const QJsonObject object = doc.object();
-  if (object.value(QStringLiteral("kind")).toString() == QStringLiteral("raw_response_items")) {
+  const QString kind = object.value(QStringLiteral("kind")).toString();
+  if (kind == QStringLiteral("raw_response_items")) {
     persistResponseItems(object.value(QStringLiteral("items")).toArray(),
                          QStringLiteral("model_output"));
     return;
   }
+  if (kind == QStringLiteral("approval_required")) {
+    m_pendingApprovals.append(object.toVariantMap());
+    emit pendingApprovalsChanged();
+    emit scrollToEndRequested();
+    return;
+  }
+  if (kind == QStringLiteral("metrics")) {
+    // Live figures for the streaming reply; the final ones replace them on finish.
+    const int row = lastAssistantChatIndex();
+    if (row < 0 || object.value(QStringLiteral("session_id")).toInt() != m_sessionId) {
+      return;
+    }
+    QVariantMap metrics = object.toVariantMap();
+    metrics.remove(QStringLiteral("kind"));
+    metrics.remove(QStringLiteral("session_id"));
+    m_messages[row].metrics = metrics;
+    const QModelIndex idx = index(row, 0);
+    emit dataChanged(idx, idx, {MetricsRole});
+    return;
+  }
+  if (kind == QStringLiteral("reasoning_delta")) {
+    if (object.value(QStringLiteral("session_id")).toInt() != m_sessionId) {
+      return;
+    }
+    const int row = streamingAssistantRow();
+    m_messages[row].reasoning += object.value(QStringLiteral("delta")).toString();
+    const QModelIndex idx = index(row, 0);
+    emit dataChanged(idx, idx, {ReasoningRole});
+    emit scrollToEndRequested();
+    return;
+  }
+  if (kind == QStringLiteral("citations")) {
+    // Sent after the round's text, so it belongs to the row that text went to.
+    const int row = lastAssistantChatIndex();
+    if (row < 0 || object.value(QStringLiteral("session_id")).toInt() != m_sessionId) {
+      return;
+    }
+    m_messages[row].citations.append(
+        object.value(QStringLiteral("citations")).toArray().toVariantList());
+    const QModelIndex idx = index(row, 0);
+    emit dataChanged(idx, idx, {CitationsRole});
+    persistMessageAt(row);
+    return;
+  }
+  if (kind == QStringLiteral("attachment")) {
+    // A model-generated image, already written to the attachments directory.
+    if (object.value(QStringLiteral("session_id")).toInt() != m_sessionId) {
+      return;
+    }
+    const int row = streamingAssistantRow();
+    m_messages[row].attachments.append(
+        object.value(QStringLiteral("attachment")).toObject().toVariantMap());
+    const QModelIndex idx = index(row, 0);
+    emit dataChanged(idx, idx, {AttachmentsRole});
+    persistMessageAt(row);
+    emit scrollToEndRequested();
+    return;
+  }
+  if (kind == QStringLiteral("schema_mismatch")) {
+    QStringList errors;
+    for (const QJsonValue& error : object.value(QStringLiteral("errors")).toArray()) {
+      errors.append(error.toString());
+    }
+    emit schemaMismatch(errors);
+    return;
+  }
+  if (kind == QStringLiteral("retry")) {
+    setStatus(QStringLiteral("Retrying (%1)...")
+                  .arg(object.value(QStringLiteral("attempt")).toInt()));
+    return;
+  }
+  if (kind == QStringLiteral("fallback")) {
+    setStatus(QStringLiteral("Falling back to %1...")
+                  .arg(object.value(QStringLiteral("to")).toString()));
+    return;
+  }
+  if (kind != QStringLiteral("tool")) {
+    // Other stream events are not tool rows.
+    return;
+  }
 
   const QJsonArray replayItems = object.value(QStringLiteral("replay_items")).toArray();
   QVariantMap tool = object.toVariantMap();
@@ -1143,10 +1713,15 @@ void QsNativeAiSession::handleToolEventJson(const QString& json) {
     if (row > 0) {
       const Message& current = m_messages.at(row - 1);
       if (current.kind == QStringLiteral("chat") && current.sender == QStringLiteral("assistant") &&
-          current.body.trimmed().isEmpty()) {
+          current.body.trimmed().isEmpty() && current.reasoning.isEmpty() &&
+          current.attachments.isEmpty()) {
         row = row - 1;
         rowId = current.id;
         replaceEmptyAssistant = true;
+      } else if (current.kind == QStringLiteral("chat") &&
+                 current.sender == QStringLiteral("assistant")) {
+        // The text, reasoning and images before a tool call are final once it starts.
+        persistMessageAt(row - 1, QStringLiteral("complete"), utcNow());
       }
     }
     bool showHeader = true;
@@ -1156,7 +1731,7 @@ void QsNativeAiSession::handleToolEventJson(const QString& json) {
         showHeader = false;
       } else if (previous.kind == QStringLiteral("chat") &&
                  previous.sender == QStringLiteral("assistant")) {
-        showHeader = previous.body.trimmed().isEmpty();
+        showHeader = previous.body.trimmed().isEmpty() && previous.reasoning.isEmpty();
       }
     }
     tool.insert(QStringLiteral("show_header"), showHeader);
@@ -1205,6 +1780,8 @@ void QsNativeAiSession::tokenCallback(void* ctx, const char* token, int done) {
     if (done == 1) {
       // Stream finished successfully.
       self->m_sessionId = -1;
+      self->clearPendingApprovals();
+      self->finishVariantRows();
       self->m_currentTurnId.clear();
       self->m_currentTurnOrdinal = -1;
       self->m_nextReplayItemOrdinal = 0;
@@ -1214,7 +1791,8 @@ void QsNativeAiSession::tokenCallback(void* ctx, const char* token, int done) {
       // Capture per-message metrics onto the last assistant message.
       const int metricsRow = self->lastAssistantChatIndex();
       if (metricsRow >= 0) {
-        self->m_messages[metricsRow].metrics = qsn::takeCborObject(QsNative_AiChat_LastMetrics());
+        self->m_messages[metricsRow].metrics =
+            qsn::takeCborObject(QsNative_AiChat_Metrics(self->m_lastSessionId));
         const QModelIndex idx = self->index(metricsRow, 0);
         emit self->dataChanged(idx, idx, {MetricsRole});
         self->persistMessageAt(metricsRow, QStringLiteral("complete"), QsNativeAiSession::utcNow());
@@ -1226,6 +1804,8 @@ void QsNativeAiSession::tokenCallback(void* ctx, const char* token, int done) {
     } else if (done == -1) {
       // Error.
       self->m_sessionId = -1;
+      self->clearPendingApprovals();
+      self->finishVariantRows();
       self->m_currentTurnId.clear();
       self->m_currentTurnOrdinal = -1;
       self->m_nextReplayItemOrdinal = 0;
@@ -1244,18 +1824,7 @@ void QsNativeAiSession::tokenCallback(void* ctx, const char* token, int done) {
       }
     } else {
       // Normal token: append to last assistant message.
-      int row = rowCountAsInt(self->m_messages.size()) - 1;
-      if (row < 0 || self->m_messages.at(row).sender != QStringLiteral("assistant") ||
-          self->m_messages.at(row).kind != QStringLiteral("chat")) {
-        const bool showHeader = row < 0 || self->m_messages.at(row).kind != QStringLiteral("tool");
-        row = rowCountAsInt(self->m_messages.size());
-        self->beginInsertRows({}, row, row);
-        self->m_messages.append({QUuid::createUuid().toString(QUuid::WithoutBraces),
-                                 QStringLiteral("assistant"), QString(), QStringLiteral("chat"),
-                                 QVariantMap{}, QVariantList{}, QVariantMap{}, showHeader});
-        self->endInsertRows();
-        self->persistMessageAt(row, QStringLiteral("streaming"));
-      }
+      const int row = self->streamingAssistantRow();
       self->m_messages[row].body += tok;
       const QModelIndex idx = self->index(row, 0);
       emit self->dataChanged(idx, idx, {BodyRole});
@@ -4,6 +4,7 @@
 #include <QJsonObject>
 #include <QList>
 #include <QString>
+#include <QStringList>
 #include <QUuid>
 #include <QVariantList>
 #include <QVariantMap>
@@ -18,16 +19,26 @@ class QsNativeAiSession : public QAbstractListModel {
                  providerConfigChanged)
   Q_PROPERTY(QVariantList disabled_tool_servers READ disabledToolServers WRITE
                  setDisabledToolServers NOTIFY disabledToolServersChanged)
+  // JSON Schema the reply must match; empty for free-form replies.
+  Q_PROPERTY(QVariantMap response_schema READ responseSchema WRITE setResponseSchema NOTIFY
+                 responseSchemaChanged)
+  // Title of the focused window, for `{{focused_window}}` in the system prompt.
+  Q_PROPERTY(QString focused_window READ focusedWindow WRITE setFocusedWindow NOTIFY
+                 focusedWindowChanged)
   Q_PROPERTY(bool busy READ busy NOTIFY busyChanged)
   Q_PROPERTY(QString status READ status NOTIFY statusChanged)
   Q_PROPERTY(QString error READ error NOTIFY errorChanged)
   Q_PROPERTY(QVariantList commands READ commands CONSTANT)
   Q_PROPERTY(QVariantList mcp_servers READ mcpServers NOTIFY mcpStateChanged)
   Q_PROPERTY(QVariantList mcp_tools READ mcpTools NOTIFY mcpStateChanged)
+  Q_PROPERTY(QVariantList mcp_prompts READ mcpPrompts NOTIFY mcpStateChanged)
+  Q_PROPERTY(QVariantList mcp_resources READ mcpResources NOTIFY mcpStateChanged)
   Q_PROPERTY(QString mcp_status READ mcpStatus NOTIFY mcpStateChanged)
   Q_PROPERTY(QString mcp_error READ mcpError NOTIFY mcpStateChanged)
   Q_PROPERTY(
       QVariantList resume_conversations READ resumeConversations NOTIFY resumeConversationsChanged)
+  Q_PROPERTY(
+      QVariantList pending_approvals READ pendingApprovals NOTIFY pendingApprovalsChanged)
 
 public:
   struct Message {
@@ -39,6 +50,9 @@ public:
     QVariantList attachments;
     QVariantMap tool;
     bool showHeader = true;
+    QString reasoning; // streamed reasoning summary / thoughts, shown apart from body
+    QVariantList citations; // web-search sources: {title, url, span: [start, end]?}
+    int variantCount = 0; // answers the turn has once regenerated, 0 before
   };
 
   enum Roles {
@@ -49,7 +63,10 @@ public:
     MetricsRole,
     AttachmentsRole,
     ToolRole,
-    ShowHeaderRole
+    ShowHeaderRole,
+    ReasoningRole,
+    CitationsRole,
+    VariantCountRole
   };
 
   explicit QsNativeAiSession(QObject* parent = nullptr);
@@ -90,6 +107,12 @@ public:
   [[nodiscard]] auto mcpTools() const -> QVariantList {
     return m_mcpTools;
   }
+  [[nodiscard]] auto mcpPrompts() const -> QVariantList {
+    return m_mcpPrompts;
+  }
+  [[nodiscard]] auto mcpResources() const -> QVariantList {
+    return m_mcpResources;
+  }
   [[nodiscard]] auto mcpStatus() const -> QString {
     return m_mcpStatus;
   }
@@ -99,20 +122,48 @@ public:
   [[nodiscard]] auto resumeConversations() const -> QVariantList {
     return m_resumeConversations;
   }
+  [[nodiscard]] auto pendingApprovals() const -> QVariantList {
+    return m_pendingApprovals;
+  }
+  [[nodiscard]] auto responseSchema() const -> QVariantMap {
+    return m_responseSchema;
+  }
+  [[nodiscard]] auto focusedWindow() const -> QString {
+    return m_focusedWindow;
+  }
 
   void setModelId(const QString& v);
   void setSystemPrompt(const QString& v);
   void setProviderConfig(const QVariantMap& v);
   void setDisabledToolServers(const QVariantList& v);
+  void setResponseSchema(const QVariantMap& v);
+  void setFocusedWindow(const QString& v);
 
   // Invokables (matching Rust interface)
   Q_INVOKABLE static void setAppLinkColor(const QColor& color);
   Q_INVOKABLE void submitInput(const QString& text);
   Q_INVOKABLE void submitInputWithAttachments(const QString& text, const QVariantList& attachments);
+  // `context` holds MCP prompt/resource references ({type, server_id, uri | name, arguments})
+  // whose rendered content is attached to the user turn.
+  Q_INVOKABLE void submitInputWithContext(const QString& text, const QVariantList& attachments,
+                                          const QVariantList& context);
+  Q_INVOKABLE static auto mcpPrompt(const QString& serverId, const QString& name,
+                                    const QVariantMap& arguments) -> QVariantMap;
+  Q_INVOKABLE static auto mcpResource(const QString& serverId, const QString& uri) -> QVariantMap;
   Q_INVOKABLE void cancel();
+  // Answers an `approval_required` tool event; the stream stays paused until then.
+  Q_INVOKABLE auto resolveApproval(const QString& toolCallId, bool allow) -> bool;
+  // Streams a new answer to the latest turn, which `messageId` belongs to; the old one stays
+  // a variant. Earlier turns are rewritten by branching from their user message instead.
   Q_INVOKABLE void regenerate(const QString& messageId);
+  // The turn's answers ({index, active, message_count, preview, created_at}); empty until
+  // it has been regenerated. Rows carry `variantCount`, so only ask when it is above 1.
+  Q_INVOKABLE auto variants(const QString& messageId) -> QVariantList;
+  Q_INVOKABLE auto switchVariant(const QString& messageId, int index) -> bool;
   Q_INVOKABLE void deleteMessage(const QString& messageId);
   Q_INVOKABLE void editMessage(const QString& messageId, const QString& newBody);
+  // Forks the conversation before the user message `messageId` and sends `newBody` there.
+  Q_INVOKABLE auto branchFrom(const QString& messageId, const QString& newBody) -> bool;
   Q_INVOKABLE void resetForModelSwitch(const QString& newModelId);
   Q_INVOKABLE void appendInfo(const QString& text);
   Q_INVOKABLE void appendToolStatus(const QString& toolCallId, const QString& toolName,
@@ -128,13 +179,26 @@ public:
       -> QVariantMap;
   Q_INVOKABLE auto refreshMcp() -> bool;
   Q_INVOKABLE auto refreshResumeConversations(const QString& query = QString()) -> bool;
+  // Fills the resume list with the other conversations in the current fork tree.
+  Q_INVOKABLE auto refreshBranchConversations() -> bool;
   Q_INVOKABLE auto resumeConversation(const QString& conversationId) -> bool;
+  Q_INVOKABLE auto renameConversation(const QString& conversationId, const QString& title) -> bool;
+  Q_INVOKABLE auto generationParams() -> QVariantMap;
+  Q_INVOKABLE auto setGenerationParams(const QVariantMap& params) -> bool;
+  Q_INVOKABLE static auto usageTotals(const QString& groupBy,
+                                      const QString& conversationId = QString(), int limit = 50)
+      -> QVariantList;
+  // Ranked full-text search across every chat; each hit also carries `snippet_html`
+  // with the matched terms in <b>.
+  Q_INVOKABLE static auto searchHistory(const QString& query, int limit = 30) -> QVariantList;
 
 signals:
   void modelIdChanged();
   void systemPromptChanged();
   void providerConfigChanged();
   void disabledToolServersChanged();
+  void responseSchemaChanged();
+  void focusedWindowChanged();
   void busyChanged();
   void statusChanged();
   void errorChanged();
@@ -145,14 +209,22 @@ signals:
   void openToolPickerRequested();
   void openMoodPickerRequested();
   void openResumePickerRequested();
+  // `/search` hits are in `resume_conversations`, to be picked like `/resume`.
+  void openSearchPickerRequested();
   void scrollToEndRequested();
   void copyAllRequested(const QString& text);
   void streamDone();
   void resumeConversationsChanged();
+  void pendingApprovalsChanged();
+  // The final reply did not match `response_schema`; the stream then ends with an error.
+  void schemaMismatch(const QStringList& errors);
 
 private:
   static void tokenCallback(void* ctx, const char* token, int done);
-  void startStream(const QString& text, const QVariantList& attachments);
+  void startStream(const QString& text, const QVariantList& attachments,
+                   const QVariantList& context = QVariantList{});
+  void streamReply(const QString& text, const QVariantList& attachments,
+                   const QVariantList& context);
   auto ensureHistoryConversation() -> bool;
   auto createHistoryConversation() -> bool;
   auto resumeHistoryConversation(const QString& conversationId = QString()) -> bool;
@@ -165,7 +237,7 @@ private:
                         const QString& completedAt = QString());
   void persistToolCallAt(int row);
   void persistResponseItems(const QJsonArray& items, const QString& source);
-  void persistDeletedFromOrdinal(int ordinal);
+  void finishVariantRows();
   [[nodiscard]] static auto extraForMessage(const Message& msg) -> QVariantMap;
   [[nodiscard]] static auto metricsForMessage(const Message& msg) -> QVariantMap;
   [[nodiscard]] static auto utcNow() -> QString;
@@ -178,18 +250,27 @@ private:
   [[nodiscard]] auto indexOfMessage(const QString& id) const -> int;
   [[nodiscard]] auto indexOfToolCall(const QString& toolCallId) const -> int;
   [[nodiscard]] auto lastAssistantChatIndex() const -> int;
+  auto streamingAssistantRow() -> int;
   void handleToolEventJson(const QString& json);
+  void clearPendingApprovals();
   void setBusy(bool v);
   void setStatus(const QString& v);
   void setError(const QString& v);
   void handleSlashCommand(const QString& cmd);
+  void handleParamsCommand(const QString& args);
+  void handleSearchCommand(const QString& query);
 
   QList<Message> m_messages;
   int m_sessionId = -1;
+  // Kept after the stream ends so its metrics can still be looked up.
+  int m_lastSessionId = -1;
   QString m_conversationId;
   QString m_currentTurnId;
   int m_currentTurnOrdinal = -1;
   int m_nextReplayItemOrdinal = 0;
+  // Set while a regenerated answer streams: the user message it answers and its index.
+  QString m_currentVariantOf;
+  int m_currentVariantIndex = 0;
   bool m_historyLoaded = false;
   bool m_restoringHistory = false;
 
@@ -197,12 +278,17 @@ private:
   QString m_systemPrompt;
   QVariantMap m_providerConfig;
   QVariantList m_disabledToolServers;
+  QVariantMap m_responseSchema;
+  QString m_focusedWindow;
   bool m_busy = false;
   QString m_status;
   QString m_error;
   QVariantList m_mcpServers;
   QVariantList m_mcpTools;
+  QVariantList m_mcpPrompts;
+  QVariantList m_mcpResources;
   QString m_mcpStatus;
   QString m_mcpError;
   QVariantList m_resumeConversations;
+  QVariantList m_pendingApprovals;
 };
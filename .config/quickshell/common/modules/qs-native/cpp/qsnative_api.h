@@ -8,6 +8,9 @@
 #include <ostream>
 #include <new>
 
+// Opaque per-instance handle owned by the C++ `QsNativeAiScheduler` `QObject`.
+struct AiSchedulerHandle;
+
 // Opaque per-instance handle owned by the C++ `QsNativeBacklight` `QObject`.
 struct BacklightHandle;
 
@@ -234,7 +237,14 @@ void QsNative_InstallPanicHook();
 // All pointer arguments must be either null or point to valid NUL-terminated
 // strings for the duration of this call, except `(provider_config_ptr,
 // provider_config_len)` which must describe a readable CBOR byte range for
-// the call (or `provider_config_ptr` may be null). `cb` must remain valid
+// the call (or `provider_config_ptr` may be null). `context_json` is a JSON
+// array of MCP prompt/resource references (`ContextRef`) whose rendered
+// content is attached to the user turn. `response_schema_json` is an
+// optional JSON Schema object: the reply is requested as JSON matching it
+// and checked before the turn finishes, with a `schema_mismatch` event and
+// an error callback when it does not match. `prompt_vars_json` is a JSON
+// object of extra `{{name}}` values for the system prompt (see `template`),
+// such as `focused_window`. `cb` must remain valid
 // until the stream sends a terminal callback, and `ctx` must remain valid
 // for each callback invocation.
 //
@@ -249,6 +259,9 @@ int QsNative_AiChat_Stream(const char *model_id,
                            const char *message,
                            const char *attachments_json,
                            const char *disabled_tool_servers_json,
+                           const char *context_json,
+                           const char *response_schema_json,
+                           const char *prompt_vars_json,
                            TokenCallback cb,
                            void *ctx);
 
@@ -259,7 +272,27 @@ int QsNative_AiChat_Stream(const char *model_id,
 // Panics if the internal session registry mutex is poisoned.
 void QsNative_AiChat_Cancel(int id);
 
-// Returns the CBOR-encoded metrics for the most recently completed turn.
+// Answers an `approval_required` event for tool call `call_id` in session
+// `id`, unblocking the stream. Returns `false` if no such call is waiting.
+//
+// # Safety
+//
+// `call_id` must be null or a valid NUL-terminated string for the duration
+// of this call.
+bool QsNative_AiChat_ResolveApproval(int id, const char *call_id, bool allow);
+
+// Returns the CBOR-encoded metrics for session `id`: live figures while it
+// streams, final ones once it ends. Returns an empty map for unknown or
+// long-finished sessions.
+//
+// # Panics
+//
+// Panics if the internal metrics mutex is poisoned.
+QsNativeBytes QsNative_AiChat_Metrics(int id);
+
+// Returns the CBOR-encoded metrics for the most recently completed turn of
+// any session. Prefer `QsNative_AiChat_Metrics` when several sessions may
+// stream at once.
 //
 // # Panics
 //
@@ -568,6 +601,84 @@ QsNativeBytes QsNative_AiHistory_ListResume(const char *model_id,
                                             const char *query,
                                             int32_t limit);
 
+// Forks `conversation_id` before its message `message_id` into a new
+// active conversation (see `Store::fork_conversation`). Returns a
+// CBOR-encoded `ApiResult` with the fork and its messages.
+//
+// # Safety
+//
+// `conversation_id` and `message_id` must be null or valid NUL-terminated
+// strings for the duration of this call. The returned buffer must be
+// released with `QsNative_FreeBytes`.
+QsNativeBytes QsNative_AiHistory_Fork(const char *conversation_id, const char *message_id);
+
+// Lists the fork tree `conversation_id` belongs to as conversation
+// summaries carrying `parent_conversation_id`/`fork_ordinal`. Returns a
+// CBOR-encoded `ApiResult`.
+//
+// # Safety
+//
+// `conversation_id` must be null or a valid NUL-terminated string for the
+// duration of this call. The returned buffer must be released with
+// `QsNative_FreeBytes`.
+QsNativeBytes QsNative_AiHistory_ListBranches(const char *conversation_id);
+
+// Full-text search over all chat history (message bodies, titles, tool-call
+// summaries). Returns a CBOR-encoded `ApiResult` whose `search_hits` are
+// ranked best first.
+//
+// # Safety
+//
+// `query` must be null or a valid NUL-terminated string for the duration of
+// this call. The returned buffer must be released with `QsNative_FreeBytes`.
+QsNativeBytes QsNative_AiHistory_Search(const char *query, int32_t limit);
+
+// Renames a conversation. Returns a CBOR-encoded `ApiResult`.
+//
+// # Safety
+//
+// Pointer arguments must be null or valid NUL-terminated strings for the
+// duration of this call. The returned buffer must be released with
+// `QsNative_FreeBytes`.
+QsNativeBytes QsNative_AiHistory_Rename(const char *conversation_id, const char *title);
+
+// Returns a conversation's generation parameters in `params_json` of a
+// CBOR-encoded `ApiResult`.
+//
+// # Safety
+//
+// `conversation_id` must be null or a valid NUL-terminated string for the
+// duration of this call. The returned buffer must be released with
+// `QsNative_FreeBytes`.
+QsNativeBytes QsNative_AiHistory_GetParams(const char *conversation_id);
+
+// Replaces a conversation's generation parameters with the JSON object
+// `params_json` after checking it against `model_id`; `{}` clears them.
+// Returns the stored parameters in a CBOR-encoded `ApiResult`.
+//
+// # Safety
+//
+// Pointer arguments must be null or valid NUL-terminated strings for the
+// duration of this call. The returned buffer must be released with
+// `QsNative_FreeBytes`.
+QsNativeBytes QsNative_AiHistory_SetParams(const char *conversation_id,
+                                           const char *model_id,
+                                           const char *params_json);
+
+// Sums the usage ledger by `conversation`, `model`, `day` or `month` (local
+// time), newest or most expensive first. A non-empty `conversation_id`
+// restricts the sums to that conversation. Returns a CBOR-encoded
+// `ApiResult`.
+//
+// # Safety
+//
+// Pointer arguments must be null or valid NUL-terminated strings for the
+// duration of this call. The returned buffer must be released with
+// `QsNative_FreeBytes`.
+QsNativeBytes QsNative_AiHistory_UsageTotals(const char *group_by,
+                                             const char *conversation_id,
+                                             int32_t limit);
+
 // Inserts or updates a message row from a CBOR-encoded object. Returns a
 // CBOR-encoded `ApiResult`.
 //
@@ -598,6 +709,39 @@ QsNativeBytes QsNative_AiHistory_MarkMessageDeleted(const char *message_id);
 // `QsNative_FreeBytes`.
 QsNativeBytes QsNative_AiHistory_DeleteFromOrdinal(const char *conversation_id, int32_t ordinal);
 
+// Parks the current answer to the user message `turn_id` as an inactive
+// variant before that turn is regenerated. Returns a CBOR-encoded
+// `ApiResult` whose `started_variant` is what the new answer persists with.
+//
+// # Safety
+//
+// `conversation_id`/`turn_id` must be null or valid NUL-terminated strings
+// for the duration of this call. The returned buffer must be released with
+// `QsNative_FreeBytes`.
+QsNativeBytes QsNative_AiHistory_StartVariant(const char *conversation_id, const char *turn_id);
+
+// Lists the answers to the turn `message_id` belongs to, oldest first.
+// Returns a CBOR-encoded `ApiResult`; `variants` is empty when the answer
+// was never regenerated.
+//
+// # Safety
+//
+// `message_id` must be null or a valid NUL-terminated string for the
+// duration of this call. The returned buffer must be released with
+// `QsNative_FreeBytes`.
+QsNativeBytes QsNative_AiHistory_ListVariants(const char *message_id);
+
+// Makes variant `variant_index` the active answer of the turn `message_id`
+// belongs to. Returns a CBOR-encoded `ApiResult` with the conversation's
+// messages as they now read.
+//
+// # Safety
+//
+// `message_id` must be null or a valid NUL-terminated string for the
+// duration of this call. The returned buffer must be released with
+// `QsNative_FreeBytes`.
+QsNativeBytes QsNative_AiHistory_SwitchVariant(const char *message_id, int32_t variant_index);
+
 // Inserts or updates a tool-call row from a CBOR-encoded object. Returns a
 // CBOR-encoded `ApiResult`.
 //
@@ -732,6 +876,29 @@ void QsNative_KeyboardLock_Stop(KeyboardLockHandle *handle);
 // Refreshes the MCP server/tool snapshot. Returns a CBOR-encoded `Snapshot`.
 QsNativeBytes QsNative_AiMcp_Refresh();
 
+// Renders an MCP prompt. `arguments_json` is a JSON object of prompt
+// arguments. Returns a CBOR-encoded `ContextResult` carrying the raw
+// `prompts/get` result.
+//
+// # Safety
+//
+// Pointer arguments must be null or valid NUL-terminated strings for the
+// duration of this call. The returned buffer must be released with
+// `QsNative_FreeBytes`.
+QsNativeBytes QsNative_AiMcp_GetPrompt(const char *server_id,
+                                       const char *name,
+                                       const char *arguments_json);
+
+// Reads an MCP resource. Returns a CBOR-encoded `ContextResult` carrying the
+// raw `resources/read` result.
+//
+// # Safety
+//
+// Pointer arguments must be null or valid NUL-terminated strings for the
+// duration of this call. The returned buffer must be released with
+// `QsNative_FreeBytes`.
+QsNativeBytes QsNative_AiMcp_ReadResource(const char *server_id, const char *uri);
+
 // Frees a string returned by a `QsNative_*` C ABI function.
 //
 // # Safety
@@ -889,6 +1056,41 @@ void QsNative_Privacy_Probe(PrivacyHandle *handle,
 // null.
 QsNativeBytes QsNative_Privacy_ClassifyPipewire(const uint8_t *ptr, uintptr_t len);
 
+AiSchedulerHandle *QsNative_AiScheduler_New();
+
+// # Safety
+// `handle` must be null or a pointer from `QsNative_AiScheduler_New` not yet
+// freed. Any in-flight worker callback is drained before the handle drops;
+// a job that is still running finishes, but reports nothing.
+//
+// # Panics
+// Panics if the gate mutex is poisoned (a worker thread panicked while holding it).
+void QsNative_AiScheduler_Delete(AiSchedulerHandle *handle);
+
+// (Re)starts the scheduler worker. `(provider_config_ptr,
+// provider_config_len)` is the CBOR provider map the chat session streams
+// with; jobs use it for their API keys and endpoints.
+//
+// # Safety
+// `handle` must be valid; `(provider_config_ptr, provider_config_len)` must
+// describe a readable CBOR byte range for the call (or the pointer may be
+// null); `ctx`/`cb` must stay valid until `QsNative_AiScheduler_Delete`
+// drains the gate.
+//
+// # Panics
+// Panics if the gate mutex is poisoned (a worker thread panicked while holding it).
+void QsNative_AiScheduler_Start(AiSchedulerHandle *handle,
+                                const uint8_t *provider_config_ptr,
+                                uintptr_t provider_config_len,
+                                void *ctx,
+                                QsNativeUpdateFn cb);
+
+// Stops the worker after the job it is running, if any.
+//
+// # Safety
+// `handle` must be null or a valid handle pointer.
+void QsNative_AiScheduler_Stop(AiSchedulerHandle *handle);
+
 SysInfoHandle *QsNative_SysInfo_New();
 
 // # Safety
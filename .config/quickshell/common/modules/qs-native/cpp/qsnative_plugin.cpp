@@ -3,6 +3,7 @@
 
 #include "qsnative_api.h"
 
+#include "QsNativeAiScheduler.h"
 #include "QsNativeAiSession.h"
 #include "QsNativeBacklight.h"
 #include "QsNativeBarModuleLogic.h"
@@ -34,6 +35,7 @@ public:
     qmlRegisterType<QsNativeBluetooth>(uri, 1, 0, "BluetoothDiagnosticsProvider");
     qmlRegisterType<QsNativeConfigResolver>(uri, 1, 0, "ConfigResolver");
     qmlRegisterType<QsNativeAiSession>(uri, 1, 0, "AiChatSession");
+    qmlRegisterType<QsNativeAiScheduler>(uri, 1, 0, "AiScheduler");
     qmlRegisterType<QsNativeBarModuleLogic>(uri, 1, 0, "BarModuleLogic");
     qmlRegisterType<QsNativePacman>(uri, 1, 0, "PacmanUpdatesProvider");
     qmlRegisterType<QsNativePrivacy>(uri, 1, 0, "PrivacyProvider");
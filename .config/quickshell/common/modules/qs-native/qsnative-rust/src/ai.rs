@@ -1,13 +1,23 @@
+mod approval;
+mod citations;
+mod compaction;
+mod documents;
+mod ollama;
+mod params;
+mod schedule;
+mod schema;
 mod stream;
+mod template;
+mod title;
 
 use std::collections::{BTreeMap, HashMap};
 use std::ffi::{c_char, c_int, c_void, CStr, CString};
 use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
 use std::sync::{Arc, Mutex, OnceLock};
 use std::thread;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-use crate::mcp::{tool_result_transcript_output, ToolDescriptor, ToolResult};
+use crate::mcp::{tool_result_transcript_output, ContextRef, ToolDescriptor, ToolResult};
 use crate::utils::first_non_empty;
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use serde::{Deserialize, Serialize};
@@ -18,6 +28,19 @@ type TokenCallback = unsafe extern "C" fn(*mut c_void, *const c_char, c_int);
 static NEXT_SESSION_ID: AtomicI32 = AtomicI32::new(1);
 static SESSIONS: OnceLock<Mutex<HashMap<i32, Arc<AtomicBool>>>> = OnceLock::new();
 static LAST_METRICS: OnceLock<Mutex<SessionMetrics>> = OnceLock::new();
+static SESSION_METRICS: OnceLock<Mutex<BTreeMap<i32, SessionMetrics>>> = OnceLock::new();
+
+/// Sessions whose metrics stay queryable after they finish; older ones are
+/// dropped first.
+const KEPT_SESSION_METRICS: usize = 64;
+/// Minimum spacing of the live `metrics` events sent while text streams.
+const LIVE_METRICS_INTERVAL: Duration = Duration::from_millis(500);
+/// Characters per token when estimating output before the provider reports it.
+const CHARS_PER_TOKEN: usize = 4;
+const MIB: u64 = 1024 * 1024;
+/// Largest file read for an attachment. PDFs over the provider file limit
+/// are still read, for their text.
+const MAX_ATTACHMENT_BYTES: u64 = 64 * MIB;
 
 #[derive(Debug, Clone, Default, Serialize)]
 struct SessionMetrics {
@@ -27,26 +50,54 @@ struct SessionMetrics {
     output_tokens: i32,
     ttf_ms: f64,
     total_ms: f64,
+    /// Output tokens per second since the first token arrived.
+    tokens_per_sec: f64,
     finished: bool,
     #[serde(skip_serializing_if = "String::is_empty")]
     error: String,
+    /// Requests re-sent after a transient provider failure.
+    #[serde(skip_serializing_if = "crate::utils::is_zero")]
+    retries: i32,
+    /// Models that failed before `model` answered, in the order tried.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fallbacks: Vec<String>,
 }
 
 struct MetricTracker {
+    /// Model currently answering, for live snapshots.
+    model: String,
     turn_start: Instant,
     round_start: Instant,
+    first_token: Option<Instant>,
     chunk_count: i32,
+    streamed_chars: usize,
+    /// Set once the UI has been sent anything it shows: text, reasoning,
+    /// citations or an image. A turn past that point cannot fall back.
+    shown: bool,
     ttf_ms: f64,
+    retries: i32,
+    fallbacks: Vec<String>,
+    /// Publishes live `metrics` events; off for one-shot background calls.
+    live: bool,
+    last_live: Option<Instant>,
 }
 
 impl MetricTracker {
     fn new() -> Self {
         let now = Instant::now();
         Self {
+            model: String::new(),
             turn_start: now,
             round_start: now,
+            first_token: None,
             chunk_count: 0,
+            streamed_chars: 0,
+            shown: false,
             ttf_ms: -1.0,
+            retries: 0,
+            fallbacks: Vec::new(),
+            live: false,
+            last_live: None,
         }
     }
 
@@ -60,14 +111,62 @@ impl MetricTracker {
         }
         if self.chunk_count == 0 {
             self.ttf_ms = self.round_start.elapsed().as_secs_f64() * 1000.0;
+            self.first_token = Some(Instant::now());
         }
         self.chunk_count += 1;
+        self.streamed_chars += token.chars().count();
+        self.shown = true;
         true
     }
 
     fn total_ms(&self) -> f64 {
         self.turn_start.elapsed().as_secs_f64() * 1000.0
     }
+
+    /// Whether a live metrics event is due, restarting the interval if so.
+    fn live_due(&mut self) -> bool {
+        if !self.live
+            || self
+                .last_live
+                .is_some_and(|last| last.elapsed() < LIVE_METRICS_INTERVAL)
+        {
+            return false;
+        }
+        self.last_live = Some(Instant::now());
+        true
+    }
+
+    /// Output tokens streamed so far, estimated from the text length.
+    fn estimated_output_tokens(&self) -> i32 {
+        i32::try_from(self.streamed_chars.div_ceil(CHARS_PER_TOKEN)).unwrap_or(i32::MAX)
+    }
+
+    /// Metrics of the turn so far, for live events and lookups mid-stream.
+    fn live_snapshot(&self) -> SessionMetrics {
+        let output_tokens = self.estimated_output_tokens();
+        SessionMetrics {
+            model: self.model.clone(),
+            chunk_count: self.chunk_count,
+            output_tokens,
+            ttf_ms: self.ttf_ms,
+            total_ms: self.total_ms(),
+            tokens_per_sec: self.tokens_per_sec(output_tokens),
+            retries: self.retries,
+            fallbacks: self.fallbacks.clone(),
+            ..SessionMetrics::default()
+        }
+    }
+
+    fn tokens_per_sec(&self, output_tokens: i32) -> f64 {
+        let Some(first_token) = self.first_token else {
+            return 0.0;
+        };
+        let seconds = first_token.elapsed().as_secs_f64();
+        if seconds <= 0.0 {
+            return 0.0;
+        }
+        f64::from(output_tokens) / seconds
+    }
 }
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
@@ -83,11 +182,15 @@ pub(crate) struct Attachment {
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
-struct ProviderConfig {
+pub(crate) struct ProviderConfig {
     #[serde(default)]
     api_key: String,
     #[serde(default)]
     base_url: String,
+    /// Wire protocol for `OpenAI`-style providers: `responses` (default) or
+    /// `chat_completions` for servers without the Responses API.
+    #[serde(default)]
+    api: String,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
@@ -175,8 +278,18 @@ struct StreamRequest {
     conversation_id: String,
     message: String,
     attachments: Vec<Attachment>,
+    context: Vec<ContextRef>,
     tools: Vec<ToolDescriptor>,
     provider_search_enabled: bool,
+    /// Offers `OpenAI`'s hosted `image_generation` tool; see
+    /// `stream::image_generation_supported`.
+    image_generation: bool,
+    approval: crate::app_config::ToolApprovalPolicy,
+    max_parallel_tools: usize,
+    context_window: usize,
+    params: params::GenerationParams,
+    /// JSON Schema the final reply must match; see `schema`.
+    response_schema: Option<Value>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -193,7 +306,14 @@ struct StreamResult {
 /// All pointer arguments must be either null or point to valid NUL-terminated
 /// strings for the duration of this call, except `(provider_config_ptr,
 /// provider_config_len)` which must describe a readable CBOR byte range for
-/// the call (or `provider_config_ptr` may be null). `cb` must remain valid
+/// the call (or `provider_config_ptr` may be null). `context_json` is a JSON
+/// array of MCP prompt/resource references (`ContextRef`) whose rendered
+/// content is attached to the user turn. `response_schema_json` is an
+/// optional JSON Schema object: the reply is requested as JSON matching it
+/// and checked before the turn finishes, with a `schema_mismatch` event and
+/// an error callback when it does not match. `prompt_vars_json` is a JSON
+/// object of extra `{{name}}` values for the system prompt (see `template`),
+/// such as `focused_window`. `cb` must remain valid
 /// until the stream sends a terminal callback, and `ctx` must remain valid
 /// for each callback invocation.
 ///
@@ -209,18 +329,16 @@ pub unsafe extern "C" fn QsNative_AiChat_Stream(
     message: *const c_char,
     attachments_json: *const c_char,
     disabled_tool_servers_json: *const c_char,
+    context_json: *const c_char,
+    response_schema_json: *const c_char,
+    prompt_vars_json: *const c_char,
     cb: TokenCallback,
     ctx: *mut c_void,
 ) -> c_int {
     // cbindgen (this version) doesn't collapse `Option<fn>` into a nullable C
     // pointer, so the callback is taken non-optional; the C++ caller never
     // passes null.
-    let id = NEXT_SESSION_ID.fetch_add(1, Ordering::SeqCst);
-    let cancelled = Arc::new(AtomicBool::new(false));
-    sessions()
-        .lock()
-        .expect("session mutex")
-        .insert(id, cancelled.clone());
+    let (id, cancelled) = register_session();
 
     // Parsed synchronously here (rather than stored as raw bytes) because the
     // CBOR buffer is only guaranteed to outlive this call, not the background
@@ -241,10 +359,14 @@ pub unsafe extern "C" fn QsNative_AiChat_Stream(
         message: c_string(message),
         attachments_json: c_string(attachments_json),
         disabled_tool_servers_json: c_string(disabled_tool_servers_json),
+        context_json: c_string(context_json),
+        response_schema_json: c_string(response_schema_json),
+        prompt_vars_json: c_string(prompt_vars_json),
         ctx: ctx as usize,
         cb,
         cancelled,
         id,
+        ..StreamArgs::default()
     };
 
     thread::spawn(move || run_stream(&args));
@@ -264,7 +386,40 @@ pub extern "C" fn QsNative_AiChat_Cancel(id: c_int) {
 }
 
 #[no_mangle]
-/// Returns the CBOR-encoded metrics for the most recently completed turn.
+/// Answers an `approval_required` event for tool call `call_id` in session
+/// `id`, unblocking the stream. Returns `false` if no such call is waiting.
+///
+/// # Safety
+///
+/// `call_id` must be null or a valid NUL-terminated string for the duration
+/// of this call.
+pub unsafe extern "C" fn QsNative_AiChat_ResolveApproval(
+    id: c_int,
+    call_id: *const c_char,
+    allow: bool,
+) -> bool {
+    approval::resolve(id, c_string(call_id).trim(), allow)
+}
+
+#[no_mangle]
+/// Returns the CBOR-encoded metrics for session `id`: live figures while it
+/// streams, final ones once it ends. Returns an empty map for unknown or
+/// long-finished sessions.
+///
+/// # Panics
+///
+/// Panics if the internal metrics mutex is poisoned.
+pub extern "C" fn QsNative_AiChat_Metrics(id: c_int) -> crate::ffi::QsNativeBytes {
+    match session_metrics().lock().expect("metrics mutex").get(&id) {
+        Some(metrics) => crate::ffi::into_cbor(metrics),
+        None => crate::ffi::into_cbor(&Map::new()),
+    }
+}
+
+#[no_mangle]
+/// Returns the CBOR-encoded metrics for the most recently completed turn of
+/// any session. Prefer `QsNative_AiChat_Metrics` when several sessions may
+/// stream at once.
 ///
 /// # Panics
 ///
@@ -311,6 +466,7 @@ pub unsafe extern "C" fn QsNative_AiModels_Catalog(
     crate::ffi::into_cbor(&catalog)
 }
 
+#[derive(Clone)]
 struct StreamArgs {
     model_id: String,
     provider_config: HashMap<String, ProviderConfig>,
@@ -319,12 +475,44 @@ struct StreamArgs {
     message: String,
     attachments_json: String,
     disabled_tool_servers_json: String,
+    context_json: String,
+    response_schema_json: String,
+    prompt_vars_json: String,
+    /// Set for turns nobody is watching (scheduled jobs): tool calls that
+    /// need approval are refused instead of asked about.
+    headless: bool,
     ctx: usize,
     cb: TokenCallback,
     cancelled: Arc<AtomicBool>,
     id: i32,
 }
 
+/// Empty args whose callback drops every event, for callers to fill in
+/// with struct update syntax.
+impl Default for StreamArgs {
+    fn default() -> Self {
+        Self {
+            model_id: String::new(),
+            provider_config: HashMap::new(),
+            system_prompt: String::new(),
+            conversation_id: String::new(),
+            message: String::new(),
+            attachments_json: String::new(),
+            disabled_tool_servers_json: String::new(),
+            context_json: String::new(),
+            response_schema_json: String::new(),
+            prompt_vars_json: String::new(),
+            headless: false,
+            ctx: 0,
+            cb: discard_tokens,
+            cancelled: Arc::new(AtomicBool::new(false)),
+            id: 0,
+        }
+    }
+}
+
+unsafe extern "C" fn discard_tokens(_ctx: *mut c_void, _token: *const c_char, _done: c_int) {}
+
 fn run_stream(args: &StreamArgs) {
     let result = run_stream_inner(args);
     sessions().lock().expect("session mutex").remove(&args.id);
@@ -335,20 +523,30 @@ fn run_stream(args: &StreamArgs) {
 
 fn run_stream_inner(args: &StreamArgs) -> Result<(), String> {
     let (provider, raw_model_id) = split_model_id(&args.model_id)?;
-    if !matches!(provider.as_str(), "openai" | "local" | "gemini") {
+    if !matches!(
+        provider.as_str(),
+        "openai" | "local" | "gemini" | "anthropic" | "ollama"
+    ) {
         let message = format!("unknown provider: {provider}");
-        store_metrics(SessionMetrics {
-            model: args.model_id.clone(),
-            ttf_ms: -1.0,
-            error: message.clone(),
-            ..SessionMetrics::default()
-        });
+        store_metrics(
+            args.id,
+            SessionMetrics {
+                model: args.model_id.clone(),
+                ttf_ms: -1.0,
+                error: message.clone(),
+                ..SessionMetrics::default()
+            },
+        );
         return Err(message);
     }
 
     let config = args.provider_config.get(&provider).cloned().unwrap_or_default();
+    if provider == "ollama" {
+        // Primes the capability cache consulted by the checks below.
+        let _ = ollama::capabilities(&config, &raw_model_id);
+    }
     let attachments = parse_json_array::<Attachment>(&args.attachments_json);
-    if !attachments.is_empty() {
+    if attachments.iter().any(is_image_attachment) {
         ensure_attachment_capability(&args.model_id)?;
     }
 
@@ -357,12 +555,22 @@ fn run_stream_inner(args: &StreamArgs) -> Result<(), String> {
         raw_model_id,
         provider: provider.clone(),
         config,
-        system_prompt: args.system_prompt.clone(),
+        system_prompt: template::expand(
+            &args.system_prompt,
+            &serde_json::from_str(&args.prompt_vars_json).unwrap_or_default(),
+        ),
         conversation_id: args.conversation_id.clone(),
         message: args.message.clone(),
         attachments,
+        context: parse_json_array::<ContextRef>(&args.context_json),
         tools: Vec::new(),
         provider_search_enabled: false,
+        image_generation: false,
+        approval: crate::app_config::ToolApprovalPolicy::default(),
+        max_parallel_tools: 1,
+        context_window: context_window(&args.model_id),
+        params: load_generation_params(&args.conversation_id),
+        response_schema: schema::parse(&args.response_schema_json)?,
     };
 
     let disabled_tool_servers = disabled_tool_servers(&args.disabled_tool_servers_json);
@@ -370,13 +578,87 @@ fn run_stream_inner(args: &StreamArgs) -> Result<(), String> {
         && !disabled_tool_servers
             .iter()
             .any(|server| server == "provider_search");
+    req.image_generation = stream::image_generation_supported(&req)
+        && !disabled_tool_servers
+            .iter()
+            .any(|server| server == "image_generation");
     if supports_tools(&req) {
         req.tools = mcp_tool_descriptors(&disabled_tool_servers);
+        let settings = crate::app_config::load_tool_settings(&crate::app_config::default_path())?;
+        req.approval = settings.approval;
+        req.max_parallel_tools = settings.max_parallel;
     }
 
     stream::run(args, &req)
 }
 
+/// The conversation's stored generation parameters. They were validated when
+/// set, so unreadable ones are dropped rather than failing the turn.
+fn load_generation_params(conversation_id: &str) -> params::GenerationParams {
+    crate::chatstore::load_generation_params(conversation_id)
+        .ok()
+        .and_then(|raw| serde_json::from_str(&raw).ok())
+        .unwrap_or_default()
+}
+
+/// Validates generation parameters given as a JSON object against
+/// `model_id` and returns them normalized, for storage.
+pub(crate) fn normalize_generation_params(model_id: &str, raw: &str) -> Result<String, String> {
+    let (provider, raw_model_id) = split_model_id(model_id)?;
+    let req = StreamRequest {
+        model_id: model_id.trim().to_owned(),
+        raw_model_id,
+        config: crate::config_resolver::provider_api(&provider)
+            .map(|api| ProviderConfig {
+                api,
+                ..ProviderConfig::default()
+            })
+            .unwrap_or_default(),
+        provider,
+        ..StreamRequest::default()
+    };
+    Ok(must_json(&params::validate(&req, raw)?))
+}
+
+/// Re-targets `base` at fallback `model_id`, keeping the turn's message,
+/// context and tools. `None` when the model is unusable for this turn.
+fn fallback_request(
+    args: &StreamArgs,
+    base: &StreamRequest,
+    model_id: &str,
+) -> Option<StreamRequest> {
+    let (provider, raw_model_id) = split_model_id(model_id).ok()?;
+    if !matches!(
+        provider.as_str(),
+        "openai" | "local" | "gemini" | "anthropic" | "ollama"
+    ) {
+        return None;
+    }
+    let config = args.provider_config.get(&provider).cloned()?;
+    if provider == "ollama" {
+        let _ = ollama::capabilities(&config, &raw_model_id);
+    }
+    if base.attachments.iter().any(is_image_attachment)
+        && ensure_attachment_capability(model_id).is_err()
+    {
+        return None;
+    }
+    let mut req = StreamRequest {
+        model_id: model_id.to_owned(),
+        raw_model_id,
+        provider,
+        config,
+        context_window: context_window(model_id),
+        ..base.clone()
+    };
+    req.provider_search_enabled &= provider_search_enabled(&req);
+    req.image_generation &= stream::image_generation_supported(&req);
+    if !supports_tools(&req) {
+        req.tools.clear();
+    }
+    Some(req)
+}
+
 fn tool_start_event_json(call: &ToolCall) -> String {
     let display_name = tool_call_display_name(call);
     must_json(&json!({
@@ -498,14 +780,17 @@ fn call_mcp_tool(call: &ToolCall) -> ToolResult {
 
 fn supports_tools(req: &StreamRequest) -> bool {
     match req.provider.trim() {
-        "openai" | "local" | "gemini" => model_supports_tools(&req.model_id).unwrap_or(true),
+        "openai" | "local" | "gemini" | "anthropic" => {
+            model_supports_tools(&req.model_id).unwrap_or(true)
+        }
+        "ollama" => model_supports_tools(&req.model_id).unwrap_or(false),
         _ => false,
     }
 }
 
 fn provider_search_enabled(req: &StreamRequest) -> bool {
     match req.provider.as_str() {
-        "openai" => true,
+        "openai" | "anthropic" => true,
         "local" | "gemini" => req.raw_model_id.trim().starts_with("gemini-3"),
         _ => false,
     }
@@ -546,8 +831,37 @@ fn capabilities(model_id: &str) -> Option<(bool, bool)> {
         | "local/gpt-5.3-codex"
         | "gemini/gemini-3.1-pro-preview"
         | "gemini/gemini-3.5-flash"
-        | "gemini/gemini-3.1-flash-lite" => Some((true, true)),
-        _ => None,
+        | "gemini/gemini-3.1-flash-lite"
+        | "anthropic/claude-opus-4-1"
+        | "anthropic/claude-sonnet-4-5"
+        | "anthropic/claude-haiku-4-5" => Some((true, true)),
+        other => {
+            let capabilities = ollama::cached_capabilities(other.strip_prefix("ollama/")?)?;
+            let flag = |key: &str| capabilities.get(key).and_then(Value::as_bool) == Some(true);
+            Some((flag("supports_images"), flag("supports_tools")))
+        }
+    }
+}
+
+/// Context window in tokens: live from Ollama, otherwise by hosted model
+/// family, with a conservative default for anything unrecognised.
+fn context_window(model_id: &str) -> usize {
+    let (provider, raw_model_id) = split_model_id(model_id).unwrap_or_default();
+    if provider == "ollama" {
+        if let Some(length) = ollama::cached_capabilities(&raw_model_id)
+            .and_then(|capabilities| capabilities.get("context_length")?.as_u64())
+        {
+            return usize::try_from(length).unwrap_or(usize::MAX);
+        }
+    }
+    if raw_model_id.starts_with("gemini-") {
+        1_048_576
+    } else if raw_model_id.starts_with("claude-") {
+        200_000
+    } else if raw_model_id.starts_with("gpt-5") {
+        400_000
+    } else {
+        compaction::DEFAULT_CONTEXT_WINDOW
     }
 }
 
@@ -577,25 +891,41 @@ fn model_catalog_value(
             out
         });
     let mut provider_order = if provider_order.is_empty() {
-        vec!["local".to_owned(), "openai".to_owned(), "gemini".to_owned()]
+        vec![
+            "local".to_owned(),
+            "openai".to_owned(),
+            "gemini".to_owned(),
+            "anthropic".to_owned(),
+            "ollama".to_owned(),
+        ]
     } else {
         provider_order
     };
-    for provider in ["local", "openai", "gemini"] {
+    for provider in ["local", "openai", "gemini", "anthropic", "ollama"] {
         if !provider_order.iter().any(|value| value == provider) {
             provider_order.push(provider.to_owned());
         }
     }
 
-    let recommended = recommended_models(configured_models);
+    let mut recommended = recommended_models(configured_models);
     let mut providers = providers_from_config(provider_config, &recommended);
     if let Some(local) = providers.iter_mut().find(|provider| provider.id == "local") {
         local.model_ids =
             live_local_model_ids(provider_config).unwrap_or_else(|| local.model_ids.clone());
     }
+    if let Some(ollama) = providers
+        .iter_mut()
+        .find(|provider| provider.id == "ollama" && provider.enabled)
+    {
+        let installed = provider_config
+            .get("ollama")
+            .and_then(ollama::discover)
+            .unwrap_or_default();
+        ollama.model_ids = add_ollama_models(&mut recommended, installed);
+    }
 
     let provider_values = provider_values(&providers, &provider_order);
-    let models = model_values(&recommended, &providers, &provider_order);
+    let models = model_values(&recommended, &providers, &provider_order, provider_config);
 
     json!({
         "models": models,
@@ -603,6 +933,30 @@ fn model_catalog_value(
     })
 }
 
+/// Installed Ollama models carry live capabilities, so they replace the
+/// configured defaults; models not listed in `models.json` are added.
+fn add_ollama_models(
+    recommended: &mut Vec<RecommendedModel>,
+    installed: Vec<(String, Map<String, Value>)>,
+) -> Vec<String> {
+    let mut ids = Vec::with_capacity(installed.len());
+    for (raw_id, capabilities) in installed {
+        if let Some(model) = recommended.iter_mut().find(|model| model.raw_id == raw_id) {
+            model.capabilities.extend(capabilities);
+        } else {
+            recommended.push(RecommendedModel {
+                label: model_label(&raw_id),
+                raw_id: raw_id.clone(),
+                description: "Installed in Ollama".to_owned(),
+                recommended: true,
+                capabilities,
+            });
+        }
+        ids.push(raw_id);
+    }
+    ids
+}
+
 fn recommended_models(configured_models: Vec<ConfiguredModel>) -> Vec<RecommendedModel> {
     let mut by_raw = BTreeMap::<String, RecommendedModel>::new();
     for model in configured_models {
@@ -636,7 +990,7 @@ fn providers_from_config(
     provider_config: &HashMap<String, ProviderConfig>,
     recommended: &[RecommendedModel],
 ) -> Vec<Provider> {
-    ["local", "openai", "gemini"]
+    ["local", "openai", "gemini", "anthropic", "ollama"]
         .into_iter()
         .map(|id| Provider {
             id: id.to_owned(),
@@ -708,6 +1062,7 @@ fn model_values(
     models: &[RecommendedModel],
     providers: &[Provider],
     order: &[String],
+    provider_config: &HashMap<String, ProviderConfig>,
 ) -> Vec<Value> {
     let mut out = Vec::new();
     for model in models {
@@ -736,6 +1091,19 @@ fn model_values(
             })
             .collect::<Vec<_>>();
         let visual_provider = model_visual_provider(&model.raw_id).unwrap_or(selected.id.as_str());
+        // Generation-parameter flags follow the selected provider's wire
+        // protocol; flags set in models.json win.
+        let mut capabilities = params::support(&StreamRequest {
+            provider: selected.id.clone(),
+            raw_model_id: model.raw_id.clone(),
+            config: provider_config
+                .get(&selected.id)
+                .cloned()
+                .unwrap_or_default(),
+            ..StreamRequest::default()
+        })
+        .capabilities();
+        capabilities.extend(model.capabilities.clone());
         out.push(json!({
             "value": model.raw_id,
             "rawId": model.raw_id,
@@ -754,7 +1122,7 @@ fn model_values(
             "provider": selected.id,
             "providerLabel": selected.label,
             "enabled": selected.enabled,
-            "capabilities": model.capabilities,
+            "capabilities": capabilities,
             "providers": provider_entries,
             "icon": provider_icon(visual_provider),
             "iconImage": provider_icon_image(visual_provider),
@@ -780,6 +1148,9 @@ fn provider_enabled(provider: &str, config: Option<&ProviderConfig>) -> bool {
     };
     if provider == "local" {
         !base_url(&config.base_url, "http://127.0.0.1:8317/v1").is_empty()
+    } else if provider == "ollama" {
+        // Opt-in: only probe the daemon when config.toml declares it.
+        !config.base_url.trim().is_empty()
     } else {
         !config.api_key.trim().is_empty()
     }
@@ -790,6 +1161,8 @@ fn provider_label(provider: &str) -> String {
         "local" => "Local".to_owned(),
         "openai" => "OpenAI".to_owned(),
         "gemini" => "Gemini".to_owned(),
+        "anthropic" => "Anthropic".to_owned(),
+        "ollama" => "Ollama".to_owned(),
         _ => provider.to_owned(),
     }
 }
@@ -830,7 +1203,7 @@ fn default_capabilities() -> Map<String, Value> {
 }
 
 fn provider_icon(provider: &str) -> &'static str {
-    if provider == "local" {
+    if provider == "local" || provider == "ollama" {
         "\u{f048b}"
     } else {
         ""
@@ -848,7 +1221,7 @@ fn provider_icon_image(provider: &str) -> &'static str {
 fn provider_accent_role(provider: &str) -> &'static str {
     match provider {
         "gemini" => "primary",
-        "local" => "secondary",
+        "local" | "ollama" => "secondary",
         _ => "tertiary",
     }
 }
@@ -858,6 +1231,8 @@ fn model_visual_provider(raw_id: &str) -> Option<&'static str> {
         Some("gemini")
     } else if raw_id.starts_with("gpt-") {
         Some("openai")
+    } else if raw_id.starts_with("claude-") {
+        Some("anthropic")
     } else {
         None
     }
@@ -876,6 +1251,8 @@ fn provider_supports_raw_model(provider: &str, raw_id: &str) -> bool {
         "local" => true,
         "openai" => raw_id.starts_with("gpt-"),
         "gemini" => raw_id.starts_with("gemini-"),
+        "anthropic" => raw_id.starts_with("claude-"),
+        // Ollama serves whatever `/api/tags` lists; see `add_ollama_models`.
         _ => false,
     }
 }
@@ -904,21 +1281,63 @@ pub(crate) fn attachment_binary(
         };
         return Ok(Some((mime.to_owned(), attachment.b64.trim().to_owned())));
     }
-    if attachment.path.trim().is_empty() {
+    let path = attachment.path.trim();
+    if path.is_empty() {
         return Ok(None);
     }
-    let raw = std::fs::read(attachment.path.trim()).map_err(|error| error.to_string())?;
+    let size = std::fs::metadata(path)
+        .map_err(|error| error.to_string())?
+        .len();
+    if size > MAX_ATTACHMENT_BYTES {
+        return Err(format!(
+            "{path} is {} MiB; attachments are limited to {} MiB",
+            size / MIB,
+            MAX_ATTACHMENT_BYTES / MIB
+        ));
+    }
+    let raw = std::fs::read(path).map_err(|error| error.to_string())?;
     if raw.is_empty() {
         return Ok(None);
     }
     let mime = if attachment.mime.trim().is_empty() {
-        mime_from_path(attachment.path.trim())
+        mime_from_path(path)
     } else {
         attachment.mime.trim().to_owned()
     };
     Ok(Some((mime, BASE64.encode(raw))))
 }
 
+/// Builds the input part for a non-image attachment; see `documents`.
+pub(crate) fn document_part(
+    attachment: &Attachment,
+    mime: &str,
+    b64: &str,
+) -> Result<Value, String> {
+    documents::input_part(attachment, mime, b64)
+}
+
+/// Runs a `[[schedule.jobs]]` prompt headless on the calling thread and saves
+/// it as a conversation tagged `scheduled`; see `schedule`. `on_start` gets
+/// the run's session id, which `QsNative_AiChat_Cancel` accepts while it runs.
+pub(crate) fn run_scheduled_job(
+    job: &crate::app_config::ScheduledJob,
+    provider_config: HashMap<String, ProviderConfig>,
+    on_start: impl FnOnce(i32),
+) -> Result<schedule::JobRun, String> {
+    schedule::run(job, provider_config, on_start)
+}
+
+/// Whether the attachment is an image, judged by its declared or guessed
+/// type without reading it. Only images need a vision-capable model.
+fn is_image_attachment(attachment: &Attachment) -> bool {
+    let mime = if attachment.mime.trim().is_empty() {
+        mime_from_path(attachment.path.trim())
+    } else {
+        attachment.mime.trim().to_ascii_lowercase()
+    };
+    !attachment.url.trim().is_empty() || mime.starts_with("image/")
+}
+
 fn mime_from_path(path: &str) -> String {
     mime_guess::from_path(path)
         .first_or_octet_stream()
@@ -1035,19 +1454,73 @@ fn metrics_snapshot(
         output_tokens: result.output_tokens,
         ttf_ms: tracker.ttf_ms,
         total_ms: tracker.total_ms(),
+        tokens_per_sec: tracker.tokens_per_sec(result.output_tokens),
         finished,
         error: error.to_owned(),
+        retries: tracker.retries,
+        fallbacks: tracker.fallbacks.clone(),
+    }
+}
+
+/// Appends a turn that reached the provider to the usage ledger, priced from
+/// the model's `[models."<id>"]` entry. `billed_prompt_tokens` sums the
+/// prompts of every tool round, where `metrics` only keeps the last one.
+fn record_usage(conversation_id: &str, metrics: &SessionMetrics, billed_prompt_tokens: i32) {
+    if billed_prompt_tokens == 0 && metrics.output_tokens == 0 {
+        return;
     }
+    let (prompt_tokens, output_tokens) = (
+        i64::from(billed_prompt_tokens),
+        i64::from(metrics.output_tokens),
+    );
+    let _ = crate::chatstore::record_usage(&crate::chatstore::UsageRecord {
+        conversation_id: conversation_id.to_owned(),
+        model_id: metrics.model.clone(),
+        prompt_tokens,
+        output_tokens,
+        ttf_ms: metrics.ttf_ms,
+        total_ms: metrics.total_ms,
+        cost_usd: crate::config_resolver::model_prices(&metrics.model)
+            .map(|prices| prices.cost(prompt_tokens, output_tokens)),
+    });
 }
 
-fn store_metrics(metrics: SessionMetrics) {
+/// Records the final metrics of session `id`.
+fn store_metrics(id: i32, metrics: SessionMetrics) {
+    update_session_metrics(id, metrics.clone());
     *last_metrics().lock().expect("metrics mutex") = metrics;
 }
 
+/// Replaces the metrics kept for session `id`, dropping the oldest sessions
+/// beyond `KEPT_SESSION_METRICS`.
+fn update_session_metrics(id: i32, metrics: SessionMetrics) {
+    let mut kept = session_metrics().lock().expect("metrics mutex");
+    kept.insert(id, metrics);
+    while kept.len() > KEPT_SESSION_METRICS {
+        kept.pop_first();
+    }
+}
+
+/// Allocates a session id and registers its cancel flag, so
+/// `QsNative_AiChat_Cancel` can reach it until the session is removed.
+fn register_session() -> (i32, Arc<AtomicBool>) {
+    let id = NEXT_SESSION_ID.fetch_add(1, Ordering::SeqCst);
+    let cancelled = Arc::new(AtomicBool::new(false));
+    sessions()
+        .lock()
+        .expect("session mutex")
+        .insert(id, cancelled.clone());
+    (id, cancelled)
+}
+
 fn sessions() -> &'static Mutex<HashMap<i32, Arc<AtomicBool>>> {
     SESSIONS.get_or_init(|| Mutex::new(HashMap::new()))
 }
 
+fn session_metrics() -> &'static Mutex<BTreeMap<i32, SessionMetrics>> {
+    SESSION_METRICS.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
 fn last_metrics() -> &'static Mutex<SessionMetrics> {
     LAST_METRICS.get_or_init(|| {
         Mutex::new(SessionMetrics {
@@ -1128,6 +1601,63 @@ mod tests {
         assert_eq!(mime, "image/svg+xml");
     }
 
+    #[test]
+    fn catalog_routes_claude_models_to_anthropic() {
+        let provider_config: HashMap<String, ProviderConfig> =
+            serde_json::from_str(r#"{"anthropic":{"api_key":"sk-ant-test"}}"#)
+                .expect("provider config");
+        let configured_models: Vec<ConfiguredModel> =
+            serde_json::from_str(r#"[{"raw_id": "claude-sonnet-4-5"}]"#)
+                .expect("configured models");
+
+        let payload = model_catalog_value(
+            &provider_config,
+            vec!["anthropic".to_owned()],
+            configured_models,
+        );
+        let model = payload["models"][0].as_object().expect("model object");
+
+        assert_eq!(model["canonicalId"], "anthropic/claude-sonnet-4-5");
+        assert_eq!(model["enabled"], true);
+        assert!(payload["providers"]
+            .as_array()
+            .expect("providers")
+            .iter()
+            .any(|provider| provider["value"] == "anthropic" && provider["label"] == "Anthropic"));
+    }
+
+    #[test]
+    fn installed_ollama_models_join_the_catalog_with_live_capabilities() {
+        let configured_models: Vec<ConfiguredModel> =
+            serde_json::from_str(r#"[{"raw_id": "gpt-5.4-mini"}, {"raw_id": "qwen3:8b"}]"#)
+                .expect("configured models");
+        let mut recommended = recommended_models(configured_models);
+        let installed = vec![
+            (
+                "qwen3:8b".to_owned(),
+                Map::from_iter([("supports_images".to_owned(), json!(false))]),
+            ),
+            (
+                "llava:7b".to_owned(),
+                Map::from_iter([("supports_images".to_owned(), json!(true))]),
+            ),
+        ];
+
+        let ids = add_ollama_models(&mut recommended, installed);
+
+        assert_eq!(ids, ["qwen3:8b", "llava:7b"]);
+        let qwen = recommended.iter().find(|model| model.raw_id == "qwen3:8b");
+        assert_eq!(
+            qwen.map(|model| &model.capabilities["supports_images"]),
+            Some(&json!(false))
+        );
+        assert!(recommended.iter().any(|model| model.raw_id == "llava:7b"));
+        assert!(!provider_enabled(
+            "ollama",
+            Some(&ProviderConfig::default())
+        ));
+    }
+
     #[test]
     fn catalog_uses_model_family_icon_when_local_routes_gemini() {
         let provider_config: HashMap<String, ProviderConfig> =
@@ -0,0 +1,178 @@
+//! Tool approval gate.
+//!
+//! Each model-requested call is checked against the `[tools.approval]` policy
+//! before it runs. In `ask` mode the stream publishes an `approval_required`
+//! event and parks the worker thread until `QsNative_AiChat_ResolveApproval`
+//! answers or the session is cancelled. Headless turns (scheduled jobs)
+//! have nobody to ask, so `ask` calls are refused there.
+
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::time::Duration;
+
+use serde_json::json;
+
+use super::{
+    callback, empty_to_null, must_json, tool_call_display_name, tool_start_subtitle, StreamArgs,
+    StreamRequest, ToolCall,
+};
+use crate::app_config::ApprovalMode;
+use crate::utils::first_non_empty;
+
+/// How often a parked stream re-checks its cancellation flag.
+const CANCEL_POLL: Duration = Duration::from_millis(250);
+
+type Pending = HashMap<(i32, String), Option<bool>>;
+
+static PENDING: OnceLock<(Mutex<Pending>, Condvar)> = OnceLock::new();
+
+/// Returns `Ok` when `call` may run, or the reason it was refused.
+pub(super) fn check(args: &StreamArgs, req: &StreamRequest, call: &ToolCall) -> Result<(), String> {
+    let risk = first_non_empty([
+        call.risk.as_str(),
+        if call.read_only {
+            "read"
+        } else if call.destructive {
+            "destructive"
+        } else {
+            "write"
+        },
+    ]);
+    match req.approval.mode_for(&call.server_id, &call.name, &risk) {
+        ApprovalMode::Auto => Ok(()),
+        ApprovalMode::Deny => Err("blocked by the tool approval policy".to_owned()),
+        ApprovalMode::Ask if args.headless => {
+            Err("needs approval, which a scheduled job cannot ask for".to_owned())
+        }
+        ApprovalMode::Ask => ask(args, call, &risk),
+    }
+}
+
+/// Answers a parked approval request. Returns `false` when nothing is waiting
+/// on `(session, call_id)`.
+pub(super) fn resolve(session: i32, call_id: &str, allow: bool) -> bool {
+    let (lock, ready) = pending();
+    let mut waiting = lock.lock().expect("approval mutex");
+    let Some(answer) = waiting.get_mut(&(session, call_id.to_owned())) else {
+        return false;
+    };
+    *answer = Some(allow);
+    ready.notify_all();
+    true
+}
+
+fn ask(args: &StreamArgs, call: &ToolCall, risk: &str) -> Result<(), String> {
+    let key = (args.id, call.id.clone());
+    let (lock, ready) = pending();
+    // Registered before the event goes out so an immediate answer is not lost.
+    lock.lock()
+        .expect("approval mutex")
+        .insert(key.clone(), None);
+    let event = json!({
+        "kind": "approval_required",
+        "session_id": args.id,
+        "tool_call_id": call.id,
+        "tool_name": call.name,
+        "tool_title": empty_to_null(&call.tool_title),
+        "server_id": empty_to_null(&call.server_id),
+        "server_label": empty_to_null(&call.server_label),
+        "risk": risk,
+        "summary": format!("allow {}?", tool_call_display_name(call)),
+        "subtitle": tool_start_subtitle(call),
+        "arguments": call.arguments,
+    });
+    callback(args.cb, args.ctx, &must_json(&event), 2);
+
+    let mut waiting = lock.lock().expect("approval mutex");
+    let answer = loop {
+        if let Some(answer) = waiting.get(&key).copied().flatten() {
+            break Some(answer);
+        }
+        if args.cancelled.load(Ordering::SeqCst) {
+            break None;
+        }
+        waiting = ready
+            .wait_timeout(waiting, CANCEL_POLL)
+            .expect("approval mutex")
+            .0;
+    };
+    waiting.remove(&key);
+    match answer {
+        Some(true) => Ok(()),
+        Some(false) => Err("denied by the user".to_owned()),
+        None => Err("cancelled before it was approved".to_owned()),
+    }
+}
+
+fn pending() -> &'static (Mutex<Pending>, Condvar) {
+    PENDING.get_or_init(|| (Mutex::new(HashMap::new()), Condvar::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{c_void, CStr};
+    use std::os::raw::{c_char, c_int};
+    use std::thread;
+
+    use super::*;
+    use crate::app_config::ToolApprovalPolicy;
+
+    unsafe extern "C" fn answer(ctx: *mut c_void, token: *const c_char, done: c_int) {
+        assert_eq!(done, 2);
+        let allow = unsafe { *ctx.cast::<bool>() };
+        let event: serde_json::Value =
+            serde_json::from_str(&unsafe { CStr::from_ptr(token) }.to_string_lossy())
+                .expect("event json");
+        assert_eq!(event["kind"], "approval_required");
+        let session = i32::try_from(event["session_id"].as_i64().expect("session")).expect("i32");
+        let call_id = event["tool_call_id"].as_str().expect("call id").to_owned();
+        // Answer from another thread, as the UI would.
+        thread::spawn(move || assert!(resolve(session, &call_id, allow)));
+    }
+
+    fn args(allow: &mut bool) -> StreamArgs {
+        StreamArgs {
+            ctx: std::ptr::from_mut(allow) as usize,
+            cb: answer,
+            id: 4242,
+            ..StreamArgs::default()
+        }
+    }
+
+    #[test]
+    fn ask_mode_blocks_until_the_call_is_resolved() {
+        let req = StreamRequest {
+            approval: ToolApprovalPolicy::default(),
+            ..StreamRequest::default()
+        };
+        let call = ToolCall {
+            id: "call_shell".to_owned(),
+            name: "shell_command".to_owned(),
+            server_id: "builtin".to_owned(),
+            destructive: true,
+            ..ToolCall::default()
+        };
+        let mut allow = true;
+        assert_eq!(check(&args(&mut allow), &req, &call), Ok(()));
+        let mut allow = false;
+        assert_eq!(
+            check(&args(&mut allow), &req, &call),
+            Err("denied by the user".to_owned())
+        );
+        assert!(!resolve(4242, "call_shell", true));
+
+        let read = ToolCall {
+            read_only: true,
+            ..call.clone()
+        };
+        assert_eq!(check(&args(&mut allow), &req, &read), Ok(()));
+
+        let headless = StreamArgs {
+            headless: true,
+            ..args(&mut allow)
+        };
+        assert!(check(&headless, &req, &call).is_err_and(|error| error.contains("scheduled job")));
+        assert_eq!(check(&headless, &req, &read), Ok(()));
+    }
+}
@@ -0,0 +1,180 @@
+//! Web-search citations.
+//!
+//! Hosted search answers come back annotated: `OpenAI` puts `url_citation`
+//! annotations on the message's text parts, Gemini sends `groundingMetadata`
+//! alongside the candidate. Both are reduced to one neutral list whose spans
+//! are character offsets into the round's reply text.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub(super) struct Citation {
+    title: String,
+    url: String,
+    /// `[start, end)` in characters of the reply text; `None` when the source
+    /// backs the answer as a whole.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    span: Option<[usize; 2]>,
+}
+
+/// `url_citation` annotations of a Responses `message` item. Offsets of later
+/// text parts are shifted past the earlier ones, which the UI shows joined.
+pub(super) fn from_openai_message(item: &Value) -> Vec<Citation> {
+    let mut citations = Vec::new();
+    let mut offset = 0;
+    for part in item
+        .get("content")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        for annotation in part
+            .get("annotations")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter(|annotation| {
+                annotation.get("type").and_then(Value::as_str) == Some("url_citation")
+            })
+        {
+            let index = |key: &str| {
+                annotation
+                    .get(key)
+                    .and_then(Value::as_u64)
+                    .and_then(|index| usize::try_from(index).ok())
+            };
+            citations.push(Citation {
+                title: text(annotation, "title"),
+                url: text(annotation, "url"),
+                span: index("start_index")
+                    .zip(index("end_index"))
+                    .map(|(start, end)| [offset + start, offset + end]),
+            });
+        }
+        offset += part
+            .get("text")
+            .and_then(Value::as_str)
+            .map_or(0, |text| text.chars().count());
+    }
+    citations
+}
+
+/// Citations from a Gemini candidate's `groundingMetadata`: one per
+/// (supported segment, source) pair, plus the sources no segment points at.
+/// Gemini counts segment offsets in UTF-8 bytes of `reply`.
+pub(super) fn from_gemini_grounding(metadata: &Value, reply: &str) -> Vec<Citation> {
+    let sources = metadata
+        .get("groundingChunks")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let source = |index: usize| {
+        let web = sources.get(index)?.get("web")?;
+        Some(Citation {
+            title: text(web, "title"),
+            url: text(web, "uri"),
+            span: None,
+        })
+    };
+    let mut cited = vec![false; sources.len()];
+    let mut citations = Vec::new();
+    for support in metadata
+        .get("groundingSupports")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let byte = |key: &str| {
+            support
+                .pointer(&format!("/segment/{key}"))
+                .and_then(Value::as_u64)
+                .and_then(|index| usize::try_from(index).ok())
+        };
+        let span = [
+            char_offset(reply, byte("startIndex").unwrap_or(0)),
+            char_offset(reply, byte("endIndex").unwrap_or(0)),
+        ];
+        for index in support
+            .get("groundingChunkIndices")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_u64)
+            .filter_map(|index| usize::try_from(index).ok())
+        {
+            if let Some(citation) = source(index) {
+                cited[index] = true;
+                citations.push(Citation {
+                    span: Some(span),
+                    ..citation
+                });
+            }
+        }
+    }
+    citations.extend(
+        (0..sources.len())
+            .filter(|index| !cited[*index])
+            .filter_map(source),
+    );
+    citations
+}
+
+fn text(value: &Value, key: &str) -> String {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .unwrap_or("")
+        .to_owned()
+}
+
+/// Characters of `text` that start before byte `byte`.
+fn char_offset(text: &str, byte: usize) -> usize {
+    text.char_indices().take_while(|(at, _)| *at < byte).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn openai_and_gemini_citations_share_one_shape() {
+        let message = json!({
+            "type": "message",
+            "content": [
+                { "type": "output_text", "text": "Première.", "annotations": [] },
+                { "type": "output_text", "text": "Rain today.", "annotations": [
+                    { "type": "url_citation", "start_index": 0, "end_index": 11,
+                      "url": "https://wx.example/today", "title": "Weather" },
+                    { "type": "file_citation", "file_id": "f_1" },
+                ] },
+            ],
+        });
+        assert_eq!(
+            serde_json::to_value(from_openai_message(&message)).expect("json"),
+            json!([{ "title": "Weather", "url": "https://wx.example/today", "span": [9, 20] }])
+        );
+
+        // "é" is two bytes: byte 11 is character 10.
+        let reply = "Première. Rain today.";
+        let grounding = json!({
+            "groundingChunks": [
+                { "web": { "uri": "https://a.example", "title": "a.example" } },
+                { "web": { "uri": "https://b.example", "title": "b.example" } },
+            ],
+            "groundingSupports": [
+                { "segment": { "startIndex": 11, "endIndex": 22 }, "groundingChunkIndices": [0, 7] },
+            ],
+        });
+        assert_eq!(
+            serde_json::to_value(from_gemini_grounding(&grounding, reply)).expect("json"),
+            json!([
+                { "title": "a.example", "url": "https://a.example", "span": [10, 21] },
+                { "title": "b.example", "url": "https://b.example" },
+            ])
+        );
+        assert!(from_gemini_grounding(&json!({}), reply).is_empty());
+    }
+}
@@ -0,0 +1,207 @@
+//! Context-window budgeting.
+//!
+//! Providers only report usage after a request, so input size is estimated
+//! from text length. When a round's input would not fit the model's budget,
+//! oversized tool outputs are cut down first; if that is not enough, the
+//! oldest turns are folded into a summary that is persisted as a compaction
+//! marker (see `chatstore::apply_compactions`).
+
+use serde_json::Value;
+
+pub(super) const DEFAULT_CONTEXT_WINDOW: usize = 128_000;
+pub(super) const SUMMARY_PROMPT: &str = "You compress chat transcripts. Summarize the \
+transcript you are given so it can stand in for it in a later request: keep the user's \
+goals, decisions, facts, names, paths, identifiers and open questions, and drop \
+pleasantries. Reply with the summary only.";
+
+const CHARS_PER_TOKEN: usize = 4;
+/// Flat estimate for an inline or linked image.
+const IMAGE_TOKENS: usize = 1_000;
+/// Longest tool output kept once history is over budget.
+const TOOL_OUTPUT_CHARS: usize = 16_000;
+/// Room left for the truncation notice inside `TOOL_OUTPUT_CHARS`.
+const NOTICE_CHARS: usize = 80;
+/// Per-entry cap when older turns are rendered for the summarizer.
+const TRANSCRIPT_ENTRY_CHARS: usize = 2_000;
+
+/// Tokens the input may use, leaving a quarter of the window for the system
+/// prompt, tool schemas and the reply.
+pub(super) fn budget(context_window: usize) -> usize {
+    context_window - context_window / 4
+}
+
+pub(super) fn estimate_tokens(items: &[Value]) -> usize {
+    items.iter().map(value_tokens).sum()
+}
+
+fn value_tokens(value: &Value) -> usize {
+    match value {
+        Value::String(text) if text.starts_with("data:") => IMAGE_TOKENS,
+        Value::String(text) => text.len().div_ceil(CHARS_PER_TOKEN),
+        Value::Array(values) => values.iter().map(value_tokens).sum(),
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| {
+                if key == "image_url" {
+                    IMAGE_TOKENS
+                } else {
+                    value_tokens(value)
+                }
+            })
+            .sum(),
+        _ => 1,
+    }
+}
+
+/// Cuts tool outputs longer than `TOOL_OUTPUT_CHARS` down to their head plus
+/// a notice. The result stays under the limit, so repeating it is a no-op.
+pub(super) fn truncate_tool_outputs(items: &mut [Value]) {
+    for item in items {
+        if !matches!(
+            item.get("type").and_then(Value::as_str),
+            Some("function_call_output" | "custom_tool_call_output")
+        ) {
+            continue;
+        }
+        let Some(Value::String(output)) = item.get_mut("output") else {
+            continue;
+        };
+        let total = output.chars().count();
+        if total <= TOOL_OUTPUT_CHARS {
+            continue;
+        }
+        let kept = TOOL_OUTPUT_CHARS - NOTICE_CHARS;
+        *output = format!(
+            "{}\n\n[{} characters truncated to fit the context window]",
+            output.chars().take(kept).collect::<String>(),
+            total - kept
+        );
+    }
+}
+
+/// Picks how many leading items to summarize: the earliest turn boundary
+/// whose remainder fits in half the budget, so compaction does not recur on
+/// the next round. Falls back to the latest boundary. `None` when the input
+/// is a single turn.
+pub(super) fn split_point(items: &[Value], budget: usize) -> Option<usize> {
+    let mut remaining = estimate_tokens(items);
+    let mut last = None;
+    for (index, pair) in items.windows(2).enumerate() {
+        remaining -= value_tokens(&pair[0]);
+        if !is_user_message(&pair[1]) || is_user_message(&pair[0]) {
+            continue;
+        }
+        last = Some(index + 1);
+        if remaining <= budget / 2 {
+            break;
+        }
+    }
+    last
+}
+
+fn is_user_message(item: &Value) -> bool {
+    item.get("type").and_then(Value::as_str) == Some("message")
+        && item.get("role").and_then(Value::as_str) == Some("user")
+}
+
+/// Renders items as a plain-text transcript of at most `max_tokens`, for the
+/// summarizer or, when that fails, as the summary itself.
+pub(super) fn transcript(items: &[Value], max_tokens: usize) -> String {
+    let mut entries = Vec::new();
+    for item in items {
+        let text = |key: &str| item.get(key).and_then(Value::as_str).unwrap_or("");
+        let entry = match text("type") {
+            "message" => {
+                let body = item
+                    .get("content")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|part| match part.get("type").and_then(Value::as_str) {
+                        Some("input_image") => Some("[image]"),
+                        Some("input_file") => Some("[file]"),
+                        _ => part.get("text").and_then(Value::as_str),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let speaker = if text("role") == "user" {
+                    "User"
+                } else {
+                    "Assistant"
+                };
+                format!("{speaker}: {}", clip(body.trim(), TRANSCRIPT_ENTRY_CHARS))
+            }
+            "function_call" | "custom_tool_call" => format!(
+                "Called {} with {}",
+                text("name"),
+                clip(
+                    first_present(text("arguments"), text("input")),
+                    TRANSCRIPT_ENTRY_CHARS
+                )
+            ),
+            "function_call_output" | "custom_tool_call_output" => format!(
+                "Tool result: {}",
+                clip(text("output"), TRANSCRIPT_ENTRY_CHARS)
+            ),
+            _ => continue,
+        };
+        entries.push(entry);
+    }
+    clip(&entries.join("\n\n"), max_tokens * CHARS_PER_TOKEN)
+}
+
+fn first_present<'a>(first: &'a str, second: &'a str) -> &'a str {
+    if first.is_empty() {
+        second
+    } else {
+        first
+    }
+}
+
+fn clip(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_owned();
+    }
+    let mut out = text.chars().take(max_chars).collect::<String>();
+    out.push('…');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn user(text: &str) -> Value {
+        json!({"type": "message", "role": "user", "content": [{"type": "input_text", "text": text}]})
+    }
+
+    #[test]
+    fn over_budget_history_truncates_tool_output_and_splits_at_a_turn() {
+        let mut items = vec![
+            user("list the logs"),
+            json!({"type": "function_call", "call_id": "c1", "name": "shell", "arguments": "{}"}),
+            json!({"type": "function_call_output", "call_id": "c1", "output": "x".repeat(40_000)}),
+            user(&"y".repeat(8_000)),
+            json!({"type": "message", "role": "assistant", "content": [{"type": "output_text", "text": "ok"}]}),
+            user("and now?"),
+        ];
+        truncate_tool_outputs(&mut items);
+        let output = items[2]["output"].as_str().expect("output");
+        assert!(output.chars().count() <= TOOL_OUTPUT_CHARS);
+        assert!(output.ends_with("[24080 characters truncated to fit the context window]"));
+        let again = items.clone();
+        truncate_tool_outputs(&mut items);
+        assert_eq!(items, again);
+
+        // The second turn alone is ~2k tokens: a 4k budget keeps it, a
+        // smaller one falls back to keeping only the latest turn.
+        assert_eq!(split_point(&items, 5_000), Some(3));
+        assert_eq!(split_point(&items, 1_000), Some(5));
+        assert_eq!(split_point(&items[..1], 1_000), None);
+
+        let digest = transcript(&items[..3], 1_000);
+        assert!(digest.starts_with("User: list the logs\n\nCalled shell with {}\n\nTool result: "));
+    }
+}
@@ -0,0 +1,213 @@
+//! Document attachments.
+//!
+//! Anything that is not an image is treated as a document. Text files
+//! (plain text, Markdown, source, logs, ...) are inlined as an `input_text`
+//! part. PDFs travel as an `input_file` part, which the Responses API,
+//! Gemini and Anthropic read natively; for the other protocols, and for PDFs
+//! too large to send, their text is extracted locally with `pdftotext`
+//! (poppler) and kept beside the stored attachments, so each PDF is extracted
+//! once. Inlined text is capped at `MAX_TEXT_CHARS`.
+
+use std::fs;
+use std::io::Write as _;
+use std::path::Path;
+use std::process::Command;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde_json::{json, Value};
+
+use super::Attachment;
+
+pub(super) const PDF_MIME: &str = "application/pdf";
+/// Longest document text inlined into a turn.
+const MAX_TEXT_CHARS: usize = 100_000;
+/// Largest PDF sent as a file; bigger ones are inlined as extracted text.
+/// Base64 adds a third, and Gemini takes at most 20 MB of inline data per
+/// request.
+const MAX_FILE_BYTES: usize = 14 * 1024 * 1024;
+
+/// The input part for a non-image attachment whose content is `b64`.
+/// Binary files other than PDFs are rejected.
+pub(super) fn input_part(attachment: &Attachment, mime: &str, b64: &str) -> Result<Value, String> {
+    let name = file_name(attachment);
+    let bytes = BASE64
+        .decode(b64)
+        .map_err(|error| format!("{name}: {error}"))?;
+    if mime.eq_ignore_ascii_case(PDF_MIME) {
+        if bytes.len() <= MAX_FILE_BYTES {
+            return Ok(json!({
+                "type": "input_file",
+                "filename": name,
+                "file_data": format!("data:{PDF_MIME};base64,{b64}"),
+            }));
+        }
+        return Ok(text_part(&name, &cached_pdf_text(&bytes)?));
+    }
+    let text = std::str::from_utf8(&bytes)
+        .ok()
+        .filter(|text| !text.contains('\0'))
+        .ok_or_else(|| format!("{name}: unsupported attachment type {mime:?}"))?;
+    Ok(text_part(&name, text))
+}
+
+/// Stands in for an `input_file` part on protocols without file input: the
+/// PDF's extracted text, or a note saying why there is none.
+pub(super) fn file_part_as_text(part: &Value) -> Value {
+    let name = part
+        .get("filename")
+        .and_then(Value::as_str)
+        .unwrap_or("attachment");
+    let text = part
+        .get("file_data")
+        .and_then(Value::as_str)
+        .and_then(|url| url.split_once(";base64,"))
+        .ok_or_else(|| "not an inline file".to_owned())
+        .and_then(|(_, data)| BASE64.decode(data).map_err(|error| error.to_string()))
+        .and_then(|bytes| cached_pdf_text(&bytes))
+        .unwrap_or_else(|error| format!("[The text of this file could not be extracted: {error}]"));
+    text_part(name, &text)
+}
+
+fn file_name(attachment: &Attachment) -> String {
+    Path::new(attachment.path.trim()).file_name().map_or_else(
+        || "attachment".to_owned(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+fn text_part(name: &str, text: &str) -> Value {
+    json!({
+        "type": "input_text",
+        "text": format!("[Attached file: {name}]\n{}", clip(text.trim_end())),
+    })
+}
+
+fn clip(text: &str) -> String {
+    let total = text.chars().count();
+    if total <= MAX_TEXT_CHARS {
+        return text.to_owned();
+    }
+    format!(
+        "{}\n\n[{} more characters of this file were left out]",
+        text.chars().take(MAX_TEXT_CHARS).collect::<String>(),
+        total - MAX_TEXT_CHARS
+    )
+}
+
+fn cached_pdf_text(bytes: &[u8]) -> Result<String, String> {
+    pdf_text_cached_in(&crate::chatstore::attachments_dir(), bytes)
+}
+
+/// A PDF's text from `<sha256>.pdf.txt` in `dir`, extracting and saving it
+/// there the first time.
+fn pdf_text_cached_in(dir: &Path, bytes: &[u8]) -> Result<String, String> {
+    let path = dir.join(format!("{}.pdf.txt", crate::chatstore::content_hash(bytes)));
+    if let Ok(text) = fs::read_to_string(&path) {
+        return Ok(text);
+    }
+    let text = pdf_text(bytes)?;
+    // An unwritable cache only means extracting again next time.
+    let _ = save_text(dir, &path, &text);
+    Ok(text)
+}
+
+/// Writes `text` to `path` through a private temporary file in `dir`, so a
+/// reader never sees a partial cache.
+fn save_text(dir: &Path, path: &Path, text: &str) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(text.as_bytes())?;
+    file.persist(path).map_err(|error| error.error)?;
+    Ok(())
+}
+
+/// Extracts a PDF's text, keeping its layout.
+fn pdf_text(bytes: &[u8]) -> Result<String, String> {
+    let mut file = tempfile::Builder::new()
+        .suffix(".pdf")
+        .tempfile()
+        .map_err(|error| error.to_string())?;
+    file.write_all(bytes).map_err(|error| error.to_string())?;
+    let output = Command::new("pdftotext")
+        .args(["-layout", "-enc", "UTF-8"])
+        .arg(file.path())
+        .arg("-")
+        .output()
+        .map_err(|error| format!("pdftotext: {error}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "pdftotext: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(path: &str) -> Attachment {
+        Attachment {
+            path: path.to_owned(),
+            ..Attachment::default()
+        }
+    }
+
+    #[test]
+    fn text_documents_are_inlined_and_capped() {
+        let log = format!("{}tail", "x".repeat(MAX_TEXT_CHARS));
+        let part = input_part(
+            &attachment("/var/log/app.log"),
+            "text/plain",
+            &BASE64.encode(&log),
+        )
+        .expect("text part");
+        let text = part["text"].as_str().expect("text");
+        assert!(text.starts_with("[Attached file: app.log]\nxxx"));
+        assert!(text.ends_with("\n\n[4 more characters of this file were left out]"));
+
+        let binary = input_part(
+            &attachment("a.bin"),
+            "application/octet-stream",
+            &BASE64.encode([0, 159, 146, 150]),
+        );
+        assert_eq!(
+            binary,
+            Err("a.bin: unsupported attachment type \"application/octet-stream\"".to_owned())
+        );
+    }
+
+    #[test]
+    fn pdfs_are_sent_as_files_with_a_text_fallback() {
+        let part =
+            input_part(&attachment("/tmp/spec.pdf"), PDF_MIME, "JVBERi0=").expect("file part");
+        assert_eq!(
+            part,
+            json!({
+                "type": "input_file",
+                "filename": "spec.pdf",
+                "file_data": "data:application/pdf;base64,JVBERi0=",
+            })
+        );
+        let fallback = file_part_as_text(&json!({ "type": "input_file", "filename": "spec.pdf" }));
+        assert_eq!(fallback["type"], "input_text");
+        assert!(fallback["text"]
+            .as_str()
+            .is_some_and(|text| text.starts_with("[Attached file: spec.pdf]\n[The text")));
+    }
+
+    #[test]
+    fn extracted_pdf_text_is_read_back_from_the_cache() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let pdf = b"%PDF-not-really";
+        // Not a PDF pdftotext could read, so the text has to come from the cache.
+        assert!(pdf_text_cached_in(dir.path(), pdf).is_err());
+        let cached = format!("{}.pdf.txt", crate::chatstore::content_hash(pdf));
+        save_text(dir.path(), &dir.path().join(cached), "Quarterly numbers").expect("seed cache");
+        assert_eq!(
+            pdf_text_cached_in(dir.path(), pdf),
+            Ok("Quarterly numbers".to_owned())
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! Ollama model discovery.
+//!
+//! Installed models come from `/api/tags`; `/api/show` reports what each one
+//! can do (vision, tools) and its context length. Results are cached per raw
+//! model id so the stream path can answer capability checks without another
+//! round trip once the catalog has been built.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+
+use super::{base_url, ProviderConfig};
+
+pub(super) const DEFAULT_BASE_URL: &str = "http://127.0.0.1:11434";
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+static CAPABILITIES: OnceLock<Mutex<HashMap<String, Map<String, Value>>>> = OnceLock::new();
+
+/// Lists installed models with their capabilities, or `None` when the server
+/// is unreachable.
+pub(super) fn discover(config: &ProviderConfig) -> Option<Vec<(String, Map<String, Value>)>> {
+    let agent = probe_agent();
+    let base = base_url(&config.base_url, DEFAULT_BASE_URL);
+    let mut response = agent.get(&format!("{base}/api/tags")).call().ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    let payload = response.body_mut().read_json::<Value>().ok()?;
+    let mut out = Vec::new();
+    for entry in payload
+        .get("models")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let Some(raw_id) = entry
+            .get("model")
+            .or_else(|| entry.get("name"))
+            .and_then(Value::as_str)
+            .and_then(super::nonempty)
+        else {
+            continue;
+        };
+        if out.iter().any(|(seen, _)| seen == &raw_id) {
+            continue;
+        }
+        let capabilities = show(&agent, &base, &raw_id).unwrap_or_else(unknown_capabilities);
+        out.push((raw_id, capabilities));
+    }
+    Some(out)
+}
+
+/// Returns the capabilities for `raw_id`, asking the server on a cache miss.
+pub(super) fn capabilities(config: &ProviderConfig, raw_id: &str) -> Option<Map<String, Value>> {
+    if let Some(cached) = cached_capabilities(raw_id) {
+        return Some(cached);
+    }
+    show(
+        &probe_agent(),
+        &base_url(&config.base_url, DEFAULT_BASE_URL),
+        raw_id,
+    )
+}
+
+pub(super) fn cached_capabilities(raw_id: &str) -> Option<Map<String, Value>> {
+    cache()
+        .lock()
+        .expect("ollama capability mutex")
+        .get(raw_id)
+        .cloned()
+}
+
+fn show(agent: &ureq::Agent, base: &str, raw_id: &str) -> Option<Map<String, Value>> {
+    let mut response = agent
+        .post(&format!("{base}/api/show"))
+        .send_json(json!({ "model": raw_id }))
+        .ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    let payload = response.body_mut().read_json::<Value>().ok()?;
+    let capabilities = capabilities_from_show(&payload);
+    cache()
+        .lock()
+        .expect("ollama capability mutex")
+        .insert(raw_id.to_owned(), capabilities.clone());
+    Some(capabilities)
+}
+
+/// Maps an `/api/show` payload onto the catalog capability keys. The context
+/// length lives under an architecture-prefixed key in `model_info`.
+fn capabilities_from_show(payload: &Value) -> Map<String, Value> {
+    let declared = payload
+        .get("capabilities")
+        .and_then(Value::as_array)
+        .map(|values| values.iter().filter_map(Value::as_str).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let vision = declared.contains(&"vision");
+    let mut out = Map::from_iter([
+        ("supports_images".to_owned(), json!(vision)),
+        (
+            "supports_tools".to_owned(),
+            json!(declared.contains(&"tools")),
+        ),
+        ("supports_multimodal".to_owned(), json!(vision)),
+    ]);
+    let info = payload.get("model_info");
+    let context_length = info
+        .and_then(|info| info.get("general.architecture"))
+        .and_then(Value::as_str)
+        .and_then(|arch| info?.get(format!("{arch}.context_length")))
+        .or_else(|| {
+            info?
+                .as_object()?
+                .iter()
+                .find(|(key, _)| key.ends_with(".context_length"))
+                .map(|(_, value)| value)
+        })
+        .and_then(Value::as_u64);
+    if let Some(context_length) = context_length {
+        out.insert("context_length".to_owned(), json!(context_length));
+    }
+    out
+}
+
+fn unknown_capabilities() -> Map<String, Value> {
+    Map::from_iter([
+        ("supports_images".to_owned(), json!(false)),
+        ("supports_tools".to_owned(), json!(false)),
+        ("supports_multimodal".to_owned(), json!(false)),
+    ])
+}
+
+fn probe_agent() -> ureq::Agent {
+    ureq::Agent::config_builder()
+        .timeout_global(Some(PROBE_TIMEOUT))
+        .build()
+        .new_agent()
+}
+
+fn cache() -> &'static Mutex<HashMap<String, Map<String, Value>>> {
+    CAPABILITIES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn show_payload_maps_to_catalog_capabilities() {
+        let payload = json!({
+            "capabilities": ["completion", "vision", "tools"],
+            "model_info": {
+                "general.architecture": "gemma3",
+                "gemma3.context_length": 131_072,
+                "gemma3.embedding_length": 2560
+            }
+        });
+        let capabilities = capabilities_from_show(&payload);
+        assert_eq!(capabilities["supports_images"], true);
+        assert_eq!(capabilities["supports_tools"], true);
+        assert_eq!(capabilities["context_length"], 131_072);
+
+        let text_only = capabilities_from_show(&json!({
+            "capabilities": ["completion"],
+            "model_info": { "llama.context_length": 8192 }
+        }));
+        assert_eq!(text_only["supports_images"], false);
+        assert_eq!(text_only["supports_tools"], false);
+        assert_eq!(text_only["context_length"], 8192);
+    }
+}
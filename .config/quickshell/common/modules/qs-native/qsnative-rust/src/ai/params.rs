@@ -0,0 +1,449 @@
+//! Per-conversation generation parameters.
+//!
+//! A conversation may pin sampling and reasoning controls. They are stored as
+//! JSON beside the conversation (`chatstore::load_generation_params`), checked
+//! against what the model accepts when they are set, and mapped onto each
+//! provider's request body every round. After a model switch or a fallback,
+//! parameters the answering model does not accept are left out rather than
+//! failing the turn.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::stream::{effective_provider, Provider};
+use super::StreamRequest;
+
+const REASONING_EFFORTS: [&str; 4] = ["minimal", "low", "medium", "high"];
+/// Most stop sequences any supported provider accepts (`OpenAI`'s limit).
+const MAX_STOP_SEQUENCES: usize = 4;
+/// Smallest `thinking.budget_tokens` Anthropic accepts.
+const MIN_ANTHROPIC_THINKING_BUDGET: u32 = 1024;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct GenerationParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    temperature: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    top_p: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    /// `minimal`, `low`, `medium` or `high`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reasoning_effort: Option<String>,
+    /// Thinking tokens for models with a budget instead of an effort level.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    thinking_budget: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    stop: Vec<String>,
+}
+
+/// Which parameters a model accepts. Output length is accepted everywhere.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "one independent flag per optional parameter, published as catalog capabilities"
+)]
+pub(super) struct Support {
+    temperature: bool,
+    top_p: bool,
+    stop: bool,
+    reasoning_effort: bool,
+    thinking_budget: bool,
+}
+
+impl Support {
+    /// Whether the model reasons before answering and can summarize it.
+    pub(super) fn reasons(self) -> bool {
+        self.reasoning_effort || self.thinking_budget
+    }
+
+    /// Catalog capability flags, merged into each model's `capabilities`.
+    pub(super) fn capabilities(self) -> Map<String, Value> {
+        Map::from_iter([
+            ("supports_temperature".to_owned(), json!(self.temperature)),
+            ("supports_top_p".to_owned(), json!(self.top_p)),
+            ("supports_stop".to_owned(), json!(self.stop)),
+            (
+                "supports_reasoning_effort".to_owned(),
+                json!(self.reasoning_effort),
+            ),
+            (
+                "supports_thinking_budget".to_owned(),
+                json!(self.thinking_budget),
+            ),
+        ])
+    }
+}
+
+pub(super) fn support(req: &StreamRequest) -> Support {
+    let raw = req.raw_model_id.trim();
+    match effective_provider(req) {
+        Provider::Gemini => Support {
+            temperature: true,
+            top_p: true,
+            stop: true,
+            reasoning_effort: raw.starts_with("gemini-3"),
+            thinking_budget: true,
+        },
+        Provider::Anthropic => Support {
+            temperature: true,
+            top_p: true,
+            stop: true,
+            // Extended thinking arrived with Claude 3.7.
+            thinking_budget: !raw.starts_with("claude-3-") || raw.starts_with("claude-3-7"),
+            ..Support::default()
+        },
+        Provider::Ollama => Support {
+            temperature: true,
+            top_p: true,
+            stop: true,
+            ..Support::default()
+        },
+        wire @ (Provider::OpenAi | Provider::ChatCompletions) => {
+            // Reasoning models only run at their default sampling.
+            let reasoning = raw.starts_with("gpt-5")
+                || (raw.starts_with('o') && raw[1..].starts_with(|c: char| c.is_ascii_digit()));
+            Support {
+                temperature: !reasoning,
+                top_p: !reasoning,
+                // The Responses API has no stop sequences.
+                stop: wire == Provider::ChatCompletions,
+                reasoning_effort: reasoning,
+                thinking_budget: false,
+            }
+        }
+    }
+}
+
+/// Parses and checks user-supplied parameters for `req`'s model, returning
+/// them normalized. An empty object clears every parameter.
+pub(super) fn validate(req: &StreamRequest, raw: &str) -> Result<GenerationParams, String> {
+    let raw = raw.trim();
+    let mut params = if raw.is_empty() {
+        GenerationParams::default()
+    } else {
+        serde_json::from_str::<GenerationParams>(raw)
+            .map_err(|error| format!("invalid generation parameters: {error}"))?
+    };
+    params.stop = params
+        .stop
+        .iter()
+        .filter(|stop| !stop.is_empty())
+        .cloned()
+        .collect();
+    params.reasoning_effort = params
+        .reasoning_effort
+        .as_deref()
+        .map(|effort| effort.trim().to_ascii_lowercase())
+        .filter(|effort| !effort.is_empty());
+
+    let model = &req.model_id;
+    let supported = support(req);
+    let unsupported = [
+        (
+            "temperature",
+            params.temperature.is_some(),
+            supported.temperature,
+        ),
+        ("top_p", params.top_p.is_some(), supported.top_p),
+        ("stop", !params.stop.is_empty(), supported.stop),
+        (
+            "reasoning_effort",
+            params.reasoning_effort.is_some(),
+            supported.reasoning_effort,
+        ),
+        (
+            "thinking_budget",
+            params.thinking_budget.is_some(),
+            supported.thinking_budget,
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, set, ok)| *set && !ok)
+    .map(|(name, _, _)| name)
+    .collect::<Vec<_>>();
+    if !unsupported.is_empty() {
+        return Err(format!(
+            "model {model:?} does not support {}",
+            unsupported.join(", ")
+        ));
+    }
+
+    let anthropic = effective_provider(req) == Provider::Anthropic;
+    if anthropic {
+        check_anthropic(&params)?;
+    }
+    let max_temperature = if anthropic { 1.0 } else { 2.0 };
+    if let Some(temperature) = params.temperature {
+        if !(0.0..=max_temperature).contains(&temperature) {
+            return Err(format!(
+                "temperature must be between 0 and {max_temperature}"
+            ));
+        }
+    }
+    if let Some(top_p) = params.top_p {
+        if !(top_p > 0.0 && top_p <= 1.0) {
+            return Err("top_p must be greater than 0 and at most 1".to_owned());
+        }
+    }
+    if params.max_output_tokens == Some(0) {
+        return Err("max_output_tokens must be positive".to_owned());
+    }
+    if let Some(effort) = &params.reasoning_effort {
+        if !REASONING_EFFORTS.contains(&effort.as_str()) {
+            return Err(format!(
+                "reasoning_effort must be one of {}",
+                REASONING_EFFORTS.join(", ")
+            ));
+        }
+    }
+    if params.stop.len() > MAX_STOP_SEQUENCES {
+        return Err(format!(
+            "at most {MAX_STOP_SEQUENCES} stop sequences are allowed"
+        ));
+    }
+    Ok(params)
+}
+
+/// Combinations the Messages API refuses: current Claude models take either
+/// `temperature` or `top_p`, and extended thinking takes neither.
+fn check_anthropic(params: &GenerationParams) -> Result<(), String> {
+    if params.temperature.is_some() && params.top_p.is_some() {
+        return Err("set temperature or top_p, not both".to_owned());
+    }
+    let Some(budget) = params.thinking_budget else {
+        return Ok(());
+    };
+    if params.temperature.is_some() || params.top_p.is_some() {
+        return Err("thinking_budget cannot be combined with temperature or top_p".to_owned());
+    }
+    if budget < MIN_ANTHROPIC_THINKING_BUDGET {
+        return Err(format!(
+            "thinking_budget must be at least {MIN_ANTHROPIC_THINKING_BUDGET}"
+        ));
+    }
+    if params.max_output_tokens.is_some_and(|max| budget >= max) {
+        return Err("thinking_budget must be less than max_output_tokens".to_owned());
+    }
+    Ok(())
+}
+
+/// Adds the parameters `req`'s model accepts to a round's request body.
+pub(super) fn apply(req: &StreamRequest, body: &mut Map<String, Value>) {
+    let params = effective(req);
+    let stop = (!params.stop.is_empty()).then_some(&params.stop);
+    match effective_provider(req) {
+        Provider::OpenAi => {
+            put(body, "temperature", params.temperature);
+            put(body, "top_p", params.top_p);
+            put(body, "max_output_tokens", params.max_output_tokens);
+            put(
+                body,
+                "reasoning",
+                params
+                    .reasoning_effort
+                    .map(|effort| json!({ "effort": effort })),
+            );
+        }
+        Provider::ChatCompletions => {
+            put(body, "temperature", params.temperature);
+            put(body, "top_p", params.top_p);
+            put(body, "max_tokens", params.max_output_tokens);
+            put(body, "reasoning_effort", params.reasoning_effort.as_deref());
+            put(body, "stop", stop);
+        }
+        Provider::Gemini => {
+            let mut config = Map::new();
+            put(&mut config, "temperature", params.temperature);
+            put(&mut config, "topP", params.top_p);
+            put(&mut config, "maxOutputTokens", params.max_output_tokens);
+            put(&mut config, "stopSequences", stop);
+            // Gemini 3 takes a coarse thinking level; a budget works on all.
+            let thinking = match (params.reasoning_effort.as_deref(), params.thinking_budget) {
+                (_, Some(budget)) => Some(json!({ "thinkingBudget": budget })),
+                (Some("minimal" | "low"), None) => Some(json!({ "thinkingLevel": "low" })),
+                (Some(_), None) => Some(json!({ "thinkingLevel": "high" })),
+                (None, None) => None,
+            };
+            put(&mut config, "thinkingConfig", thinking);
+            if !config.is_empty() {
+                body.insert("generationConfig".to_owned(), Value::Object(config));
+            }
+        }
+        Provider::Anthropic => {
+            put(body, "temperature", params.temperature);
+            put(body, "top_p", params.top_p);
+            // Thinking counts against `max_tokens`; without a pinned limit the
+            // budget comes on top of the default reply length.
+            let max_tokens = match (params.max_output_tokens, params.thinking_budget) {
+                (Some(max), _) => Some(u64::from(max)),
+                (None, Some(budget)) => body
+                    .get("max_tokens")
+                    .and_then(Value::as_u64)
+                    .map(|reply| reply + u64::from(budget)),
+                (None, None) => None,
+            };
+            put(body, "max_tokens", max_tokens);
+            put(
+                body,
+                "thinking",
+                params
+                    .thinking_budget
+                    .map(|budget| json!({ "type": "enabled", "budget_tokens": budget })),
+            );
+            put(body, "stop_sequences", stop);
+        }
+        Provider::Ollama => {
+            let mut options = Map::new();
+            put(&mut options, "temperature", params.temperature);
+            put(&mut options, "top_p", params.top_p);
+            put(&mut options, "num_predict", params.max_output_tokens);
+            put(&mut options, "stop", stop);
+            if !options.is_empty() {
+                body.insert("options".to_owned(), Value::Object(options));
+            }
+        }
+    }
+}
+
+fn put<T: Serialize>(map: &mut Map<String, Value>, key: &str, value: Option<T>) {
+    if let Some(value) = value {
+        map.insert(key.to_owned(), json!(value));
+    }
+}
+
+/// `req.params` without what the answering model does not accept.
+fn effective(req: &StreamRequest) -> GenerationParams {
+    let supported = support(req);
+    let mut params = req.params.clone();
+    if effective_provider(req) == Provider::Anthropic {
+        // Parameters pinned for another model may not suit Claude; keep a
+        // combination it accepts.
+        params.thinking_budget = params.thinking_budget.filter(|budget| {
+            *budget >= MIN_ANTHROPIC_THINKING_BUDGET
+                && params.max_output_tokens.is_none_or(|max| *budget < max)
+        });
+        if params.thinking_budget.is_some() && supported.thinking_budget {
+            params.temperature = None;
+            params.top_p = None;
+        } else if params.temperature.is_some() {
+            params.top_p = None;
+        }
+    }
+    GenerationParams {
+        temperature: params.temperature.filter(|_| supported.temperature),
+        top_p: params.top_p.filter(|_| supported.top_p),
+        max_output_tokens: params.max_output_tokens,
+        reasoning_effort: params
+            .reasoning_effort
+            .filter(|_| supported.reasoning_effort),
+        thinking_budget: params.thinking_budget.filter(|_| supported.thinking_budget),
+        stop: if supported.stop {
+            params.stop
+        } else {
+            Vec::new()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(model_id: &str, api: &str) -> StreamRequest {
+        let (provider, raw_model_id) = super::super::split_model_id(model_id).expect("model id");
+        StreamRequest {
+            model_id: model_id.to_owned(),
+            raw_model_id,
+            provider,
+            config: super::super::ProviderConfig {
+                api: api.to_owned(),
+                ..Default::default()
+            },
+            ..StreamRequest::default()
+        }
+    }
+
+    #[test]
+    fn parameters_are_checked_against_the_model_and_mapped_per_provider() {
+        let gpt = request("openai/gpt-5.5", "");
+        assert_eq!(
+            validate(&gpt, r#"{"temperature": 0.2, "stop": ["END"]}"#),
+            Err("model \"openai/gpt-5.5\" does not support temperature, stop".to_owned())
+        );
+        let mut gpt = StreamRequest {
+            params: validate(
+                &gpt,
+                r#"{"reasoning_effort": " High ", "max_output_tokens": 900}"#,
+            )
+            .expect("valid"),
+            ..gpt
+        };
+        let mut body = Map::new();
+        apply(&gpt, &mut body);
+        assert_eq!(
+            Value::Object(body),
+            json!({ "reasoning": { "effort": "high" }, "max_output_tokens": 900 })
+        );
+
+        // A fallback that cannot reason keeps only what it accepts.
+        gpt.provider = "anthropic".to_owned();
+        gpt.raw_model_id = "claude-haiku-4-5".to_owned();
+        let mut body = Map::new();
+        apply(&gpt, &mut body);
+        assert_eq!(Value::Object(body), json!({ "max_tokens": 900 }));
+
+        let gemini = request("gemini/gemini-3.5-flash", "");
+        let gemini = StreamRequest {
+            params: validate(
+                &gemini,
+                r#"{"temperature": 0.7, "top_p": 0.9, "reasoning_effort": "low", "stop": ["", "END"]}"#,
+            )
+            .expect("valid"),
+            ..gemini
+        };
+        let mut body = Map::new();
+        apply(&gemini, &mut body);
+        assert_eq!(
+            body["generationConfig"],
+            json!({
+                "temperature": 0.7,
+                "topP": 0.9,
+                "stopSequences": ["END"],
+                "thinkingConfig": { "thinkingLevel": "low" }
+            })
+        );
+
+        let claude = request("anthropic/claude-sonnet-4-5", "");
+        assert!(validate(&claude, r#"{"temperature": 1.5}"#).is_err());
+        assert_eq!(
+            validate(&claude, r#"{"temperature": 0.5, "top_p": 0.9}"#),
+            Err("set temperature or top_p, not both".to_owned())
+        );
+        assert!(validate(&claude, r#"{"thinking_budget": 512}"#).is_err());
+        assert!(validate(&claude, r#"{"thinking_budget": 4096, "temperature": 0.5}"#).is_err());
+        let thinking = StreamRequest {
+            params: validate(&claude, r#"{"thinking_budget": 4096}"#).expect("valid"),
+            ..claude.clone()
+        };
+        let mut body = Map::from_iter([("max_tokens".to_owned(), json!(8192))]);
+        apply(&thinking, &mut body);
+        assert_eq!(
+            Value::Object(body),
+            json!({
+                "max_tokens": 12288,
+                "thinking": { "type": "enabled", "budget_tokens": 4096 }
+            })
+        );
+        assert!(validate(
+            &request("anthropic/claude-3-5-haiku", ""),
+            r#"{"thinking_budget": 4096}"#
+        )
+        .is_err());
+        assert!(validate(&claude, r#"{"temprature": 0.5}"#).is_err());
+        let local = request("local/qwen3", "chat_completions");
+        assert!(validate(&local, r#"{"stop": ["a", "b", "c", "d", "e"]}"#).is_err());
+        assert_eq!(validate(&local, " "), Ok(GenerationParams::default()));
+    }
+}
@@ -0,0 +1,161 @@
+//! Headless runs of scheduled jobs.
+//!
+//! A `[[schedule.jobs]]` prompt goes through the same turn as a panel
+//! message (history, tools, fallbacks, the `drive` loop), with nobody
+//! watching: tool calls the approval policy would ask about are refused, and
+//! the events the panel would persist are collected here instead, then
+//! written to a conversation of the job's own, tagged `scheduled`. Runs are
+//! registered like panel sessions, so `QsNative_AiChat_Cancel` stops them.
+
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::sync::{Mutex, PoisonError};
+
+use chrono::Local;
+use serde_json::Value;
+
+use super::{register_session, run_stream_inner, sessions, Attachment, ProviderConfig, StreamArgs};
+use crate::app_config::ScheduledJob;
+
+/// The conversation a finished job wrote to.
+pub(crate) struct JobRun {
+    pub(crate) conversation_id: String,
+    pub(crate) title: String,
+    /// Why the turn failed, if it did; the reply records it as well.
+    pub(crate) error: Option<String>,
+}
+
+/// What the panel would have persisted for the turn, rebuilt from its events.
+#[derive(Default)]
+struct Transcript {
+    reply: String,
+    /// `(source, item)` pairs in the order they were produced.
+    items: Vec<(String, Value)>,
+    attachments: Vec<Attachment>,
+}
+
+pub(super) fn run(
+    job: &ScheduledJob,
+    provider_config: HashMap<String, ProviderConfig>,
+    on_start: impl FnOnce(i32),
+) -> Result<JobRun, String> {
+    let model_id = crate::utils::non_empty_trimmed(&job.model)
+        .unwrap_or_else(crate::config_resolver::default_model);
+    let title = format!("{} · {}", job.label, Local::now().format("%a %-d %b"));
+    // Titled up front, so the automatic title job leaves it (and the
+    // transcript below, which it would otherwise outlive) alone.
+    let (conversation_id, turn_id) = crate::chatstore::open_scheduled_conversation(
+        &model_id,
+        &job.system_prompt,
+        &title,
+        &job.prompt,
+    )?;
+    let transcript = Mutex::new(Transcript::default());
+    let (id, cancelled) = register_session();
+    let args = StreamArgs {
+        model_id,
+        provider_config,
+        system_prompt: job.system_prompt.clone(),
+        conversation_id: conversation_id.clone(),
+        message: job.prompt.clone(),
+        headless: true,
+        ctx: std::ptr::from_ref(&transcript) as usize,
+        cb: collect,
+        cancelled,
+        id,
+        ..StreamArgs::default()
+    };
+    on_start(id);
+    let error = run_stream_inner(&args).err();
+    sessions()
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .remove(&id);
+    let transcript = transcript
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner);
+    crate::chatstore::finish_scheduled_conversation(
+        &conversation_id,
+        &turn_id,
+        transcript.items,
+        &transcript.reply,
+        &transcript.attachments,
+        error.as_deref().unwrap_or_default(),
+    )?;
+    Ok(JobRun {
+        conversation_id,
+        title,
+        error,
+    })
+}
+
+unsafe extern "C" fn collect(ctx: *mut c_void, token: *const c_char, done: c_int) {
+    let transcript = unsafe { &*ctx.cast::<Mutex<Transcript>>() };
+    let token = unsafe { CStr::from_ptr(token) }.to_string_lossy();
+    let mut transcript = transcript.lock().unwrap_or_else(PoisonError::into_inner);
+    match done {
+        0 => transcript.reply.push_str(&token),
+        2 => transcript.record(&token),
+        _ => {}
+    }
+}
+
+impl Transcript {
+    /// Keeps the parts of a stream event that history needs: model output,
+    /// tool results and generated images.
+    fn record(&mut self, event: &str) {
+        let Ok(mut event) = serde_json::from_str::<Value>(event) else {
+            return;
+        };
+        let (source, items) = match event["kind"].as_str() {
+            Some("raw_response_items") => ("model_output", event["items"].take()),
+            Some("tool") => ("tool_output", event["replay_items"].take()),
+            Some("attachment") => {
+                if let Ok(attachment) = serde_json::from_value(event["attachment"].take()) {
+                    self.attachments.push(attachment);
+                }
+                return;
+            }
+            _ => return,
+        };
+        if let Value::Array(items) = items {
+            self.items
+                .extend(items.into_iter().map(|item| (source.to_owned(), item)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcript_keeps_replayable_items_in_order() {
+        let mut transcript = Transcript::default();
+        for event in [
+            r#"{"kind":"raw_response_items","items":[{"type":"function_call","call_id":"c1"}]}"#,
+            r#"{"kind":"tool","phase":"tool_start","tool_call_id":"c1"}"#,
+            r#"{"kind":"tool","phase":"tool_done","replay_items":[{"type":"function_call_output","call_id":"c1"}]}"#,
+            r#"{"kind":"reasoning_delta","delta":"thinking"}"#,
+            r#"{"kind":"attachment","attachment":{"path":"/tmp/a.png","mime":"image/png"}}"#,
+            r#"{"kind":"raw_response_items","items":[{"type":"message","role":"assistant"}]}"#,
+        ] {
+            transcript.record(event);
+        }
+        let sources: Vec<_> = transcript
+            .items
+            .iter()
+            .map(|(source, item)| format!("{source}:{}", item["type"].as_str().unwrap_or("")))
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                "model_output:function_call",
+                "tool_output:function_call_output",
+                "model_output:message",
+            ]
+        );
+        assert_eq!(transcript.attachments.len(), 1);
+        assert_eq!(transcript.attachments[0].path, "/tmp/a.png");
+    }
+}
@@ -0,0 +1,560 @@
+//! Structured output.
+//!
+//! A stream request may carry a JSON Schema for the reply. It is passed to
+//! each provider's native structured-output control where there is one and
+//! spelled out in the system prompt where there is not, and the final reply
+//! is checked against it before the turn is reported finished.
+//!
+//! The checker covers the keywords structured-output schemas use: `type`,
+//! `enum`, `const`, `properties`, `required`, `additionalProperties`,
+//! `items`, `anyOf`, local `$ref`s, and the length, count and range bounds.
+//! `pattern` and `format` are not checked.
+
+use serde_json::{json, Map, Value};
+
+use super::stream::{effective_provider, Provider};
+use super::StreamRequest;
+
+/// Name the schema is registered under with `OpenAI`.
+const SCHEMA_NAME: &str = "response";
+/// Keywords Gemini's OpenAPI-style `responseSchema` rejects.
+const GEMINI_UNSUPPORTED: [&str; 6] = [
+    "$schema",
+    "$id",
+    "$defs",
+    "definitions",
+    "additionalProperties",
+    "title",
+];
+/// How deep `$ref`s are inlined for Gemini before a recursive schema is cut.
+const MAX_INLINE_DEPTH: usize = 16;
+
+/// Parses the schema given with a stream request. Empty means none.
+pub(super) fn parse(raw: &str) -> Result<Option<Value>, String> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Ok(None);
+    }
+    match serde_json::from_str::<Value>(raw) {
+        Ok(schema @ Value::Object(_)) => Ok(Some(schema)),
+        Ok(_) => Err("invalid response schema: expected a JSON object".to_owned()),
+        Err(error) => Err(format!("invalid response schema: {error}")),
+    }
+}
+
+/// Asks `req`'s provider for a reply matching `req.response_schema`.
+/// `OpenAI`'s strict mode is asked for only when the schema meets its rules.
+pub(super) fn apply(req: &StreamRequest, body: &mut Map<String, Value>) {
+    let Some(schema) = &req.response_schema else {
+        return;
+    };
+    let strict = strict_compatible(schema);
+    match effective_provider(req) {
+        Provider::OpenAi => {
+            body.insert(
+                "text".into(),
+                json!({ "format": {
+                    "type": "json_schema",
+                    "name": SCHEMA_NAME,
+                    "schema": schema,
+                    "strict": strict,
+                } }),
+            );
+        }
+        Provider::ChatCompletions => {
+            body.insert(
+                "response_format".into(),
+                json!({ "type": "json_schema", "json_schema": {
+                    "name": SCHEMA_NAME,
+                    "schema": schema,
+                    "strict": strict,
+                } }),
+            );
+        }
+        Provider::Gemini => {
+            if let Value::Object(config) =
+                body.entry("generationConfig").or_insert_with(|| json!({}))
+            {
+                config.insert("responseMimeType".into(), json!("application/json"));
+                config.insert(
+                    "responseSchema".into(),
+                    gemini_schema(schema, schema, MAX_INLINE_DEPTH),
+                );
+            }
+        }
+        Provider::Ollama => {
+            body.insert("format".into(), schema.clone());
+        }
+        Provider::Anthropic => {
+            let instruction = format!(
+                "Reply with a single JSON value that matches this JSON Schema, and nothing \
+                 else:\n{schema}"
+            );
+            let system = match body.get("system").and_then(Value::as_str) {
+                Some(system) => format!("{system}\n\n{instruction}"),
+                None => instruction,
+            };
+            body.insert("system".into(), json!(system));
+        }
+    }
+}
+
+/// Whether `OpenAI`'s strict mode accepts `schema`: it rejects a schema
+/// unless every object sets `additionalProperties: false` and requires all
+/// of its properties.
+fn strict_compatible(schema: &Value) -> bool {
+    let Some(schema) = schema.as_object() else {
+        return true;
+    };
+    let properties = schema.get("properties").and_then(Value::as_object);
+    let is_object = properties.is_some()
+        || match schema.get("type") {
+            Some(Value::String(name)) => name == "object",
+            Some(Value::Array(names)) => names.iter().any(|name| name == "object"),
+            _ => false,
+        };
+    if is_object {
+        let required: Vec<&str> = schema
+            .get("required")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .collect();
+        if schema.get("additionalProperties") != Some(&Value::Bool(false))
+            || properties
+                .into_iter()
+                .flatten()
+                .any(|(name, _)| !required.contains(&name.as_str()))
+        {
+            return false;
+        }
+    }
+    let defs = ["$defs", "definitions"]
+        .into_iter()
+        .filter_map(|key| schema.get(key).and_then(Value::as_object));
+    let any_of = schema.get("anyOf").and_then(Value::as_array);
+    properties
+        .into_iter()
+        .chain(defs)
+        .flat_map(Map::values)
+        .chain(schema.get("items"))
+        .chain(any_of.into_iter().flatten())
+        .all(strict_compatible)
+}
+
+/// Parses the final reply and checks it against `schema`, returning every
+/// mismatch as `path: problem`.
+pub(super) fn check(schema: &Value, reply: &str) -> Result<Value, Vec<String>> {
+    let value = serde_json::from_str::<Value>(strip_fence(reply))
+        .map_err(|error| vec![format!("$: reply is not JSON ({error})")])?;
+    let mut errors = Vec::new();
+    validate(schema, schema, &value, "$", &mut errors);
+    if errors.is_empty() {
+        Ok(value)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Drops a Markdown code fence models without native structured output
+/// sometimes wrap JSON in.
+fn strip_fence(reply: &str) -> &str {
+    let reply = reply.trim();
+    let Some(inner) = reply
+        .strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+    else {
+        return reply;
+    };
+    let inner = inner.strip_prefix("json").unwrap_or(inner);
+    inner.trim()
+}
+
+fn validate(root: &Value, schema: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    let Some(schema) = schema.as_object() else {
+        // `true` accepts anything and `false` nothing.
+        if schema == &Value::Bool(false) {
+            errors.push(format!("{path}: no value is allowed here"));
+        }
+        return;
+    };
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        match resolve(root, reference) {
+            Some(target) => validate(root, target, value, path, errors),
+            None => errors.push(format!("{path}: unresolvable $ref {reference:?}")),
+        }
+        return;
+    }
+    if let Some(options) = schema.get("anyOf").and_then(Value::as_array) {
+        let matches = options.iter().any(|option| {
+            let mut scratch = Vec::new();
+            validate(root, option, value, path, &mut scratch);
+            scratch.is_empty()
+        });
+        if !matches {
+            errors.push(format!("{path}: matches none of the anyOf options"));
+        }
+    }
+    if let Some(types) = schema.get("type") {
+        let allowed: Vec<&str> = match types {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !allowed.is_empty() && !allowed.iter().any(|name| has_type(value, name)) {
+            errors.push(format!(
+                "{path}: expected {}, got {}",
+                allowed.join(" or "),
+                type_name(value)
+            ));
+            return;
+        }
+    }
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        if !options.contains(value) {
+            errors.push(format!(
+                "{path}: {value} is not one of {}",
+                Value::from(options.clone())
+            ));
+        }
+    }
+    if let Some(expected) = schema.get("const") {
+        if expected != value {
+            errors.push(format!("{path}: expected {expected}"));
+        }
+    }
+    match value {
+        Value::Object(object) => validate_object(root, schema, object, path, errors),
+        Value::Array(items) => {
+            errors.extend(
+                out_of_bounds(schema, ["minItems", "maxItems"], items.len(), "items")
+                    .map(|problem| format!("{path}: {problem}")),
+            );
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    validate(root, item_schema, item, &format!("{path}[{index}]"), errors);
+                }
+            }
+        }
+        Value::String(text) => {
+            let length = text.chars().count();
+            errors.extend(
+                out_of_bounds(schema, ["minLength", "maxLength"], length, "characters")
+                    .map(|problem| format!("{path}: {problem}")),
+            );
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or_default();
+            let below = |key| schema.get(key).and_then(Value::as_f64);
+            if below("minimum").is_some_and(|min| number < min)
+                || below("exclusiveMinimum").is_some_and(|min| number <= min)
+            {
+                errors.push(format!("{path}: {number} is below the minimum"));
+            }
+            if below("maximum").is_some_and(|max| number > max)
+                || below("exclusiveMaximum").is_some_and(|max| number >= max)
+            {
+                errors.push(format!("{path}: {number} is above the maximum"));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn validate_object(
+    root: &Value,
+    schema: &Map<String, Value>,
+    object: &Map<String, Value>,
+    path: &str,
+    errors: &mut Vec<String>,
+) {
+    for name in schema
+        .get("required")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+    {
+        if !object.contains_key(name) {
+            errors.push(format!("{path}: missing required property {name:?}"));
+        }
+    }
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (name, field) in object {
+        let field_path = format!("{path}.{name}");
+        match properties.and_then(|properties| properties.get(name)) {
+            Some(field_schema) => validate(root, field_schema, field, &field_path, errors),
+            None => match schema.get("additionalProperties") {
+                Some(Value::Bool(false)) => {
+                    errors.push(format!("{path}: unexpected property {name:?}"));
+                }
+                Some(extra @ Value::Object(_)) => {
+                    validate(root, extra, field, &field_path, errors);
+                }
+                _ => {}
+            },
+        }
+    }
+}
+
+/// Checks a length or count against the `[min, max]` keywords in `keys`.
+fn out_of_bounds(
+    schema: &Map<String, Value>,
+    [min_key, max_key]: [&str; 2],
+    count: usize,
+    unit: &str,
+) -> Option<String> {
+    let limit = |key| {
+        schema
+            .get(key)
+            .and_then(Value::as_u64)
+            .and_then(|limit| usize::try_from(limit).ok())
+    };
+    if let Some(min) = limit(min_key).filter(|min| count < *min) {
+        return Some(format!("has {count} {unit}, at least {min} required"));
+    }
+    limit(max_key)
+        .filter(|max| count > *max)
+        .map(|max| format!("has {count} {unit}, at most {max} allowed"))
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        // JSON Schema counts `2.0` as an integer too.
+        "integer" => {
+            value.as_i64().is_some()
+                || value.as_u64().is_some()
+                || value.as_f64().is_some_and(|number| number.fract() == 0.0)
+        }
+        "number" => value.is_number(),
+        other => type_name(value) == other,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Resolves a local `#/...` JSON pointer reference.
+fn resolve<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    root.pointer(reference.strip_prefix('#')?)
+}
+
+/// Rewrites a JSON Schema into Gemini's `OpenAPI` subset: local `$ref`s are
+/// inlined, the keywords it rejects are dropped, and `type` arrays become a
+/// single type (plus `nullable`) or an `anyOf`.
+fn gemini_schema(root: &Value, schema: &Value, depth: usize) -> Value {
+    match schema {
+        Value::Object(object) => {
+            if let Some(target) = object
+                .get("$ref")
+                .and_then(Value::as_str)
+                .and_then(|reference| resolve(root, reference))
+            {
+                return if depth == 0 {
+                    json!({ "type": "object" })
+                } else {
+                    gemini_schema(root, target, depth - 1)
+                };
+            }
+            let mut converted: Map<String, Value> = object
+                .iter()
+                .filter(|(key, _)| !GEMINI_UNSUPPORTED.contains(&key.as_str()))
+                .map(|(key, value)| {
+                    // Property names are not keywords, so only their
+                    // schemas are rewritten.
+                    let value = if key == "properties" {
+                        match value {
+                            Value::Object(fields) => Value::Object(
+                                fields
+                                    .iter()
+                                    .map(|(name, field)| {
+                                        (name.clone(), gemini_schema(root, field, depth))
+                                    })
+                                    .collect(),
+                            ),
+                            other => other.clone(),
+                        }
+                    } else {
+                        gemini_schema(root, value, depth)
+                    };
+                    (key.clone(), value)
+                })
+                .collect();
+            if let Some(Value::Array(types)) = converted.get("type").cloned() {
+                converted.remove("type");
+                gemini_types(&mut converted, &types);
+            }
+            Value::Object(converted)
+        }
+        Value::Array(values) => Value::Array(
+            values
+                .iter()
+                .map(|value| gemini_schema(root, value, depth))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Gemini takes one `type`; `null` in a JSON Schema type list becomes
+/// `nullable` and several other types become an `anyOf`.
+fn gemini_types(schema: &mut Map<String, Value>, types: &[Value]) {
+    let mut kinds: Vec<&Value> = types.iter().filter(|kind| *kind != "null").collect();
+    if kinds.len() < types.len() {
+        schema.insert("nullable".to_owned(), json!(true));
+    }
+    match kinds.len() {
+        0 => {}
+        1 => {
+            schema.insert("type".to_owned(), kinds.remove(0).clone());
+        }
+        _ => {
+            let any_of = kinds.iter().map(|kind| json!({ "type": kind })).collect();
+            schema.insert("anyOf".to_owned(), Value::Array(any_of));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ProviderConfig;
+    use super::*;
+
+    fn meeting_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "title": { "type": "string", "minLength": 1 },
+                "start": { "type": "string" },
+                "attendees": { "type": "array", "items": { "$ref": "#/$defs/person" } },
+                "priority": { "enum": ["low", "high"] }
+            },
+            "required": ["title", "start", "attendees"],
+            "additionalProperties": false,
+            "$defs": {
+                "person": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "age": { "type": ["integer", "null"], "minimum": 0 }
+                    },
+                    "required": ["name"]
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn replies_are_checked_against_the_schema() {
+        let schema = meeting_schema();
+        let reply = "```json\n{\"title\": \"Sync\", \"start\": \"10:00\", \
+                     \"attendees\": [{\"name\": \"Ana\", \"age\": null}]}\n```";
+        assert_eq!(
+            check(&schema, reply).map(|value| value["title"].clone()),
+            Ok(json!("Sync"))
+        );
+
+        let reply =
+            r#"{"title": "", "attendees": [{"age": 2.5}], "priority": "urgent", "room": 4}"#;
+        assert_eq!(
+            check(&schema, reply),
+            Err(vec![
+                "$: missing required property \"start\"".to_owned(),
+                "$.attendees[0]: missing required property \"name\"".to_owned(),
+                "$.attendees[0].age: expected integer or null, got number".to_owned(),
+                "$.priority: \"urgent\" is not one of [\"low\",\"high\"]".to_owned(),
+                "$: unexpected property \"room\"".to_owned(),
+                "$.title: has 0 characters, at least 1 required".to_owned(),
+            ])
+        );
+        assert!(check(&schema, "Sure! Here it is.").is_err());
+        assert_eq!(parse(" "), Ok(None));
+        assert!(parse("[1]").is_err());
+    }
+
+    #[test]
+    fn integral_floats_count_as_integers() {
+        let schema = json!({ "type": "integer" });
+        assert!(check(&schema, "2.0").is_ok());
+        assert!(check(&schema, "-3").is_ok());
+        assert!(check(&schema, "2.5").is_err());
+    }
+
+    fn openai_format(schema: Value) -> Value {
+        let req = StreamRequest {
+            provider: "openai".to_owned(),
+            response_schema: Some(schema),
+            ..StreamRequest::default()
+        };
+        let mut body = Map::new();
+        apply(&req, &mut body);
+        body["text"]["format"].clone()
+    }
+
+    #[test]
+    fn schemas_strict_mode_rejects_are_sent_without_it() {
+        // `priority` and `age` are optional and `person` allows extra keys.
+        assert_eq!(openai_format(meeting_schema())["strict"], json!(false));
+        let mut schema = meeting_schema();
+        schema["required"] = json!(["title", "start", "attendees", "priority"]);
+        assert_eq!(openai_format(schema.clone())["strict"], json!(false));
+        schema["$defs"]["person"]["required"] = json!(["name", "age"]);
+        assert_eq!(openai_format(schema)["strict"], json!(false));
+    }
+
+    #[test]
+    fn schemas_that_meet_strict_rules_are_sent_strict() {
+        let mut schema = meeting_schema();
+        schema["required"] = json!(["title", "start", "attendees", "priority"]);
+        schema["$defs"]["person"]["required"] = json!(["name", "age"]);
+        schema["$defs"]["person"]["additionalProperties"] = json!(false);
+        assert_eq!(openai_format(schema.clone())["strict"], json!(true));
+
+        let req = StreamRequest {
+            provider: "openai".to_owned(),
+            config: ProviderConfig {
+                api: "chat_completions".to_owned(),
+                ..ProviderConfig::default()
+            },
+            response_schema: Some(schema),
+            ..StreamRequest::default()
+        };
+        let mut body = Map::new();
+        apply(&req, &mut body);
+        assert_eq!(
+            body["response_format"]["json_schema"]["strict"],
+            json!(true)
+        );
+    }
+
+    #[test]
+    fn gemini_gets_an_inlined_schema_without_unsupported_keywords() {
+        let schema = meeting_schema();
+        let converted = gemini_schema(&schema, &schema, MAX_INLINE_DEPTH);
+        assert!(converted.get("additionalProperties").is_none());
+        assert!(converted.get("$defs").is_none());
+        let person = &converted["properties"]["attendees"]["items"]["properties"];
+        assert_eq!(person["name"], json!({ "type": "string" }));
+        assert_eq!(
+            person["age"],
+            json!({ "type": "integer", "nullable": true, "minimum": 0 })
+        );
+        let either = json!({ "type": ["string", "number"] });
+        assert_eq!(
+            gemini_schema(&either, &either, 1),
+            json!({ "anyOf": [{ "type": "string" }, { "type": "number" }] })
+        );
+        // A property named like a dropped keyword is kept.
+        let titled = json!({ "properties": { "title": { "type": "string" } } });
+        assert_eq!(gemini_schema(&titled, &titled, 1), titled);
+    }
+}
@@ -1,30 +1,53 @@
 //! Streaming chat backend.
 //!
-//! Talks to the `OpenAI` Responses API (`/v1/responses`) and Google Gemini
-//! (`streamGenerateContent`) directly over `ureq`, parsing server-sent events on
-//! the calling worker thread. Conversation history is carried in the `OpenAI`
-//! Responses "input item" shape as the neutral representation; the Gemini path
-//! converts it to `contents` on the fly. The multi-turn tool loop, model-output
+//! Talks to the `OpenAI` Responses API (`/v1/responses`), Google Gemini
+//! (`streamGenerateContent`) and the Anthropic Messages API (`/v1/messages`)
+//! directly over `ureq`, parsing server-sent events on the calling worker
+//! thread. `OpenAI`-compatible servers that only implement
+//! `/v1/chat/completions` are reached by setting `api = "chat_completions"` on
+//! their provider, and Ollama is driven through its native `/api/chat` stream.
+//! Conversation history is carried in the `OpenAI` Responses "input item"
+//! shape as the neutral representation; the other paths convert it to
+//! `contents`/`messages` on the fly. The multi-turn tool loop, model-output
 //! persistence, and tool dispatch are provider-agnostic.
 
 use std::io::{BufRead, BufReader, Read};
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use serde_json::{json, Map, Value};
 
 use super::{
-    base_url, call_mcp_tool, callback, default_schema, enrich_tool_call, metrics_snapshot,
-    must_json, store_metrics, tool_done_event_json, tool_output_item, tool_start_event_json,
-    MetricTracker, StreamArgs, StreamRequest, StreamResult, ToolCall,
+    approval, base_url, call_mcp_tool, callback, citations, compaction, default_schema,
+    discard_tokens, documents, enrich_tool_call, fallback_request, metrics_snapshot, must_json,
+    nonempty, ollama, params, record_usage, schema, store_metrics, title, tool_done_event_json,
+    tool_output_item, tool_start_event_json, update_session_metrics, MetricTracker, SessionMetrics,
+    StreamArgs, StreamRequest, StreamResult, ToolCall, CHARS_PER_TOKEN,
 };
 use crate::mcp::ToolDescriptor;
 
 const MAX_TOOL_TURNS: usize = 8;
 const BODY_SNIPPET: usize = 800;
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const ANTHROPIC_MAX_TOKENS: u32 = 8192;
+const CHAT_COMPLETIONS_API: &str = "chat_completions";
+const MAX_RETRIES: u32 = 3;
+const RETRY_BASE: Duration = Duration::from_secs(1);
+/// Longest `Retry-After` worth waiting out; beyond it the fallback chain is
+/// the better bet.
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(30);
+/// How often a retry wait re-checks cancellation.
+const RETRY_POLL: Duration = Duration::from_millis(250);
+
+/// Responses endpoints (by URL) that refused reasoning summaries, which need
+/// a verified `OpenAI` organization. Later rounds leave the summary out.
+static SUMMARIES_REFUSED: Mutex<Vec<String>> = Mutex::new(Vec::new());
 
 pub(super) fn run(args: &StreamArgs, req: &StreamRequest) -> Result<(), String> {
-    let input = if req.conversation_id.trim().is_empty() {
+    let mut input = if req.conversation_id.trim().is_empty() {
         vec![crate::chatstore::user_input_item(
             &req.message,
             &req.attachments,
@@ -32,24 +55,76 @@ pub(super) fn run(args: &StreamArgs, req: &StreamRequest) -> Result<(), String>
     } else {
         crate::chatstore::load_history_items(req.conversation_id.trim())?
     };
+    // Selected MCP prompts/resources follow the user message. Emitting them
+    // with the model output persists them on this turn so history replays them.
+    if let Some(item) = crate::mcp::context_input_item(&req.context)? {
+        emit_model_items(args, std::slice::from_ref(&item));
+        input.push(item);
+    }
 
-    let gemini = effective_provider(req) == Provider::Gemini;
-    drive(args, req, input, gemini)
+    // A model that fails before anything reached the UI hands the turn to the
+    // next model in its `fallback` chain.
+    let mut metrics = MetricTracker {
+        live: true,
+        ..MetricTracker::new()
+    };
+    let mut fallbacks = crate::config_resolver::fallback_models(&req.model_id).into_iter();
+    let mut current = req.clone();
+    loop {
+        let Err(failure) = drive(args, &current, &mut input, &mut metrics) else {
+            return Ok(());
+        };
+        let next = if failure.answered || args.cancelled.load(Ordering::SeqCst) {
+            None
+        } else {
+            fallbacks
+                .by_ref()
+                .find_map(|model_id| fallback_request(args, req, &model_id))
+        };
+        let Some(next) = next else {
+            store_metrics(
+                args.id,
+                SessionMetrics {
+                    error: failure.message.clone(),
+                    ..metrics.live_snapshot()
+                },
+            );
+            return Err(failure.message);
+        };
+        let event = json!({
+            "kind": "fallback",
+            "from": current.model_id,
+            "to": next.model_id,
+            "reason": failure.message,
+        });
+        callback(args.cb, args.ctx, &must_json(&event), 2);
+        metrics.fallbacks.push(current.model_id.clone());
+        current = next;
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
-enum Provider {
+pub(super) enum Provider {
     OpenAi,
+    ChatCompletions,
     Gemini,
+    Anthropic,
+    Ollama,
 }
 
-fn effective_provider(req: &StreamRequest) -> Provider {
+pub(super) fn effective_provider(req: &StreamRequest) -> Provider {
     // The hosted Google API and the local proxy (which exposes the native Gemini
     // API under /v1beta) both speak the Gemini protocol for gemini-* models.
     if req.provider == "gemini"
         || (req.provider == "local" && req.raw_model_id.trim().starts_with("gemini-"))
     {
         Provider::Gemini
+    } else if req.provider == "anthropic" {
+        Provider::Anthropic
+    } else if req.provider == "ollama" {
+        Provider::Ollama
+    } else if req.config.api.trim() == CHAT_COMPLETIONS_API {
+        Provider::ChatCompletions
     } else {
         Provider::OpenAi
     }
@@ -62,74 +137,332 @@ struct RoundOutcome {
     model_items: Vec<Value>,
     /// Tool calls the model requested this round.
     tool_calls: Vec<ToolCall>,
+    /// Web-search sources backing this round's reply text.
+    citations: Vec<citations::Citation>,
     prompt_tokens: i32,
     output_tokens: i32,
 }
 
+/// Why a turn stopped early. `answered` is set once anything reached the UI
+/// (text, reasoning, citations, an image or model items), after which the
+/// turn can no longer move to another model.
+struct TurnFailure {
+    message: String,
+    answered: bool,
+}
+
 /// Runs the multi-turn loop: request → stream → run tools → repeat until the
 /// model stops calling tools or the turn budget is exhausted.
 fn drive(
     args: &StreamArgs,
     req: &StreamRequest,
-    mut input: Vec<Value>,
-    gemini: bool,
-) -> Result<(), String> {
+    input: &mut Vec<Value>,
+    metrics: &mut MetricTracker,
+) -> Result<(), TurnFailure> {
     let agent = stream_agent();
-    let mut metrics = MetricTracker::new();
+    let provider = effective_provider(req);
+    metrics.model.clone_from(&req.model_id);
     let mut combined = StreamResult::default();
+    // Every round resends the history, so each one's prompt is billed.
+    let mut billed_prompt_tokens = 0_i32;
     let mut finished = false;
+    let mut answered = false;
+    let mut reply = String::new();
 
     for _ in 0..MAX_TOOL_TURNS {
         if args.cancelled.load(Ordering::SeqCst) {
             break;
         }
+        fit_context(args, req, input);
         metrics.begin_provider_round();
-        let outcome = if gemini {
-            gemini_round(&agent, args, req, &input, &mut metrics)?
-        } else {
-            openai_round(&agent, args, req, &input, &mut metrics)?
+        let streamed_before = metrics.streamed_chars;
+        let outcome = round(&agent, args, req, input, metrics, provider);
+        let (prompt_tokens, output_tokens) =
+            round_usage(args, input, metrics, streamed_before, &outcome);
+        combined.prompt_tokens = prompt_tokens;
+        billed_prompt_tokens = billed_prompt_tokens.saturating_add(prompt_tokens);
+        combined.output_tokens = combined.output_tokens.saturating_add(output_tokens);
+        let outcome = match outcome {
+            Ok(outcome) => outcome,
+            Err(message) => {
+                let snapshot = metrics_snapshot(&req.model_id, metrics, &combined, false, &message);
+                record_usage(&req.conversation_id, &snapshot, billed_prompt_tokens);
+                return Err(TurnFailure {
+                    message,
+                    answered: answered || metrics.shown,
+                });
+            }
         };
 
-        combined.prompt_tokens = outcome.prompt_tokens;
-        combined.output_tokens = combined.output_tokens.saturating_add(outcome.output_tokens);
-
+        if !outcome.citations.is_empty() {
+            let event = json!({
+                "kind": "citations",
+                "session_id": args.id,
+                "citations": outcome.citations,
+            });
+            callback(args.cb, args.ctx, &must_json(&event), 2);
+            metrics.shown = true;
+        }
         if !outcome.model_items.is_empty() {
             emit_model_items(args, &outcome.model_items);
             input.extend(outcome.model_items.iter().cloned());
+            answered = true;
         }
 
         if outcome.tool_calls.is_empty() {
             finished = true;
+            reply = output_text(&outcome.model_items);
+            title::spawn(args, req, &reply);
             break;
         }
 
-        for call in &outcome.tool_calls {
-            if args.cancelled.load(Ordering::SeqCst) {
-                break;
+        let results = run_tools(args, req, &outcome.tool_calls);
+        for (call, result) in outcome.tool_calls.iter().zip(results) {
+            if let Some(result) = result {
+                input.push(tool_output_item(call, &result));
             }
-            let result = run_tool(args, call);
-            input.push(tool_output_item(call, &result));
         }
     }
 
-    store_metrics(metrics_snapshot(
-        &args.model_id,
-        &metrics,
-        &combined,
-        finished,
-        "",
-    ));
-    if finished {
-        callback(args.cb, args.ctx, "", 1);
+    let snapshot = metrics_snapshot(&req.model_id, metrics, &combined, finished, "");
+    record_usage(&req.conversation_id, &snapshot, billed_prompt_tokens);
+    store_metrics(args.id, snapshot);
+    if !finished {
+        return Ok(());
+    }
+    if let Some(schema) = &req.response_schema {
+        if let Err(errors) = schema::check(schema, &reply) {
+            let event = json!({
+                "kind": "schema_mismatch",
+                "session_id": args.id,
+                "errors": errors,
+            });
+            callback(args.cb, args.ctx, &must_json(&event), 2);
+            return Err(TurnFailure {
+                message: format!(
+                    "reply does not match the response schema: {}",
+                    errors.join("; ")
+                ),
+                answered: true,
+            });
+        }
     }
+    callback(args.cb, args.ctx, "", 1);
     Ok(())
 }
 
-/// Emits `tool_start`, dispatches to the local MCP catalog, emits `tool_done`.
-fn run_tool(args: &StreamArgs, call: &ToolCall) -> crate::mcp::ToolResult {
+fn round(
+    agent: &ureq::Agent,
+    args: &StreamArgs,
+    req: &StreamRequest,
+    input: &[Value],
+    metrics: &mut MetricTracker,
+    provider: Provider,
+) -> Result<RoundOutcome, String> {
+    match provider {
+        Provider::OpenAi => openai_round(agent, args, req, input, metrics),
+        Provider::ChatCompletions => chat_round(agent, args, req, input, metrics),
+        Provider::Gemini => gemini_round(agent, args, req, input, metrics),
+        Provider::Anthropic => anthropic_round(agent, args, req, input, metrics),
+        Provider::Ollama => ollama_round(agent, args, req, input, metrics),
+    }
+}
+
+/// Shrinks `input` to the model's context budget before a round. Oversized
+/// tool outputs are cut first; if that is not enough the oldest turns are
+/// summarized and the summary is persisted as a compaction marker, so later
+/// replays of this conversation start from the same compacted history.
+fn fit_context(args: &StreamArgs, req: &StreamRequest, input: &mut Vec<Value>) {
+    let budget = compaction::budget(req.context_window);
+    if req.context_window == 0 || compaction::estimate_tokens(input) <= budget {
+        return;
+    }
+    compaction::truncate_tool_outputs(input);
+    if compaction::estimate_tokens(input) <= budget {
+        return;
+    }
+    let Some(split) = compaction::split_point(input, budget) else {
+        return;
+    };
+    let transcript = compaction::transcript(&input[..split], budget / 2);
+    let summary =
+        complete(args, req, compaction::SUMMARY_PROMPT, &transcript).unwrap_or(transcript);
+    let marker = crate::chatstore::compaction_item(&summary, split);
+    emit_model_items(args, std::slice::from_ref(&marker));
+    input.push(marker);
+    *input = crate::chatstore::apply_compactions(std::mem::take(input));
+}
+
+/// Sends `text` to `req`'s model as a single tool-free turn under
+/// `instructions` and returns the reply. Nothing reaches the UI, but the
+/// call's tokens go to the conversation's usage ledger.
+pub(super) fn complete(
+    args: &StreamArgs,
+    req: &StreamRequest,
+    instructions: &str,
+    text: &str,
+) -> Option<String> {
+    let quiet = StreamArgs {
+        ctx: 0,
+        cb: discard_tokens,
+        ..args.clone()
+    };
+    let one_shot = StreamRequest {
+        system_prompt: instructions.to_owned(),
+        tools: Vec::new(),
+        provider_search_enabled: false,
+        image_generation: false,
+        params: params::GenerationParams::default(),
+        response_schema: None,
+        ..req.clone()
+    };
+    let input = [crate::chatstore::user_input_item(text, &[]).ok()?];
+    let mut metrics = MetricTracker::new();
+    metrics.begin_provider_round();
+    let outcome = round(
+        &stream_agent(),
+        &quiet,
+        &one_shot,
+        &input,
+        &mut metrics,
+        effective_provider(&one_shot),
+    );
+    let (prompt_tokens, output_tokens) = round_usage(&quiet, &input, &metrics, 0, &outcome);
+    let usage = StreamResult {
+        prompt_tokens,
+        output_tokens,
+    };
+    let snapshot = metrics_snapshot(&req.model_id, &metrics, &usage, outcome.is_ok(), "");
+    record_usage(&req.conversation_id, &snapshot, prompt_tokens);
+    nonempty(output_text(&outcome.ok()?.model_items).trim())
+}
+
+/// Prompt and output tokens one round is billed for. A round the provider
+/// never reported usage for, because it was cancelled or broke off after
+/// streaming began, is estimated from its prompt and the text it streamed
+/// since `streamed_before`.
+fn round_usage(
+    args: &StreamArgs,
+    input: &[Value],
+    metrics: &MetricTracker,
+    streamed_before: usize,
+    outcome: &Result<RoundOutcome, String>,
+) -> (i32, i32) {
+    let streamed = metrics.streamed_chars.saturating_sub(streamed_before);
+    let unreported = match outcome {
+        Ok(outcome) if outcome.prompt_tokens != 0 || outcome.output_tokens != 0 => {
+            return (outcome.prompt_tokens, outcome.output_tokens);
+        }
+        Ok(_) => args.cancelled.load(Ordering::SeqCst),
+        Err(_) => streamed > 0,
+    };
+    if !unreported {
+        return (0, 0);
+    }
+    let tokens = |count: usize| i32::try_from(count).unwrap_or(i32::MAX);
+    (
+        tokens(compaction::estimate_tokens(input)),
+        tokens(streamed.div_ceil(CHARS_PER_TOKEN)),
+    )
+}
+
+/// Concatenates the text parts of the message items in `items`.
+fn output_text(items: &[Value]) -> String {
+    items
+        .iter()
+        .filter(|item| item.get("type").and_then(Value::as_str) == Some("message"))
+        .filter_map(|item| item.get("content").and_then(Value::as_array))
+        .flatten()
+        .filter_map(|part| part.get("text").and_then(Value::as_str))
+        .collect()
+}
+
+/// Runs one round's calls and returns their results in call order; `None`
+/// marks a call skipped by cancellation.
+///
+/// Consecutive read-only or idempotent calls form a batch that runs on up to
+/// `max_parallel_tools` threads once each has passed the approval gate. Any
+/// other call runs alone, so side effects keep the order the model chose.
+fn run_tools(
+    args: &StreamArgs,
+    req: &StreamRequest,
+    calls: &[ToolCall],
+) -> Vec<Option<crate::mcp::ToolResult>> {
+    run_tools_with(args, req, calls, &call_mcp_tool)
+}
+
+/// [`run_tools`] with the call dispatcher passed in.
+fn run_tools_with(
+    args: &StreamArgs,
+    req: &StreamRequest,
+    calls: &[ToolCall],
+    dispatch: &(dyn Fn(&ToolCall) -> crate::mcp::ToolResult + Sync),
+) -> Vec<Option<crate::mcp::ToolResult>> {
+    let independent = |call: &ToolCall| call.read_only || call.idempotent;
+    let mut results = vec![None; calls.len()];
+    let mut start = 0;
+    while start < calls.len() && !args.cancelled.load(Ordering::SeqCst) {
+        let len = calls[start..]
+            .iter()
+            .take_while(|call| independent(call))
+            .count()
+            .max(1);
+        let batch = start..start + len;
+        start += len;
+
+        let mut allowed = Vec::with_capacity(len);
+        for index in batch {
+            let call = &calls[index];
+            match approval::check(args, req, call) {
+                Ok(()) => allowed.push(index),
+                Err(reason) => results[index] = Some(refuse_tool(args, call, &reason)),
+            }
+        }
+        let workers = req.max_parallel_tools.clamp(1, allowed.len().max(1));
+        if workers == 1 {
+            for index in allowed {
+                if args.cancelled.load(Ordering::SeqCst) {
+                    break;
+                }
+                results[index] = Some(run_tool(args, &calls[index], dispatch));
+            }
+            continue;
+        }
+        let next = AtomicUsize::new(0);
+        let finished = Mutex::new(Vec::with_capacity(allowed.len()));
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    while let Some(&index) = allowed.get(next.fetch_add(1, Ordering::SeqCst)) {
+                        if args.cancelled.load(Ordering::SeqCst) {
+                            break;
+                        }
+                        let result = run_tool(args, &calls[index], dispatch);
+                        finished
+                            .lock()
+                            .expect("tool result mutex")
+                            .push((index, result));
+                    }
+                });
+            }
+        });
+        for (index, result) in finished.into_inner().expect("tool result mutex") {
+            results[index] = Some(result);
+        }
+    }
+    results
+}
+
+/// Emits `tool_start`, dispatches the call (to the local MCP catalog outside
+/// tests), emits `tool_done`.
+fn run_tool(
+    args: &StreamArgs,
+    call: &ToolCall,
+    dispatch: &(dyn Fn(&ToolCall) -> crate::mcp::ToolResult + Sync),
+) -> crate::mcp::ToolResult {
     callback(args.cb, args.ctx, &tool_start_event_json(call), 2);
     let started = Instant::now();
-    let mut result = call_mcp_tool(call);
+    let mut result = dispatch(call);
     if result.duration_ms == 0 {
         result.duration_ms = started.elapsed().as_millis().try_into().unwrap_or(i64::MAX);
     }
@@ -143,6 +476,21 @@ fn run_tool(args: &StreamArgs, call: &ToolCall) -> crate::mcp::ToolResult {
     result
 }
 
+/// Reports a call the approval gate refused as a failed tool row; the error
+/// result goes back to the model in place of the tool output.
+fn refuse_tool(args: &StreamArgs, call: &ToolCall, reason: &str) -> crate::mcp::ToolResult {
+    callback(args.cb, args.ctx, &tool_start_event_json(call), 2);
+    let result = crate::mcp::ToolResult {
+        tool_call_id: call.id.clone(),
+        name: call.name.clone(),
+        text: format!("Tool call {reason}; it was not run."),
+        is_error: true,
+        ..crate::mcp::ToolResult::default()
+    };
+    callback(args.cb, args.ctx, &tool_done_event_json(call, &result), 2);
+    result
+}
+
 /// Publishes model-output items so the UI layer can persist them as
 /// `model_output` response items (`function_call`, message, reasoning, ...).
 fn emit_model_items(args: &StreamArgs, items: &[Value]) {
@@ -153,6 +501,56 @@ fn emit_model_items(args: &StreamArgs, items: &[Value]) {
     callback(args.cb, args.ctx, &must_json(&event), 2);
 }
 
+/// Sends a text delta to the UI. While a session streams, a `metrics` event
+/// with its running TTFT and throughput follows at most every
+/// `LIVE_METRICS_INTERVAL`, and the same figures answer
+/// `QsNative_AiChat_Metrics`.
+fn forward_token(args: &StreamArgs, metrics: &mut MetricTracker, token: &str) {
+    if !metrics.observe_token(token) {
+        return;
+    }
+    callback(args.cb, args.ctx, token, 0);
+    if !metrics.live_due() {
+        return;
+    }
+    let live = metrics.live_snapshot();
+    let mut event = json!({ "kind": "metrics", "session_id": args.id });
+    if let (Value::Object(event), Ok(Value::Object(fields))) =
+        (&mut event, serde_json::to_value(&live))
+    {
+        event.extend(fields);
+    }
+    update_session_metrics(args.id, live);
+    callback(args.cb, args.ctx, &must_json(&event), 2);
+}
+
+/// Stores a base64 image the model generated and sends it to the UI as an
+/// `attachment` event, which records it on the reply. An image that cannot
+/// be decoded or written is dropped: the reply text still stands.
+fn emit_generated_image(args: &StreamArgs, metrics: &mut MetricTracker, mime: &str, data: &str) {
+    let Ok(bytes) = BASE64.decode(data.trim()) else {
+        return;
+    };
+    let Ok(attachment) = crate::chatstore::store_generated_attachment(&bytes, mime) else {
+        return;
+    };
+    let event = json!({ "kind": "attachment", "session_id": args.id, "attachment": attachment });
+    callback(args.cb, args.ctx, &must_json(&event), 2);
+    metrics.shown = true;
+}
+
+/// Sends a reasoning-summary or thought delta to the UI as a
+/// `reasoning_delta` event. It is shown apart from the reply and never
+/// replayed to the model.
+fn forward_reasoning(args: &StreamArgs, metrics: &mut MetricTracker, delta: &str) {
+    if delta.is_empty() {
+        return;
+    }
+    let event = json!({ "kind": "reasoning_delta", "session_id": args.id, "delta": delta });
+    callback(args.cb, args.ctx, &must_json(&event), 2);
+    metrics.shown = true;
+}
+
 // --- OpenAI Responses -------------------------------------------------------
 
 fn openai_round(
@@ -162,12 +560,7 @@ fn openai_round(
     input: &[Value],
     metrics: &mut MetricTracker,
 ) -> Result<RoundOutcome, String> {
-    let base = if req.provider == "local" {
-        base_url(&req.config.base_url, "http://127.0.0.1:8317/v1")
-    } else {
-        base_url(&req.config.base_url, "https://api.openai.com/v1")
-    };
-    let url = format!("{base}/responses");
+    let url = format!("{}/responses", openai_base(req));
 
     let sanitized: Vec<Value> = input.iter().filter_map(sanitize_input_item).collect();
     let mut body = Map::new();
@@ -178,27 +571,33 @@ fn openai_round(
     if !req.system_prompt.trim().is_empty() {
         body.insert("instructions".into(), json!(req.system_prompt.trim()));
     }
+    params::apply(req, &mut body);
+    schema::apply(req, &mut body);
+    // Summaries are opt-in; without them reasoning models stream nothing
+    // until the answer starts.
+    let summarize = params::support(req).reasons() && !summaries_refused(&url);
+    if summarize {
+        if let Value::Object(reasoning) = body.entry("reasoning").or_insert_with(|| json!({})) {
+            reasoning.insert("summary".into(), json!("auto"));
+        }
+    }
     let tools = openai_tools(req);
     if !tools.is_empty() {
         body.insert("tools".into(), json!(tools));
     }
 
-    let mut request = agent.post(&url).header("Content-Type", "application/json");
-    let key = req.config.api_key.trim();
-    if !key.is_empty() {
-        request = request.header("Authorization", &format!("Bearer {key}"));
-    }
-    let mut response = request
-        .send_json(Value::Object(body))
-        .map_err(|error| error.to_string())?;
-    if !response.status().is_success() {
-        return Err(http_error(
-            "openai",
-            response.status().as_u16(),
-            &mut response,
-        ));
-    }
-
+    let mut response = match openai_post(agent, args, &url, req, body.clone(), metrics) {
+        Err(error) if summarize && refuses_summaries(&error) => {
+            if let Ok(mut refused) = SUMMARIES_REFUSED.lock() {
+                refused.push(url.clone());
+            }
+            if let Some(Value::Object(reasoning)) = body.get_mut("reasoning") {
+                reasoning.remove("summary");
+            }
+            openai_post(agent, args, &url, req, body, metrics)?
+        }
+        response => response?,
+    };
     let mut outcome = RoundOutcome::default();
     read_sse(response.body_mut().as_reader(), &args.cancelled, |event| {
         openai_event(&event, args, req, metrics, &mut outcome);
@@ -206,6 +605,48 @@ fn openai_round(
     Ok(outcome)
 }
 
+fn summaries_refused(url: &str) -> bool {
+    SUMMARIES_REFUSED
+        .lock()
+        .is_ok_and(|refused| refused.iter().any(|refused| refused == url))
+}
+
+/// Whether a failed Responses request was refused for asking for a reasoning
+/// summary, as organizations that are not verified are.
+fn refuses_summaries(error: &str) -> bool {
+    let error = error.to_ascii_lowercase();
+    error.contains(" 400:")
+        && (error.contains("reasoning summar") || error.contains("reasoning.summary"))
+}
+
+fn openai_base(req: &StreamRequest) -> String {
+    if req.provider == "local" {
+        base_url(&req.config.base_url, "http://127.0.0.1:8317/v1")
+    } else {
+        base_url(&req.config.base_url, "https://api.openai.com/v1")
+    }
+}
+
+fn openai_post(
+    agent: &ureq::Agent,
+    args: &StreamArgs,
+    url: &str,
+    req: &StreamRequest,
+    body: Map<String, Value>,
+    metrics: &mut MetricTracker,
+) -> Result<ureq::http::Response<ureq::Body>, String> {
+    let key = req.config.api_key.trim();
+    let build = || {
+        let request = agent.post(url).header("Content-Type", "application/json");
+        if key.is_empty() {
+            request
+        } else {
+            request.header("Authorization", &format!("Bearer {key}"))
+        }
+    };
+    send_streaming(args, req, metrics, "openai", build, &Value::Object(body))
+}
+
 fn openai_event(
     event: &Value,
     args: &StreamArgs,
@@ -216,17 +657,46 @@ fn openai_event(
     match event.get("type").and_then(Value::as_str).unwrap_or("") {
         "response.output_text.delta" => {
             if let Some(delta) = event.get("delta").and_then(Value::as_str) {
-                if metrics.observe_token(delta) {
-                    callback(args.cb, args.ctx, delta, 0);
-                }
+                forward_token(args, metrics, delta);
+            }
+        }
+        "response.reasoning_summary_text.delta" => {
+            if let Some(delta) = event.get("delta").and_then(Value::as_str) {
+                forward_reasoning(args, metrics, delta);
             }
         }
+        // Summary parts are paragraphs of one running summary.
+        "response.reasoning_summary_part.added"
+            if event.get("summary_index").and_then(Value::as_u64) > Some(0) =>
+        {
+            forward_reasoning(args, metrics, "\n\n");
+        }
         "response.output_item.done" => {
             let Some(item) = event.get("item") else {
                 return;
             };
-            if item.get("type").and_then(Value::as_str) == Some("function_call") {
-                outcome.tool_calls.push(openai_tool_call(item, &req.tools));
+            match item.get("type").and_then(Value::as_str) {
+                Some("function_call") => {
+                    outcome.tool_calls.push(openai_tool_call(item, &req.tools));
+                }
+                Some("message") => {
+                    outcome
+                        .citations
+                        .extend(citations::from_openai_message(item));
+                }
+                // Replayed from the stored attachment instead: the item
+                // carries the whole image as base64.
+                Some("image_generation_call") => {
+                    if let Some(data) = item.get("result").and_then(Value::as_str) {
+                        let format = item
+                            .get("output_format")
+                            .and_then(Value::as_str)
+                            .unwrap_or("png");
+                        emit_generated_image(args, metrics, &format!("image/{format}"), data);
+                    }
+                    return;
+                }
+                _ => {}
             }
             outcome.model_items.push(item.clone());
         }
@@ -282,9 +752,23 @@ fn openai_tools(req: &StreamRequest) -> Vec<Value> {
     if req.provider_search_enabled {
         tools.push(json!({ "type": "web_search" }));
     }
+    if req.image_generation {
+        tools.push(json!({ "type": "image_generation" }));
+    }
     tools
 }
 
+/// Whether the hosted `image_generation` tool can be offered: only the
+/// `OpenAI` Responses API has it, for the models that can call it.
+pub(super) fn image_generation_supported(req: &StreamRequest) -> bool {
+    let model = req.raw_model_id.trim();
+    req.provider == "openai"
+        && matches!(effective_provider(req), Provider::OpenAi)
+        && ["gpt-5", "gpt-4.1", "gpt-4o", "o3"]
+            .iter()
+            .any(|prefix| model.starts_with(prefix))
+}
+
 /// Strips non-standard/echo-unsafe fields so historical items replay cleanly.
 ///
 /// Server-generated item `id`s (`msg_…`, `fc_…`) are dropped because replaying
@@ -303,164 +787,947 @@ fn sanitize_input_item(item: &Value) -> Option<Value> {
     Some(Value::Object(out))
 }
 
-// --- Gemini -----------------------------------------------------------------
+// --- OpenAI Chat Completions ------------------------------------------------
 
-fn gemini_round(
+/// A `tool_calls[index]` entry being assembled from streamed fragments.
+#[derive(Debug, Default)]
+struct ChatToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+fn chat_round(
     agent: &ureq::Agent,
     args: &StreamArgs,
     req: &StreamRequest,
     input: &[Value],
     metrics: &mut MetricTracker,
 ) -> Result<RoundOutcome, String> {
-    let url = format!(
-        "{}/models/{}:streamGenerateContent?alt=sse",
-        gemini_base(req),
-        req.raw_model_id.trim()
-    );
+    let url = format!("{}/chat/completions", openai_base(req));
 
     let mut body = Map::new();
-    body.insert("contents".into(), json!(gemini_contents(input)));
-    if !req.system_prompt.trim().is_empty() {
-        body.insert(
-            "systemInstruction".into(),
-            json!({ "parts": [{ "text": req.system_prompt.trim() }] }),
-        );
-    }
-    let tools = gemini_tools(req);
+    body.insert("model".into(), json!(req.raw_model_id));
+    body.insert("messages".into(), json!(chat_messages(req, input)));
+    body.insert("stream".into(), json!(true));
+    body.insert("stream_options".into(), json!({ "include_usage": true }));
+    params::apply(req, &mut body);
+    schema::apply(req, &mut body);
+    let tools = chat_tools(req);
     if !tools.is_empty() {
         body.insert("tools".into(), json!(tools));
     }
 
-    let mut request = agent.post(&url).header("Content-Type", "application/json");
-    let key = req.config.api_key.trim();
-    if !key.is_empty() {
-        request = request.header("x-goog-api-key", key);
-    }
-    let mut response = request
-        .send_json(Value::Object(body))
-        .map_err(|error| error.to_string())?;
-    if !response.status().is_success() {
-        return Err(http_error(
-            "gemini",
-            response.status().as_u16(),
-            &mut response,
-        ));
-    }
-
+    let mut response = openai_post(agent, args, &url, req, body, metrics)?;
     let mut outcome = RoundOutcome::default();
     let mut text = String::new();
-    let mut calls: Vec<(String, Value)> = Vec::new();
+    let mut calls: Vec<ChatToolCall> = Vec::new();
+    let mut stream_error = None;
     read_sse(response.body_mut().as_reader(), &args.cancelled, |event| {
-        gemini_event(&event, args, metrics, &mut text, &mut calls, &mut outcome);
+        if let Some(error) = chat_event(&event, args, metrics, &mut text, &mut calls, &mut outcome)
+        {
+            stream_error = Some(error);
+        }
     })?;
-
-    // Reassemble the round into neutral Responses items.
-    if !text.is_empty() {
-        outcome.model_items.push(json!({
-            "type": "message",
-            "role": "assistant",
-            "content": [{ "type": "output_text", "text": text }],
-        }));
-    }
-    for (index, (name, arguments)) in calls.into_iter().enumerate() {
-        let call_id = format!("call_{}_{index}", sanitize_id(&name));
-        outcome.model_items.push(json!({
-            "type": "function_call",
-            "call_id": call_id,
-            "name": name,
-            "arguments": arguments.to_string(),
-        }));
-        let kind = tool_kind(&req.tools, &name);
-        let mut call = ToolCall {
-            id: call_id,
-            name: name.clone(),
-            arguments: value_to_arguments(&arguments, kind),
-            input: string_input(&arguments),
-            ..ToolCall::default()
-        };
-        enrich_tool_call(&mut call, &req.tools);
-        outcome.tool_calls.push(call);
+    if let Some(error) = stream_error {
+        return Err(format!("openai: {error}"));
     }
+    chat_finish(&text, calls, req, &mut outcome);
     Ok(outcome)
 }
 
-fn gemini_event(
+/// Applies one `chat.completion.chunk`; returns the message of an in-stream
+/// `error` object, which some compatible servers send instead of an HTTP error.
+fn chat_event(
     event: &Value,
     args: &StreamArgs,
     metrics: &mut MetricTracker,
     text: &mut String,
-    calls: &mut Vec<(String, Value)>,
+    calls: &mut Vec<ChatToolCall>,
     outcome: &mut RoundOutcome,
-) {
-    if let Some(parts) = event
-        .pointer("/candidates/0/content/parts")
+) -> Option<String> {
+    if let Some(error) = event.get("error") {
+        return Some(
+            error
+                .get("message")
+                .and_then(Value::as_str)
+                .map_or_else(|| error.to_string(), str::to_owned),
+        );
+    }
+    if let Some(usage) = event.get("usage").filter(|usage| usage.is_object()) {
+        outcome.prompt_tokens = usage_i32(usage, "prompt_tokens");
+        outcome.output_tokens = usage_i32(usage, "completion_tokens");
+    }
+    let delta = event.pointer("/choices/0/delta")?;
+    if let Some(content) = delta.get("content").and_then(Value::as_str) {
+        text.push_str(content);
+        forward_token(args, metrics, content);
+    }
+    for fragment in delta
+        .get("tool_calls")
         .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
     {
-        for part in parts {
-            if let Some(delta) = part.get("text").and_then(Value::as_str) {
-                text.push_str(delta);
-                if metrics.observe_token(delta) {
-                    callback(args.cb, args.ctx, delta, 0);
-                }
-            } else if let Some(call) = part.get("functionCall") {
-                let name = call
-                    .get("name")
-                    .and_then(Value::as_str)
-                    .unwrap_or("")
-                    .to_owned();
-                let arguments = call.get("args").cloned().unwrap_or_else(|| json!({}));
-                calls.push((name, arguments));
+        // Servers that omit `index` send each call whole, in order.
+        let index = fragment
+            .get("index")
+            .and_then(Value::as_u64)
+            .and_then(|index| usize::try_from(index).ok())
+            .unwrap_or(calls.len());
+        if calls.len() <= index {
+            calls.resize_with(index + 1, ChatToolCall::default);
+        }
+        let call = &mut calls[index];
+        if let Some(id) = fragment.get("id").and_then(Value::as_str) {
+            id.clone_into(&mut call.id);
+        }
+        if let Some(function) = fragment.get("function") {
+            if let Some(name) = function.get("name").and_then(Value::as_str) {
+                call.name.push_str(name);
+            }
+            if let Some(arguments) = function.get("arguments").and_then(Value::as_str) {
+                call.arguments.push_str(arguments);
             }
         }
     }
-    if let Some(usage) = event.get("usageMetadata") {
-        outcome.prompt_tokens = usage_i32(usage, "promptTokenCount");
-        outcome.output_tokens = usage_i32(usage, "candidatesTokenCount");
-    }
+    None
 }
 
-fn gemini_base(req: &StreamRequest) -> String {
-    let configured = req.config.base_url.trim();
-    if req.provider == "local" {
-        // The local proxy exposes the native Gemini API under /v1beta, alongside
-        // its OpenAI-compatible /v1 surface. Swap the OpenAI version segment.
-        let root = base_url(configured, "http://127.0.0.1:8317/v1");
-        let root = root.trim_end_matches('/');
-        let root = root.strip_suffix("/v1").unwrap_or(root);
-        return format!("{root}/v1beta");
+/// Turns the accumulated text and tool-call fragments into neutral items.
+fn chat_finish(
+    text: &str,
+    calls: Vec<ChatToolCall>,
+    req: &StreamRequest,
+    outcome: &mut RoundOutcome,
+) {
+    if !text.is_empty() {
+        outcome.model_items.push(json!({
+            "type": "message",
+            "role": "assistant",
+            "content": [{ "type": "output_text", "text": text }],
+        }));
     }
-    if configured.is_empty() {
-        "https://generativelanguage.googleapis.com/v1beta".to_owned()
-    } else {
-        base_url(configured, "")
+    for (index, call) in calls.into_iter().enumerate() {
+        if call.name.trim().is_empty() {
+            continue;
+        }
+        let arguments = if call.arguments.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str::<Value>(&call.arguments).unwrap_or_else(|_| json!({}))
+        };
+        let call_id = first_id([
+            call.id.as_str(),
+            &format!("call_{index}_{}", sanitize_id(&call.name)),
+        ]);
+        push_function_call(outcome, req, call_id, &call.name, &arguments);
     }
 }
 
-/// Converts neutral Responses input items into Gemini `contents`, merging
-/// consecutive same-role turns and pairing tool outputs back to their call name.
-fn gemini_contents(input: &[Value]) -> Vec<Value> {
-    let names = call_id_names(input);
-    let mut contents: Vec<Value> = Vec::new();
+fn chat_messages(req: &StreamRequest, input: &[Value]) -> Vec<Value> {
+    let mut messages: Vec<Value> = Vec::new();
+    if !req.system_prompt.trim().is_empty() {
+        messages.push(json!({ "role": "system", "content": req.system_prompt.trim() }));
+    }
+    for item in input {
+        match item.get("type").and_then(Value::as_str).unwrap_or("") {
+            "message" => {
+                let role = item.get("role").and_then(Value::as_str).unwrap_or("user");
+                let Some(content) = item.get("content").and_then(Value::as_array) else {
+                    continue;
+                };
+                if role == "user" {
+                    let parts = content
+                        .iter()
+                        .filter_map(chat_user_part)
+                        .collect::<Vec<_>>();
+                    if !parts.is_empty() {
+                        messages.push(json!({ "role": "user", "content": parts }));
+                    }
+                } else {
+                    let text = content
+                        .iter()
+                        .filter_map(|part| part.get("text").and_then(Value::as_str))
+                        .collect::<String>();
+                    if !text.is_empty() {
+                        messages.push(json!({ "role": role, "content": text }));
+                    }
+                }
+            }
+            "function_call" => {
+                let call = json!({
+                    "id": item.get("call_id").and_then(Value::as_str).unwrap_or(""),
+                    "type": "function",
+                    "function": {
+                        "name": item.get("name").and_then(Value::as_str).unwrap_or(""),
+                        "arguments": item.get("arguments").and_then(Value::as_str).unwrap_or("{}"),
+                    },
+                });
+                // Parallel calls share the assistant message that issued them.
+                match messages.last_mut() {
+                    Some(last) if last.get("role").and_then(Value::as_str) == Some("assistant") => {
+                        match last.get_mut("tool_calls").and_then(Value::as_array_mut) {
+                            Some(existing) => existing.push(call),
+                            None => last["tool_calls"] = json!([call]),
+                        }
+                    }
+                    _ => messages.push(json!({
+                        "role": "assistant",
+                        "content": Value::Null,
+                        "tool_calls": [call],
+                    })),
+                }
+            }
+            "function_call_output" => messages.push(json!({
+                "role": "tool",
+                "tool_call_id": item.get("call_id").and_then(Value::as_str).unwrap_or(""),
+                "content": item.get("output").and_then(Value::as_str).unwrap_or(""),
+            })),
+            _ => {}
+        }
+    }
+    messages
+}
+
+fn chat_user_part(part: &Value) -> Option<Value> {
+    match part.get("type").and_then(Value::as_str).unwrap_or("") {
+        "input_text" | "output_text" => part
+            .get("text")
+            .and_then(Value::as_str)
+            .filter(|text| !text.is_empty())
+            .map(|text| json!({ "type": "text", "text": text })),
+        "input_image" => {
+            let url = part.get("image_url").and_then(Value::as_str)?;
+            Some(json!({ "type": "image_url", "image_url": { "url": url } }))
+        }
+        "input_file" => chat_user_part(&documents::file_part_as_text(part)),
+        _ => None,
+    }
+}
+
+fn chat_tools(req: &StreamRequest) -> Vec<Value> {
+    req.tools
+        .iter()
+        .map(|tool| {
+            json!({
+                "type": "function",
+                "function": {
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": tool_parameters(tool),
+                },
+            })
+        })
+        .collect()
+}
+
+// --- Ollama -----------------------------------------------------------------
+
+fn ollama_round(
+    agent: &ureq::Agent,
+    args: &StreamArgs,
+    req: &StreamRequest,
+    input: &[Value],
+    metrics: &mut MetricTracker,
+) -> Result<RoundOutcome, String> {
+    let base = base_url(&req.config.base_url, ollama::DEFAULT_BASE_URL);
+    let url = format!("{base}/api/chat");
+
+    let mut body = Map::new();
+    body.insert("model".into(), json!(req.raw_model_id));
+    body.insert("messages".into(), json!(ollama_messages(req, input)));
+    body.insert("stream".into(), json!(true));
+    params::apply(req, &mut body);
+    schema::apply(req, &mut body);
+    let tools = chat_tools(req);
+    if !tools.is_empty() {
+        body.insert("tools".into(), json!(tools));
+    }
+
+    let build = || agent.post(&url).header("Content-Type", "application/json");
+    let mut response = send_streaming(args, req, metrics, "ollama", build, &Value::Object(body))?;
+
+    // Synthesized call ids continue numbering across rounds so they stay
+    // unique within the turn.
+    let call_base = input
+        .iter()
+        .filter(|item| item.get("type").and_then(Value::as_str) == Some("function_call"))
+        .count();
+    let mut outcome = RoundOutcome::default();
+    let mut text = String::new();
+    let mut stream_error = None;
+    read_ndjson(response.body_mut().as_reader(), &args.cancelled, |chunk| {
+        let state = (&mut text, call_base);
+        if let Some(error) = ollama_chunk(&chunk, args, req, metrics, state, &mut outcome) {
+            stream_error = Some(error);
+        }
+    })?;
+    if let Some(error) = stream_error {
+        return Err(format!("ollama: {error}"));
+    }
+    if !text.is_empty() {
+        // The reply text precedes this round's calls in history.
+        outcome.model_items.insert(
+            0,
+            json!({
+                "type": "message",
+                "role": "assistant",
+                "content": [{ "type": "output_text", "text": text }],
+            }),
+        );
+    }
+    Ok(outcome)
+}
+
+/// Applies one `/api/chat` line. Tool calls arrive whole, not as fragments;
+/// Ollama assigns no call ids, so one is synthesized from the call position
+/// offset by `call_base`, the number of calls earlier in the conversation.
+fn ollama_chunk(
+    chunk: &Value,
+    args: &StreamArgs,
+    req: &StreamRequest,
+    metrics: &mut MetricTracker,
+    (text, call_base): (&mut String, usize),
+    outcome: &mut RoundOutcome,
+) -> Option<String> {
+    if let Some(error) = chunk.get("error").and_then(Value::as_str) {
+        return Some(error.to_owned());
+    }
+    let message = chunk.get("message").unwrap_or(&Value::Null);
+    if let Some(content) = message.get("content").and_then(Value::as_str) {
+        text.push_str(content);
+        forward_token(args, metrics, content);
+    }
+    for call in message
+        .get("tool_calls")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let function = call.get("function").unwrap_or(&Value::Null);
+        let name = function.get("name").and_then(Value::as_str).unwrap_or("");
+        if name.trim().is_empty() {
+            continue;
+        }
+        let arguments = match function.get("arguments") {
+            Some(Value::String(raw)) => {
+                serde_json::from_str::<Value>(raw).unwrap_or_else(|_| json!({}))
+            }
+            Some(value @ Value::Object(_)) => value.clone(),
+            _ => json!({}),
+        };
+        let call_id = first_id([
+            call.get("id").and_then(Value::as_str).unwrap_or(""),
+            &format!(
+                "call_{}_{}",
+                call_base + outcome.tool_calls.len(),
+                sanitize_id(name)
+            ),
+        ]);
+        push_function_call(outcome, req, call_id, name, &arguments);
+    }
+    if chunk.get("done").and_then(Value::as_bool) == Some(true) {
+        outcome.prompt_tokens = usage_i32(chunk, "prompt_eval_count");
+        outcome.output_tokens = usage_i32(chunk, "eval_count");
+    }
+    None
+}
+
+fn ollama_messages(req: &StreamRequest, input: &[Value]) -> Vec<Value> {
+    let names = call_id_names(input);
+    let mut messages: Vec<Value> = Vec::new();
+    if !req.system_prompt.trim().is_empty() {
+        messages.push(json!({ "role": "system", "content": req.system_prompt.trim() }));
+    }
+    for item in input {
+        match item.get("type").and_then(Value::as_str).unwrap_or("") {
+            "message" => {
+                let role = item.get("role").and_then(Value::as_str).unwrap_or("user");
+                let Some(content) = item.get("content").and_then(Value::as_array) else {
+                    continue;
+                };
+                let text = content
+                    .iter()
+                    .map(|part| match part.get("type").and_then(Value::as_str) {
+                        Some("input_file") => documents::file_part_as_text(part),
+                        _ => part.clone(),
+                    })
+                    .filter_map(|part| part.get("text").and_then(Value::as_str).map(str::to_owned))
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                // Only inline base64 images are accepted; remote URLs are dropped.
+                let images = content
+                    .iter()
+                    .filter(|part| part.get("type").and_then(Value::as_str) == Some("input_image"))
+                    .filter_map(|part| part.get("image_url").and_then(Value::as_str))
+                    .filter_map(data_uri_parts)
+                    .map(|(_mime, data)| data)
+                    .collect::<Vec<_>>();
+                if text.is_empty() && images.is_empty() {
+                    continue;
+                }
+                let mut message = json!({ "role": role, "content": text });
+                if !images.is_empty() {
+                    message["images"] = json!(images);
+                }
+                messages.push(message);
+            }
+            "function_call" => {
+                let arguments = item
+                    .get("arguments")
+                    .and_then(Value::as_str)
+                    .and_then(|raw| serde_json::from_str::<Value>(raw).ok())
+                    .filter(Value::is_object)
+                    .unwrap_or_else(|| json!({}));
+                let call = json!({
+                    "function": {
+                        "name": item.get("name").and_then(Value::as_str).unwrap_or(""),
+                        "arguments": arguments,
+                    },
+                });
+                match messages.last_mut() {
+                    Some(last) if last.get("role").and_then(Value::as_str) == Some("assistant") => {
+                        match last.get_mut("tool_calls").and_then(Value::as_array_mut) {
+                            Some(existing) => existing.push(call),
+                            None => last["tool_calls"] = json!([call]),
+                        }
+                    }
+                    _ => messages.push(json!({
+                        "role": "assistant",
+                        "content": "",
+                        "tool_calls": [call],
+                    })),
+                }
+            }
+            "function_call_output" => {
+                let call_id = item.get("call_id").and_then(Value::as_str).unwrap_or("");
+                messages.push(json!({
+                    "role": "tool",
+                    "tool_name": names.get(call_id).and_then(Value::as_str).unwrap_or(""),
+                    "content": item.get("output").and_then(Value::as_str).unwrap_or(""),
+                }));
+            }
+            _ => {}
+        }
+    }
+    messages
+}
+
+// --- Gemini -----------------------------------------------------------------
+
+fn gemini_round(
+    agent: &ureq::Agent,
+    args: &StreamArgs,
+    req: &StreamRequest,
+    input: &[Value],
+    metrics: &mut MetricTracker,
+) -> Result<RoundOutcome, String> {
+    let url = format!(
+        "{}/models/{}:streamGenerateContent?alt=sse",
+        gemini_base(req),
+        req.raw_model_id.trim()
+    );
+
+    let mut body = Map::new();
+    body.insert("contents".into(), json!(gemini_contents(input)));
+    if !req.system_prompt.trim().is_empty() {
+        body.insert(
+            "systemInstruction".into(),
+            json!({ "parts": [{ "text": req.system_prompt.trim() }] }),
+        );
+    }
+    params::apply(req, &mut body);
+    schema::apply(req, &mut body);
+    if gemini_thinks(req) {
+        let config = body.entry("generationConfig").or_insert_with(|| json!({}));
+        if let Some(thinking) = config
+            .as_object_mut()
+            .map(|config| config.entry("thinkingConfig").or_insert_with(|| json!({})))
+        {
+            thinking["includeThoughts"] = json!(true);
+        }
+    }
+    if req.raw_model_id.contains("-image") {
+        let config = body.entry("generationConfig").or_insert_with(|| json!({}));
+        config["responseModalities"] = json!(["TEXT", "IMAGE"]);
+    }
+    let tools = gemini_tools(req);
+    if !tools.is_empty() {
+        body.insert("tools".into(), json!(tools));
+    }
+
+    let key = req.config.api_key.trim();
+    let build = || {
+        let request = agent.post(&url).header("Content-Type", "application/json");
+        if key.is_empty() {
+            request
+        } else {
+            request.header("x-goog-api-key", key)
+        }
+    };
+    let mut response = send_streaming(args, req, metrics, "gemini", build, &Value::Object(body))?;
+
+    let mut outcome = RoundOutcome::default();
+    let mut text = String::new();
+    let mut calls: Vec<(String, Value)> = Vec::new();
+    let mut grounding = None;
+    read_sse(response.body_mut().as_reader(), &args.cancelled, |event| {
+        gemini_event(
+            &event,
+            args,
+            metrics,
+            &mut text,
+            &mut calls,
+            &mut grounding,
+            &mut outcome,
+        );
+    })?;
+    if let Some(grounding) = grounding {
+        outcome.citations = citations::from_gemini_grounding(&grounding, &text);
+    }
+
+    // Reassemble the round into neutral Responses items.
+    if !text.is_empty() {
+        outcome.model_items.push(json!({
+            "type": "message",
+            "role": "assistant",
+            "content": [{ "type": "output_text", "text": text }],
+        }));
+    }
+    for (index, (name, arguments)) in calls.into_iter().enumerate() {
+        let call_id = format!("call_{}_{index}", sanitize_id(&name));
+        push_function_call(&mut outcome, req, call_id, &name, &arguments);
+    }
+    Ok(outcome)
+}
+
+fn gemini_event(
+    event: &Value,
+    args: &StreamArgs,
+    metrics: &mut MetricTracker,
+    text: &mut String,
+    calls: &mut Vec<(String, Value)>,
+    grounding: &mut Option<Value>,
+    outcome: &mut RoundOutcome,
+) {
+    // Sent with the closing chunks; the last copy covers the whole reply.
+    if let Some(metadata) = event.pointer("/candidates/0/groundingMetadata") {
+        *grounding = Some(metadata.clone());
+    }
+    if let Some(parts) = event
+        .pointer("/candidates/0/content/parts")
+        .and_then(Value::as_array)
+    {
+        for part in parts {
+            let thought = part.get("thought").and_then(Value::as_bool) == Some(true);
+            if let Some(delta) = part.get("text").and_then(Value::as_str).filter(|_| thought) {
+                forward_reasoning(args, metrics, delta);
+            } else if let Some(delta) = part.get("text").and_then(Value::as_str) {
+                text.push_str(delta);
+                forward_token(args, metrics, delta);
+            } else if let Some(image) = part.get("inlineData") {
+                let field = |key: &str| image.get(key).and_then(Value::as_str).unwrap_or("");
+                emit_generated_image(args, metrics, field("mimeType"), field("data"));
+            } else if let Some(call) = part.get("functionCall") {
+                let name = call
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .unwrap_or("")
+                    .to_owned();
+                let arguments = call.get("args").cloned().unwrap_or_else(|| json!({}));
+                calls.push((name, arguments));
+            }
+        }
+    }
+    if let Some(usage) = event.get("usageMetadata") {
+        outcome.prompt_tokens = usage_i32(usage, "promptTokenCount");
+        outcome.output_tokens = usage_i32(usage, "candidatesTokenCount");
+    }
+}
+
+/// Whether `req`'s Gemini model thinks, and so accepts `includeThoughts`.
+/// Models before 2.5 and the `-image` models reject a `thinkingConfig`.
+fn gemini_thinks(req: &StreamRequest) -> bool {
+    let raw = req.raw_model_id.trim();
+    !(raw.starts_with("gemini-1") || raw.starts_with("gemini-2.0") || raw.contains("-image"))
+}
+
+fn gemini_base(req: &StreamRequest) -> String {
+    let configured = req.config.base_url.trim();
+    if req.provider == "local" {
+        // The local proxy exposes the native Gemini API under /v1beta, alongside
+        // its OpenAI-compatible /v1 surface. Swap the OpenAI version segment.
+        let root = base_url(configured, "http://127.0.0.1:8317/v1");
+        let root = root.trim_end_matches('/');
+        let root = root.strip_suffix("/v1").unwrap_or(root);
+        return format!("{root}/v1beta");
+    }
+    if configured.is_empty() {
+        "https://generativelanguage.googleapis.com/v1beta".to_owned()
+    } else {
+        base_url(configured, "")
+    }
+}
+
+/// Converts neutral Responses input items into Gemini `contents`, merging
+/// consecutive same-role turns and pairing tool outputs back to their call name.
+fn gemini_contents(input: &[Value]) -> Vec<Value> {
+    let names = call_id_names(input);
+    let mut contents: Vec<Value> = Vec::new();
     for item in input {
         let Some((role, parts)) = gemini_item(item, &names) else {
             continue;
         };
-        if parts.is_empty() {
+        push_turn(&mut contents, role, "parts", parts);
+    }
+    contents
+}
+
+fn gemini_item(item: &Value, names: &Map<String, Value>) -> Option<(&'static str, Vec<Value>)> {
+    match item.get("type").and_then(Value::as_str).unwrap_or("") {
+        "message" => {
+            let role = item.get("role").and_then(Value::as_str).unwrap_or("user");
+            let content = item.get("content").and_then(Value::as_array)?;
+            if role == "assistant" {
+                let text = content
+                    .iter()
+                    .filter_map(|part| part.get("text").and_then(Value::as_str))
+                    .collect::<String>();
+                (!text.is_empty()).then(|| ("model", vec![json!({ "text": text })]))
+            } else {
+                let parts = content
+                    .iter()
+                    .filter_map(gemini_user_part)
+                    .collect::<Vec<_>>();
+                Some(("user", parts))
+            }
+        }
+        "function_call" => {
+            let name = item.get("name").and_then(Value::as_str).unwrap_or("");
+            let arguments = item
+                .get("arguments")
+                .and_then(Value::as_str)
+                .and_then(|raw| serde_json::from_str::<Value>(raw).ok())
+                .unwrap_or_else(|| json!({}));
+            Some((
+                "model",
+                vec![json!({ "functionCall": { "name": name, "args": arguments } })],
+            ))
+        }
+        "function_call_output" => {
+            let call_id = item.get("call_id").and_then(Value::as_str).unwrap_or("");
+            let name = names
+                .get(call_id)
+                .and_then(Value::as_str)
+                .unwrap_or(call_id);
+            let output = item.get("output").and_then(Value::as_str).unwrap_or("");
+            Some((
+                "user",
+                vec![json!({
+                    "functionResponse": {
+                        "name": name,
+                        "response": gemini_tool_response(output),
+                    }
+                })],
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn gemini_user_part(part: &Value) -> Option<Value> {
+    match part.get("type").and_then(Value::as_str).unwrap_or("") {
+        "input_text" | "output_text" => part
+            .get("text")
+            .and_then(Value::as_str)
+            .map(|text| json!({ "text": text })),
+        "input_image" => {
+            let url = part.get("image_url").and_then(Value::as_str)?;
+            let (mime, data) = data_uri_parts(url)?;
+            Some(json!({ "inlineData": { "mimeType": mime, "data": data } }))
+        }
+        "input_file" => {
+            let url = part.get("file_data").and_then(Value::as_str)?;
+            let (mime, data) = data_uri_parts(url)?;
+            Some(json!({ "inlineData": { "mimeType": mime, "data": data } }))
+        }
+        _ => None,
+    }
+}
+
+fn gemini_tool_response(output: &str) -> Value {
+    match serde_json::from_str::<Value>(output) {
+        Ok(value @ Value::Object(_)) => value,
+        Ok(value) => json!({ "result": value }),
+        Err(_) => json!({ "output": output }),
+    }
+}
+
+fn gemini_tools(req: &StreamRequest) -> Vec<Value> {
+    let mut tools = Vec::new();
+    if !req.tools.is_empty() {
+        let declarations = req
+            .tools
+            .iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.name,
+                    "description": tool.description,
+                    "parameters": tool_parameters(tool),
+                })
+            })
+            .collect::<Vec<_>>();
+        tools.push(json!({ "functionDeclarations": declarations }));
+    }
+    if req.provider_search_enabled {
+        tools.push(json!({ "googleSearch": {} }));
+    }
+    tools
+}
+
+/// Maps each `call_id` to the tool name from the `function_call` that produced it.
+fn call_id_names(input: &[Value]) -> Map<String, Value> {
+    let mut names = Map::new();
+    for item in input {
+        if item.get("type").and_then(Value::as_str) == Some("function_call") {
+            if let (Some(id), Some(name)) = (
+                item.get("call_id").and_then(Value::as_str),
+                item.get("name").cloned(),
+            ) {
+                names.insert(id.to_owned(), name);
+            }
+        }
+    }
+    names
+}
+
+// --- Anthropic Messages -----------------------------------------------------
+
+/// A content block being assembled from `content_block_*` events.
+#[derive(Debug, Default)]
+struct AnthropicBlock {
+    kind: String,
+    id: String,
+    name: String,
+    /// Reply text, thinking, or the opaque data of a redacted thinking block.
+    text: String,
+    partial_json: String,
+    signature: String,
+}
+
+fn anthropic_round(
+    agent: &ureq::Agent,
+    args: &StreamArgs,
+    req: &StreamRequest,
+    input: &[Value],
+    metrics: &mut MetricTracker,
+) -> Result<RoundOutcome, String> {
+    let base = base_url(&req.config.base_url, "https://api.anthropic.com/v1");
+    let url = format!("{base}/messages");
+
+    let mut body = Map::new();
+    body.insert("model".into(), json!(req.raw_model_id));
+    body.insert("max_tokens".into(), json!(ANTHROPIC_MAX_TOKENS));
+    body.insert("messages".into(), json!(anthropic_messages(input)));
+    body.insert("stream".into(), json!(true));
+    if !req.system_prompt.trim().is_empty() {
+        body.insert("system".into(), json!(req.system_prompt.trim()));
+    }
+    params::apply(req, &mut body);
+    schema::apply(req, &mut body);
+    let tools = anthropic_tools(req);
+    if !tools.is_empty() {
+        body.insert("tools".into(), json!(tools));
+    }
+
+    let key = req.config.api_key.trim();
+    let build = || {
+        let request = agent
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .header("anthropic-version", ANTHROPIC_VERSION);
+        if key.is_empty() {
+            request
+        } else {
+            request.header("x-api-key", key)
+        }
+    };
+    let mut response =
+        send_streaming(args, req, metrics, "anthropic", build, &Value::Object(body))?;
+
+    let mut outcome = RoundOutcome::default();
+    let mut blocks: Vec<AnthropicBlock> = Vec::new();
+    let mut stream_error = None;
+    read_sse(response.body_mut().as_reader(), &args.cancelled, |event| {
+        if let Some(error) = anthropic_event(&event, args, metrics, &mut blocks, &mut outcome) {
+            stream_error = Some(error);
+        }
+    })?;
+    if let Some(error) = stream_error {
+        return Err(format!("anthropic: {error}"));
+    }
+    anthropic_finish(blocks, req, &mut outcome);
+    Ok(outcome)
+}
+
+/// Applies one Messages stream event; returns the message of an `error` event.
+fn anthropic_event(
+    event: &Value,
+    args: &StreamArgs,
+    metrics: &mut MetricTracker,
+    blocks: &mut Vec<AnthropicBlock>,
+    outcome: &mut RoundOutcome,
+) -> Option<String> {
+    let index = event
+        .get("index")
+        .and_then(Value::as_u64)
+        .and_then(|index| usize::try_from(index).ok())
+        .unwrap_or(0);
+    match event.get("type").and_then(Value::as_str).unwrap_or("") {
+        "message_start" => {
+            if let Some(usage) = event.pointer("/message/usage") {
+                outcome.prompt_tokens = usage_i32(usage, "input_tokens")
+                    .saturating_add(usage_i32(usage, "cache_creation_input_tokens"))
+                    .saturating_add(usage_i32(usage, "cache_read_input_tokens"));
+                outcome.output_tokens = usage_i32(usage, "output_tokens");
+            }
+        }
+        "content_block_start" => {
+            if blocks.len() <= index {
+                blocks.resize_with(index + 1, AnthropicBlock::default);
+            }
+            let start = event.get("content_block").unwrap_or(&Value::Null);
+            let field = |key: &str| {
+                start
+                    .get(key)
+                    .and_then(Value::as_str)
+                    .unwrap_or("")
+                    .to_owned()
+            };
+            let kind = field("type");
+            let text = match kind.as_str() {
+                "thinking" => field("thinking"),
+                "redacted_thinking" => field("data"),
+                _ => field("text"),
+            };
+            blocks[index] = AnthropicBlock {
+                kind,
+                id: field("id"),
+                name: field("name"),
+                text,
+                ..AnthropicBlock::default()
+            };
+        }
+        "content_block_delta" => {
+            let block = blocks.get_mut(index)?;
+            let delta = event.get("delta").unwrap_or(&Value::Null);
+            match delta.get("type").and_then(Value::as_str).unwrap_or("") {
+                "text_delta" => {
+                    let text = delta.get("text").and_then(Value::as_str).unwrap_or("");
+                    block.text.push_str(text);
+                    forward_token(args, metrics, text);
+                }
+                "input_json_delta" => {
+                    if let Some(partial) = delta.get("partial_json").and_then(Value::as_str) {
+                        block.partial_json.push_str(partial);
+                    }
+                }
+                "thinking_delta" => {
+                    let thinking = delta.get("thinking").and_then(Value::as_str).unwrap_or("");
+                    block.text.push_str(thinking);
+                    forward_reasoning(args, metrics, thinking);
+                }
+                "signature_delta" => {
+                    if let Some(signature) = delta.get("signature").and_then(Value::as_str) {
+                        block.signature.push_str(signature);
+                    }
+                }
+                _ => {}
+            }
+        }
+        "message_delta" => {
+            // `output_tokens` here is cumulative for the message.
+            if let Some(usage) = event.get("usage") {
+                outcome.output_tokens = usage_i32(usage, "output_tokens");
+            }
+        }
+        "error" => {
+            return Some(
+                event
+                    .pointer("/error/message")
+                    .and_then(Value::as_str)
+                    .unwrap_or("stream error")
+                    .to_owned(),
+            );
+        }
+        _ => {}
+    }
+    None
+}
+
+/// Reassembles the round's content blocks into neutral Responses items.
+/// Server-tool blocks (web search) are answered by the API itself and are not
+/// replayed. Thinking becomes `reasoning` items carrying Anthropic's signature
+/// (or redacted data), which the Messages API wants back ahead of the tool
+/// use it led to; the other wire formats skip them.
+fn anthropic_finish(blocks: Vec<AnthropicBlock>, req: &StreamRequest, outcome: &mut RoundOutcome) {
+    for block in &blocks {
+        match block.kind.as_str() {
+            "thinking" => outcome.model_items.push(json!({
+                "type": "reasoning",
+                "summary": [{ "type": "summary_text", "text": block.text }],
+                "signature": block.signature,
+            })),
+            "redacted_thinking" => outcome.model_items.push(json!({
+                "type": "reasoning",
+                "summary": [],
+                "redacted_data": block.text,
+            })),
+            _ => {}
+        }
+    }
+    let text = blocks
+        .iter()
+        .filter(|block| block.kind == "text")
+        .map(|block| block.text.as_str())
+        .collect::<String>();
+    if !text.is_empty() {
+        outcome.model_items.push(json!({
+            "type": "message",
+            "role": "assistant",
+            "content": [{ "type": "output_text", "text": text }],
+        }));
+    }
+    for block in blocks {
+        if block.kind != "tool_use" {
+            continue;
+        }
+        let arguments = if block.partial_json.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str::<Value>(&block.partial_json).unwrap_or_else(|_| json!({}))
+        };
+        let call_id = first_id([
+            block.id.as_str(),
+            &format!("toolu_{}", sanitize_id(&block.name)),
+        ]);
+        push_function_call(outcome, req, call_id, &block.name, &arguments);
+    }
+}
+
+fn anthropic_messages(input: &[Value]) -> Vec<Value> {
+    let mut messages: Vec<Value> = Vec::new();
+    for item in input {
+        let Some((role, blocks)) = anthropic_item(item) else {
             continue;
-        }
-        match contents.last_mut() {
-            Some(last) if last.get("role").and_then(Value::as_str) == Some(role) => {
-                if let Some(existing) = last.get_mut("parts").and_then(Value::as_array_mut) {
-                    existing.extend(parts);
-                }
-            }
-            _ => contents.push(json!({ "role": role, "parts": parts })),
-        }
+        };
+        push_turn(&mut messages, role, "content", blocks);
     }
-    contents
+    messages
 }
 
-fn gemini_item(item: &Value, names: &Map<String, Value>) -> Option<(&'static str, Vec<Value>)> {
+fn anthropic_item(item: &Value) -> Option<(&'static str, Vec<Value>)> {
     match item.get("type").and_then(Value::as_str).unwrap_or("") {
         "message" => {
             let role = item.get("role").and_then(Value::as_str).unwrap_or("user");
@@ -470,110 +1737,156 @@ fn gemini_item(item: &Value, names: &Map<String, Value>) -> Option<(&'static str
                     .iter()
                     .filter_map(|part| part.get("text").and_then(Value::as_str))
                     .collect::<String>();
-                (!text.is_empty()).then(|| ("model", vec![json!({ "text": text })]))
+                (!text.is_empty())
+                    .then(|| ("assistant", vec![json!({ "type": "text", "text": text })]))
             } else {
-                let parts = content
+                let blocks = content
                     .iter()
-                    .filter_map(gemini_user_part)
+                    .filter_map(anthropic_user_block)
                     .collect::<Vec<_>>();
-                Some(("user", parts))
+                Some(("user", blocks))
             }
         }
         "function_call" => {
+            let call_id = item.get("call_id").and_then(Value::as_str).unwrap_or("");
             let name = item.get("name").and_then(Value::as_str).unwrap_or("");
             let arguments = item
                 .get("arguments")
                 .and_then(Value::as_str)
                 .and_then(|raw| serde_json::from_str::<Value>(raw).ok())
+                .filter(Value::is_object)
                 .unwrap_or_else(|| json!({}));
             Some((
-                "model",
-                vec![json!({ "functionCall": { "name": name, "args": arguments } })],
+                "assistant",
+                vec![
+                    json!({ "type": "tool_use", "id": call_id, "name": name, "input": arguments }),
+                ],
+            ))
+        }
+        "reasoning" => {
+            if let Some(data) = item.get("redacted_data").and_then(Value::as_str) {
+                return Some((
+                    "assistant",
+                    vec![json!({ "type": "redacted_thinking", "data": data })],
+                ));
+            }
+            // Reasoning from other providers carries no signature.
+            let signature = item.get("signature").and_then(Value::as_str)?;
+            let thinking = item
+                .get("summary")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|part| part.get("text").and_then(Value::as_str))
+                .collect::<String>();
+            Some((
+                "assistant",
+                vec![json!({ "type": "thinking", "thinking": thinking, "signature": signature })],
             ))
         }
         "function_call_output" => {
             let call_id = item.get("call_id").and_then(Value::as_str).unwrap_or("");
-            let name = names
-                .get(call_id)
-                .and_then(Value::as_str)
-                .unwrap_or(call_id);
             let output = item.get("output").and_then(Value::as_str).unwrap_or("");
-            Some((
-                "user",
-                vec![json!({
-                    "functionResponse": {
-                        "name": name,
-                        "response": gemini_tool_response(output),
-                    }
-                })],
-            ))
+            let mut block = json!({ "type": "tool_result", "tool_use_id": call_id });
+            if !output.is_empty() {
+                block["content"] = json!(output);
+            }
+            Some(("user", vec![block]))
         }
         _ => None,
     }
 }
 
-fn gemini_user_part(part: &Value) -> Option<Value> {
+fn anthropic_user_block(part: &Value) -> Option<Value> {
     match part.get("type").and_then(Value::as_str).unwrap_or("") {
         "input_text" | "output_text" => part
             .get("text")
             .and_then(Value::as_str)
-            .map(|text| json!({ "text": text })),
+            .filter(|text| !text.is_empty())
+            .map(|text| json!({ "type": "text", "text": text })),
         "input_image" => {
             let url = part.get("image_url").and_then(Value::as_str)?;
+            let source = match data_uri_parts(url) {
+                Some((mime, data)) => json!({ "type": "base64", "media_type": mime, "data": data }),
+                None => json!({ "type": "url", "url": url }),
+            };
+            Some(json!({ "type": "image", "source": source }))
+        }
+        "input_file" => {
+            let url = part.get("file_data").and_then(Value::as_str)?;
             let (mime, data) = data_uri_parts(url)?;
-            Some(json!({ "inlineData": { "mimeType": mime, "data": data } }))
+            Some(json!({
+                "type": "document",
+                "source": { "type": "base64", "media_type": mime, "data": data },
+            }))
         }
         _ => None,
     }
 }
 
-fn gemini_tool_response(output: &str) -> Value {
-    match serde_json::from_str::<Value>(output) {
-        Ok(value @ Value::Object(_)) => value,
-        Ok(value) => json!({ "result": value }),
-        Err(_) => json!({ "output": output }),
-    }
-}
-
-fn gemini_tools(req: &StreamRequest) -> Vec<Value> {
-    let mut tools = Vec::new();
-    if !req.tools.is_empty() {
-        let declarations = req
-            .tools
-            .iter()
-            .map(|tool| {
-                json!({
-                    "name": tool.name,
-                    "description": tool.description,
-                    "parameters": tool_parameters(tool),
-                })
+fn anthropic_tools(req: &StreamRequest) -> Vec<Value> {
+    let mut tools: Vec<Value> = req
+        .tools
+        .iter()
+        .map(|tool| {
+            json!({
+                "name": tool.name,
+                "description": tool.description,
+                "input_schema": tool_parameters(tool),
             })
-            .collect::<Vec<_>>();
-        tools.push(json!({ "functionDeclarations": declarations }));
-    }
+        })
+        .collect();
     if req.provider_search_enabled {
-        tools.push(json!({ "googleSearch": {} }));
+        tools.push(json!({ "type": "web_search_20250305", "name": "web_search" }));
     }
     tools
 }
 
-/// Maps each `call_id` to the tool name from the `function_call` that produced it.
-fn call_id_names(input: &[Value]) -> Map<String, Value> {
-    let mut names = Map::new();
-    for item in input {
-        if item.get("type").and_then(Value::as_str) == Some("function_call") {
-            if let (Some(id), Some(name)) = (
-                item.get("call_id").and_then(Value::as_str),
-                item.get("name").cloned(),
-            ) {
-                names.insert(id.to_owned(), name);
+// --- shared helpers ---------------------------------------------------------
+
+/// Appends `parts` to the trailing turn when it has the same role, otherwise
+/// starts a new `{ role, <key>: parts }` turn. Gemini and Anthropic both reject
+/// consecutive turns from the same role.
+fn push_turn(turns: &mut Vec<Value>, role: &str, key: &str, parts: Vec<Value>) {
+    if parts.is_empty() {
+        return;
+    }
+    match turns.last_mut() {
+        Some(last) if last.get("role").and_then(Value::as_str) == Some(role) => {
+            if let Some(existing) = last.get_mut(key).and_then(Value::as_array_mut) {
+                existing.extend(parts);
             }
         }
+        _ => turns.push(json!({ "role": role, key: parts })),
     }
-    names
 }
 
-// --- shared helpers ---------------------------------------------------------
+/// Records a model-requested call as a neutral `function_call` item plus the
+/// `ToolCall` the loop dispatches.
+fn push_function_call(
+    outcome: &mut RoundOutcome,
+    req: &StreamRequest,
+    call_id: String,
+    name: &str,
+    arguments: &Value,
+) {
+    outcome.model_items.push(json!({
+        "type": "function_call",
+        "call_id": call_id,
+        "name": name,
+        "arguments": arguments.to_string(),
+    }));
+    let kind = tool_kind(&req.tools, name);
+    let mut call = ToolCall {
+        id: call_id,
+        name: name.to_owned(),
+        arguments: value_to_arguments(arguments, kind),
+        input: string_input(arguments),
+        ..ToolCall::default()
+    };
+    enrich_tool_call(&mut call, &req.tools);
+    outcome.tool_calls.push(call);
+}
 
 /// Reads an SSE stream line by line, invoking `on_event` for each `data:` JSON
 /// payload. `on_event` returns `true` to stop early. Cancellation is polled
@@ -608,6 +1921,30 @@ where
     }
 }
 
+/// Reads a newline-delimited JSON stream, polling cancellation between lines.
+fn read_ndjson<F>(reader: impl Read, cancelled: &AtomicBool, mut on_chunk: F) -> Result<(), String>
+where
+    F: FnMut(Value),
+{
+    let mut buffered = BufReader::new(reader);
+    let mut line = String::new();
+    loop {
+        if cancelled.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        line.clear();
+        let read = buffered
+            .read_line(&mut line)
+            .map_err(|error| error.to_string())?;
+        if read == 0 {
+            return Ok(());
+        }
+        if let Ok(value) = serde_json::from_str::<Value>(line.trim()) {
+            on_chunk(value);
+        }
+    }
+}
+
 fn stream_agent() -> ureq::Agent {
     ureq::Agent::config_builder()
         .http_status_as_error(false)
@@ -615,6 +1952,114 @@ fn stream_agent() -> ureq::Agent {
         .new_agent()
 }
 
+/// Sends a streaming request, re-sending it after 408, 429 and 5xx responses
+/// or dropped connections. Nothing has been streamed at this point, so a
+/// retry cannot duplicate output. Each retry is announced with a `retry`
+/// event and waits for `Retry-After` when the server sends one, else an
+/// exponential backoff.
+fn send_streaming(
+    args: &StreamArgs,
+    req: &StreamRequest,
+    metrics: &mut MetricTracker,
+    provider: &str,
+    build: impl Fn() -> ureq::RequestBuilder<ureq::typestate::WithBody>,
+    body: &Value,
+) -> Result<ureq::http::Response<ureq::Body>, String> {
+    let mut attempt = 0;
+    loop {
+        let (reason, delay) = match build().send_json(body) {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(mut response) => {
+                let status = response.status().as_u16();
+                let retry_after = response
+                    .headers()
+                    .get("retry-after")
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_owned);
+                let reason = http_error(provider, status, &mut response);
+                let delay = retry_delay(Some(status), retry_after.as_deref(), attempt);
+                (reason, delay)
+            }
+            Err(error) => {
+                let delay = is_transient(&error)
+                    .then(|| retry_delay(None, None, attempt))
+                    .flatten();
+                (error.to_string(), delay)
+            }
+        };
+        let Some(delay) = delay else {
+            return Err(reason);
+        };
+        attempt += 1;
+        metrics.retries += 1;
+        let event = json!({
+            "kind": "retry",
+            "model": req.model_id,
+            "attempt": attempt,
+            "delay_ms": u64::try_from(delay.as_millis()).unwrap_or(u64::MAX),
+            "reason": reason,
+        });
+        callback(args.cb, args.ctx, &must_json(&event), 2);
+        let deadline = Instant::now() + delay;
+        while Instant::now() < deadline {
+            if args.cancelled.load(Ordering::SeqCst) {
+                return Err(reason);
+            }
+            thread::sleep(RETRY_POLL.min(deadline - Instant::now()));
+        }
+    }
+}
+
+/// How long to wait before retry number `attempt + 1`, or `None` when the
+/// failure is permanent, retries are used up, or the server asks for a longer
+/// pause than is worth holding the turn for. `status` is `None` for a
+/// connection that dropped before a response.
+fn retry_delay(status: Option<u16>, retry_after: Option<&str>, attempt: u32) -> Option<Duration> {
+    if attempt >= MAX_RETRIES {
+        return None;
+    }
+    if let Some(status) = status {
+        if !matches!(status, 408 | 429 | 500 | 502 | 503 | 504 | 529) {
+            return None;
+        }
+    }
+    match retry_after.and_then(parse_retry_after) {
+        Some(wait) if wait > MAX_RETRY_WAIT => None,
+        Some(wait) => Some(wait),
+        None => Some(RETRY_BASE * 2u32.pow(attempt)),
+    }
+}
+
+/// Parses `Retry-After` as delay-seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = time::OffsetDateTime::parse(
+        &value.replace("GMT", "+0000"),
+        &time::format_description::well_known::Rfc2822,
+    )
+    .ok()?;
+    let wait = date - time::OffsetDateTime::now_utc();
+    Some(Duration::try_from(wait).unwrap_or(Duration::ZERO))
+}
+
+fn is_transient(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::Timeout(_) => true,
+        ureq::Error::Io(error) => matches!(
+            error.kind(),
+            std::io::ErrorKind::ConnectionReset
+                | std::io::ErrorKind::ConnectionAborted
+                | std::io::ErrorKind::BrokenPipe
+                | std::io::ErrorKind::UnexpectedEof
+                | std::io::ErrorKind::TimedOut
+        ),
+        _ => false,
+    }
+}
+
 fn http_error(
     provider: &str,
     status: u16,
@@ -698,6 +2143,142 @@ fn data_uri_parts(url: &str) -> Option<(String, String)> {
 mod tests {
     use super::*;
 
+    unsafe extern "C" fn collect_events(
+        ctx: *mut std::ffi::c_void,
+        token: *const std::os::raw::c_char,
+        done: std::os::raw::c_int,
+    ) {
+        let events = unsafe { &mut *ctx.cast::<Vec<(String, i32)>>() };
+        let text = unsafe { std::ffi::CStr::from_ptr(token) }.to_string_lossy();
+        events.push((text.into_owned(), done));
+    }
+
+    #[test]
+    fn live_metrics_are_published_and_kept_per_session() {
+        let mut events: Vec<(String, i32)> = Vec::new();
+        let args = StreamArgs {
+            ctx: &raw mut events as usize,
+            cb: collect_events,
+            id: 9_001,
+            ..StreamArgs::default()
+        };
+        let mut metrics = MetricTracker {
+            model: "openai/gpt-5.5".to_owned(),
+            live: true,
+            ..MetricTracker::new()
+        };
+        forward_token(&args, &mut metrics, "Hello there");
+        forward_token(&args, &mut metrics, "!");
+        assert_eq!(
+            events.iter().map(|(_, done)| *done).collect::<Vec<_>>(),
+            [0, 2, 0],
+            "one live event per interval"
+        );
+        let event: Value = serde_json::from_str(&events[1].0).expect("metrics event");
+        assert_eq!(event["kind"], "metrics");
+        assert_eq!(event["session_id"], 9_001);
+        assert_eq!(event["model"], "openai/gpt-5.5");
+        assert_eq!(event["output_tokens"], 3);
+        assert!(event["ttf_ms"].as_f64().expect("ttft") >= 0.0);
+
+        let kept = |id| {
+            crate::ai::session_metrics()
+                .lock()
+                .expect("metrics mutex")
+                .get(&id)
+                .cloned()
+        };
+        assert!(!kept(9_001).expect("live metrics").finished);
+        assert!(kept(9_002).is_none());
+        let result = StreamResult {
+            prompt_tokens: 10,
+            output_tokens: 4,
+        };
+        store_metrics(
+            9_001,
+            metrics_snapshot("openai/gpt-5.5", &metrics, &result, true, ""),
+        );
+        let done = kept(9_001).expect("final metrics");
+        assert!(done.finished);
+        assert_eq!(done.output_tokens, 4);
+
+        // One-shot calls (titles, summaries) never publish live metrics.
+        let mut quiet = MetricTracker::new();
+        forward_token(&args, &mut quiet, "hi");
+        assert_eq!(events.last().map(|(_, done)| *done), Some(0));
+    }
+
+    #[test]
+    fn reasoning_streams_as_its_own_event_kind() {
+        let mut events: Vec<(String, i32)> = Vec::new();
+        let args = StreamArgs {
+            ctx: &raw mut events as usize,
+            cb: collect_events,
+            id: 7,
+            ..StreamArgs::default()
+        };
+        let req = StreamRequest::default();
+        let mut metrics = MetricTracker::new();
+        let mut outcome = RoundOutcome::default();
+        for event in [
+            json!({ "type": "response.reasoning_summary_part.added", "summary_index": 0 }),
+            json!({ "type": "response.reasoning_summary_text.delta", "delta": "Checking" }),
+            json!({ "type": "response.reasoning_summary_part.added", "summary_index": 1 }),
+            json!({ "type": "response.output_text.delta", "delta": "Done." }),
+        ] {
+            openai_event(&event, &args, &req, &mut metrics, &mut outcome);
+        }
+        let mut text = String::new();
+        gemini_event(
+            &json!({ "candidates": [{ "content": { "parts": [
+                { "text": "Planning", "thought": true },
+                { "text": "Hi" },
+            ] } }] }),
+            &args,
+            &mut metrics,
+            &mut text,
+            &mut Vec::new(),
+            &mut None,
+            &mut outcome,
+        );
+        assert_eq!(text, "Hi", "thoughts stay out of the reply");
+
+        let reasoning = |(event, done): &(String, i32)| {
+            let event: Value = serde_json::from_str(event).ok().filter(|_| *done == 2)?;
+            assert_eq!(event["kind"], "reasoning_delta");
+            assert_eq!(event["session_id"], 7);
+            event["delta"].as_str().map(str::to_owned)
+        };
+        assert_eq!(
+            events.iter().filter_map(reasoning).collect::<Vec<_>>(),
+            ["Checking", "\n\n", "Planning"]
+        );
+        assert_eq!(
+            events
+                .iter()
+                .filter(|(_, done)| *done == 0)
+                .map(|(token, _)| token.as_str())
+                .collect::<Vec<_>>(),
+            ["Done.", "Hi"]
+        );
+    }
+
+    #[test]
+    fn reasoning_alone_keeps_a_turn_from_falling_back() {
+        let args = StreamArgs::default();
+        let mut metrics = MetricTracker::new();
+        let event = json!({ "type": "response.reasoning_summary_text.delta", "delta": "Hmm" });
+        openai_event(
+            &event,
+            &args,
+            &StreamRequest::default(),
+            &mut metrics,
+            &mut RoundOutcome::default(),
+        );
+        assert_eq!(metrics.chunk_count, 0);
+        assert!(metrics.shown);
+    }
+
     #[test]
     fn sanitize_drops_reasoning_and_namespace() {
         assert!(sanitize_input_item(&json!({ "type": "reasoning", "id": "rs_1" })).is_none());
@@ -733,6 +2314,304 @@ mod tests {
         assert_eq!(response["response"]["temp"], 21);
     }
 
+    #[test]
+    fn anthropic_messages_round_trip_tool_use_and_images() {
+        let input = vec![
+            json!({ "type": "message", "role": "user", "content": [
+                { "type": "input_text", "text": "what is this?" },
+                { "type": "input_image", "image_url": "data:image/png;base64,AAAB" },
+            ] }),
+            json!({ "type": "message", "role": "assistant", "content": [{ "type": "output_text", "text": "Checking." }] }),
+            json!({ "type": "function_call", "call_id": "toolu_1", "name": "weather", "arguments": "{\"city\":\"NYC\"}" }),
+            json!({ "type": "function_call_output", "call_id": "toolu_1", "output": "{\"temp\":21}" }),
+            json!({ "type": "reasoning", "id": "rs_1" }),
+        ];
+        let messages = anthropic_messages(&input);
+        assert_eq!(messages.len(), 3);
+        assert_eq!(
+            messages[0]["content"][1]["source"]["media_type"],
+            "image/png"
+        );
+        assert_eq!(messages[0]["content"][1]["source"]["data"], "AAAB");
+        // The assistant text and tool_use merge into one assistant turn.
+        assert_eq!(messages[1]["role"], "assistant");
+        assert_eq!(messages[1]["content"][1]["type"], "tool_use");
+        assert_eq!(messages[1]["content"][1]["input"]["city"], "NYC");
+        assert_eq!(messages[2]["content"][0]["type"], "tool_result");
+        assert_eq!(messages[2]["content"][0]["tool_use_id"], "toolu_1");
+    }
+
+    #[test]
+    fn anthropic_finish_builds_message_and_function_call_items() {
+        let blocks = vec![
+            AnthropicBlock {
+                kind: "text".to_owned(),
+                text: "Let me look.".to_owned(),
+                ..AnthropicBlock::default()
+            },
+            AnthropicBlock {
+                kind: "tool_use".to_owned(),
+                id: "toolu_9".to_owned(),
+                name: "weather".to_owned(),
+                partial_json: "{\"city\":\"Oslo\"}".to_owned(),
+                ..AnthropicBlock::default()
+            },
+        ];
+        let mut outcome = RoundOutcome::default();
+        anthropic_finish(blocks, &StreamRequest::default(), &mut outcome);
+        assert_eq!(outcome.model_items[0]["content"][0]["text"], "Let me look.");
+        assert_eq!(outcome.model_items[1]["call_id"], "toolu_9");
+        assert_eq!(outcome.tool_calls[0].id, "toolu_9");
+        assert_eq!(outcome.tool_calls[0].arguments["city"], "Oslo");
+    }
+
+    #[test]
+    fn anthropic_thinking_is_replayed_ahead_of_its_tool_use() {
+        let blocks = vec![
+            AnthropicBlock {
+                kind: "thinking".to_owned(),
+                text: "Need the weather.".to_owned(),
+                signature: "sig-1".to_owned(),
+                ..AnthropicBlock::default()
+            },
+            AnthropicBlock {
+                kind: "tool_use".to_owned(),
+                id: "toolu_9".to_owned(),
+                name: "weather".to_owned(),
+                ..AnthropicBlock::default()
+            },
+        ];
+        let mut outcome = RoundOutcome::default();
+        anthropic_finish(blocks, &StreamRequest::default(), &mut outcome);
+        let mut input = vec![json!({ "type": "message", "role": "user", "content": [
+            { "type": "input_text", "text": "weather?" },
+        ] })];
+        input.extend(outcome.model_items);
+        input.push(json!({ "type": "reasoning", "id": "rs_openai", "summary": [] }));
+        let messages = anthropic_messages(&input);
+        assert_eq!(
+            messages[1]["content"],
+            json!([
+                { "type": "thinking", "thinking": "Need the weather.", "signature": "sig-1" },
+                { "type": "tool_use", "id": "toolu_9", "name": "weather", "input": {} },
+            ])
+        );
+    }
+
+    #[test]
+    fn chat_messages_group_parallel_calls_under_one_assistant_turn() {
+        let req = StreamRequest {
+            system_prompt: "Be brief.".to_owned(),
+            ..StreamRequest::default()
+        };
+        let input = vec![
+            json!({ "type": "message", "role": "user", "content": [
+                { "type": "input_text", "text": "weather?" },
+                { "type": "input_image", "image_url": "data:image/png;base64,AAAB" },
+            ] }),
+            json!({ "type": "function_call", "call_id": "call_a", "name": "weather", "arguments": "{\"city\":\"NYC\"}" }),
+            json!({ "type": "function_call", "call_id": "call_b", "name": "weather", "arguments": "{\"city\":\"Oslo\"}" }),
+            json!({ "type": "function_call_output", "call_id": "call_a", "output": "21" }),
+            json!({ "type": "reasoning", "id": "rs_1" }),
+        ];
+        let messages = chat_messages(&req, &input);
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(
+            messages[1]["content"][1]["image_url"]["url"],
+            "data:image/png;base64,AAAB"
+        );
+        assert_eq!(messages[2]["tool_calls"].as_array().map(Vec::len), Some(2));
+        assert_eq!(messages[2]["tool_calls"][1]["function"]["name"], "weather");
+        assert_eq!(messages[3]["role"], "tool");
+        assert_eq!(messages[3]["tool_call_id"], "call_a");
+    }
+
+    #[test]
+    fn chat_tool_call_fragments_reassemble_by_index() {
+        let chunks = [
+            json!({ "choices": [{ "delta": { "content": "On it." } }] }),
+            json!({ "choices": [{ "delta": { "tool_calls": [
+                { "index": 0, "id": "call_1", "type": "function", "function": { "name": "weather", "arguments": "" } },
+            ] } }] }),
+            json!({ "choices": [{ "delta": { "tool_calls": [
+                { "index": 1, "id": "call_2", "function": { "name": "time", "arguments": "{}" } },
+                { "index": 0, "function": { "arguments": "{\"city\":" } },
+            ] } }] }),
+            json!({ "choices": [{ "delta": { "tool_calls": [
+                { "index": 0, "function": { "arguments": "\"Oslo\"}" } },
+            ] } }] }),
+            json!({ "choices": [], "usage": { "prompt_tokens": 12, "completion_tokens": 5 } }),
+        ];
+        let args = StreamArgs::default();
+        let mut metrics = MetricTracker::new();
+        let mut text = String::new();
+        let mut calls = Vec::new();
+        let mut outcome = RoundOutcome::default();
+        for chunk in &chunks {
+            assert!(chat_event(
+                chunk,
+                &args,
+                &mut metrics,
+                &mut text,
+                &mut calls,
+                &mut outcome
+            )
+            .is_none());
+        }
+        chat_finish(&text, calls, &StreamRequest::default(), &mut outcome);
+        assert_eq!(outcome.prompt_tokens, 12);
+        assert_eq!(outcome.output_tokens, 5);
+        assert_eq!(outcome.model_items[0]["content"][0]["text"], "On it.");
+        assert_eq!(outcome.tool_calls.len(), 2);
+        assert_eq!(outcome.tool_calls[0].id, "call_1");
+        assert_eq!(outcome.tool_calls[0].arguments["city"], "Oslo");
+        assert_eq!(outcome.tool_calls[1].name, "time");
+    }
+
+    #[test]
+    fn ollama_stream_collects_text_calls_and_usage() {
+        let stream = concat!(
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"Let me \"},\"done\":false}\n",
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"check.\",\"tool_calls\":",
+            "[{\"function\":{\"name\":\"weather\",\"arguments\":{\"city\":\"Oslo\"}}}]},\"done\":false}\n",
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,",
+            "\"prompt_eval_count\":30,\"eval_count\":7}\n",
+        );
+        let args = StreamArgs::default();
+        let req = StreamRequest::default();
+        let mut metrics = MetricTracker::new();
+        let mut text = String::new();
+        let mut outcome = RoundOutcome::default();
+        read_ndjson(stream.as_bytes(), &args.cancelled, |chunk| {
+            let state = (&mut text, 2);
+            assert!(ollama_chunk(&chunk, &args, &req, &mut metrics, state, &mut outcome).is_none());
+        })
+        .expect("read");
+        assert_eq!(text, "Let me check.");
+        assert_eq!(outcome.prompt_tokens, 30);
+        assert_eq!(outcome.output_tokens, 7);
+        assert_eq!(outcome.tool_calls[0].id, "call_2_weather");
+        assert_eq!(outcome.tool_calls[0].arguments["city"], "Oslo");
+
+        // Replaying the round names the tool result and inlines image data.
+        let input = vec![
+            json!({ "type": "message", "role": "user", "content": [
+                { "type": "input_text", "text": "weather?" },
+                { "type": "input_image", "image_url": "data:image/png;base64,AAAB" },
+            ] }),
+            outcome.model_items[0].clone(),
+            json!({ "type": "function_call_output", "call_id": "call_2_weather", "output": "21" }),
+        ];
+        let messages = ollama_messages(&req, &input);
+        assert_eq!(messages[0]["images"][0], "AAAB");
+        assert_eq!(
+            messages[1]["tool_calls"][0]["function"]["arguments"]["city"],
+            "Oslo"
+        );
+        assert_eq!(messages[2]["tool_name"], "weather");
+    }
+
+    #[test]
+    fn run_tools_keeps_call_order_across_parallel_batches() {
+        let call = |id: &str, read_only: bool| ToolCall {
+            id: id.to_owned(),
+            name: format!("lookup_{id}"),
+            server_id: "offline".to_owned(),
+            read_only,
+            risk: if read_only { "read" } else { "write" }.to_owned(),
+            ..ToolCall::default()
+        };
+        let calls = [
+            call("a", true),
+            call("b", true),
+            call("c", false),
+            call("d", true),
+            call("e", true),
+        ];
+        let req = StreamRequest {
+            max_parallel_tools: 2,
+            ..StreamRequest::default()
+        };
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let dispatch = |call: &ToolCall| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            // `a` finishes after `b`, so the order must come from the call list.
+            let delay = if call.id == "a" { 60 } else { 20 };
+            thread::sleep(Duration::from_millis(delay));
+            running.fetch_sub(1, Ordering::SeqCst);
+            crate::mcp::ToolResult {
+                tool_call_id: call.id.clone(),
+                text: call.name.clone(),
+                ..crate::mcp::ToolResult::default()
+            }
+        };
+        let results = run_tools_with(&StreamArgs::default(), &req, &calls, &dispatch);
+        let ids = results
+            .iter()
+            .map(|result| result.as_ref().map(|result| result.tool_call_id.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [Some("a"), Some("b"), Some("c"), Some("d"), Some("e")]);
+        assert!(results.iter().flatten().all(|result| !result.is_error));
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn rounds_without_a_usage_report_are_billed_by_estimate() {
+        let args = StreamArgs::default();
+        let input = [json!({ "type": "message", "role": "user", "content": "x".repeat(40) })];
+        let mut metrics = MetricTracker::new();
+        metrics.observe_token(&"y".repeat(20));
+        let reported = Ok(RoundOutcome {
+            prompt_tokens: 12,
+            output_tokens: 3,
+            ..RoundOutcome::default()
+        });
+        assert_eq!(round_usage(&args, &input, &metrics, 0, &reported), (12, 3));
+
+        // A stream that broke off was still billed; a refused request was not.
+        let failed = Err("connection reset".to_owned());
+        let estimate = round_usage(&args, &input, &metrics, 0, &failed);
+        assert_eq!(estimate.1, 5);
+        assert!(estimate.0 >= 10);
+        assert_eq!(round_usage(&args, &input, &metrics, 20, &failed), (0, 0));
+
+        let unreported = Ok(RoundOutcome::default());
+        assert_eq!(round_usage(&args, &input, &metrics, 0, &unreported), (0, 0));
+        args.cancelled.store(true, Ordering::SeqCst);
+        assert_eq!(
+            round_usage(&args, &input, &metrics, 0, &unreported),
+            estimate
+        );
+    }
+
+    #[test]
+    fn unverified_organizations_are_retried_without_reasoning_summaries() {
+        assert!(refuses_summaries(
+            "openai HTTP 400: {\"error\":{\"message\":\"Your organization must be verified to \
+             generate reasoning summaries.\",\"param\":\"reasoning.summary\"}}"
+        ));
+        assert!(!refuses_summaries("openai HTTP 400: invalid model"));
+        assert!(!refuses_summaries(
+            "openai HTTP 500: reasoning summaries unavailable"
+        ));
+    }
+
+    #[test]
+    fn gemini_thoughts_are_only_requested_from_thinking_models() {
+        let thinks = |raw: &str| {
+            gemini_thinks(&StreamRequest {
+                raw_model_id: raw.to_owned(),
+                ..StreamRequest::default()
+            })
+        };
+        assert!(thinks("gemini-2.5-flash"));
+        assert!(!thinks("gemini-2.0-flash"));
+        assert!(!thinks("gemini-2.5-flash-image"));
+    }
+
     #[test]
     fn data_uri_parts_splits_mime_and_payload() {
         let (mime, data) = data_uri_parts("data:image/png;base64,AAAB").expect("parsed");
@@ -740,6 +2619,26 @@ mod tests {
         assert_eq!(data, "AAAB");
     }
 
+    #[test]
+    fn retry_delay_honors_retry_after_and_backs_off() {
+        assert_eq!(
+            retry_delay(Some(429), Some("2"), 0),
+            Some(Duration::from_secs(2))
+        );
+        assert_eq!(
+            retry_delay(Some(503), None, 2),
+            Some(Duration::from_secs(4))
+        );
+        assert_eq!(retry_delay(None, None, 1), Some(Duration::from_secs(2)));
+        assert_eq!(retry_delay(Some(429), None, MAX_RETRIES), None);
+        assert_eq!(retry_delay(Some(400), None, 0), None);
+        assert_eq!(retry_delay(Some(429), Some("120"), 0), None);
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(Duration::ZERO)
+        );
+    }
+
     // End-to-end streaming against the local proxy's native Gemini endpoint.
     // Ignored by default; run with `--ignored` when the local server is up:
     //   cargo test ... -p qsnative_rust --release ai::stream::tests::live -- --ignored --nocapture
@@ -748,8 +2647,6 @@ mod tests {
     fn live_local_gemini_streams() {
         use std::ffi::{c_void, CStr};
         use std::os::raw::{c_char, c_int};
-        use std::sync::atomic::AtomicBool;
-        use std::sync::Arc;
 
         unsafe extern "C" fn collect(ctx: *mut c_void, token: *const c_char, done: c_int) {
             let events = unsafe { &mut *ctx.cast::<Vec<(String, i32)>>() };
@@ -767,16 +2664,10 @@ mod tests {
         let mut events: Vec<(String, i32)> = Vec::new();
         let args = crate::ai::StreamArgs {
             model_id: "local/gemini-pro-latest".to_owned(),
-            provider_config: std::collections::HashMap::new(),
-            system_prompt: String::new(),
-            conversation_id: String::new(),
             message: prompt.to_owned(),
-            attachments_json: String::new(),
-            disabled_tool_servers_json: String::new(),
             ctx: &raw mut events as usize,
             cb: collect,
-            cancelled: Arc::new(AtomicBool::new(false)),
-            id: 0,
+            ..crate::ai::StreamArgs::default()
         };
         let req = crate::ai::StreamRequest {
             model_id: "local/gemini-pro-latest".to_owned(),
@@ -0,0 +1,76 @@
+//! System-prompt templating.
+//!
+//! `{{name}}` placeholders in the system prompt are expanded when a turn
+//! starts: `date`, `time`, `hostname`, `user` and `cwd` come from the
+//! process, `todoist.today` and `calendar.today` from the Todoist and
+//! calendar caches, and anything else from the UI-supplied variables (such
+//! as `focused_window`). Unknown placeholders are left as written.
+
+use std::collections::HashMap;
+
+use chrono::Local;
+
+/// Expands the placeholders of `template`. Each variable is looked up once,
+/// and only when the template uses it.
+pub(super) fn expand(template: &str, vars: &HashMap<String, String>) -> String {
+    let mut resolved: HashMap<&str, Option<String>> = HashMap::new();
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let placeholder = &rest[start..start + 2 + len + 2];
+        let name = placeholder[2..placeholder.len() - 2].trim();
+        out.push_str(&rest[..start]);
+        let value = resolved.entry(name).or_insert_with(|| resolve(name, vars));
+        out.push_str(value.as_deref().unwrap_or(placeholder));
+        rest = &rest[start + placeholder.len()..];
+    }
+    out.push_str(rest);
+    out
+}
+
+fn resolve(name: &str, vars: &HashMap<String, String>) -> Option<String> {
+    match name {
+        "date" => Some(Local::now().format("%A, %-d %B %Y").to_string()),
+        "time" => Some(Local::now().format("%H:%M (UTC%:z)").to_string()),
+        "hostname" => std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .ok()
+            .map(|name| name.trim().to_owned()),
+        "user" => std::env::var("USER")
+            .or_else(|_| std::env::var("LOGNAME"))
+            .ok(),
+        "cwd" => std::env::current_dir()
+            .ok()
+            .map(|dir| dir.display().to_string()),
+        "todoist.today" => Some(
+            crate::todoist::today_digest()
+                .unwrap_or_else(|| "Todoist tasks are unavailable.".to_owned()),
+        ),
+        "calendar.today" => Some(
+            crate::ical::today_digest()
+                .unwrap_or_else(|| "Calendar events are unavailable.".to_owned()),
+        ),
+        other => vars.get(other).cloned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_expand_and_unknown_ones_stay() {
+        let vars = HashMap::from([("focused_window".to_owned(), "kitty — nvim".to_owned())]);
+        assert_eq!(
+            expand(
+                "Focused: {{ focused_window }}; {{nope}}; {{focused_window}} {{unclosed",
+                &vars
+            ),
+            "Focused: kitty — nvim; {{nope}}; kitty — nvim {{unclosed"
+        );
+        assert_eq!(expand("no placeholders", &vars), "no placeholders");
+        assert!(!expand("Today is {{date}}, {{time}}.", &vars).contains("{{"));
+    }
+}
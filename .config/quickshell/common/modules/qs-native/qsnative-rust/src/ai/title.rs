@@ -0,0 +1,119 @@
+//! Automatic conversation titles.
+//!
+//! After a turn finishes, a detached job asks the `[model] title` model (the
+//! chat model when unset) to name a still-untitled conversation. The title is
+//! only written while the conversation is untitled, so a manual rename made
+//! in the meantime wins.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::thread;
+
+use super::{split_model_id, stream, StreamArgs, StreamRequest};
+
+const TITLE_PROMPT: &str = "Name this conversation in at most six words. Reply with the \
+title only, without quotes or trailing punctuation.";
+/// How much of the first exchange the title model sees, per side.
+const EXCERPT_CHARS: usize = 1_500;
+const MAX_TITLE_CHARS: usize = 60;
+
+pub(super) fn spawn(args: &StreamArgs, req: &StreamRequest, reply: &str) {
+    let conversation_id = req.conversation_id.trim().to_owned();
+    if conversation_id.is_empty() || reply.trim().is_empty() {
+        return;
+    }
+    let exchange = format!(
+        "User: {}\n\nAssistant: {}",
+        excerpt(&req.message),
+        excerpt(reply)
+    );
+    // The title outlives the stream, so it must not observe its cancellation.
+    let args = StreamArgs {
+        cancelled: Arc::new(AtomicBool::new(false)),
+        ..args.clone()
+    };
+    let chat_model_id = req.model_id.clone();
+    thread::spawn(move || {
+        if !crate::chatstore::is_untitled(&conversation_id).unwrap_or(false) {
+            return;
+        }
+        let model_id = crate::config_resolver::title_model().unwrap_or(chat_model_id);
+        let Some(req) = title_request(&args, &model_id) else {
+            return;
+        };
+        let Some(title) = stream::complete(&args, &req, TITLE_PROMPT, &exchange)
+            .as_deref()
+            .and_then(clean_title)
+        else {
+            return;
+        };
+        let _ = crate::chatstore::fill_title(&conversation_id, &title);
+    });
+}
+
+fn title_request(args: &StreamArgs, model_id: &str) -> Option<StreamRequest> {
+    let (provider, raw_model_id) = split_model_id(model_id).ok()?;
+    Some(StreamRequest {
+        model_id: model_id.to_owned(),
+        raw_model_id,
+        config: args
+            .provider_config
+            .get(&provider)
+            .cloned()
+            .unwrap_or_default(),
+        provider,
+        ..StreamRequest::default()
+    })
+}
+
+fn excerpt(text: &str) -> String {
+    text.trim().chars().take(EXCERPT_CHARS).collect()
+}
+
+/// Reduces a model reply to a one-line title, dropping the decoration small
+/// models like to add (a `Title:` label, quotes, markdown, a final period).
+fn clean_title(reply: &str) -> Option<String> {
+    let line = reply.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = match line.split_once(':') {
+        Some((label, rest))
+            if label
+                .trim_matches(['*', '#', ' '])
+                .eq_ignore_ascii_case("title") =>
+        {
+            rest
+        }
+        _ => line,
+    };
+    let title = line
+        .trim_matches(|c: char| {
+            c.is_whitespace() || matches!(c, '"' | '\'' | '`' | '*' | '#' | '“' | '”')
+        })
+        .trim_end_matches(['.', '!', ':'])
+        .trim();
+    if title.is_empty() {
+        return None;
+    }
+    Some(title.chars().take(MAX_TITLE_CHARS).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn model_replies_are_reduced_to_a_plain_title() {
+        assert_eq!(
+            clean_title("\n**Title:** \"Fixing the Wi-Fi applet.\"\n\nSome notes"),
+            Some("Fixing the Wi-Fi applet".to_owned())
+        );
+        assert_eq!(
+            clean_title("# Rust lifetimes explained"),
+            Some("Rust lifetimes explained".to_owned())
+        );
+        assert_eq!(clean_title("  \"\"  "), None);
+        assert_eq!(
+            clean_title(&"x".repeat(200)).map(|title| title.len()),
+            Some(MAX_TITLE_CHARS)
+        );
+    }
+}
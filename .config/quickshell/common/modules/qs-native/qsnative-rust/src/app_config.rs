@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -17,12 +18,108 @@ pub struct CalendarSource {
     pub calendar_ids: Vec<String>,
 }
 
+/// An external Model Context Protocol server declared under `[[mcp.servers]]`.
+///
+/// Exactly one of `command` (stdio transport) or `url` (Streamable HTTP
+/// transport) is set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct McpServerConfig {
+    pub id: String,
+    pub label: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    pub url: String,
+    pub headers: BTreeMap<String, String>,
+    /// Secret Service key holding a bearer token for `url`.
+    pub bearer_token_secret: String,
+    pub enabled: bool,
+    pub timeout_ms: u64,
+}
+
+/// How a model-requested tool call is authorized before it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalMode {
+    /// Run without asking.
+    Auto,
+    /// Pause the stream until the user allows or denies the call.
+    Ask,
+    /// Refuse the call; the model sees a tool error.
+    Deny,
+}
+
+/// Tool approval policy from `[tools.approval]`: one mode per risk level
+/// (`read`, `write`, `destructive`) plus per-tool overrides keyed by the tool
+/// name the model sees or its `server__tool` qualified name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolApprovalPolicy {
+    pub read: ApprovalMode,
+    pub write: ApprovalMode,
+    pub destructive: ApprovalMode,
+    pub tools: BTreeMap<String, ApprovalMode>,
+}
+
+impl Default for ToolApprovalPolicy {
+    fn default() -> Self {
+        Self {
+            read: ApprovalMode::Auto,
+            write: ApprovalMode::Auto,
+            destructive: ApprovalMode::Ask,
+            tools: BTreeMap::new(),
+        }
+    }
+}
+
+impl ToolApprovalPolicy {
+    /// Resolves the mode for a call; unknown risk levels are treated as `write`.
+    #[must_use]
+    pub fn mode_for(&self, server_id: &str, tool_name: &str, risk: &str) -> ApprovalMode {
+        let qualified = format!("{}__{}", server_id.trim(), tool_name.trim());
+        if let Some(mode) = self
+            .tools
+            .get(tool_name.trim())
+            .or_else(|| self.tools.get(&qualified))
+        {
+            return *mode;
+        }
+        match risk.trim() {
+            "read" => self.read,
+            "destructive" => self.destructive,
+            _ => self.write,
+        }
+    }
+}
+
+/// Settings from the `[tools]` section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ToolSettings {
+    pub approval: ToolApprovalPolicy,
+    /// How many independent (read-only or idempotent) calls from one model
+    /// round may run at once.
+    pub max_parallel: usize,
+}
+
+impl Default for ToolSettings {
+    fn default() -> Self {
+        Self {
+            approval: ToolApprovalPolicy::default(),
+            max_parallel: DEFAULT_MAX_PARALLEL_TOOLS,
+        }
+    }
+}
+
 #[derive(Debug, Default, Deserialize)]
 struct Config {
     #[serde(default)]
     email: EmailSection,
     #[serde(default)]
     calendar: CalendarSection,
+    #[serde(default)]
+    mcp: McpSection,
+    #[serde(default)]
+    tools: ToolsSection,
+    #[serde(default)]
+    schedule: ScheduleSection,
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -57,6 +154,87 @@ struct RawCalendarAccount {
     calendar_ids: Vec<String>,
 }
 
+#[derive(Debug, Default, Deserialize)]
+struct McpSection {
+    #[serde(default)]
+    servers: Vec<RawMcpServer>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RawMcpServer {
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    label: String,
+    #[serde(default)]
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: BTreeMap<String, String>,
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    bearer_token_secret: String,
+    #[serde(default)]
+    enabled: Option<bool>,
+    #[serde(default)]
+    timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ToolsSection {
+    #[serde(default)]
+    approval: RawApproval,
+    #[serde(default)]
+    max_parallel: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RawApproval {
+    #[serde(default)]
+    read: Option<String>,
+    #[serde(default)]
+    write: Option<String>,
+    #[serde(default)]
+    destructive: Option<String>,
+    #[serde(default)]
+    tools: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ScheduleSection {
+    #[serde(default)]
+    jobs: Vec<RawScheduledJob>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RawScheduledJob {
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    label: String,
+    #[serde(default)]
+    prompt: String,
+    #[serde(default)]
+    at: String,
+    #[serde(default)]
+    days: Vec<String>,
+    #[serde(default)]
+    model: String,
+    #[serde(default)]
+    system_prompt: String,
+    #[serde(default)]
+    enabled: Option<bool>,
+}
+
+const DEFAULT_MCP_TIMEOUT_MS: u64 = 30_000;
+pub(crate) const DEFAULT_MAX_PARALLEL_TOOLS: usize = 4;
+const RESERVED_MCP_SERVER_IDS: [&str; 4] =
+    ["builtin", "email", "image_generation", "provider_search"];
+
 /// Returns the path to `leftpanel/config.toml`, searching from environment
 /// variables and the current directory upwards.
 #[must_use]
@@ -237,6 +415,208 @@ pub fn load_all_accounts(path: &Path) -> Result<Vec<EmailAccount>, String> {
         .collect()
 }
 
+/// Loads every `[[mcp.servers]]` entry, including disabled ones so the UI can
+/// list them.
+///
+/// Server ids become the tool-name prefix (`<id>__<tool>`) sent to providers,
+/// so they are restricted to ASCII alphanumerics, `-` and `_`, must not
+/// contain the `__` separator, must be unique, and must not shadow a built-in
+/// server.
+///
+/// # Errors
+/// Returns `Err` if the config cannot be read/parsed, or a server is missing
+/// its id, sets neither or both of `command` and `url`, has a non-HTTP url,
+/// has an invalid or duplicate id, or uses a reserved id.
+pub fn load_mcp_servers(path: &Path) -> Result<Vec<McpServerConfig>, String> {
+    let config = load_config(path)?;
+    let mut out: Vec<McpServerConfig> = Vec::with_capacity(config.mcp.servers.len());
+    for raw in config.mcp.servers {
+        let id = crate::utils::non_empty_trimmed(&raw.id)
+            .ok_or_else(|| "mcp server has no id".to_owned())?;
+        if !id
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+        {
+            return Err(format!(
+                "mcp server id {id:?} may only contain ASCII letters, digits, '-' and '_'"
+            ));
+        }
+        if id.contains("__") {
+            return Err(format!(
+                "mcp server id {id:?} may not contain '__', which separates it from tool names"
+            ));
+        }
+        if RESERVED_MCP_SERVER_IDS.contains(&id.as_str()) {
+            return Err(format!("mcp server id {id:?} is reserved"));
+        }
+        if out.iter().any(|server| server.id == id) {
+            return Err(format!("duplicate mcp server id {id:?}"));
+        }
+        let command = raw.command.trim().to_owned();
+        let url = raw.url.trim().to_owned();
+        match (command.is_empty(), url.is_empty()) {
+            (true, true) => return Err(format!("mcp server {id} needs a command or a url")),
+            (false, false) => {
+                return Err(format!("mcp server {id} sets both command and url"));
+            }
+            _ => {}
+        }
+        if !(url.is_empty() || url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(format!("mcp server {id} url must be http:// or https://"));
+        }
+        out.push(McpServerConfig {
+            label: crate::utils::first_non_empty([&raw.label, &id]),
+            id,
+            command,
+            args: raw.args,
+            env: raw.env,
+            url,
+            headers: raw.headers,
+            bearer_token_secret: raw.bearer_token_secret.trim().to_owned(),
+            enabled: raw.enabled.unwrap_or(true),
+            timeout_ms: raw
+                .timeout_ms
+                .filter(|ms| *ms > 0)
+                .unwrap_or(DEFAULT_MCP_TIMEOUT_MS),
+        });
+    }
+    Ok(out)
+}
+
+/// Loads the `[tools]` section. The approval policy falls back to `ask` for
+/// destructive tools and `auto` otherwise; `max_parallel` defaults to
+/// [`DEFAULT_MAX_PARALLEL_TOOLS`].
+///
+/// # Errors
+/// Returns `Err` if the config cannot be read/parsed, an approval mode is not
+/// one of `auto`, `ask` or `deny`, or `max_parallel` is zero.
+pub fn load_tool_settings(path: &Path) -> Result<ToolSettings, String> {
+    let raw = load_config(path)?.tools;
+    let max_parallel = match raw.max_parallel {
+        Some(0) => return Err("tools.max_parallel must be at least 1".to_owned()),
+        Some(limit) => limit,
+        None => DEFAULT_MAX_PARALLEL_TOOLS,
+    };
+    Ok(ToolSettings {
+        approval: approval_policy(&raw.approval)?,
+        max_parallel,
+    })
+}
+
+fn approval_policy(raw: &RawApproval) -> Result<ToolApprovalPolicy, String> {
+    let defaults = ToolApprovalPolicy::default();
+    let mode = |value: Option<&String>, fallback: ApprovalMode, key: &str| match value
+        .map(|value| value.trim().to_ascii_lowercase())
+        .as_deref()
+    {
+        None => Ok(fallback),
+        Some("auto") => Ok(ApprovalMode::Auto),
+        Some("ask") => Ok(ApprovalMode::Ask),
+        Some("deny") => Ok(ApprovalMode::Deny),
+        Some(other) => Err(format!(
+            "tools.approval.{key} must be auto, ask or deny, not {other:?}"
+        )),
+    };
+    let mut tools = BTreeMap::new();
+    for (name, value) in &raw.tools {
+        let name = name.trim();
+        let parsed = mode(Some(value), ApprovalMode::Auto, &format!("tools.{name}"))?;
+        tools.insert(name.to_owned(), parsed);
+    }
+    Ok(ToolApprovalPolicy {
+        read: mode(raw.read.as_ref(), defaults.read, "read")?,
+        write: mode(raw.write.as_ref(), defaults.write, "write")?,
+        destructive: mode(
+            raw.destructive.as_ref(),
+            defaults.destructive,
+            "destructive",
+        )?,
+        tools,
+    })
+}
+
+/// A recurring prompt declared under `[[schedule.jobs]]`, run in the
+/// background at `hour:minute` local time on each of `days`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledJob {
+    pub id: String,
+    pub label: String,
+    pub prompt: String,
+    pub hour: u32,
+    pub minute: u32,
+    /// Never empty; `days` left unset means every day.
+    pub days: Vec<chrono::Weekday>,
+    /// Canonical model id; empty means the `[model] default`.
+    pub model: String,
+    pub system_prompt: String,
+}
+
+/// Loads the enabled `[[schedule.jobs]]`. `at` is a local `HH:MM` time and
+/// `days` a list of `mon`..`sun`, `weekdays`, `weekends` or `daily`.
+///
+/// # Errors
+/// Returns `Err` if the config cannot be read/parsed, or a job is missing its
+/// id or prompt, has a duplicate id, or has a malformed `at` or day name.
+pub fn load_scheduled_jobs(path: &Path) -> Result<Vec<ScheduledJob>, String> {
+    let config = load_config(path)?;
+    let mut out: Vec<ScheduledJob> = Vec::with_capacity(config.schedule.jobs.len());
+    let mut ids = Vec::with_capacity(config.schedule.jobs.len());
+    for raw in config.schedule.jobs {
+        let id = crate::utils::non_empty_trimmed(&raw.id)
+            .ok_or_else(|| "scheduled job has no id".to_owned())?;
+        if ids.contains(&id) {
+            return Err(format!("duplicate scheduled job id {id:?}"));
+        }
+        ids.push(id.clone());
+        let prompt = crate::utils::non_empty_trimmed(&raw.prompt)
+            .ok_or_else(|| format!("scheduled job {id} has no prompt"))?;
+        let (hour, minute) = parse_time_of_day(&raw.at)
+            .ok_or_else(|| format!("scheduled job {id} at must be HH:MM, not {:?}", raw.at))?;
+        let mut days = Vec::new();
+        for name in &raw.days {
+            let parsed = parse_days(name)
+                .ok_or_else(|| format!("scheduled job {id} has an unknown day {name:?}"))?;
+            days.extend(parsed);
+        }
+        if days.is_empty() {
+            days = parse_days("daily").unwrap_or_default();
+        }
+        days.sort_by_key(chrono::Weekday::num_days_from_monday);
+        days.dedup();
+        if !raw.enabled.unwrap_or(true) {
+            continue;
+        }
+        out.push(ScheduledJob {
+            label: crate::utils::first_non_empty([&raw.label, &id]),
+            id,
+            prompt,
+            hour,
+            minute,
+            days,
+            model: raw.model.trim().to_owned(),
+            system_prompt: raw.system_prompt.trim().to_owned(),
+        });
+    }
+    Ok(out)
+}
+
+fn parse_time_of_day(raw: &str) -> Option<(u32, u32)> {
+    let (hour, minute) = raw.trim().split_once(':')?;
+    let hour = hour.parse::<u32>().ok().filter(|hour| *hour < 24)?;
+    let minute = minute.parse::<u32>().ok().filter(|minute| *minute < 60)?;
+    Some((hour, minute))
+}
+
+fn parse_days(raw: &str) -> Option<Vec<chrono::Weekday>> {
+    use chrono::Weekday::{Fri, Mon, Sat, Sun, Thu, Tue, Wed};
+    Some(match raw.trim().to_ascii_lowercase().as_str() {
+        "daily" => vec![Mon, Tue, Wed, Thu, Fri, Sat, Sun],
+        "weekdays" => vec![Mon, Tue, Wed, Thu, Fri],
+        "weekends" => vec![Sat, Sun],
+        other => vec![other.parse::<chrono::Weekday>().ok()?],
+    })
+}
+
 /// Returns the account whose `id` or `address` case-insensitively matches
 /// `selector`. If `selector` is empty the first account is returned.
 /// Returns `Err` if no accounts are configured or the selector does not match.
@@ -273,3 +653,206 @@ pub fn select_account_by_id_or_address<'a>(
         "unknown email account {selector:?}; available accounts: {available}"
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_mcp_servers_with_defaults() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            r#"
+            [[mcp.servers]]
+            id = "git"
+            command = " uvx "
+            args = ["mcp-server-git", "--repository", "."]
+
+            [[mcp.servers]]
+            id = "fs"
+            label = "Filesystem"
+            command = "npx"
+            env = { NODE_ENV = "production" }
+            enabled = false
+            timeout_ms = 5000
+            "#,
+        )
+        .expect("write config");
+
+        let servers = load_mcp_servers(&path).expect("servers");
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].label, "git");
+        assert_eq!(servers[0].command, "uvx");
+        assert!(servers[0].enabled);
+        assert_eq!(servers[0].timeout_ms, DEFAULT_MCP_TIMEOUT_MS);
+        assert_eq!(servers[1].env["NODE_ENV"], "production");
+        assert!(!servers[1].enabled);
+        assert_eq!(servers[1].timeout_ms, 5000);
+    }
+
+    #[test]
+    fn rejects_reserved_and_malformed_mcp_server_ids() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("config.toml");
+        for body in [
+            "[[mcp.servers]]\nid = \"email\"\ncommand = \"x\"",
+            "[[mcp.servers]]\nid = \"has space\"\ncommand = \"x\"",
+            "[[mcp.servers]]\nid = \"my__server\"\ncommand = \"x\"",
+            "[[mcp.servers]]\nid = \"a\"\ncommand = \"x\"\n[[mcp.servers]]\nid = \"a\"\ncommand = \"y\"",
+            "[[mcp.servers]]\nid = \"a\"",
+        ] {
+            fs::write(&path, body).expect("write config");
+            assert!(load_mcp_servers(&path).is_err(), "accepted {body:?}");
+        }
+    }
+
+    #[test]
+    fn tool_settings_layer_tool_overrides_over_risk_levels() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("config.toml");
+        assert_eq!(
+            load_tool_settings(&path).expect("defaults"),
+            ToolSettings::default()
+        );
+
+        fs::write(
+            &path,
+            r#"
+            [tools]
+            max_parallel = 2
+
+            [tools.approval]
+            write = "Ask"
+
+            [tools.approval.tools]
+            shell_command = "auto"
+            git__git_push = "deny"
+            "#,
+        )
+        .expect("write config");
+        let settings = load_tool_settings(&path).expect("settings");
+        assert_eq!(settings.max_parallel, 2);
+        let policy = settings.approval;
+        assert_eq!(
+            policy.mode_for("builtin", "shell_command", "destructive"),
+            ApprovalMode::Auto
+        );
+        assert_eq!(
+            policy.mode_for("git", "git__git_push", "write"),
+            ApprovalMode::Deny
+        );
+        assert_eq!(
+            policy.mode_for("email", "email_read", "read"),
+            ApprovalMode::Auto
+        );
+        assert_eq!(
+            policy.mode_for("fs", "fs__write_file", "write"),
+            ApprovalMode::Ask
+        );
+        assert_eq!(
+            policy.mode_for("fs", "fs__rm", "destructive"),
+            ApprovalMode::Ask
+        );
+
+        for body in [
+            "[tools.approval]\nread = \"sometimes\"",
+            "[tools]\nmax_parallel = 0",
+        ] {
+            fs::write(&path, body).expect("write config");
+            assert!(load_tool_settings(&path).is_err(), "accepted {body:?}");
+        }
+        let body = "[tools.approval.tools]\nshell_command = \"never\"";
+        fs::write(&path, body).expect("write config");
+        let error = load_tool_settings(&path).expect_err("bad tool mode");
+        assert!(
+            error.starts_with("tools.approval.tools.shell_command must be"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn mcp_servers_take_either_command_or_http_url() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            r#"
+            [[mcp.servers]]
+            id = "remote"
+            url = "https://mcp.example.com/mcp"
+            bearer_token_secret = "REMOTE_MCP_TOKEN"
+            headers = { X-Team = "home" }
+            "#,
+        )
+        .expect("write config");
+        let servers = load_mcp_servers(&path).expect("servers");
+        assert_eq!(servers[0].url, "https://mcp.example.com/mcp");
+        assert!(servers[0].command.is_empty());
+        assert_eq!(servers[0].bearer_token_secret, "REMOTE_MCP_TOKEN");
+        assert_eq!(servers[0].headers["X-Team"], "home");
+
+        for body in [
+            "[[mcp.servers]]\nid = \"a\"\ncommand = \"x\"\nurl = \"https://a\"",
+            "[[mcp.servers]]\nid = \"a\"\nurl = \"ftp://a\"",
+        ] {
+            fs::write(&path, body).expect("write config");
+            assert!(load_mcp_servers(&path).is_err(), "accepted {body:?}");
+        }
+    }
+
+    #[test]
+    fn scheduled_jobs_parse_times_and_day_sets() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            r#"
+            [[schedule.jobs]]
+            id = "morning"
+            label = "Morning briefing"
+            prompt = " Summarize unread mail and today's calendar. "
+            at = "08:30"
+            days = ["weekdays", "mon"]
+
+            [[schedule.jobs]]
+            id = "review"
+            prompt = "Review the week."
+            at = "7:05"
+            days = ["Sunday"]
+            model = "openai/gpt-5.4"
+
+            [[schedule.jobs]]
+            id = "paused"
+            prompt = "x"
+            at = "12:00"
+            enabled = false
+            "#,
+        )
+        .expect("write config");
+        let jobs = load_scheduled_jobs(&path).expect("jobs");
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].label, "Morning briefing");
+        assert_eq!(
+            jobs[0].prompt,
+            "Summarize unread mail and today's calendar."
+        );
+        assert_eq!((jobs[0].hour, jobs[0].minute), (8, 30));
+        assert_eq!(jobs[0].days.len(), 5);
+        assert!(jobs[0].model.is_empty());
+        assert_eq!(jobs[1].label, "review");
+        assert_eq!((jobs[1].hour, jobs[1].minute), (7, 5));
+        assert_eq!(jobs[1].days, vec![chrono::Weekday::Sun]);
+
+        for body in [
+            "[[schedule.jobs]]\nid = \"a\"\nprompt = \"x\"\nat = \"24:00\"",
+            "[[schedule.jobs]]\nid = \"a\"\nprompt = \"x\"\nat = \"08:30\"\ndays = [\"someday\"]",
+            "[[schedule.jobs]]\nid = \"a\"\nat = \"08:30\"",
+            "[[schedule.jobs]]\nid = \"a\"\nprompt = \"x\"\nat = \"08:30\"\n[[schedule.jobs]]\nid = \"a\"\nprompt = \"y\"\nat = \"09:00\"",
+        ] {
+            fs::write(&path, body).expect("write config");
+            assert!(load_scheduled_jobs(&path).is_err(), "accepted {body:?}");
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use libc::c_char;
-use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use rusqlite::{params, Connection, OptionalExtension, Transaction, TransactionBehavior};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::collections::{HashMap, HashSet};
@@ -11,6 +11,8 @@ use std::ptr;
 use uuid::Uuid;
 
 use crate::config_resolver::DEFAULT_MODEL as DEFAULT_MODEL_ID;
+/// Tag on conversations written by scheduled background jobs.
+pub(crate) const SCHEDULED_TAG: &str = "scheduled";
 const SCHEMA_SQL: &str = r"
 CREATE TABLE IF NOT EXISTS conversations (
   id TEXT PRIMARY KEY,
@@ -25,7 +27,9 @@ CREATE TABLE IF NOT EXISTS conversations (
   created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
   updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
   closed_at TEXT,
-  deleted_at TEXT
+  deleted_at TEXT,
+  parent_conversation_id TEXT NOT NULL DEFAULT '',
+  fork_ordinal INTEGER
 );
 
 CREATE TABLE IF NOT EXISTS messages (
@@ -43,7 +47,9 @@ CREATE TABLE IF NOT EXISTS messages (
   updated_at TEXT,
   completed_at TEXT,
   deleted_at TEXT,
-  UNIQUE(conversation_id, ordinal),
+  variant_of TEXT NOT NULL DEFAULT '',
+  variant_index INTEGER NOT NULL DEFAULT 0,
+  variant_active INTEGER NOT NULL DEFAULT 1 CHECK (variant_active IN (0, 1)),
   CHECK ((kind = 'tool') = (sender = 'tool'))
 );
 
@@ -74,18 +80,54 @@ CREATE TABLE IF NOT EXISTS response_items (
   call_id TEXT NOT NULL DEFAULT '',
   raw BLOB NOT NULL CHECK (json_valid(raw, 8)),
   created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+  variant_index INTEGER NOT NULL DEFAULT 0,
+  variant_active INTEGER NOT NULL DEFAULT 1 CHECK (variant_active IN (0, 1)),
   UNIQUE(conversation_id, turn_id, item_ordinal)
 );
 
+CREATE TABLE IF NOT EXISTS conversation_params (
+  conversation_id TEXT PRIMARY KEY REFERENCES conversations(id) ON DELETE CASCADE,
+  params BLOB NOT NULL DEFAULT (jsonb('{}')) CHECK (json_valid(params, 8)),
+  updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS conversation_tags (
+  conversation_id TEXT NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+  tag TEXT NOT NULL,
+  PRIMARY KEY (conversation_id, tag)
+);
+
+CREATE TABLE IF NOT EXISTS usage_ledger (
+  id TEXT PRIMARY KEY,
+  conversation_id TEXT NOT NULL DEFAULT '',
+  model_id TEXT NOT NULL,
+  prompt_tokens INTEGER NOT NULL DEFAULT 0,
+  output_tokens INTEGER NOT NULL DEFAULT 0,
+  ttf_ms REAL NOT NULL DEFAULT -1,
+  total_ms REAL NOT NULL DEFAULT 0,
+  cost_usd REAL,
+  created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
 CREATE INDEX IF NOT EXISTS idx_conversations_status_updated
 ON conversations(status, updated_at DESC);
 
+CREATE INDEX IF NOT EXISTS idx_conversations_parent
+ON conversations(parent_conversation_id) WHERE parent_conversation_id != '';
+
 CREATE INDEX IF NOT EXISTS idx_messages_conversation_ordinal
 ON messages(conversation_id, ordinal);
 
 CREATE INDEX IF NOT EXISTS idx_messages_status
 ON messages(status);
 
+-- Ordinals are the chat's row order; parked answer variants sit outside it.
+CREATE UNIQUE INDEX IF NOT EXISTS idx_messages_active_ordinal
+ON messages(conversation_id, ordinal) WHERE variant_active = 1;
+
+CREATE INDEX IF NOT EXISTS idx_messages_variant
+ON messages(variant_of, variant_index) WHERE variant_of != '';
+
 CREATE INDEX IF NOT EXISTS idx_tool_calls_message
 ON tool_calls(message_id);
 
@@ -97,8 +139,190 @@ ON response_items(conversation_id, turn_ordinal, item_ordinal);
 
 CREATE INDEX IF NOT EXISTS idx_response_items_call
 ON response_items(conversation_id, call_id);
+
+CREATE INDEX IF NOT EXISTS idx_conversation_tags_tag
+ON conversation_tags(tag);
+
+CREATE INDEX IF NOT EXISTS idx_usage_ledger_conversation
+ON usage_ledger(conversation_id);
+
+CREATE INDEX IF NOT EXISTS idx_usage_ledger_created
+ON usage_ledger(created_at);
+
+-- Full-text index over what a chat says: chat message bodies (not info
+-- notices), conversation titles and tool-call summaries, all from active
+-- variants that are not deleted. Each source row owns the index rowid
+-- `source rowid * 4 + 0/1/2`, so the triggers below keep it in sync
+-- without scanning.
+CREATE VIRTUAL TABLE IF NOT EXISTS chat_search USING fts5(
+  text,
+  source UNINDEXED,
+  conversation_id UNINDEXED,
+  message_id UNINDEXED,
+  tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER IF NOT EXISTS chat_search_message_insert AFTER INSERT ON messages
+WHEN new.kind = 'chat' AND new.variant_active = 1 AND new.status != 'deleted'
+  AND trim(new.body) != ''
+BEGIN
+  INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+  VALUES (new.rowid * 4, new.body, 'message', new.conversation_id, new.id);
+END;
+
+-- A message that is deleted, parked or brought back takes its tool
+-- summaries with it.
+CREATE TRIGGER IF NOT EXISTS chat_search_message_update
+AFTER UPDATE OF body, status, variant_active ON messages
+BEGIN
+  DELETE FROM chat_search WHERE rowid = old.rowid * 4;
+  INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+  SELECT new.rowid * 4, new.body, 'message', new.conversation_id, new.id
+  WHERE new.kind = 'chat' AND new.variant_active = 1 AND new.status != 'deleted'
+    AND trim(new.body) != '';
+  DELETE FROM chat_search
+  WHERE (old.status != new.status OR old.variant_active != new.variant_active)
+    AND rowid IN (SELECT t.rowid * 4 + 2 FROM tool_calls t WHERE t.message_id = new.id);
+  INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+  SELECT t.rowid * 4 + 2, trim(t.summary || char(10) || t.subtitle), 'tool',
+         new.conversation_id, t.message_id
+  FROM tool_calls t
+  WHERE (old.status != new.status OR old.variant_active != new.variant_active)
+    AND new.variant_active = 1 AND new.status != 'deleted'
+    AND t.message_id = new.id AND trim(t.summary || t.subtitle) != '';
+END;
+
+CREATE TRIGGER IF NOT EXISTS chat_search_message_delete AFTER DELETE ON messages
+BEGIN
+  DELETE FROM chat_search WHERE rowid = old.rowid * 4;
+END;
+
+CREATE TRIGGER IF NOT EXISTS chat_search_title_insert AFTER INSERT ON conversations
+WHEN trim(new.title) != ''
+BEGIN
+  INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+  VALUES (new.rowid * 4 + 1, new.title, 'title', new.id, '');
+END;
+
+CREATE TRIGGER IF NOT EXISTS chat_search_title_update AFTER UPDATE OF title ON conversations
+BEGIN
+  DELETE FROM chat_search WHERE rowid = old.rowid * 4 + 1;
+  INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+  SELECT new.rowid * 4 + 1, new.title, 'title', new.id, ''
+  WHERE trim(new.title) != '';
+END;
+
+CREATE TRIGGER IF NOT EXISTS chat_search_title_delete AFTER DELETE ON conversations
+BEGIN
+  DELETE FROM chat_search WHERE rowid = old.rowid * 4 + 1;
+END;
+
+CREATE TRIGGER IF NOT EXISTS chat_search_tool_insert AFTER INSERT ON tool_calls
+WHEN trim(new.summary || new.subtitle) != ''
+BEGIN
+  INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+  SELECT new.rowid * 4 + 2, trim(new.summary || char(10) || new.subtitle), 'tool',
+         m.conversation_id, new.message_id
+  FROM messages m
+  WHERE m.id = new.message_id AND m.variant_active = 1 AND m.status != 'deleted';
+END;
+
+CREATE TRIGGER IF NOT EXISTS chat_search_tool_update AFTER UPDATE OF summary, subtitle ON tool_calls
+BEGIN
+  DELETE FROM chat_search WHERE rowid = old.rowid * 4 + 2;
+  INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+  SELECT new.rowid * 4 + 2, trim(new.summary || char(10) || new.subtitle), 'tool',
+         m.conversation_id, new.message_id
+  FROM messages m
+  WHERE m.id = new.message_id AND m.variant_active = 1 AND m.status != 'deleted'
+    AND trim(new.summary || new.subtitle) != '';
+END;
+
+CREATE TRIGGER IF NOT EXISTS chat_search_tool_delete AFTER DELETE ON tool_calls
+BEGIN
+  DELETE FROM chat_search WHERE rowid = old.rowid * 4 + 2;
+END;
 ";
 
+/// One upgrade step for an existing store. `SCHEMA_SQL` runs after the
+/// steps and creates whatever is missing, so a step only reshapes what is
+/// already there. Steps spell out their own DDL rather than borrowing
+/// `SCHEMA_SQL`, which keeps changing after they ship.
+#[derive(Clone, Copy)]
+struct Migration {
+    name: &'static str,
+    apply: fn(&Transaction<'_>) -> rusqlite::Result<()>,
+}
+
+/// Upgrade steps, oldest first; a store's `PRAGMA user_version` counts the
+/// ones it has had. Only ever append. Stores from before versioning are at
+/// 0 but may already have some of the early changes, so those steps check
+/// before acting.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        name: "fork columns on conversations",
+        apply: add_fork_columns,
+    },
+    Migration {
+        name: "answer variants on messages",
+        apply: rebuild_messages_for_variants,
+    },
+    Migration {
+        name: "full-text index",
+        apply: index_chat_search,
+    },
+    Migration {
+        name: "full-text index scoped to live chat text",
+        apply: scope_chat_search,
+    },
+];
+
+/// Marks around the matched terms in search snippets (STX/ETX, which chat
+/// text does not contain), for the caller to style after escaping.
+const SNIPPET_OPEN: &str = "\u{2}";
+const SNIPPET_CLOSE: &str = "\u{3}";
+
+/// Matches `conversations c` rows tagged [`SCHEDULED_TAG`] (bound to the `?`).
+/// `/resume` offers scheduled runs whatever model they used.
+const SCHEDULED_CONVERSATION: &str = "EXISTS (
+  SELECT 1 FROM conversation_tags t WHERE t.conversation_id = c.id AND t.tag = ?
+)";
+
+/// Select list `scan_conversation_summary` reads, over `conversations c`.
+const CONVERSATION_SUMMARY_COLUMNS: &str = "
+  c.id,
+  c.title,
+  c.model_id,
+  c.provider_id,
+  c.status,
+  c.created_at,
+  c.updated_at,
+  coalesce(c.closed_at, ''),
+  (
+    SELECT count(*)
+    FROM messages m
+    WHERE m.conversation_id = c.id
+      AND m.status != 'deleted'
+      AND m.variant_active = 1
+  ) AS message_count,
+  coalesce((
+    SELECT m.body
+    FROM messages m
+    WHERE m.conversation_id = c.id
+      AND m.status != 'deleted'
+      AND m.variant_active = 1
+      AND trim(m.body) != ''
+    ORDER BY m.ordinal DESC
+    LIMIT 1
+  ), '') AS preview,
+  coalesce((
+    SELECT group_concat(t.tag, ',')
+    FROM conversation_tags t
+    WHERE t.conversation_id = c.id
+  ), '') AS tags,
+  c.parent_conversation_id,
+  c.fork_ordinal";
+
 #[derive(Default)]
 struct OpenConversationOptions {
     model_id: String,
@@ -121,6 +345,16 @@ struct ApiResult {
     messages: Vec<Message>,
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     response_items: Vec<ResponseItem>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    usage: Vec<UsageTotal>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    params_json: String,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    variants: Vec<Variant>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    started_variant: Option<StartedVariant>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    search_hits: Vec<SearchHit>,
 }
 
 #[derive(Clone, Default, Serialize)]
@@ -150,6 +384,13 @@ struct ConversationSummary {
     closed_at: String,
     message_count: i64,
     preview: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// The conversation this one was forked from, before row `fork_ordinal`.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    parent_conversation_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fork_ordinal: Option<i64>,
 }
 
 #[derive(Default, Deserialize, Serialize)]
@@ -173,6 +414,16 @@ struct Message {
     completed_at: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     deleted_at: String,
+    /// The user message this row answers, once that answer has been
+    /// regenerated; empty for rows that never had siblings.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    variant_of: String,
+    #[serde(default)]
+    variant_index: i64,
+    /// How many answers the turn has; 0 for rows that never had siblings.
+    /// Read-only: computed when listing, ignored on upsert.
+    #[serde(default)]
+    variant_count: i64,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     tool_calls: Vec<ToolCall>,
 }
@@ -219,6 +470,72 @@ struct ResponseItem {
     raw_json: String,
     #[serde(default, skip_serializing_if = "String::is_empty")]
     created_at: String,
+    #[serde(default)]
+    variant_index: i64,
+}
+
+/// One full-text match, best first.
+#[derive(Default, Serialize)]
+struct SearchHit {
+    conversation_id: String,
+    /// Empty for a title match.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    message_id: String,
+    /// `message`, `title` or `tool`.
+    source: String,
+    /// Matched text around the terms, which sit between `SNIPPET_OPEN` and
+    /// `SNIPPET_CLOSE`.
+    snippet: String,
+    /// BM25 score; lower is better.
+    rank: f64,
+    title: String,
+    model_id: String,
+    status: String,
+    updated_at: String,
+}
+
+/// One answer to a regenerated turn.
+#[derive(Default, Serialize)]
+struct Variant {
+    index: i64,
+    active: bool,
+    /// Rows the answer spans (assistant text, tool calls, notices).
+    message_count: i64,
+    preview: String,
+    created_at: String,
+}
+
+/// Where a regenerated answer goes: its variant index and the first
+/// response-item ordinal its turn has free.
+#[derive(Serialize)]
+struct StartedVariant {
+    variant_index: i64,
+    next_item_ordinal: i64,
+}
+
+/// One finished model turn, as appended to the usage ledger.
+pub(crate) struct UsageRecord {
+    pub(crate) conversation_id: String,
+    pub(crate) model_id: String,
+    pub(crate) prompt_tokens: i64,
+    pub(crate) output_tokens: i64,
+    pub(crate) ttf_ms: f64,
+    pub(crate) total_ms: f64,
+    /// `None` when the model has no configured prices.
+    pub(crate) cost_usd: Option<f64>,
+}
+
+#[derive(Default, Serialize)]
+struct UsageTotal {
+    key: String,
+    label: String,
+    turns: i64,
+    prompt_tokens: i64,
+    output_tokens: i64,
+    total_ms: f64,
+    cost_usd: f64,
+    /// Turns whose model had no configured prices, so `cost_usd` undercounts.
+    unpriced_turns: i64,
 }
 
 struct Store {
@@ -237,6 +554,136 @@ pub(crate) fn load_history_items(conversation_id: &str) -> Result<Vec<Value>, St
         .map_err(|error| error.to_string())?
 }
 
+/// Reports whether a conversation still has an empty title.
+pub(crate) fn is_untitled(conversation_id: &str) -> Result<bool, String> {
+    let store = Store::open("").map_err(|error| error.to_string())?;
+    store
+        .is_untitled(conversation_id)
+        .map_err(|error| error.to_string())
+}
+
+/// Stores a generated title unless the conversation was named meanwhile.
+pub(crate) fn fill_title(conversation_id: &str, title: &str) -> Result<(), String> {
+    let store = Store::open("").map_err(|error| error.to_string())?;
+    store
+        .set_title(conversation_id, title, true)
+        .map_err(|error| error.to_string())
+}
+
+/// Loads a conversation's generation parameters as a JSON object (`{}` when
+/// none are set).
+pub(crate) fn load_generation_params(conversation_id: &str) -> Result<String, String> {
+    if conversation_id.trim().is_empty() {
+        return Ok("{}".to_owned());
+    }
+    let store = Store::open("").map_err(|error| error.to_string())?;
+    store
+        .generation_params(conversation_id)
+        .map_err(|error| error.to_string())
+}
+
+/// Starts the conversation a scheduled job writes to: tagged
+/// [`SCHEDULED_TAG`], already closed so the panel's active conversation is
+/// left alone, and holding `prompt` as its first user turn. Returns the
+/// conversation id and the turn (user message) id.
+pub(crate) fn open_scheduled_conversation(
+    model_id: &str,
+    system_prompt: &str,
+    title: &str,
+    prompt: &str,
+) -> Result<(String, String), String> {
+    let mut store = Store::open("").map_err(|error| error.to_string())?;
+    store.open_tagged_conversation(model_id, system_prompt, title, SCHEDULED_TAG, prompt)
+}
+
+/// Stores how a scheduled job's turn ended: its response items as
+/// `(source, item)` pairs, the reply with any generated `attachments`, or
+/// the error that stopped it.
+pub(crate) fn finish_scheduled_conversation(
+    conversation_id: &str,
+    turn_id: &str,
+    items: Vec<(String, Value)>,
+    reply: &str,
+    attachments: &[crate::ai::Attachment],
+    error: &str,
+) -> Result<(), String> {
+    let mut store = Store::open("").map_err(|error| error.to_string())?;
+    store.finish_background_turn(conversation_id, turn_id, items, reply, attachments, error)
+}
+
+/// Saves model-generated file content under the attachments directory next
+/// to the chat database and returns it as an attachment referencing the file.
+/// Files are named by content hash, so the same image is stored once.
+pub(crate) fn store_generated_attachment(
+    bytes: &[u8],
+    mime: &str,
+) -> Result<crate::ai::Attachment, String> {
+    write_attachment(&attachments_dir(), bytes, mime).map_err(|error| error.to_string())
+}
+
+/// The directory attachment files are stored in, next to the chat database.
+pub(crate) fn attachments_dir() -> PathBuf {
+    default_path().parent().map_or_else(
+        || PathBuf::from("attachments"),
+        |parent| parent.join("attachments"),
+    )
+}
+
+/// Lowercase hex SHA-256 of `bytes`, which names stored attachment files.
+pub(crate) fn content_hash(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    use std::fmt::Write as _;
+
+    Sha256::digest(bytes)
+        .iter()
+        .fold(String::new(), |mut name, byte| {
+            let _ = write!(name, "{byte:02x}");
+            name
+        })
+}
+
+fn write_attachment(
+    dir: &Path,
+    bytes: &[u8],
+    mime: &str,
+) -> std::io::Result<crate::ai::Attachment> {
+    let mut name = content_hash(bytes);
+    let extension = match mime {
+        "image/jpeg" => Some("jpg"),
+        _ => mime_guess::get_mime_extensions_str(mime)
+            .and_then(|extensions| extensions.first().copied()),
+    };
+    if let Some(extension) = extension {
+        name = format!("{name}.{extension}");
+    }
+    fs::create_dir_all(dir)?;
+    let path = dir.join(name);
+    if !path.exists() {
+        // Written aside and renamed so a reader never sees a partial file.
+        let partial = dir.join(format!(".{}.partial", Uuid::new_v4()));
+        fs::write(&partial, bytes)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&partial, fs::Permissions::from_mode(0o600))?;
+        }
+        fs::rename(&partial, &path)?;
+    }
+    Ok(crate::ai::Attachment {
+        path: path.to_string_lossy().into_owned(),
+        mime: mime.to_owned(),
+        ..crate::ai::Attachment::default()
+    })
+}
+
+/// Appends a finished turn to the usage ledger.
+pub(crate) fn record_usage(record: &UsageRecord) -> Result<(), String> {
+    let store = Store::open("").map_err(|error| error.to_string())?;
+    store
+        .insert_usage(record, &timestamp())
+        .map_err(|error| error.to_string())
+}
+
 #[no_mangle]
 /// Restores the latest active conversation for a model. Returns a
 /// CBOR-encoded `ApiResult`.
@@ -380,6 +827,190 @@ pub unsafe extern "C" fn QsNative_AiHistory_ListResume(
     crate::ffi::into_cbor(&result)
 }
 
+#[no_mangle]
+/// Forks `conversation_id` before its message `message_id` into a new
+/// active conversation (see `Store::fork_conversation`). Returns a
+/// CBOR-encoded `ApiResult` with the fork and its messages.
+///
+/// # Safety
+///
+/// `conversation_id` and `message_id` must be null or valid NUL-terminated
+/// strings for the duration of this call. The returned buffer must be
+/// released with `QsNative_FreeBytes`.
+pub unsafe extern "C" fn QsNative_AiHistory_Fork(
+    conversation_id: *const c_char,
+    message_id: *const c_char,
+) -> crate::ffi::QsNativeBytes {
+    let conversation_id = unsafe { c_arg(conversation_id) };
+    let message_id = unsafe { c_arg(message_id) };
+    let result = with_store("", |store| {
+        let (conv, messages) = store.fork_conversation(&conversation_id, &message_id)?;
+        Ok(ApiResult {
+            ok: true,
+            conversation: Some(conv),
+            messages,
+            ..Default::default()
+        })
+    });
+    crate::ffi::into_cbor(&result)
+}
+
+#[no_mangle]
+/// Lists the fork tree `conversation_id` belongs to as conversation
+/// summaries carrying `parent_conversation_id`/`fork_ordinal`. Returns a
+/// CBOR-encoded `ApiResult`.
+///
+/// # Safety
+///
+/// `conversation_id` must be null or a valid NUL-terminated string for the
+/// duration of this call. The returned buffer must be released with
+/// `QsNative_FreeBytes`.
+pub unsafe extern "C" fn QsNative_AiHistory_ListBranches(
+    conversation_id: *const c_char,
+) -> crate::ffi::QsNativeBytes {
+    let conversation_id = unsafe { c_arg(conversation_id) };
+    let result = with_store("", |store| {
+        Ok(ApiResult {
+            ok: true,
+            conversations: store.list_branches(&conversation_id)?,
+            ..Default::default()
+        })
+    });
+    crate::ffi::into_cbor(&result)
+}
+
+#[no_mangle]
+/// Full-text search over all chat history (message bodies, titles, tool-call
+/// summaries). Returns a CBOR-encoded `ApiResult` whose `search_hits` are
+/// ranked best first.
+///
+/// # Safety
+///
+/// `query` must be null or a valid NUL-terminated string for the duration of
+/// this call. The returned buffer must be released with `QsNative_FreeBytes`.
+pub unsafe extern "C" fn QsNative_AiHistory_Search(
+    query: *const c_char,
+    limit: i32,
+) -> crate::ffi::QsNativeBytes {
+    let query = unsafe { c_arg(query) };
+    let result = with_store("", |store| {
+        Ok(ApiResult {
+            ok: true,
+            search_hits: store.search(&query, i64::from(limit))?,
+            ..Default::default()
+        })
+    });
+    crate::ffi::into_cbor(&result)
+}
+
+#[no_mangle]
+/// Renames a conversation. Returns a CBOR-encoded `ApiResult`.
+///
+/// # Safety
+///
+/// Pointer arguments must be null or valid NUL-terminated strings for the
+/// duration of this call. The returned buffer must be released with
+/// `QsNative_FreeBytes`.
+pub unsafe extern "C" fn QsNative_AiHistory_Rename(
+    conversation_id: *const c_char,
+    title: *const c_char,
+) -> crate::ffi::QsNativeBytes {
+    let conversation_id = unsafe { c_arg(conversation_id) };
+    let title = unsafe { c_arg(title) };
+    let result = with_store("", |store| {
+        store.set_title(&conversation_id, &title, false)?;
+        Ok(ok_result())
+    });
+    crate::ffi::into_cbor(&result)
+}
+
+#[no_mangle]
+/// Returns a conversation's generation parameters in `params_json` of a
+/// CBOR-encoded `ApiResult`.
+///
+/// # Safety
+///
+/// `conversation_id` must be null or a valid NUL-terminated string for the
+/// duration of this call. The returned buffer must be released with
+/// `QsNative_FreeBytes`.
+pub unsafe extern "C" fn QsNative_AiHistory_GetParams(
+    conversation_id: *const c_char,
+) -> crate::ffi::QsNativeBytes {
+    let conversation_id = unsafe { c_arg(conversation_id) };
+    let result = with_store("", |store| {
+        Ok(ApiResult {
+            ok: true,
+            params_json: store.generation_params(&conversation_id)?,
+            ..Default::default()
+        })
+    });
+    crate::ffi::into_cbor(&result)
+}
+
+#[no_mangle]
+/// Replaces a conversation's generation parameters with the JSON object
+/// `params_json` after checking it against `model_id`; `{}` clears them.
+/// Returns the stored parameters in a CBOR-encoded `ApiResult`.
+///
+/// # Safety
+///
+/// Pointer arguments must be null or valid NUL-terminated strings for the
+/// duration of this call. The returned buffer must be released with
+/// `QsNative_FreeBytes`.
+pub unsafe extern "C" fn QsNative_AiHistory_SetParams(
+    conversation_id: *const c_char,
+    model_id: *const c_char,
+    params_json: *const c_char,
+) -> crate::ffi::QsNativeBytes {
+    let conversation_id = unsafe { c_arg(conversation_id) };
+    let model_id = unsafe { c_arg(model_id) };
+    let params_json = unsafe { c_arg(params_json) };
+    let result = match crate::ai::normalize_generation_params(&model_id, &params_json) {
+        Ok(params_json) => with_store("", |store| {
+            store.set_generation_params(&conversation_id, &params_json)?;
+            Ok(ApiResult {
+                ok: true,
+                params_json,
+                ..Default::default()
+            })
+        }),
+        Err(error) => error_result(error),
+    };
+    crate::ffi::into_cbor(&result)
+}
+
+#[no_mangle]
+/// Sums the usage ledger by `conversation`, `model`, `day` or `month` (local
+/// time), newest or most expensive first. A non-empty `conversation_id`
+/// restricts the sums to that conversation. Returns a CBOR-encoded
+/// `ApiResult`.
+///
+/// # Safety
+///
+/// Pointer arguments must be null or valid NUL-terminated strings for the
+/// duration of this call. The returned buffer must be released with
+/// `QsNative_FreeBytes`.
+pub unsafe extern "C" fn QsNative_AiHistory_UsageTotals(
+    group_by: *const c_char,
+    conversation_id: *const c_char,
+    limit: i32,
+) -> crate::ffi::QsNativeBytes {
+    let group_by = unsafe { c_arg(group_by) };
+    let conversation_id = unsafe { c_arg(conversation_id) };
+    let result = match UsageGrouping::parse(&group_by) {
+        Some(grouping) => with_store("", |store| {
+            let usage = store.usage_totals(grouping, &conversation_id, i64::from(limit))?;
+            Ok(ApiResult {
+                ok: true,
+                usage,
+                ..Default::default()
+            })
+        }),
+        None => error_result(format!("unknown usage grouping: {group_by}")),
+    };
+    crate::ffi::into_cbor(&result)
+}
+
 #[no_mangle]
 /// Inserts or updates a message row from a CBOR-encoded object. Returns a
 /// CBOR-encoded `ApiResult`.
@@ -446,23 +1077,100 @@ pub unsafe extern "C" fn QsNative_AiHistory_DeleteFromOrdinal(
 }
 
 #[no_mangle]
-/// Inserts or updates a tool-call row from a CBOR-encoded object. Returns a
-/// CBOR-encoded `ApiResult`.
+/// Parks the current answer to the user message `turn_id` as an inactive
+/// variant before that turn is regenerated. Returns a CBOR-encoded
+/// `ApiResult` whose `started_variant` is what the new answer persists with.
 ///
 /// # Safety
 ///
-/// `(tool_call_ptr, tool_call_len)` must describe a readable CBOR byte range
-/// for the call, or `tool_call_ptr` may be null. The returned buffer must be
-/// released with `QsNative_FreeBytes`.
-pub unsafe extern "C" fn QsNative_AiHistory_UpsertToolCall(
-    tool_call_ptr: *const u8,
-    tool_call_len: usize,
+/// `conversation_id`/`turn_id` must be null or valid NUL-terminated strings
+/// for the duration of this call. The returned buffer must be released with
+/// `QsNative_FreeBytes`.
+pub unsafe extern "C" fn QsNative_AiHistory_StartVariant(
+    conversation_id: *const c_char,
+    turn_id: *const c_char,
 ) -> crate::ffi::QsNativeBytes {
-    let result = match unsafe { crate::ffi::from_cbor::<Value>(tool_call_ptr, tool_call_len) } {
-        Some(value) => upsert_json(value, decode_tool_call, |store, call| {
-            store
-                .upsert_tool_call(call)
-                .map_err(rusqlite::Error::InvalidParameterName)
+    let conversation_id = unsafe { c_arg(conversation_id) };
+    let turn_id = unsafe { c_arg(turn_id) };
+    let result = with_store("", |store| {
+        let started = store.start_variant(&conversation_id, &turn_id)?;
+        Ok(ApiResult {
+            ok: true,
+            started_variant: Some(started),
+            ..ApiResult::default()
+        })
+    });
+    crate::ffi::into_cbor(&result)
+}
+
+#[no_mangle]
+/// Lists the answers to the turn `message_id` belongs to, oldest first.
+/// Returns a CBOR-encoded `ApiResult`; `variants` is empty when the answer
+/// was never regenerated.
+///
+/// # Safety
+///
+/// `message_id` must be null or a valid NUL-terminated string for the
+/// duration of this call. The returned buffer must be released with
+/// `QsNative_FreeBytes`.
+pub unsafe extern "C" fn QsNative_AiHistory_ListVariants(
+    message_id: *const c_char,
+) -> crate::ffi::QsNativeBytes {
+    let message_id = unsafe { c_arg(message_id) };
+    let result = with_store("", |store| {
+        Ok(ApiResult {
+            ok: true,
+            variants: store.list_variants(&message_id)?,
+            ..ApiResult::default()
+        })
+    });
+    crate::ffi::into_cbor(&result)
+}
+
+#[no_mangle]
+/// Makes variant `variant_index` the active answer of the turn `message_id`
+/// belongs to. Returns a CBOR-encoded `ApiResult` with the conversation's
+/// messages as they now read.
+///
+/// # Safety
+///
+/// `message_id` must be null or a valid NUL-terminated string for the
+/// duration of this call. The returned buffer must be released with
+/// `QsNative_FreeBytes`.
+pub unsafe extern "C" fn QsNative_AiHistory_SwitchVariant(
+    message_id: *const c_char,
+    variant_index: i32,
+) -> crate::ffi::QsNativeBytes {
+    let message_id = unsafe { c_arg(message_id) };
+    let result = with_store("", |store| {
+        let conversation_id = store.switch_variant(&message_id, i64::from(variant_index))?;
+        Ok(ApiResult {
+            ok: true,
+            messages: store.list_messages(&conversation_id)?,
+            ..ApiResult::default()
+        })
+    });
+    crate::ffi::into_cbor(&result)
+}
+
+#[no_mangle]
+/// Inserts or updates a tool-call row from a CBOR-encoded object. Returns a
+/// CBOR-encoded `ApiResult`.
+///
+/// # Safety
+///
+/// `(tool_call_ptr, tool_call_len)` must describe a readable CBOR byte range
+/// for the call, or `tool_call_ptr` may be null. The returned buffer must be
+/// released with `QsNative_FreeBytes`.
+pub unsafe extern "C" fn QsNative_AiHistory_UpsertToolCall(
+    tool_call_ptr: *const u8,
+    tool_call_len: usize,
+) -> crate::ffi::QsNativeBytes {
+    let result = match unsafe { crate::ffi::from_cbor::<Value>(tool_call_ptr, tool_call_len) } {
+        Some(value) => upsert_json(value, decode_tool_call, |store, call| {
+            store
+                .upsert_tool_call(call)
+                .map_err(rusqlite::Error::InvalidParameterName)
         }),
         None => error_result("invalid cbor payload".to_string()),
     };
@@ -559,9 +1267,9 @@ impl Store {
             fs::create_dir_all(parent).map_err(io_to_sql)?;
         }
         let conn = Connection::open(&path)?;
-        let store = Store { conn };
+        let mut store = Store { conn };
         store.configure()?;
-        store.create_schema()?;
+        store.create_schema(&path)?;
         secure_files(&path).map_err(io_to_sql)?;
         Ok(store)
     }
@@ -571,10 +1279,67 @@ impl Store {
             .execute_batch("PRAGMA foreign_keys = ON; PRAGMA journal_mode = WAL;")
     }
 
-    fn create_schema(&self) -> rusqlite::Result<()> {
+    fn create_schema(&mut self, path: &Path) -> rusqlite::Result<()> {
+        self.migrate(path, MIGRATIONS)?;
         self.conn.execute_batch(SCHEMA_SQL)
     }
 
+    /// Runs the `migrations` the store has not had yet, in order. Each step
+    /// commits together with its `user_version` bump, after a copy of the
+    /// store is left at `<path>.v<version>.bak`. A new store is created at
+    /// the latest version and skips them all.
+    fn migrate(&mut self, path: &Path, migrations: &[Migration]) -> rusqlite::Result<()> {
+        let version: i64 = self
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if !has_column(&self.conn, "conversations", "id")? {
+            return set_user_version(&self.conn, migrations.len());
+        }
+        let version = usize::try_from(version).unwrap_or(usize::MAX);
+        if version > migrations.len() {
+            return Err(schema_error(format!(
+                "chat history is at schema version {version}, newer than this build ({})",
+                migrations.len()
+            )));
+        }
+        for (from, migration) in migrations.iter().enumerate().skip(version) {
+            back_up_store(&self.conn, path, from)?;
+            self.apply_migration(migration, from + 1).map_err(|err| {
+                schema_error(format!(
+                    "schema migration {} ({}) failed: {err}",
+                    from + 1,
+                    migration.name
+                ))
+            })?;
+        }
+        Ok(())
+    }
+
+    fn apply_migration(&mut self, migration: &Migration, version: usize) -> rusqlite::Result<()> {
+        // Foreign keys off so a step can rebuild a table others reference;
+        // legacy renames so those references keep naming the rebuilt table.
+        // Neither pragma changes inside a transaction.
+        self.conn
+            .execute_batch("PRAGMA foreign_keys = OFF; PRAGMA legacy_alter_table = ON;")?;
+        // Taking the write lock up front serializes connections opening the
+        // store at once; whoever waited finds the step done and skips it.
+        let applied = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .and_then(|tx| {
+                let current: i64 = tx.pragma_query_value(None, "user_version", |row| row.get(0))?;
+                if usize::try_from(current).is_ok_and(|current| current >= version) {
+                    return Ok(());
+                }
+                (migration.apply)(&tx)?;
+                set_user_version(&tx, version)?;
+                tx.commit()
+            });
+        self.conn
+            .execute_batch("PRAGMA legacy_alter_table = OFF; PRAGMA foreign_keys = ON;")?;
+        applied
+    }
+
     fn restore_conversation(
         &self,
         opts: &OpenConversationOptions,
@@ -630,6 +1395,105 @@ impl Store {
         Ok(conv)
     }
 
+    /// Creates a closed, tagged conversation whose first message is `prompt`,
+    /// for turns that run without the panel. Unlike `create_conversation` it
+    /// does not close the model's active conversation.
+    fn open_tagged_conversation(
+        &mut self,
+        model_id: &str,
+        system_prompt: &str,
+        title: &str,
+        tag: &str,
+        prompt: &str,
+    ) -> Result<(String, String), String> {
+        let model_id = self::model_id(model_id);
+        let provider_id = model_id
+            .split_once('/')
+            .map(|(provider, _)| provider.to_owned())
+            .unwrap_or_default();
+        let conversation_id = new_id();
+        let turn_id = new_id();
+        let now = timestamp();
+        let tx = self.conn.transaction().map_err(|err| err.to_string())?;
+        tx.execute(
+            "INSERT INTO conversations (
+                id, title, model_id, provider_id, system_prompt, status, created_at, updated_at, closed_at
+             ) VALUES (?, ?, ?, ?, ?, 'closed', ?, ?, ?)",
+            params![
+                conversation_id,
+                title.trim(),
+                model_id,
+                provider_id,
+                system_prompt.trim(),
+                now,
+                now,
+                now
+            ],
+        )
+        .map_err(|err| err.to_string())?;
+        tx.execute(
+            "INSERT INTO conversation_tags (conversation_id, tag) VALUES (?, ?)",
+            params![conversation_id, tag],
+        )
+        .map_err(|err| err.to_string())?;
+        tx.execute(
+            "INSERT INTO messages (id, conversation_id, ordinal, sender, kind, status, body, created_at, completed_at)
+             VALUES (?, ?, 0, 'user', 'chat', 'complete', ?, ?, ?)",
+            params![turn_id, conversation_id, prompt, now, now],
+        )
+        .map_err(|err| err.to_string())?;
+        tx.commit().map_err(|err| err.to_string())?;
+        Ok((conversation_id, turn_id))
+    }
+
+    /// Appends the assistant message that ends a background turn, after the
+    /// turn's response items.
+    fn finish_background_turn(
+        &mut self,
+        conversation_id: &str,
+        turn_id: &str,
+        items: Vec<(String, Value)>,
+        reply: &str,
+        attachments: &[crate::ai::Attachment],
+        error: &str,
+    ) -> Result<(), String> {
+        let items = items
+            .into_iter()
+            .zip(0_i64..)
+            .map(|((source, raw), item_ordinal)| ResponseItem {
+                item_ordinal,
+                source,
+                raw_json: raw.to_string(),
+                ..ResponseItem::default()
+            })
+            .collect();
+        self.upsert_response_items(conversation_id, turn_id, 0, items)
+            .map_err(|err| err.to_string())?;
+        let failed = !error.trim().is_empty();
+        let now = timestamp();
+        self.upsert_message(Message {
+            id: new_id(),
+            conversation_id: conversation_id.to_owned(),
+            ordinal: 1,
+            sender: "assistant".to_owned(),
+            kind: "chat".to_owned(),
+            status: if failed { "error" } else { "complete" }.to_owned(),
+            body: if failed && reply.trim().is_empty() {
+                error.trim().to_owned()
+            } else {
+                reply.to_owned()
+            },
+            extra_json: if attachments.is_empty() {
+                String::new()
+            } else {
+                json!({ "attachments": attachments }).to_string()
+            },
+            completed_at: now.clone(),
+            created_at: now,
+            ..Message::default()
+        })
+    }
+
     fn close_conversation(&self, id: &str) -> rusqlite::Result<()> {
         let id = id.trim();
         if id.is_empty() {
@@ -657,19 +1521,18 @@ impl Store {
         let mut conv = if target_id.is_empty() {
             self.conn
                 .query_row(
-                    "SELECT id, title, model_id, provider_id, mood_id, mood_name, system_prompt, status, created_at, updated_at
-                     FROM conversations
-                     WHERE model_id = ?
-                       AND id != ?
-                       AND status = 'closed'
+                    &format!("SELECT id, title, model_id, provider_id, mood_id, mood_name, system_prompt, status, created_at, updated_at
+                     FROM conversations c
+                     WHERE (c.model_id = ? OR {SCHEDULED_CONVERSATION})
+                       AND c.id != ?
+                       AND c.status = 'closed'
                        AND EXISTS (
-                         SELECT 1 FROM messages
-                         WHERE messages.conversation_id = conversations.id
-                           AND messages.status != 'deleted'
+                         SELECT 1 FROM messages m
+                         WHERE m.conversation_id = c.id AND m.status != 'deleted'
                        )
-                     ORDER BY coalesce(closed_at, updated_at) DESC, updated_at DESC
-                     LIMIT 1",
-                    params![model_id, current_id],
+                     ORDER BY coalesce(c.closed_at, c.updated_at) DESC, c.updated_at DESC
+                     LIMIT 1"),
+                    params![model_id, SCHEDULED_TAG, current_id],
                     scan_conversation,
                 )
                 .optional()?
@@ -678,8 +1541,8 @@ impl Store {
                 .query_row(
                     "SELECT id, title, model_id, provider_id, mood_id, mood_name, system_prompt, status, created_at, updated_at
                      FROM conversations
-                     WHERE id = ? AND model_id = ? AND status = 'closed'",
-                    params![target_id, model_id],
+                     WHERE id = ? AND status = 'closed'",
+                    params![target_id],
                     scan_conversation,
                 )
                 .optional()?
@@ -689,10 +1552,18 @@ impl Store {
         if !current_id.is_empty() {
             self.close_conversation(current_id)?;
         }
+        // A scheduled run or a search hit may have used another model; it
+        // carries on with the one resuming it.
+        if conv.model_id != model_id {
+            conv.model_id = model_id;
+            conv.provider_id = opts.provider_id.trim().to_string();
+        }
         let now = timestamp();
         self.conn.execute(
-            "UPDATE conversations SET status = 'active', closed_at = NULL, updated_at = ? WHERE id = ?",
-            params![now, conv.id],
+            "UPDATE conversations
+             SET status = 'active', closed_at = NULL, model_id = ?, provider_id = ?, updated_at = ?
+             WHERE id = ?",
+            params![conv.model_id, conv.provider_id, now, conv.id],
         )?;
         conv.status = "active".to_string();
         conv.updated_at = now;
@@ -700,6 +1571,156 @@ impl Store {
         Ok((conv, messages))
     }
 
+    /// Starts a new active conversation from the rows of `source_id` before
+    /// its message `message_id`: their messages, tool calls, response items
+    /// and generation params are copied under new ids, parked answer variants
+    /// are not. The source is closed like any other conversation the model
+    /// had open.
+    fn fork_conversation(
+        &mut self,
+        source_id: &str,
+        message_id: &str,
+    ) -> rusqlite::Result<(Conversation, Vec<Message>)> {
+        let source_id = source_id.trim();
+        let message_id = message_id.trim();
+        let tx = self.conn.transaction()?;
+        // Soft-deleted rows keep their ordinals, so a row's place in the
+        // panel is not its ordinal.
+        let ordinal: i64 = tx
+            .query_row(
+                "SELECT ordinal FROM messages
+                  WHERE id = ? AND conversation_id = ? AND variant_active = 1
+                    AND status != 'deleted'",
+                params![message_id, source_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| {
+                rusqlite::Error::InvalidParameterName(format!("unknown message: {message_id}"))
+            })?;
+        let source = tx
+            .query_row(
+                "SELECT id, title, model_id, provider_id, mood_id, mood_name, system_prompt, status, created_at, updated_at
+                 FROM conversations
+                 WHERE id = ?",
+                params![source_id],
+                scan_conversation,
+            )
+            .optional()?
+            .ok_or_else(|| {
+                rusqlite::Error::InvalidParameterName(format!("unknown conversation: {source_id}"))
+            })?;
+        let now = timestamp();
+        let fork = Conversation {
+            id: new_id(),
+            status: "active".to_string(),
+            created_at: now.clone(),
+            updated_at: now.clone(),
+            ..source
+        };
+        tx.execute(
+            "UPDATE conversations
+             SET status = 'closed', closed_at = ?, updated_at = ?
+             WHERE status = 'active' AND model_id = ?",
+            params![now, now, fork.model_id],
+        )?;
+        tx.execute(
+            "INSERT INTO conversations (
+                id, title, model_id, provider_id, mood_id, mood_name, system_prompt, status,
+                created_at, updated_at, parent_conversation_id, fork_ordinal
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                fork.id,
+                fork.title,
+                fork.model_id,
+                fork.provider_id,
+                fork.mood_id,
+                fork.mood_name,
+                fork.system_prompt,
+                fork.status,
+                fork.created_at,
+                fork.updated_at,
+                source_id,
+                ordinal
+            ],
+        )?;
+        tx.execute(
+            "INSERT INTO conversation_params (conversation_id, params, updated_at)
+             SELECT ?, params, ? FROM conversation_params WHERE conversation_id = ?",
+            params![fork.id, now, source_id],
+        )?;
+        copy_rows_before(&tx, source_id, &fork.id, ordinal)?;
+        tx.commit()?;
+        let messages = self.list_messages(&fork.id)?;
+        Ok((fork, messages))
+    }
+
+    /// Every conversation in the fork tree `conversation_id` belongs to,
+    /// root first, in the order they were created.
+    fn list_branches(&self, conversation_id: &str) -> rusqlite::Result<Vec<ConversationSummary>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "WITH RECURSIVE
+               ancestors(id, parent) AS (
+                 SELECT id, parent_conversation_id FROM conversations WHERE id = ?
+                 UNION
+                 SELECT c.id, c.parent_conversation_id
+                 FROM conversations c JOIN ancestors a ON c.id = a.parent
+               ),
+               tree(id) AS (
+                 SELECT a.id FROM ancestors a
+                 WHERE NOT EXISTS (SELECT 1 FROM conversations p WHERE p.id = a.parent)
+                 UNION
+                 SELECT c.id FROM conversations c JOIN tree t ON c.parent_conversation_id = t.id
+               )
+             SELECT {CONVERSATION_SUMMARY_COLUMNS}
+             FROM conversations c
+             WHERE c.id IN (SELECT id FROM tree) AND c.status IN ('active', 'closed')
+             ORDER BY c.created_at ASC"
+        ))?;
+        let rows = stmt.query_map(params![conversation_id.trim()], scan_conversation_summary)?;
+        rows.collect()
+    }
+
+    /// Ranked full-text search over every conversation that is not deleted,
+    /// whatever its model or status.
+    fn search(&self, query: &str, limit: i64) -> rusqlite::Result<Vec<SearchHit>> {
+        let Some(query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+        let limit = if limit <= 0 || limit > 100 { 30 } else { limit };
+        let mut stmt = self.conn.prepare(
+            "SELECT
+                chat_search.conversation_id,
+                chat_search.message_id,
+                chat_search.source,
+                snippet(chat_search, 0, ?, ?, '…', 16),
+                chat_search.rank,
+                c.title,
+                c.model_id,
+                c.status,
+                c.updated_at
+             FROM chat_search
+             JOIN conversations c ON c.id = chat_search.conversation_id
+             WHERE chat_search MATCH ? AND c.status != 'deleted'
+             ORDER BY chat_search.rank
+             LIMIT ?",
+        )?;
+        let rows = stmt.query_map(params![SNIPPET_OPEN, SNIPPET_CLOSE, query, limit], |row| {
+            Ok(SearchHit {
+                conversation_id: row.get(0)?,
+                message_id: row.get(1)?,
+                source: row.get(2)?,
+                snippet: row.get(3)?,
+                rank: row.get(4)?,
+                title: row.get(5)?,
+                model_id: row.get(6)?,
+                status: row.get(7)?,
+                updated_at: row.get(8)?,
+            })
+        })?;
+        rows.collect()
+    }
+
     fn list_closed_conversations(
         &self,
         opts: &OpenConversationOptions,
@@ -712,32 +1733,10 @@ impl Store {
         let query = query.trim();
         let limit = if limit <= 0 || limit > 100 { 50 } else { limit };
         let like = format!("%{query}%");
-        let mut stmt = self.conn.prepare(
-            "SELECT
-                c.id,
-                c.title,
-                c.model_id,
-                c.provider_id,
-                c.status,
-                c.created_at,
-                c.updated_at,
-                coalesce(c.closed_at, ''),
-                (
-                  SELECT count(*)
-                  FROM messages m
-                  WHERE m.conversation_id = c.id AND m.status != 'deleted'
-                ) AS message_count,
-                coalesce((
-                  SELECT m.body
-                  FROM messages m
-                  WHERE m.conversation_id = c.id
-                    AND m.status != 'deleted'
-                    AND trim(m.body) != ''
-                  ORDER BY m.ordinal DESC
-                  LIMIT 1
-                ), '') AS preview
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {CONVERSATION_SUMMARY_COLUMNS}
              FROM conversations c
-             WHERE c.model_id = ?
+             WHERE (c.model_id = ? OR {SCHEDULED_CONVERSATION})
                AND c.id != ?
                AND c.status = 'closed'
                AND EXISTS (
@@ -756,24 +1755,20 @@ impl Store {
                  )
                )
              ORDER BY coalesce(c.closed_at, c.updated_at) DESC, c.updated_at DESC
-             LIMIT ?",
-        )?;
+             LIMIT ?"
+        ))?;
         let rows = stmt.query_map(
-            params![model_id, current_id, query, like, like, like, limit],
-            |row| {
-                Ok(ConversationSummary {
-                    id: row.get(0)?,
-                    title: row.get(1)?,
-                    model_id: row.get(2)?,
-                    provider_id: row.get(3)?,
-                    status: row.get(4)?,
-                    created_at: row.get(5)?,
-                    updated_at: row.get(6)?,
-                    closed_at: row.get(7)?,
-                    message_count: row.get(8)?,
-                    preview: row.get(9)?,
-                })
-            },
+            params![
+                model_id,
+                SCHEDULED_TAG,
+                current_id,
+                query,
+                like,
+                like,
+                like,
+                limit
+            ],
+            scan_conversation_summary,
         )?;
         rows.collect()
     }
@@ -793,12 +1788,15 @@ impl Store {
         }
         let metrics = json_text(&msg.metrics_json);
         let extra = json_text(&msg.extra_json);
+        // A row's variant is fixed when it is first written; later saves
+        // (edits, metrics) leave it alone.
         self.conn
             .execute(
                 "INSERT INTO messages (
                     id, conversation_id, ordinal, sender, kind, status, body,
-                    metrics, extra, created_at, updated_at, completed_at, deleted_at
-                 ) VALUES (?, ?, ?, ?, ?, ?, ?, jsonb(?), jsonb(?), ?, nullif(?, ''), nullif(?, ''), nullif(?, ''))
+                    metrics, extra, created_at, updated_at, completed_at, deleted_at,
+                    variant_of, variant_index
+                 ) VALUES (?, ?, ?, ?, ?, ?, ?, jsonb(?), jsonb(?), ?, nullif(?, ''), nullif(?, ''), nullif(?, ''), ?, ?)
                  ON CONFLICT(id) DO UPDATE SET
                     conversation_id = excluded.conversation_id,
                     ordinal = excluded.ordinal,
@@ -824,7 +1822,9 @@ impl Store {
                     msg.created_at,
                     msg.updated_at,
                     msg.completed_at,
-                    msg.deleted_at
+                    msg.deleted_at,
+                    msg.variant_of.trim(),
+                    msg.variant_index
                 ],
             )
             .map_err(|err| err.to_string())?;
@@ -850,7 +1850,10 @@ impl Store {
         };
         let now = timestamp();
         let tx = self.conn.transaction()?;
-        tx.execute("DELETE FROM messages WHERE id = ?", params![id])?;
+        tx.execute(
+            "DELETE FROM messages WHERE id = ?1 OR (variant_of = ?1 AND variant_active = 0)",
+            params![id],
+        )?;
         tx.execute(
             "DELETE FROM response_items WHERE conversation_id = ? AND turn_ordinal = ?",
             params![conv_id, ordinal],
@@ -858,13 +1861,13 @@ impl Store {
         tx.execute(
             "UPDATE messages
                 SET ordinal = -ordinal - 1
-              WHERE conversation_id = ? AND ordinal > ?",
+              WHERE conversation_id = ? AND variant_active = 1 AND ordinal > ?",
             params![conv_id, ordinal],
         )?;
         tx.execute(
             "UPDATE messages
                 SET ordinal = -ordinal - 2
-              WHERE conversation_id = ? AND ordinal < 0",
+              WHERE conversation_id = ? AND variant_active = 1 AND ordinal < 0",
             params![conv_id],
         )?;
         tx.execute(
@@ -887,8 +1890,15 @@ impl Store {
         }
         let now = timestamp();
         let tx = self.conn.transaction()?;
+        // Parked answers go with the turns they answer, wherever they sit.
         tx.execute(
-            "DELETE FROM messages WHERE conversation_id = ? AND ordinal >= ?",
+            "DELETE FROM messages
+              WHERE conversation_id = ?1
+                AND (variant_active = 1 AND ordinal >= ?2
+                     OR variant_active = 0 AND variant_of IN (
+                       SELECT id FROM messages
+                        WHERE conversation_id = ?1 AND variant_active = 1 AND ordinal >= ?2
+                     ))",
             params![conversation_id, ordinal],
         )?;
         tx.execute(
@@ -902,6 +1912,199 @@ impl Store {
         tx.commit()
     }
 
+    /// Parks the answer to `turn_id` (the active rows between that user
+    /// message and the next) as an inactive variant, response items included,
+    /// so a regenerated answer can take its ordinals.
+    fn start_variant(
+        &mut self,
+        conversation_id: &str,
+        turn_id: &str,
+    ) -> rusqlite::Result<StartedVariant> {
+        let conversation_id = conversation_id.trim();
+        let turn_id = turn_id.trim();
+        let tx = self.conn.transaction()?;
+        let turn_ordinal: i64 = tx
+            .query_row(
+                "SELECT ordinal FROM messages
+                  WHERE id = ? AND conversation_id = ? AND variant_active = 1",
+                params![turn_id, conversation_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| {
+                rusqlite::Error::InvalidParameterName(format!("unknown turn: {turn_id}"))
+            })?;
+        let answer_end: i64 = tx.query_row(
+            "SELECT coalesce(min(ordinal), 9223372036854775807) FROM messages
+              WHERE conversation_id = ? AND variant_active = 1 AND ordinal > ?
+                AND sender = 'user' AND kind = 'chat'",
+            params![conversation_id, turn_ordinal],
+            |row| row.get(0),
+        )?;
+        // The first regenerate turns the original answer into variant 0.
+        tx.execute(
+            "UPDATE messages SET variant_of = ?1
+              WHERE conversation_id = ?2 AND variant_active = 1
+                AND ordinal > ?3 AND ordinal < ?4 AND variant_of = ''",
+            params![turn_id, conversation_id, turn_ordinal, answer_end],
+        )?;
+        tx.execute(
+            "UPDATE messages SET variant_active = 0
+              WHERE conversation_id = ? AND variant_active = 1 AND ordinal > ? AND ordinal < ?",
+            params![conversation_id, turn_ordinal, answer_end],
+        )?;
+        tx.execute(
+            "UPDATE response_items SET variant_active = 0
+              WHERE conversation_id = ? AND turn_id = ?",
+            params![conversation_id, turn_id],
+        )?;
+        let variant_index: i64 = tx.query_row(
+            "SELECT coalesce(max(variant_index) + 1, 0) FROM messages
+              WHERE conversation_id = ? AND variant_of = ?",
+            params![conversation_id, turn_id],
+            |row| row.get(0),
+        )?;
+        let next_item_ordinal: i64 = tx.query_row(
+            "SELECT coalesce(max(item_ordinal) + 1, 0) FROM response_items
+              WHERE conversation_id = ? AND turn_id = ?",
+            params![conversation_id, turn_id],
+            |row| row.get(0),
+        )?;
+        tx.execute(
+            "UPDATE conversations SET updated_at = ? WHERE id = ?",
+            params![timestamp(), conversation_id],
+        )?;
+        tx.commit()?;
+        Ok(StartedVariant {
+            variant_index,
+            next_item_ordinal,
+        })
+    }
+
+    fn list_variants(&self, message_id: &str) -> rusqlite::Result<Vec<Variant>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT
+                m.variant_index,
+                max(m.variant_active),
+                count(*),
+                coalesce((
+                  SELECT a.body FROM messages a
+                   WHERE a.variant_of = m.variant_of AND a.variant_index = m.variant_index
+                     AND a.sender = 'assistant' AND a.kind = 'chat' AND trim(a.body) != ''
+                   ORDER BY a.ordinal ASC
+                   LIMIT 1
+                ), ''),
+                min(m.created_at)
+             FROM messages m
+             WHERE m.variant_of != ''
+               AND m.variant_of = (SELECT variant_of FROM messages WHERE id = ?)
+             GROUP BY m.variant_index
+             ORDER BY m.variant_index ASC",
+        )?;
+        let rows = stmt.query_map(params![message_id.trim()], |row| {
+            Ok(Variant {
+                index: row.get(0)?,
+                active: row.get(1)?,
+                message_count: row.get(2)?,
+                preview: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Swaps the active answer of the turn `message_id` belongs to for
+    /// variant `variant_index`, moving later rows to fit its length. Returns
+    /// the conversation id.
+    fn switch_variant(&mut self, message_id: &str, variant_index: i64) -> rusqlite::Result<String> {
+        let tx = self.conn.transaction()?;
+        let (conversation_id, turn_id, turn_ordinal): (String, String, i64) = tx
+            .query_row(
+                "SELECT m.conversation_id, m.variant_of, u.ordinal
+                   FROM messages m
+                   JOIN messages u ON u.id = m.variant_of AND u.variant_active = 1
+                  WHERE m.id = ?",
+                params![message_id.trim()],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?
+            .ok_or_else(|| {
+                rusqlite::Error::InvalidParameterName(format!(
+                    "message has no variants: {message_id}"
+                ))
+            })?;
+        let parked: Vec<String> = {
+            let mut stmt = tx.prepare(
+                "SELECT id FROM messages
+                  WHERE conversation_id = ? AND variant_of = ? AND variant_index = ?
+                    AND variant_active = 0
+                  ORDER BY ordinal ASC, created_at ASC",
+            )?;
+            let rows = stmt.query_map(params![conversation_id, turn_id, variant_index], |row| {
+                row.get(0)
+            })?;
+            rows.collect::<rusqlite::Result<_>>()?
+        };
+        if parked.is_empty() {
+            // Already active, or no such variant.
+            return Ok(conversation_id);
+        }
+        let active: i64 = tx.query_row(
+            "SELECT count(*) FROM messages
+              WHERE conversation_id = ? AND variant_of = ? AND variant_active = 1",
+            params![conversation_id, turn_id],
+            |row| row.get(0),
+        )?;
+        tx.execute(
+            "UPDATE messages SET variant_active = 0
+              WHERE conversation_id = ? AND variant_of = ? AND variant_active = 1",
+            params![conversation_id, turn_id],
+        )?;
+        #[expect(
+            clippy::cast_possible_wrap,
+            reason = "a variant spans a handful of rows, far from i64::MAX"
+        )]
+        let shift = parked.len() as i64 - active;
+        if shift != 0 {
+            // Through negative ordinals, so no two active rows ever share one.
+            tx.execute(
+                "UPDATE messages
+                    SET ordinal = -(ordinal + ?) - 1
+                  WHERE conversation_id = ? AND variant_active = 1 AND ordinal > ?",
+                params![shift, conversation_id, turn_ordinal],
+            )?;
+            tx.execute(
+                "UPDATE messages
+                    SET ordinal = -ordinal - 1
+                  WHERE conversation_id = ? AND variant_active = 1 AND ordinal < 0",
+                params![conversation_id],
+            )?;
+            tx.execute(
+                "UPDATE response_items
+                    SET turn_ordinal = turn_ordinal + ?
+                  WHERE conversation_id = ? AND turn_ordinal > ?",
+                params![shift, conversation_id, turn_ordinal],
+            )?;
+        }
+        for (ordinal, id) in (turn_ordinal + 1..).zip(&parked) {
+            tx.execute(
+                "UPDATE messages SET variant_active = 1, ordinal = ? WHERE id = ?",
+                params![ordinal, id],
+            )?;
+        }
+        tx.execute(
+            "UPDATE response_items SET variant_active = (variant_index = ?)
+              WHERE conversation_id = ? AND turn_id = ?",
+            params![variant_index, conversation_id, turn_id],
+        )?;
+        tx.execute(
+            "UPDATE conversations SET updated_at = ? WHERE id = ?",
+            params![timestamp(), conversation_id],
+        )?;
+        tx.commit()?;
+        Ok(conversation_id)
+    }
+
     fn upsert_tool_call(&self, mut call: ToolCall) -> Result<(), String> {
         if call.id.trim().is_empty() {
             return Err("tool call row id is required".to_string());
@@ -999,9 +2202,9 @@ impl Store {
     fn list_response_items(&self, conversation_id: &str) -> rusqlite::Result<Vec<ResponseItem>> {
         let mut stmt = self.conn.prepare(
             "SELECT id, conversation_id, turn_id, turn_ordinal, item_ordinal, source,
-                    item_type, call_id, json(raw), created_at
+                    item_type, call_id, json(raw), created_at, variant_index
              FROM response_items
-             WHERE conversation_id = ?
+             WHERE conversation_id = ? AND variant_active = 1
              ORDER BY turn_ordinal ASC, item_ordinal ASC",
         )?;
         let rows = stmt.query_map(params![conversation_id.trim()], |row| {
@@ -1017,6 +2220,7 @@ impl Store {
                 call_id: row.get(7)?,
                 raw_json,
                 created_at: row.get(9)?,
+                variant_index: row.get(10)?,
             })
         })?;
         rows.collect()
@@ -1027,9 +2231,13 @@ impl Store {
             "SELECT
                 id, conversation_id, ordinal, sender, kind, status, body,
                 json(metrics), json(extra), created_at, coalesce(updated_at, ''),
-                coalesce(completed_at, ''), coalesce(deleted_at, '')
-             FROM messages
-             WHERE conversation_id = ? AND status != 'deleted'
+                coalesce(completed_at, ''), coalesce(deleted_at, ''), variant_of, variant_index,
+                CASE WHEN m.variant_of = '' THEN 0 ELSE (
+                  SELECT count(DISTINCT v.variant_index) FROM messages v
+                   WHERE v.variant_of = m.variant_of
+                ) END
+             FROM messages m
+             WHERE conversation_id = ? AND status != 'deleted' AND variant_active = 1
              ORDER BY ordinal ASC",
         )?;
         let rows = stmt.query_map(params![conversation_id.trim()], |row| {
@@ -1047,6 +2255,9 @@ impl Store {
                 updated_at: row.get(10)?,
                 completed_at: row.get(11)?,
                 deleted_at: row.get(12)?,
+                variant_of: row.get(13)?,
+                variant_index: row.get(14)?,
+                variant_count: row.get(15)?,
                 tool_calls: Vec::new(),
             })
         })?;
@@ -1057,10 +2268,12 @@ impl Store {
         Ok(messages)
     }
 
+    /// Only the active answer of a regenerated turn is replayed: parked
+    /// variants never leave `list_messages` or `list_response_items`.
     fn history_items(&self, conversation_id: &str) -> rusqlite::Result<Result<Vec<Value>, String>> {
         let messages = self.list_messages(conversation_id)?;
         let replay_items = self.list_response_items(conversation_id)?;
-        Ok(shaped_history(messages, replay_items))
+        Ok(shaped_history(messages, replay_items).map(apply_compactions))
     }
 
     fn close_active_conversations(&self, raw_model_id: &str) -> rusqlite::Result<()> {
@@ -1103,42 +2316,154 @@ impl Store {
         rows.collect()
     }
 
-    fn touch_conversation(&self, id: &str) -> rusqlite::Result<()> {
-        if id.trim().is_empty() {
+    fn is_untitled(&self, id: &str) -> rusqlite::Result<bool> {
+        self.conn
+            .query_row(
+                "SELECT trim(title) = '' FROM conversations WHERE id = ?",
+                params![id.trim()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map(|untitled| untitled.unwrap_or(false))
+    }
+
+    /// Sets the title; with `only_if_untitled` an existing title is kept so a
+    /// late generated title never overwrites a manual rename.
+    fn set_title(&self, id: &str, title: &str, only_if_untitled: bool) -> rusqlite::Result<()> {
+        let id = id.trim();
+        if id.is_empty() {
             return Ok(());
         }
         self.conn.execute(
-            "UPDATE conversations SET updated_at = ? WHERE id = ?",
-            params![timestamp(), id.trim()],
+            "UPDATE conversations SET title = ?
+             WHERE id = ? AND (? = 0 OR trim(title) = '')",
+            params![title.trim(), id, only_if_untitled],
         )?;
         Ok(())
     }
-}
 
-fn decode_message(value: Value) -> Result<Message, String> {
-    let Value::Object(object) = value else {
-        return Err("invalid type: expected map".to_string());
-    };
-    Ok(Message {
-        id: value_string(object.get("id")),
-        conversation_id: value_string(object.get("conversation_id")),
-        ordinal: value_i64(object.get("ordinal")),
-        sender: value_string(object.get("sender")),
-        kind: value_string(object.get("kind")),
-        status: value_string(object.get("status")),
-        body: value_string(object.get("body")),
-        metrics_json: object
-            .get("metrics_json")
-            .and_then(flexible_json_value)
-            .unwrap_or_else(|| "{}".to_string()),
-        extra_json: object
-            .get("extra_json")
-            .and_then(flexible_json_value)
-            .unwrap_or_else(|| "{}".to_string()),
-        created_at: value_string(object.get("created_at")),
-        updated_at: value_string(object.get("updated_at")),
-        completed_at: value_string(object.get("completed_at")),
+    fn generation_params(&self, conversation_id: &str) -> rusqlite::Result<String> {
+        self.conn
+            .query_row(
+                "SELECT json(params) FROM conversation_params WHERE conversation_id = ?",
+                params![conversation_id.trim()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map(|params| params.unwrap_or_else(|| "{}".to_owned()))
+    }
+
+    fn set_generation_params(
+        &self,
+        conversation_id: &str,
+        params_json: &str,
+    ) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO conversation_params (conversation_id, params, updated_at)
+             VALUES (?, jsonb(?), ?)
+             ON CONFLICT(conversation_id) DO UPDATE
+             SET params = excluded.params, updated_at = excluded.updated_at",
+            params![conversation_id.trim(), params_json, timestamp()],
+        )?;
+        Ok(())
+    }
+
+    fn insert_usage(&self, record: &UsageRecord, created_at: &str) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO usage_ledger (
+                id, conversation_id, model_id, prompt_tokens, output_tokens,
+                ttf_ms, total_ms, cost_usd, created_at
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                new_id(),
+                record.conversation_id.trim(),
+                record.model_id.trim(),
+                record.prompt_tokens,
+                record.output_tokens,
+                record.ttf_ms,
+                record.total_ms,
+                record.cost_usd,
+                created_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn usage_totals(
+        &self,
+        grouping: UsageGrouping,
+        conversation_id: &str,
+        limit: i64,
+    ) -> rusqlite::Result<Vec<UsageTotal>> {
+        let limit = if limit <= 0 || limit > 100 { 50 } else { limit };
+        let (key, label, order) = grouping.sql();
+        let sql = format!(
+            "SELECT {key} AS key, {label} AS label, count(*),
+                    sum(u.prompt_tokens), sum(u.output_tokens), sum(u.total_ms),
+                    coalesce(sum(u.cost_usd), 0), count(*) - count(u.cost_usd)
+             FROM usage_ledger u
+             LEFT JOIN conversations c ON c.id = u.conversation_id
+             WHERE ? = '' OR u.conversation_id = ?
+             GROUP BY key
+             ORDER BY {order}
+             LIMIT ?"
+        );
+        let conversation_id = conversation_id.trim();
+        let mut stmt = self.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params![conversation_id, conversation_id, limit], |row| {
+            Ok(UsageTotal {
+                key: row.get(0)?,
+                label: row.get(1)?,
+                turns: row.get(2)?,
+                prompt_tokens: row.get(3)?,
+                output_tokens: row.get(4)?,
+                total_ms: row.get(5)?,
+                cost_usd: row.get(6)?,
+                unpriced_turns: row.get(7)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    fn touch_conversation(&self, id: &str) -> rusqlite::Result<()> {
+        if id.trim().is_empty() {
+            return Ok(());
+        }
+        self.conn.execute(
+            "UPDATE conversations SET updated_at = ? WHERE id = ?",
+            params![timestamp(), id.trim()],
+        )?;
+        Ok(())
+    }
+}
+
+fn decode_message(value: Value) -> Result<Message, String> {
+    let Value::Object(object) = value else {
+        return Err("invalid type: expected map".to_string());
+    };
+    Ok(Message {
+        id: value_string(object.get("id")),
+        conversation_id: value_string(object.get("conversation_id")),
+        ordinal: value_i64(object.get("ordinal")),
+        sender: value_string(object.get("sender")),
+        kind: value_string(object.get("kind")),
+        status: value_string(object.get("status")),
+        body: value_string(object.get("body")),
+        metrics_json: object
+            .get("metrics_json")
+            .and_then(flexible_json_value)
+            .unwrap_or_else(|| "{}".to_string()),
+        extra_json: object
+            .get("extra_json")
+            .and_then(flexible_json_value)
+            .unwrap_or_else(|| "{}".to_string()),
+        created_at: value_string(object.get("created_at")),
+        updated_at: value_string(object.get("updated_at")),
+        completed_at: value_string(object.get("completed_at")),
         deleted_at: value_string(object.get("deleted_at")),
+        variant_of: value_string(object.get("variant_of")),
+        variant_index: value_i64(object.get("variant_index")),
+        variant_count: 0,
         tool_calls: Vec::new(),
     })
 }
@@ -1191,6 +2516,7 @@ fn decode_response_items(value: Value) -> Result<Vec<ResponseItem>, String> {
             call_id: value_string(object.get("call_id")),
             raw_json: response_item_raw_json(&raw, &raw_json),
             created_at: value_string(object.get("created_at")),
+            variant_index: value_i64(object.get("variant_index")),
         });
     }
     Ok(items)
@@ -1257,8 +2583,8 @@ fn upsert_response_item(tx: &Transaction<'_>, mut item: ResponseItem) -> rusqlit
     tx.execute(
         "INSERT INTO response_items (
             id, conversation_id, turn_id, turn_ordinal, item_ordinal, source,
-            item_type, call_id, raw, created_at
-         ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, jsonb(?), ?)
+            item_type, call_id, raw, created_at, variant_index
+         ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, jsonb(?), ?, ?)
          ON CONFLICT(conversation_id, turn_id, item_ordinal) DO UPDATE SET
             id = excluded.id,
             source = excluded.source,
@@ -1275,12 +2601,87 @@ fn upsert_response_item(tx: &Transaction<'_>, mut item: ResponseItem) -> rusqlit
             item.item_type.trim(),
             item.call_id.trim(),
             item.raw_json,
-            item.created_at
+            item.created_at,
+            item.variant_index
         ],
     )?;
     Ok(())
 }
 
+/// Copies the active rows of `source_id` before `ordinal` into `fork_id`,
+/// with their tool calls and response items.
+fn copy_rows_before(
+    tx: &Transaction<'_>,
+    source_id: &str,
+    fork_id: &str,
+    ordinal: i64,
+) -> rusqlite::Result<()> {
+    let copied: Vec<(String, bool)> = {
+        let mut stmt = tx.prepare(
+            "SELECT id, sender = 'user' AND kind = 'chat' FROM messages
+              WHERE conversation_id = ? AND variant_active = 1 AND ordinal < ?
+              ORDER BY ordinal ASC",
+        )?;
+        let rows = stmt.query_map(params![source_id, ordinal], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        rows.collect::<rusqlite::Result<_>>()?
+    };
+    // A fork inside an answer keeps the rows before it but not that
+    // turn's raw items, which would replay the whole answer.
+    let cut_turn = tx
+        .query_row(
+            "SELECT 1 FROM messages
+              WHERE conversation_id = ? AND variant_active = 1 AND ordinal = ?
+                AND NOT (sender = 'user' AND kind = 'chat')",
+            params![source_id, ordinal],
+            |_| Ok(()),
+        )
+        .optional()?
+        .and_then(|()| copied.iter().rev().find(|(_, is_turn)| *is_turn))
+        .map(|(id, _)| id.clone());
+    for (old_id, _) in &copied {
+        let new_message_id = new_id();
+        tx.execute(
+            "INSERT INTO messages (
+                id, conversation_id, ordinal, sender, kind, status, body,
+                metrics, extra, created_at, updated_at, completed_at, deleted_at
+             )
+             SELECT ?, ?, ordinal, sender, kind, status, body,
+                    metrics, extra, created_at, updated_at, completed_at, deleted_at
+             FROM messages WHERE id = ?",
+            params![new_message_id, fork_id, old_id],
+        )?;
+        // Tool-call row ids are global, so the copies get their own.
+        tx.execute(
+            "INSERT INTO tool_calls (
+                id, message_id, tool_call_id, tool_name, phase, status, is_error,
+                summary, subtitle, payload, created_at, updated_at
+             )
+             SELECT ?1 || ':' || tool_call_id, ?1, tool_call_id, tool_name, phase, status,
+                    is_error, summary, subtitle, payload, created_at, updated_at
+             FROM tool_calls WHERE message_id = ?2",
+            params![new_message_id, old_id],
+        )?;
+        // Response items hang off their turn's user message id.
+        if cut_turn.as_ref() == Some(old_id) {
+            continue;
+        }
+        tx.execute(
+            "INSERT INTO response_items (
+                id, conversation_id, turn_id, turn_ordinal, item_ordinal, source,
+                item_type, call_id, raw, created_at
+             )
+             SELECT ?1 || ':' || ?2 || ':' || item_ordinal, ?1, ?2, turn_ordinal,
+                    item_ordinal, source, item_type, call_id, raw, created_at
+             FROM response_items
+             WHERE conversation_id = ?3 AND turn_id = ?4 AND variant_active = 1",
+            params![fork_id, new_message_id, source_id, old_id],
+        )?;
+    }
+    Ok(())
+}
+
 fn shaped_history(
     messages: Vec<Message>,
     response_items: Vec<ResponseItem>,
@@ -1299,7 +2700,10 @@ fn shaped_history(
         if !replay_by_turn.contains_key(&item.turn_id) {
             replay_turn_order.push(item.turn_id.clone());
         }
-        if raw.get("type").map(string_from_value).unwrap_or_default() == "message" {
+        // User-role items are attached MCP context, not the assistant reply.
+        if raw.get("type").map(string_from_value).unwrap_or_default() == "message"
+            && raw.get("role").map(string_from_value).unwrap_or_default() != "user"
+        {
             replay_turns_with_message.insert(item.turn_id.clone());
         }
         replay_by_turn.entry(item.turn_id).or_default().push(raw);
@@ -1334,28 +2738,24 @@ fn shaped_history(
             active_turn_has_raw_message = replay_turns_with_message.contains(&message.id);
             continue;
         }
-        if active_turn_has_replay {
-            if message.kind == "tool" {
-                continue;
-            }
-            if message.kind == "chat"
-                && message.sender == "assistant"
-                && active_turn_has_raw_message
-            {
-                continue;
-            }
-        }
         if message.kind != "chat" {
             continue;
         }
-        if message.sender == "assistant" && message.body.trim().is_empty() {
-            continue;
-        }
         if message.sender == "assistant" {
-            out.push(assistant_text_item(
-                &responses_message_id(&message.id),
-                &message.body,
-            ));
+            // A turn's raw items already carry its reply text.
+            let replayed = active_turn_has_replay && active_turn_has_raw_message;
+            if !replayed && !message.body.trim().is_empty() {
+                out.push(assistant_text_item(
+                    &responses_message_id(&message.id),
+                    &message.body,
+                ));
+            }
+            // Generated images return as user input; assistant turns cannot
+            // carry images.
+            let generated = message_attachments(&message)?;
+            if !generated.is_empty() {
+                out.push(user_input_item(GENERATED_IMAGES_NOTE, &generated)?);
+            }
         } else {
             out.push(user_input_item(
                 &message.body,
@@ -1390,9 +2790,9 @@ fn message_attachments(message: &Message) -> Result<Vec<crate::ai::Attachment>,
 
 /// Builds a Responses API `message` input item for a user turn.
 ///
-/// Text becomes an `input_text` part; attachments become `input_image` parts
-/// (referencing a URL directly, or an inline `data:` URI for binary payloads).
-/// Only image attachments are supported.
+/// Text becomes an `input_text` part; image attachments become `input_image`
+/// parts (referencing a URL directly, or an inline `data:` URI for binary
+/// payloads), and documents become text or file parts (see `ai::documents`).
 pub(crate) fn user_input_item(
     text: &str,
     attachments: &[crate::ai::Attachment],
@@ -1412,7 +2812,8 @@ pub(crate) fn user_input_item(
         }
         if let Some((mime, b64)) = crate::ai::attachment_binary(attachment)? {
             if !mime.to_ascii_lowercase().starts_with("image/") {
-                return Err("only image attachments are supported".to_owned());
+                content.push(crate::ai::document_part(attachment, &mime, &b64)?);
+                continue;
             }
             content.push(json!({
                 "type": "input_image",
@@ -1437,6 +2838,90 @@ fn assistant_text_item(id: &str, body: &str) -> Value {
     })
 }
 
+/// Labels replayed images that the assistant generated in an earlier turn.
+const GENERATED_IMAGES_NOTE: &str = "[Images you generated in your previous reply]";
+
+/// Builds the persisted marker for a history compaction: `summary` stands in
+/// for the first `replaced_items` items that preceded it when it was written.
+pub(crate) fn compaction_item(summary: &str, replaced_items: usize) -> Value {
+    json!({
+        "type": "message",
+        "role": "user",
+        "content": [{
+            "type": "input_text",
+            "text": format!("Summary of the earlier conversation:\n\n{}", summary.trim()),
+        }],
+        "compaction": {"replaced_items": replaced_items},
+    })
+}
+
+/// Replays compaction markers in order. Each marker drops the items it
+/// summarized and moves to the front as a plain user message, so a replay
+/// sends exactly what the model saw after the compaction.
+pub(crate) fn apply_compactions(items: Vec<Value>) -> Vec<Value> {
+    let mut out = Vec::with_capacity(items.len());
+    for item in items {
+        let Some(replaced) = item
+            .pointer("/compaction/replaced_items")
+            .and_then(Value::as_u64)
+        else {
+            out.push(item);
+            continue;
+        };
+        let text = item
+            .get("content")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|part| part.get("text").and_then(Value::as_str))
+            .collect::<String>();
+        let replaced = usize::try_from(replaced)
+            .unwrap_or(usize::MAX)
+            .min(out.len());
+        out.drain(..replaced);
+        out.insert(
+            0,
+            json!({"type": "message", "role": "user", "content": [{"type": "input_text", "text": text}]}),
+        );
+    }
+    out
+}
+
+/// Turns what the user typed into an FTS5 query: every word must appear,
+/// the last as a prefix so results follow along while typing. Words are
+/// quoted, so FTS5 syntax in the input is matched as text.
+fn fts_query(raw: &str) -> Option<String> {
+    let mut words: Vec<String> = raw
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect();
+    words.last_mut()?.push('*');
+    Some(words.join(" "))
+}
+
+fn scan_conversation_summary(row: &rusqlite::Row<'_>) -> rusqlite::Result<ConversationSummary> {
+    Ok(ConversationSummary {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        model_id: row.get(2)?,
+        provider_id: row.get(3)?,
+        status: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+        closed_at: row.get(7)?,
+        message_count: row.get(8)?,
+        preview: row.get(9)?,
+        tags: row
+            .get::<_, String>(10)?
+            .split(',')
+            .filter(|tag| !tag.is_empty())
+            .map(str::to_owned)
+            .collect(),
+        parent_conversation_id: row.get(11)?,
+        fork_ordinal: row.get(12)?,
+    })
+}
+
 fn scan_conversation(row: &rusqlite::Row<'_>) -> rusqlite::Result<Conversation> {
     Ok(Conversation {
         id: row.get(0)?,
@@ -1616,7 +3101,266 @@ fn secure_files(path: &Path) -> std::io::Result<()> {
     Ok(())
 }
 
-#[cfg(unix)]
+/// Stores from before branching lack the fork columns on `conversations`.
+fn add_fork_columns(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    if has_column(tx, "conversations", "parent_conversation_id")? {
+        return Ok(());
+    }
+    tx.execute_batch(
+        "ALTER TABLE conversations
+           ADD COLUMN parent_conversation_id TEXT NOT NULL DEFAULT '';
+         ALTER TABLE conversations ADD COLUMN fork_ordinal INTEGER;",
+    )
+}
+
+/// Ordinals used to be unique per conversation at the table level; with
+/// answer variants only active rows are, and a table constraint can only be
+/// dropped by rebuilding the table.
+fn rebuild_messages_for_variants(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    if !has_column(tx, "messages", "ordinal")? || has_column(tx, "messages", "variant_of")? {
+        return Ok(());
+    }
+    if has_column(tx, "response_items", "turn_id")? {
+        tx.execute_batch(
+            "ALTER TABLE response_items
+               ADD COLUMN variant_index INTEGER NOT NULL DEFAULT 0;
+             ALTER TABLE response_items
+               ADD COLUMN variant_active INTEGER NOT NULL DEFAULT 1 CHECK (variant_active IN (0, 1));",
+        )?;
+    }
+    tx.execute_batch(
+        "ALTER TABLE messages RENAME TO messages_legacy;
+         CREATE TABLE messages (
+           id TEXT PRIMARY KEY,
+           conversation_id TEXT NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+           ordinal INTEGER NOT NULL,
+           sender TEXT NOT NULL CHECK (sender IN ('user', 'assistant', 'tool')),
+           kind TEXT NOT NULL CHECK (kind IN ('chat', 'info', 'tool')),
+           status TEXT NOT NULL DEFAULT 'complete'
+             CHECK (status IN ('streaming', 'complete', 'error', 'deleted')),
+           body TEXT NOT NULL DEFAULT '',
+           metrics BLOB NOT NULL DEFAULT (jsonb('{}')) CHECK (json_valid(metrics, 8)),
+           extra BLOB NOT NULL DEFAULT (jsonb('{}')) CHECK (json_valid(extra, 8)),
+           created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+           updated_at TEXT,
+           completed_at TEXT,
+           deleted_at TEXT,
+           variant_of TEXT NOT NULL DEFAULT '',
+           variant_index INTEGER NOT NULL DEFAULT 0,
+           variant_active INTEGER NOT NULL DEFAULT 1 CHECK (variant_active IN (0, 1)),
+           CHECK ((kind = 'tool') = (sender = 'tool'))
+         );
+         INSERT INTO messages (
+            id, conversation_id, ordinal, sender, kind, status, body,
+            metrics, extra, created_at, updated_at, completed_at, deleted_at
+         )
+         SELECT id, conversation_id, ordinal, sender, kind, status, body,
+                metrics, extra, created_at, updated_at, completed_at, deleted_at
+         FROM messages_legacy;
+         DROP TABLE messages_legacy;",
+    )
+}
+
+/// Creates `chat_search` and its triggers as they first shipped, then
+/// indexes the rows already stored.
+fn index_chat_search(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE VIRTUAL TABLE IF NOT EXISTS chat_search USING fts5(
+           text,
+           source UNINDEXED,
+           conversation_id UNINDEXED,
+           message_id UNINDEXED,
+           tokenize = 'unicode61 remove_diacritics 2'
+         );
+
+         CREATE TRIGGER IF NOT EXISTS chat_search_message_insert AFTER INSERT ON messages
+         WHEN new.variant_active = 1 AND new.status != 'deleted' AND trim(new.body) != ''
+         BEGIN
+           INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+           VALUES (new.rowid * 4, new.body, 'message', new.conversation_id, new.id);
+         END;
+
+         CREATE TRIGGER IF NOT EXISTS chat_search_message_update
+         AFTER UPDATE OF body, status, variant_active ON messages
+         BEGIN
+           DELETE FROM chat_search WHERE rowid = old.rowid * 4;
+           INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+           SELECT new.rowid * 4, new.body, 'message', new.conversation_id, new.id
+           WHERE new.variant_active = 1 AND new.status != 'deleted' AND trim(new.body) != '';
+         END;
+
+         CREATE TRIGGER IF NOT EXISTS chat_search_message_delete AFTER DELETE ON messages
+         BEGIN
+           DELETE FROM chat_search WHERE rowid = old.rowid * 4;
+         END;
+
+         CREATE TRIGGER IF NOT EXISTS chat_search_title_insert AFTER INSERT ON conversations
+         WHEN trim(new.title) != ''
+         BEGIN
+           INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+           VALUES (new.rowid * 4 + 1, new.title, 'title', new.id, '');
+         END;
+
+         CREATE TRIGGER IF NOT EXISTS chat_search_title_update AFTER UPDATE OF title ON conversations
+         BEGIN
+           DELETE FROM chat_search WHERE rowid = old.rowid * 4 + 1;
+           INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+           SELECT new.rowid * 4 + 1, new.title, 'title', new.id, ''
+           WHERE trim(new.title) != '';
+         END;
+
+         CREATE TRIGGER IF NOT EXISTS chat_search_title_delete AFTER DELETE ON conversations
+         BEGIN
+           DELETE FROM chat_search WHERE rowid = old.rowid * 4 + 1;
+         END;
+
+         CREATE TRIGGER IF NOT EXISTS chat_search_tool_insert AFTER INSERT ON tool_calls
+         WHEN trim(new.summary || new.subtitle) != ''
+         BEGIN
+           INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+           SELECT new.rowid * 4 + 2, trim(new.summary || char(10) || new.subtitle), 'tool',
+                  m.conversation_id, new.message_id
+           FROM messages m WHERE m.id = new.message_id;
+         END;
+
+         CREATE TRIGGER IF NOT EXISTS chat_search_tool_update AFTER UPDATE OF summary, subtitle ON tool_calls
+         BEGIN
+           DELETE FROM chat_search WHERE rowid = old.rowid * 4 + 2;
+           INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+           SELECT new.rowid * 4 + 2, trim(new.summary || char(10) || new.subtitle), 'tool',
+                  m.conversation_id, new.message_id
+           FROM messages m
+           WHERE m.id = new.message_id AND trim(new.summary || new.subtitle) != '';
+         END;
+
+         CREATE TRIGGER IF NOT EXISTS chat_search_tool_delete AFTER DELETE ON tool_calls
+         BEGIN
+           DELETE FROM chat_search WHERE rowid = old.rowid * 4 + 2;
+         END;
+
+         DELETE FROM chat_search;
+         INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+         SELECT rowid * 4, body, 'message', conversation_id, id FROM messages
+         WHERE variant_active = 1 AND status != 'deleted' AND trim(body) != '';
+         INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+         SELECT rowid * 4 + 1, title, 'title', id, '' FROM conversations
+         WHERE trim(title) != '';
+         INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+         SELECT t.rowid * 4 + 2, trim(t.summary || char(10) || t.subtitle), 'tool',
+                m.conversation_id, t.message_id
+         FROM tool_calls t JOIN messages m ON m.id = t.message_id
+         WHERE trim(t.summary || t.subtitle) != '';",
+    )
+}
+
+/// Re-creates the message and tool triggers so the index skips info
+/// notices and follows a message being deleted or parked, then re-indexes.
+fn scope_chat_search(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "DROP TRIGGER IF EXISTS chat_search_message_insert;
+         DROP TRIGGER IF EXISTS chat_search_message_update;
+         DROP TRIGGER IF EXISTS chat_search_tool_insert;
+         DROP TRIGGER IF EXISTS chat_search_tool_update;
+
+         CREATE TRIGGER chat_search_message_insert AFTER INSERT ON messages
+         WHEN new.kind = 'chat' AND new.variant_active = 1 AND new.status != 'deleted'
+           AND trim(new.body) != ''
+         BEGIN
+           INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+           VALUES (new.rowid * 4, new.body, 'message', new.conversation_id, new.id);
+         END;
+
+         CREATE TRIGGER chat_search_message_update
+         AFTER UPDATE OF body, status, variant_active ON messages
+         BEGIN
+           DELETE FROM chat_search WHERE rowid = old.rowid * 4;
+           INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+           SELECT new.rowid * 4, new.body, 'message', new.conversation_id, new.id
+           WHERE new.kind = 'chat' AND new.variant_active = 1 AND new.status != 'deleted'
+             AND trim(new.body) != '';
+           DELETE FROM chat_search
+           WHERE (old.status != new.status OR old.variant_active != new.variant_active)
+             AND rowid IN (SELECT t.rowid * 4 + 2 FROM tool_calls t WHERE t.message_id = new.id);
+           INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+           SELECT t.rowid * 4 + 2, trim(t.summary || char(10) || t.subtitle), 'tool',
+                  new.conversation_id, t.message_id
+           FROM tool_calls t
+           WHERE (old.status != new.status OR old.variant_active != new.variant_active)
+             AND new.variant_active = 1 AND new.status != 'deleted'
+             AND t.message_id = new.id AND trim(t.summary || t.subtitle) != '';
+         END;
+
+         CREATE TRIGGER chat_search_tool_insert AFTER INSERT ON tool_calls
+         WHEN trim(new.summary || new.subtitle) != ''
+         BEGIN
+           INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+           SELECT new.rowid * 4 + 2, trim(new.summary || char(10) || new.subtitle), 'tool',
+                  m.conversation_id, new.message_id
+           FROM messages m
+           WHERE m.id = new.message_id AND m.variant_active = 1 AND m.status != 'deleted';
+         END;
+
+         CREATE TRIGGER chat_search_tool_update AFTER UPDATE OF summary, subtitle ON tool_calls
+         BEGIN
+           DELETE FROM chat_search WHERE rowid = old.rowid * 4 + 2;
+           INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+           SELECT new.rowid * 4 + 2, trim(new.summary || char(10) || new.subtitle), 'tool',
+                  m.conversation_id, new.message_id
+           FROM messages m
+           WHERE m.id = new.message_id AND m.variant_active = 1 AND m.status != 'deleted'
+             AND trim(new.summary || new.subtitle) != '';
+         END;
+
+         DELETE FROM chat_search;
+         INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+         SELECT rowid * 4, body, 'message', conversation_id, id FROM messages
+         WHERE kind = 'chat' AND variant_active = 1 AND status != 'deleted'
+           AND trim(body) != '';
+         INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+         SELECT rowid * 4 + 1, title, 'title', id, '' FROM conversations
+         WHERE trim(title) != '';
+         INSERT INTO chat_search (rowid, text, source, conversation_id, message_id)
+         SELECT t.rowid * 4 + 2, trim(t.summary || char(10) || t.subtitle), 'tool',
+                m.conversation_id, t.message_id
+         FROM tool_calls t JOIN messages m ON m.id = t.message_id
+         WHERE m.variant_active = 1 AND m.status != 'deleted'
+           AND trim(t.summary || t.subtitle) != '';",
+    )
+}
+
+fn set_user_version(conn: &Connection, version: usize) -> rusqlite::Result<()> {
+    let version = i64::try_from(version)
+        .map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?;
+    conn.pragma_update(None, "user_version", version)
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT count(*) > 0 FROM pragma_table_info(?) WHERE name = ?",
+        params![table, column],
+        |row| row.get(0),
+    )
+}
+
+/// Copies the store to `<path>.v<version>.bak` before it is migrated past
+/// `version`, replacing an earlier copy from the same version.
+fn back_up_store(conn: &Connection, path: &Path, version: usize) -> rusqlite::Result<()> {
+    let backup = sqlite_sidecar_path(path, &format!(".v{version}.bak"));
+    match fs::remove_file(&backup) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(io_to_sql(err)),
+        _ => {}
+    }
+    conn.execute("VACUUM INTO ?", [backup.to_string_lossy()])?;
+    secure_files(&backup).map_err(io_to_sql)
+}
+
+fn schema_error(message: String) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
+        Some(message),
+    )
+}
+
 fn sqlite_sidecar_path(path: &Path, suffix: &str) -> PathBuf {
     let mut raw = path.as_os_str().to_owned();
     raw.push(suffix);
@@ -1627,6 +3371,53 @@ fn io_to_sql(err: std::io::Error) -> rusqlite::Error {
     rusqlite::Error::ToSqlConversionFailure(Box::new(err))
 }
 
+#[derive(Clone, Copy)]
+enum UsageGrouping {
+    Conversation,
+    Model,
+    Day,
+    Month,
+}
+
+impl UsageGrouping {
+    fn parse(raw: &str) -> Option<Self> {
+        match raw.trim() {
+            "" | "conversation" => Some(Self::Conversation),
+            "model" => Some(Self::Model),
+            "day" => Some(Self::Day),
+            "month" => Some(Self::Month),
+            _ => None,
+        }
+    }
+
+    /// Key, label and ordering expressions over `usage_ledger u` joined with
+    /// `conversations c`.
+    fn sql(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Self::Conversation => (
+                "u.conversation_id",
+                "coalesce(nullif(trim(max(c.title)), ''), '')",
+                "max(u.created_at) DESC",
+            ),
+            Self::Model => (
+                "u.model_id",
+                "u.model_id",
+                "sum(u.cost_usd) DESC, count(*) DESC",
+            ),
+            Self::Day => (
+                "strftime('%Y-%m-%d', u.created_at, 'localtime')",
+                "strftime('%Y-%m-%d', u.created_at, 'localtime')",
+                "key DESC",
+            ),
+            Self::Month => (
+                "strftime('%Y-%m', u.created_at, 'localtime')",
+                "strftime('%Y-%m', u.created_at, 'localtime')",
+                "key DESC",
+            ),
+        }
+    }
+}
+
 fn ok_result() -> ApiResult {
     ApiResult {
         ok: true,
@@ -1872,6 +3663,853 @@ mod tests {
         assert_eq!(history[1]["id"], "msg_assistant1");
     }
 
+    #[test]
+    fn generated_images_are_stored_once_and_replayed_as_input() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let first = write_attachment(dir.path(), b"png bytes", "image/png").expect("write");
+        let again = write_attachment(dir.path(), b"png bytes", "image/png").expect("rewrite");
+        assert_eq!(first.path, again.path);
+        assert!(Path::new(&first.path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.len() == 64 + ".png".len()));
+        assert_eq!(fs::read_dir(dir.path()).expect("list").count(), 1);
+
+        let (store, conversation_id) = test_store();
+        upsert_chat(&store, &conversation_id, "user-1", 0, "user", "draw a cat");
+        store
+            .upsert_message(Message {
+                id: "assistant-1".to_string(),
+                conversation_id: conversation_id.clone(),
+                ordinal: 1,
+                sender: "assistant".to_string(),
+                kind: "chat".to_string(),
+                status: "complete".to_string(),
+                extra_json: json!({ "attachments": [first] }).to_string(),
+                ..Message::default()
+            })
+            .expect("upsert image reply");
+        let history = store
+            .history_items(&conversation_id)
+            .expect("history query")
+            .expect("shaped history");
+        assert_eq!(history.len(), 2, "an image-only reply has no text item");
+        assert_eq!(history[1]["role"], "user");
+        assert_eq!(history[1]["content"][0]["text"], GENERATED_IMAGES_NOTE);
+        assert!(history[1]["content"][1]["image_url"]
+            .as_str()
+            .is_some_and(|url| url.starts_with("data:image/png;base64,")));
+    }
+
+    #[test]
+    fn compaction_marker_replaces_the_turns_it_summarized_on_replay() {
+        let (mut store, conversation_id) = test_store();
+        for (ordinal, sender, body) in [
+            (0, "user", "first"),
+            (1, "assistant", "second"),
+            (2, "user", "third"),
+        ] {
+            upsert_chat(
+                &store,
+                &conversation_id,
+                &format!("msg-{ordinal}"),
+                ordinal,
+                sender,
+                body,
+            );
+        }
+        let marker = compaction_item("asked first, answered second", 2);
+        store
+            .upsert_response_items(
+                &conversation_id,
+                "msg-2",
+                2,
+                vec![ResponseItem {
+                    turn_id: "msg-2".to_string(),
+                    item_ordinal: 0,
+                    source: "model_output".to_string(),
+                    raw_json: marker.to_string(),
+                    ..ResponseItem::default()
+                }],
+            )
+            .expect("upsert compaction");
+
+        let history = store
+            .history_items(&conversation_id)
+            .expect("history query")
+            .expect("shaped history");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0]["content"][0]["type"], "input_text");
+        assert!(history[0]["content"][0]["text"]
+            .as_str()
+            .is_some_and(|text| text.ends_with("asked first, answered second")));
+        assert!(history[0].get("compaction").is_none());
+        assert_eq!(history[1]["content"][0]["text"], "third");
+    }
+
+    #[test]
+    fn generated_titles_fill_once_and_are_searchable() {
+        let (mut store, conversation_id) = test_store();
+        upsert_chat(&store, &conversation_id, "msg-0", 0, "user", "hello");
+        assert!(store.is_untitled(&conversation_id).expect("untitled"));
+        store
+            .set_title(&conversation_id, "Renamed by hand", false)
+            .expect("rename");
+        store
+            .set_title(&conversation_id, "Generated", true)
+            .expect("fill");
+        assert!(!store.is_untitled(&conversation_id).expect("titled"));
+
+        let opts = OpenConversationOptions {
+            model_id: "local/gpt-5.4-mini".to_string(),
+            ..OpenConversationOptions::default()
+        };
+        store
+            .create_conversation(&opts)
+            .expect("close by creating another");
+        let found = store
+            .list_closed_conversations(&opts, "", "by hand", 10)
+            .expect("search");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].title, "Renamed by hand");
+    }
+
+    #[test]
+    fn scheduled_turns_are_tagged_and_leave_the_active_conversation_open() {
+        let (mut store, active_id) = test_store();
+        let (conversation_id, turn_id) = store
+            .open_tagged_conversation(
+                "local/gpt-5.4-mini",
+                "",
+                "Morning briefing",
+                SCHEDULED_TAG,
+                "Summarize my inbox.",
+            )
+            .expect("open scheduled");
+        store
+            .finish_background_turn(
+                &conversation_id,
+                &turn_id,
+                vec![
+                    (
+                        "model_output".to_owned(),
+                        json!({"type":"function_call","call_id":"c1","name":"email_list","arguments":"{}"}),
+                    ),
+                    (
+                        "tool_output".to_owned(),
+                        json!({"type":"function_call_output","call_id":"c1","output":"2 unread"}),
+                    ),
+                    (
+                        "model_output".to_owned(),
+                        json!({"type":"message","role":"assistant","content":[{"type":"output_text","text":"Two unread."}]}),
+                    ),
+                ],
+                "Two unread.",
+                &[],
+                "",
+            )
+            .expect("finish");
+
+        let opts = OpenConversationOptions {
+            model_id: "local/gpt-5.4-mini".to_string(),
+            ..OpenConversationOptions::default()
+        };
+        assert_eq!(
+            store
+                .restore_conversation(&opts)
+                .expect("restore")
+                .map(|conv| conv.id),
+            Some(active_id)
+        );
+        let closed = store
+            .list_closed_conversations(&opts, "", "", 10)
+            .expect("list");
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].title, "Morning briefing");
+        assert_eq!(closed[0].tags, vec![SCHEDULED_TAG.to_owned()]);
+        assert_eq!(closed[0].preview, "Two unread.");
+
+        let history = store
+            .history_items(&conversation_id)
+            .expect("history")
+            .expect("shaped");
+        let types: Vec<_> = history
+            .iter()
+            .map(|item| item["type"].as_str().unwrap_or_default().to_owned())
+            .collect();
+        assert_eq!(
+            types,
+            [
+                "message",
+                "function_call",
+                "function_call_output",
+                "message"
+            ]
+        );
+    }
+
+    #[test]
+    fn scheduled_runs_of_other_models_resume_under_the_current_model() {
+        let (mut store, active_id) = test_store();
+        let (conversation_id, turn_id) = store
+            .open_tagged_conversation(
+                "gemini/gemini-2.5-flash",
+                "",
+                "Weekly digest",
+                SCHEDULED_TAG,
+                "Digest my week.",
+            )
+            .expect("open scheduled");
+        store
+            .finish_background_turn(&conversation_id, &turn_id, Vec::new(), "Done.", &[], "")
+            .expect("finish");
+
+        let opts = OpenConversationOptions {
+            model_id: "local/gpt-5.4-mini".to_string(),
+            provider_id: "local".to_string(),
+            ..OpenConversationOptions::default()
+        };
+        let closed = store
+            .list_closed_conversations(&opts, &active_id, "", 10)
+            .expect("list");
+        assert_eq!(
+            closed
+                .iter()
+                .map(|conv| conv.id.as_str())
+                .collect::<Vec<_>>(),
+            [conversation_id.as_str()]
+        );
+
+        let (resumed, messages) = store
+            .resume_conversation(&opts, &active_id, &conversation_id)
+            .expect("resume");
+        assert_eq!(resumed.model_id, "local/gpt-5.4-mini");
+        assert_eq!(resumed.provider_id, "local");
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            store
+                .restore_conversation(&opts)
+                .expect("restore")
+                .map(|conv| conv.id),
+            Some(conversation_id)
+        );
+    }
+
+    #[test]
+    fn closed_chats_of_other_models_resume_only_when_picked_by_id() {
+        let (mut store, active_id) = test_store();
+        let other = store
+            .create_conversation(&OpenConversationOptions {
+                model_id: "local/other".to_owned(),
+                provider_id: "local".to_owned(),
+                ..OpenConversationOptions::default()
+            })
+            .expect("other model");
+        upsert_chat(&store, &other.id, "q1", 0, "user", "Found by search");
+        store.close_conversation(&other.id).expect("close");
+
+        let opts = OpenConversationOptions {
+            model_id: "local/gpt-5.4-mini".to_owned(),
+            provider_id: "local".to_owned(),
+            ..OpenConversationOptions::default()
+        };
+        let closed = store
+            .list_closed_conversations(&opts, &active_id, "", 10)
+            .expect("list");
+        assert!(closed.is_empty());
+        assert!(store.resume_conversation(&opts, &active_id, "").is_err());
+
+        let (resumed, messages) = store
+            .resume_conversation(&opts, &active_id, &other.id)
+            .expect("resume by id");
+        assert_eq!(resumed.model_id, "local/gpt-5.4-mini");
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[test]
+    fn regenerated_answers_are_kept_as_switchable_variants() {
+        let (mut store, conv) = test_store();
+        upsert_chat(&store, &conv, "q1", 0, "user", "Question");
+        upsert_chat(&store, &conv, "a1", 1, "assistant", "Let me check.");
+        upsert_chat(&store, &conv, "a1b", 2, "assistant", "First answer.");
+        let reply = |text: &str, item_ordinal, variant_index| ResponseItem {
+            item_ordinal,
+            variant_index,
+            raw_json: json!({ "type": "message", "role": "assistant", "content": text })
+                .to_string(),
+            ..ResponseItem::default()
+        };
+        store
+            .upsert_response_items(&conv, "q1", 0, vec![reply("First answer.", 0, 0)])
+            .expect("first items");
+
+        let started = store.start_variant(&conv, "q1").expect("start variant");
+        assert_eq!(started.variant_index, 1);
+        assert_eq!(started.next_item_ordinal, 1);
+        assert_eq!(store.list_messages(&conv).expect("list").len(), 1);
+        store
+            .upsert_message(Message {
+                id: "a2".to_owned(),
+                conversation_id: conv.clone(),
+                ordinal: 1,
+                sender: "assistant".to_owned(),
+                kind: "chat".to_owned(),
+                body: "Second answer.".to_owned(),
+                variant_of: "q1".to_owned(),
+                variant_index: 1,
+                ..Message::default()
+            })
+            .expect("regenerated answer");
+        store
+            .upsert_response_items(&conv, "q1", 0, vec![reply("Second answer.", 1, 1)])
+            .expect("second items");
+        upsert_chat(&store, &conv, "q2", 2, "user", "Follow-up");
+        upsert_chat(&store, &conv, "a3", 3, "assistant", "Sure.");
+
+        let history = store
+            .history_items(&conv)
+            .expect("history query")
+            .expect("shaped history");
+        let text = serde_json::to_string(&history).expect("history json");
+        assert!(text.contains("Second answer.") && !text.contains("First answer."));
+        let variants = store.list_variants("a2").expect("variants");
+        let summary: Vec<_> = variants
+            .iter()
+            .map(|v| (v.index, v.active, v.message_count, v.preview.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0, false, 2, "Let me check."),
+                (1, true, 1, "Second answer.")
+            ]
+        );
+
+        // The longer original pushes the follow-up turn down a row.
+        assert_eq!(store.switch_variant("a2", 0).expect("switch"), conv);
+        let rows: Vec<_> = store
+            .list_messages(&conv)
+            .expect("list")
+            .into_iter()
+            .map(|m| (m.ordinal, m.id, m.variant_count))
+            .collect();
+        let expected = [
+            (0, "q1", 0),
+            (1, "a1", 2),
+            (2, "a1b", 2),
+            (3, "q2", 0),
+            (4, "a3", 0),
+        ];
+        assert_eq!(
+            rows,
+            expected.map(|(ordinal, id, count)| (ordinal, id.to_owned(), count))
+        );
+        let history = store
+            .history_items(&conv)
+            .expect("history query")
+            .expect("shaped history");
+        let text = serde_json::to_string(&history).expect("history json");
+        assert!(text.contains("First answer.") && !text.contains("Second answer."));
+
+        store.delete_from_ordinal(&conv, 0).expect("delete all");
+        let left: i64 = store
+            .conn
+            .query_row("SELECT count(*) FROM messages", [], |row| row.get(0))
+            .expect("count");
+        assert_eq!(left, 0);
+    }
+
+    #[test]
+    fn stores_from_before_variants_are_rebuilt_in_place() {
+        let dir = tempfile_dir();
+        let path = dir.join("conversations.sqlite");
+        let legacy = Connection::open(&path).expect("open legacy");
+        legacy
+            .execute_batch(
+                "CREATE TABLE conversations (
+                   id TEXT PRIMARY KEY, model_id TEXT NOT NULL, title TEXT NOT NULL DEFAULT '',
+                   status TEXT NOT NULL DEFAULT 'active', updated_at TEXT NOT NULL DEFAULT ''
+                 );
+                 CREATE TABLE messages (
+                   id TEXT PRIMARY KEY,
+                   conversation_id TEXT NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+                   ordinal INTEGER NOT NULL,
+                   sender TEXT NOT NULL, kind TEXT NOT NULL,
+                   status TEXT NOT NULL DEFAULT 'complete', body TEXT NOT NULL DEFAULT '',
+                   metrics BLOB NOT NULL DEFAULT (jsonb('{}')),
+                   extra BLOB NOT NULL DEFAULT (jsonb('{}')),
+                   created_at TEXT NOT NULL DEFAULT '', updated_at TEXT,
+                   completed_at TEXT, deleted_at TEXT,
+                   UNIQUE(conversation_id, ordinal)
+                 );
+                 CREATE TABLE tool_calls (
+                   id TEXT PRIMARY KEY,
+                   message_id TEXT NOT NULL REFERENCES messages(id) ON DELETE CASCADE,
+                   tool_call_id TEXT NOT NULL, tool_name TEXT NOT NULL,
+                   phase TEXT NOT NULL, status TEXT NOT NULL,
+                   is_error INTEGER NOT NULL DEFAULT 0,
+                   summary TEXT NOT NULL DEFAULT '', subtitle TEXT NOT NULL DEFAULT '',
+                   payload BLOB NOT NULL DEFAULT (jsonb('{}')),
+                   created_at TEXT NOT NULL DEFAULT '', updated_at TEXT
+                 );
+                 INSERT INTO conversations (id, model_id) VALUES ('c1', 'local/gpt-5.4-mini');
+                 INSERT INTO messages (id, conversation_id, ordinal, sender, kind, body)
+                 VALUES ('m1', 'c1', 0, 'user', 'chat', 'kept');
+                 INSERT INTO tool_calls (id, message_id, tool_call_id, tool_name, phase, status)
+                 VALUES ('t1', 'm1', 'call-1', 'search', 'tool_done', 'success');",
+            )
+            .expect("legacy schema");
+        drop(legacy);
+
+        let store = Store::open(&path.to_string_lossy()).expect("upgrade");
+        assert!(has_column(&store.conn, "messages", "variant_of").expect("columns"));
+        let messages = store.list_messages("c1").expect("list");
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].body, "kept");
+        assert_eq!(messages[0].tool_calls.len(), 1);
+        assert_eq!(store.search("kept", 5).expect("search").len(), 1);
+        let version: i64 = store
+            .conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .expect("version");
+        assert_eq!(usize::try_from(version).ok(), Some(MIGRATIONS.len()));
+        // Each step left a copy of the store it started from.
+        let before = Connection::open(dir.join("conversations.sqlite.v0.bak")).expect("backup");
+        assert!(!has_column(&before, "messages", "variant_of").expect("backup columns"));
+        assert!(dir.join("conversations.sqlite.v2.bak").exists());
+        // Tool calls still cascade from the rebuilt table.
+        store
+            .conn
+            .execute("DELETE FROM messages WHERE id = 'm1'", [])
+            .expect("delete");
+        let calls: i64 = store
+            .conn
+            .query_row("SELECT count(*) FROM tool_calls", [], |row| row.get(0))
+            .expect("count");
+        assert_eq!(calls, 0);
+    }
+
+    #[test]
+    fn failed_migrations_roll_back_and_newer_stores_are_refused() {
+        fn add_pinned(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+            tx.execute_batch("ALTER TABLE conversations ADD COLUMN pinned INTEGER")
+        }
+        fn broken(tx: &Transaction<'_>) -> rusqlite::Result<()> {
+            tx.execute_batch(
+                "ALTER TABLE conversations ADD COLUMN archived INTEGER;
+                 UPDATE missing_table SET x = 1;",
+            )
+        }
+        let (mut store, _) = test_store();
+        let path = PathBuf::from(store.conn.path().expect("path"));
+        let version = |store: &Store| -> usize {
+            let version: i64 = store
+                .conn
+                .pragma_query_value(None, "user_version", |row| row.get(0))
+                .expect("version");
+            usize::try_from(version).expect("non-negative version")
+        };
+        let latest = MIGRATIONS.len();
+        assert_eq!(version(&store), latest);
+        assert!(!sqlite_sidecar_path(&path, ".v0.bak").exists());
+
+        let migrations = [
+            Migration {
+                name: "pinned",
+                apply: add_pinned,
+            },
+            Migration {
+                name: "broken",
+                apply: broken,
+            },
+        ];
+        let err = store
+            .migrate(&path, &[MIGRATIONS, &migrations].concat())
+            .expect_err("broken step");
+        let failed = format!("schema migration {} (broken)", latest + 2);
+        assert!(err.to_string().contains(&failed));
+        // The step before it stuck; the broken one left nothing behind.
+        assert_eq!(version(&store), latest + 1);
+        assert!(has_column(&store.conn, "conversations", "pinned").expect("pinned"));
+        assert!(!has_column(&store.conn, "conversations", "archived").expect("archived"));
+        assert!(sqlite_sidecar_path(&path, &format!(".v{}.bak", latest + 1)).exists());
+        let foreign_keys: bool = store
+            .conn
+            .pragma_query_value(None, "foreign_keys", |row| row.get(0))
+            .expect("foreign keys");
+        assert!(foreign_keys);
+
+        // Another connection took the step while this one waited to write.
+        Connection::open(&path)
+            .expect("second connection")
+            .pragma_update(
+                None,
+                "user_version",
+                i64::try_from(latest + 2).expect("version"),
+            )
+            .expect("bump version");
+        store
+            .apply_migration(&migrations[1], latest + 2)
+            .expect("skipped step");
+        assert!(!has_column(&store.conn, "conversations", "archived").expect("archived"));
+
+        // This build does not know the extra steps.
+        drop(store);
+        let err = Store::open(&path.to_string_lossy())
+            .err()
+            .expect("newer store");
+        assert!(err
+            .to_string()
+            .contains(&format!("schema version {}", latest + 2)));
+    }
+
+    #[test]
+    fn forks_copy_the_rows_before_the_fork_point_and_list_as_a_tree() {
+        let (mut store, root) = test_store();
+        for (ordinal, id, sender, body) in [
+            (0, "q1", "user", "Question"),
+            (1, "a1", "assistant", "Answer"),
+            (2, "q2", "user", "Follow-up"),
+            (3, "a2", "assistant", "Second answer"),
+        ] {
+            upsert_chat(&store, &root, id, ordinal, sender, body);
+        }
+        for (turn, ordinal, text) in [("q1", 0, "Answer"), ("q2", 2, "Second answer")] {
+            let item = ResponseItem {
+                raw_json: json!({ "type": "message", "role": "assistant", "content": text })
+                    .to_string(),
+                ..ResponseItem::default()
+            };
+            store
+                .upsert_response_items(&root, turn, ordinal, vec![item])
+                .expect("items");
+        }
+        store
+            .upsert_tool_call(ToolCall {
+                id: "call-1".to_owned(),
+                message_id: "a1".to_owned(),
+                call_id: "call-1".to_owned(),
+                tool_name: "search".to_owned(),
+                phase: "tool_done".to_owned(),
+                status: "success".to_owned(),
+                ..ToolCall::default()
+            })
+            .expect("tool call");
+
+        // Edit the follow-up: fork before it.
+        let (fork, messages) = store.fork_conversation(&root, "q2").expect("fork");
+        let bodies: Vec<_> = messages.iter().map(|m| m.body.as_str()).collect();
+        assert_eq!(bodies, ["Question", "Answer"]);
+        assert_ne!(messages[1].id, "a1");
+        assert_eq!(messages[1].tool_calls.len(), 1);
+        let text = serde_json::to_string(
+            &store
+                .history_items(&fork.id)
+                .expect("history query")
+                .expect("shaped history"),
+        )
+        .expect("history json");
+        assert!(text.contains("Answer") && !text.contains("Second answer"));
+        // The source is closed, untouched.
+        assert_eq!(store.list_messages(&root).expect("source").len(), 4);
+        assert!(!store
+            .list_closed_conversations(
+                &OpenConversationOptions {
+                    model_id: "local/gpt-5.4-mini".to_owned(),
+                    ..OpenConversationOptions::default()
+                },
+                &fork.id,
+                "",
+                10,
+            )
+            .expect("closed")
+            .is_empty());
+
+        let nested_at = &messages[1].id;
+        let (nested, _) = store
+            .fork_conversation(&fork.id, nested_at)
+            .expect("nested fork");
+        let tree: Vec<_> = store
+            .list_branches(&nested.id)
+            .expect("branches")
+            .into_iter()
+            .map(|b| (b.id, b.parent_conversation_id, b.fork_ordinal))
+            .collect();
+        assert_eq!(
+            tree,
+            [
+                (root.clone(), String::new(), None),
+                (fork.id.clone(), root, Some(2)),
+                (nested.id, fork.id, Some(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn forks_find_their_fork_point_past_deleted_rows() {
+        let (mut store, conv) = test_store();
+        for (ordinal, id, sender, body) in [
+            (0, "q1", "user", "Question"),
+            (1, "a1", "assistant", "Answer"),
+            (2, "q2", "user", "Follow-up"),
+            (3, "a2", "assistant", "Second answer"),
+            (4, "q3", "user", "Last one"),
+        ] {
+            upsert_chat(&store, &conv, id, ordinal, sender, body);
+        }
+        store.mark_message_deleted("a1").expect("delete");
+
+        // `q3` is the panel's third row but sits at ordinal 4.
+        let (fork, messages) = store.fork_conversation(&conv, "q3").expect("fork");
+        let bodies: Vec<_> = messages.iter().map(|m| m.body.as_str()).collect();
+        assert_eq!(bodies, ["Question", "Follow-up", "Second answer"]);
+        assert!(store.fork_conversation(&fork.id, "a1").is_err());
+    }
+
+    #[test]
+    fn search_ranks_messages_titles_and_tool_summaries_across_models() {
+        let (mut store, conv) = test_store();
+        upsert_chat(
+            &store,
+            &conv,
+            "q1",
+            0,
+            "user",
+            "How do I tune the quokka kernel?",
+        );
+        upsert_chat(
+            &store,
+            &conv,
+            "a1",
+            1,
+            "assistant",
+            "Raise the sysctl limit.",
+        );
+        upsert_chat(&store, &conv, "gone", 2, "user", "quokka typo");
+        store.mark_message_deleted("gone").expect("delete");
+        store
+            .upsert_tool_call(ToolCall {
+                id: "call-1".to_owned(),
+                message_id: "a1".to_owned(),
+                call_id: "call-1".to_owned(),
+                tool_name: "shell".to_owned(),
+                phase: "tool_done".to_owned(),
+                status: "success".to_owned(),
+                summary: "Ran sysctl -a".to_owned(),
+                ..ToolCall::default()
+            })
+            .expect("tool call");
+        let other = store
+            .create_conversation(&OpenConversationOptions {
+                model_id: "local/other".to_owned(),
+                provider_id: "local".to_owned(),
+                ..OpenConversationOptions::default()
+            })
+            .expect("second model");
+        store
+            .set_title(&other.id, "Quokka facts", false)
+            .expect("title");
+        upsert_chat(&store, &other.id, "q2", 0, "user", "Tell me something");
+        upsert_chat(
+            &store,
+            &other.id,
+            "parked",
+            1,
+            "assistant",
+            "A quokka answer",
+        );
+        store
+            .conn
+            .execute(
+                "UPDATE messages SET variant_active = 0 WHERE id = 'parked'",
+                [],
+            )
+            .expect("park variant");
+
+        let hits = |store: &Store, query: &str| -> Vec<(String, String)> {
+            store
+                .search(query, 10)
+                .expect("search")
+                .into_iter()
+                .map(|hit| (hit.source, hit.message_id))
+                .collect()
+        };
+        let mut found = hits(&store, "quok");
+        found.sort();
+        let expected = [("message", "q1"), ("title", "")];
+        assert_eq!(found, expected.map(|(s, id)| (s.to_owned(), id.to_owned())));
+        let mut found = hits(&store, "SYSCTL");
+        found.sort();
+        let expected = [("message", "a1"), ("tool", "a1")];
+        assert_eq!(found, expected.map(|(s, id)| (s.to_owned(), id.to_owned())));
+        assert!(hits(&store, "  ").is_empty());
+        assert!(hits(&store, "\"unbalanced AND (").is_empty());
+        let snippet = &store.search("kernel", 1).expect("search")[0].snippet;
+        assert!(snippet.contains(&format!("{SNIPPET_OPEN}kernel{SNIPPET_CLOSE}")));
+
+        // Stores from before the index are filled in when opened.
+        store
+            .conn
+            .execute_batch("DROP TABLE chat_search; PRAGMA user_version = 2;")
+            .expect("drop index");
+        let path = store.conn.path().expect("path").to_owned();
+        drop(store);
+        let store = Store::open(&path).expect("reopen");
+        assert_eq!(
+            hits(&store, "quokka kernel"),
+            [("message".to_owned(), "q1".to_owned())]
+        );
+    }
+
+    #[test]
+    fn search_skips_info_notices_and_tools_of_hidden_messages() {
+        let (mut store, conv) = test_store();
+        upsert_chat(&store, &conv, "q1", 0, "user", "Check the wombat");
+        store
+            .upsert_message(Message {
+                id: "note".to_owned(),
+                conversation_id: conv.clone(),
+                ordinal: 1,
+                sender: "assistant".to_owned(),
+                kind: "info".to_owned(),
+                status: "complete".to_owned(),
+                body: "**Search:** wombat".to_owned(),
+                ..Message::default()
+            })
+            .expect("info notice");
+        for (id, ordinal) in [("a1", 2), ("a2", 3)] {
+            upsert_chat(&store, &conv, id, ordinal, "assistant", "Done.");
+            store
+                .upsert_tool_call(ToolCall {
+                    id: format!("call-{id}"),
+                    message_id: id.to_owned(),
+                    call_id: format!("call-{id}"),
+                    tool_name: "shell".to_owned(),
+                    phase: "tool_done".to_owned(),
+                    status: "success".to_owned(),
+                    summary: "Ran numbat --probe".to_owned(),
+                    ..ToolCall::default()
+                })
+                .expect("tool call");
+        }
+
+        let hits = |store: &Store, query: &str| -> Vec<(String, String)> {
+            let mut hits: Vec<_> = store
+                .search(query, 10)
+                .expect("search")
+                .into_iter()
+                .map(|hit| (hit.source, hit.message_id))
+                .collect();
+            hits.sort();
+            hits
+        };
+        let tool = |id: &str| ("tool".to_owned(), id.to_owned());
+        assert_eq!(
+            hits(&store, "wombat"),
+            [("message".to_owned(), "q1".to_owned())]
+        );
+        assert_eq!(hits(&store, "numbat"), [tool("a1"), tool("a2")]);
+
+        store.mark_message_deleted("a1").expect("delete");
+        let park = "UPDATE messages SET variant_active = ? WHERE id = 'a2'";
+        store.conn.execute(park, [0]).expect("park");
+        assert!(hits(&store, "numbat").is_empty());
+        // Tool calls recorded while a message is hidden stay out too.
+        store
+            .upsert_tool_call(ToolCall {
+                id: "call-late".to_owned(),
+                message_id: "a2".to_owned(),
+                call_id: "call-late".to_owned(),
+                tool_name: "shell".to_owned(),
+                phase: "tool_done".to_owned(),
+                status: "success".to_owned(),
+                summary: "Ran numbat --late".to_owned(),
+                ..ToolCall::default()
+            })
+            .expect("late tool call");
+        assert!(hits(&store, "numbat").is_empty());
+
+        store.conn.execute(park, [1]).expect("unpark");
+        assert_eq!(hits(&store, "numbat"), [tool("a2"), tool("a2")]);
+    }
+
+    #[test]
+    fn generation_params_round_trip_and_default_to_empty() {
+        let (store, conversation_id) = test_store();
+        assert_eq!(
+            store.generation_params(&conversation_id).expect("unset"),
+            "{}"
+        );
+        store
+            .set_generation_params(&conversation_id, r#"{"temperature":0.3}"#)
+            .expect("set");
+        store
+            .set_generation_params(&conversation_id, r#"{"max_output_tokens":512}"#)
+            .expect("replace");
+        assert_eq!(
+            store.generation_params(&conversation_id).expect("stored"),
+            r#"{"max_output_tokens":512}"#
+        );
+        assert!(store
+            .set_generation_params("missing-conversation", "{}")
+            .is_err());
+    }
+
+    #[test]
+    fn usage_ledger_sums_by_conversation_model_and_month() {
+        let (store, conversation_id) = test_store();
+        store
+            .set_title(&conversation_id, "Spend check", false)
+            .expect("title");
+        for (model_id, cost_usd, created_at) in [
+            ("openai/gpt-5.5", Some(0.01), "2026-09-15T12:00:00.000Z"),
+            ("openai/gpt-5.5", Some(0.02), "2026-10-02T12:00:00.000Z"),
+            ("local/llama3", None, "2026-10-03T12:00:00.000Z"),
+        ] {
+            let record = UsageRecord {
+                conversation_id: conversation_id.clone(),
+                model_id: model_id.to_owned(),
+                prompt_tokens: 1_000,
+                output_tokens: 200,
+                ttf_ms: 300.0,
+                total_ms: 1_500.0,
+                cost_usd,
+            };
+            store
+                .insert_usage(&record, created_at)
+                .expect("record usage");
+        }
+
+        let by_conversation = store
+            .usage_totals(UsageGrouping::Conversation, "", 10)
+            .expect("by conversation");
+        assert_eq!(by_conversation.len(), 1);
+        assert_eq!(by_conversation[0].label, "Spend check");
+        assert_eq!(by_conversation[0].turns, 3);
+        assert_eq!(by_conversation[0].prompt_tokens, 3_000);
+        assert_eq!(by_conversation[0].unpriced_turns, 1);
+
+        let by_model = store
+            .usage_totals(UsageGrouping::Model, &conversation_id, 10)
+            .expect("by model");
+        assert_eq!(by_model[0].key, "openai/gpt-5.5");
+        assert!((by_model[0].cost_usd - 0.03).abs() < 1e-9);
+        assert_eq!(by_model[1].key, "local/llama3");
+        assert_eq!(by_model[1].unpriced_turns, 1);
+
+        let by_month = store
+            .usage_totals(UsageGrouping::Month, "", 10)
+            .expect("by month");
+        assert_eq!(
+            by_month.iter().map(|total| total.turns).collect::<Vec<_>>(),
+            [2, 1]
+        );
+        assert!(store
+            .usage_totals(UsageGrouping::Day, "other", 10)
+            .expect("filtered")
+            .is_empty());
+    }
+
     #[test]
     fn responses_message_id_maps_local_uuid_to_msg_id() {
         assert_eq!(
@@ -20,7 +20,12 @@ use crate::secrets;
 /// Canonical default model id, also consumed by `chatstore` for new conversations.
 pub(crate) const DEFAULT_MODEL: &str = "local/gpt-5.4-mini";
 const DEFAULT_LOCAL_BASE_URL: &str = "http://127.0.0.1:8317/v1";
-const SECRET_KEYS: [&str; 3] = ["OPENAI_API_KEY", "GEMINI_API_KEY", "LOCAL_API_KEY"];
+const SECRET_KEYS: [&str; 4] = [
+    "OPENAI_API_KEY",
+    "GEMINI_API_KEY",
+    "ANTHROPIC_API_KEY",
+    "LOCAL_API_KEY",
+];
 
 /// A single resolved config entry, borrowed for the duration of the callback.
 #[repr(C)]
@@ -38,18 +43,58 @@ struct Config {
     model: ModelConfig,
     #[serde(default)]
     providers: BTreeMap<String, ProviderConfig>,
+    /// Per-model settings keyed by canonical model id.
+    #[serde(default)]
+    models: BTreeMap<String, ModelOverrides>,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
 struct ModelConfig {
     #[serde(default)]
     default: String,
+    /// Small model used to name conversations; empty means the chat model.
+    #[serde(default)]
+    title: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ModelOverrides {
+    /// Models tried in order when this one fails before answering.
+    #[serde(default)]
+    fallback: Vec<String>,
+    /// USD per million prompt tokens.
+    #[serde(default)]
+    input_price: Option<f64>,
+    /// USD per million output tokens.
+    #[serde(default)]
+    output_price: Option<f64>,
+}
+
+/// Per-token prices for one model, from `[models."<id>"]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ModelPrices {
+    pub(crate) input_per_million: f64,
+    pub(crate) output_per_million: f64,
+}
+
+impl ModelPrices {
+    /// Estimated USD cost of one turn.
+    pub(crate) fn cost(self, prompt_tokens: i64, output_tokens: i64) -> f64 {
+        #[expect(
+            clippy::cast_precision_loss,
+            reason = "token counts stay far below 2^52"
+        )]
+        let (prompt, output) = (prompt_tokens as f64, output_tokens as f64);
+        (prompt * self.input_per_million + output * self.output_per_million) / 1_000_000.0
+    }
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
 struct ProviderConfig {
     #[serde(default)]
     base_url: String,
+    #[serde(default)]
+    api: String,
 }
 
 /// Resolves config on a background thread (Secret Service lookups block on
@@ -99,6 +144,35 @@ fn resolve_values() -> BTreeMap<String, String> {
     values
 }
 
+/// The `[model] default` id, falling back to [`DEFAULT_MODEL`].
+pub(crate) fn default_model() -> String {
+    load_config(default_path()).model.default
+}
+
+/// The configured `[model] title` id, if any.
+pub(crate) fn title_model() -> Option<String> {
+    crate::utils::non_empty_trimmed(&load_config(default_path()).model.title)
+}
+
+/// The `[models."<id>"] fallback` chain for `model_id`, without blanks or
+/// repeats of the model itself.
+pub(crate) fn fallback_models(model_id: &str) -> Vec<String> {
+    load_config(default_path()).fallback_chain(model_id)
+}
+
+/// The `[providers.<id>] api` wire protocol, if one is configured.
+pub(crate) fn provider_api(provider: &str) -> Option<String> {
+    load_config(default_path())
+        .providers
+        .get(provider)
+        .and_then(|provider| crate::utils::non_empty_trimmed(&provider.api))
+}
+
+/// The configured prices for `model_id`, or `None` when neither is set.
+pub(crate) fn model_prices(model_id: &str) -> Option<ModelPrices> {
+    load_config(default_path()).prices(model_id)
+}
+
 fn load_config(path: Option<PathBuf>) -> Config {
     let Some(path) = path else {
         return Config::default().normalize();
@@ -119,12 +193,14 @@ fn default_path() -> Option<PathBuf> {
 impl Config {
     fn normalize(mut self) -> Self {
         self.model.default = crate::utils::first_non_empty([&self.model.default, DEFAULT_MODEL]);
+        self.model.title = self.model.title.trim().to_owned();
 
         let local = self.providers.entry("local".to_owned()).or_default();
         local.base_url = crate::utils::first_non_empty([&local.base_url, DEFAULT_LOCAL_BASE_URL]);
 
         for provider in self.providers.values_mut() {
             provider.base_url = provider.base_url.trim().to_owned();
+            provider.api = provider.api.trim().to_ascii_lowercase();
         }
 
         self
@@ -140,10 +216,57 @@ impl Config {
         if let Some(base_url) = self.provider_base_url("local") {
             values.insert("LOCAL_BASE_URL".to_owned(), base_url);
         }
+        if let Some(base_url) = self.provider_base_url("anthropic") {
+            values.insert("ANTHROPIC_BASE_URL".to_owned(), base_url);
+        }
+        if let Some(base_url) = self.provider_base_url("ollama") {
+            values.insert("OLLAMA_BASE_URL".to_owned(), base_url);
+        }
+        for (provider, key) in [("openai", "OPENAI_WIRE_API"), ("local", "LOCAL_WIRE_API")] {
+            if let Some(api) = self
+                .providers
+                .get(provider)
+                .map(|provider| provider.api.clone())
+                .filter(|api| !api.is_empty())
+            {
+                values.insert(key.to_owned(), api);
+            }
+        }
 
         values
     }
 
+    fn fallback_chain(&self, model_id: &str) -> Vec<String> {
+        let model_id = model_id.trim();
+        let mut chain = Vec::<String>::new();
+        for candidate in self
+            .models
+            .get(model_id)
+            .map(|model| model.fallback.as_slice())
+            .unwrap_or_default()
+        {
+            let candidate = candidate.trim();
+            if !candidate.is_empty()
+                && candidate != model_id
+                && !chain.iter().any(|seen| seen == candidate)
+            {
+                chain.push(candidate.to_owned());
+            }
+        }
+        chain
+    }
+
+    fn prices(&self, model_id: &str) -> Option<ModelPrices> {
+        let model = self.models.get(model_id.trim())?;
+        if model.input_price.is_none() && model.output_price.is_none() {
+            return None;
+        }
+        Some(ModelPrices {
+            input_per_million: model.input_price.unwrap_or_default(),
+            output_per_million: model.output_price.unwrap_or_default(),
+        })
+    }
+
     fn provider_base_url(&self, provider: &str) -> Option<String> {
         self.providers
             .get(provider)
@@ -166,17 +289,45 @@ mod tests {
 
             [providers.local]
             base_url = "  "
+            api = " Chat_Completions "
 
             [providers.openai]
             base_url = " https://example.test/v1 "
+
+            [providers.anthropic]
+            base_url = "https://anthropic.example.test/v1"
+
+            [providers.ollama]
+            base_url = "http://127.0.0.1:11434"
+
+            [models."openai/gpt-5.5"]
+            fallback = [" gemini/gemini-3.5-flash ", "", "openai/gpt-5.5", "gemini/gemini-3.5-flash", "local/gpt-5.4-mini"]
+            input_price = 1.25
+            output_price = 10.0
             "#,
         )
         .expect("parse config")
         .normalize();
 
+        assert_eq!(
+            cfg.fallback_chain("openai/gpt-5.5"),
+            ["gemini/gemini-3.5-flash", "local/gpt-5.4-mini"]
+        );
+        assert!(cfg.fallback_chain("local/gpt-5.4-mini").is_empty());
+        let prices = cfg.prices("openai/gpt-5.5").expect("prices");
+        assert!((prices.cost(200_000, 10_000) - 0.35).abs() < 1e-9);
+        assert_eq!(cfg.prices("local/gpt-5.4-mini"), None);
+
         let values = cfg.public_values();
         assert_eq!(values["OPENAI_MODEL"], DEFAULT_MODEL);
         assert_eq!(values["LOCAL_BASE_URL"], DEFAULT_LOCAL_BASE_URL);
         assert_eq!(values["OPENAI_BASE_URL"], "https://example.test/v1");
+        assert_eq!(values["LOCAL_WIRE_API"], "chat_completions");
+        assert!(!values.contains_key("OPENAI_WIRE_API"));
+        assert_eq!(values["OLLAMA_BASE_URL"], "http://127.0.0.1:11434");
+        assert_eq!(
+            values["ANTHROPIC_BASE_URL"],
+            "https://anthropic.example.test/v1"
+        );
     }
 }
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::os::raw::c_void;
+use std::sync::{Mutex, OnceLock};
 use std::thread;
 
 use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime, TimeZone, Utc};
@@ -10,6 +11,9 @@ use crate::app_config::{self, CalendarSource};
 use crate::ffi::{emit_snapshot, QsNativeUpdateFn};
 use crate::google_auth;
 
+/// Events of the latest successful refresh, by day, for `today_digest`.
+static LAST_EVENTS: OnceLock<Mutex<HashMap<String, Vec<EventOut>>>> = OnceLock::new();
+
 #[derive(Debug, Serialize, Clone)]
 struct EventOut {
     uid: String,
@@ -83,11 +87,17 @@ fn run_refresh(days: i32) -> String {
             if !errors.is_empty() {
                 eprintln!("[IcalCache] {status}: {}", errors.join("; "));
             }
+            let events_by_day = organize_events(events, range_start, range_end);
+            if success_count > 0 {
+                if let Ok(mut last) = LAST_EVENTS.get_or_init(Mutex::default).lock() {
+                    last.clone_from(&events_by_day);
+                }
+            }
             marshal_output(&Output {
                 generated_at: Local::now().to_rfc3339(),
                 status: status.to_owned(),
                 error: (!errors.is_empty()).then(|| errors.join("; ")),
-                events_by_day: organize_events(events, range_start, range_end),
+                events_by_day,
             })
         }
         Err(error) => output_error(error),
@@ -344,6 +354,43 @@ fn organize_events(
     result
 }
 
+/// Today's events from the latest refresh, one line each, for the
+/// assistant's system prompt. `None` before the first successful refresh.
+pub(crate) fn today_digest() -> Option<String> {
+    let last = LAST_EVENTS.get()?.lock().ok()?;
+    let today = Local::now().format("%Y-%m-%d").to_string();
+    Some(render_day_digest(
+        last.get(&today).map_or(&[], Vec::as_slice),
+    ))
+}
+
+fn render_day_digest(events: &[EventOut]) -> String {
+    if events.is_empty() {
+        return "No events today.".to_owned();
+    }
+    let clock = |time: &str| {
+        DateTime::parse_from_rfc3339(time)
+            .map(|time| time.with_timezone(&Local).format("%H:%M").to_string())
+            .unwrap_or_default()
+    };
+    events
+        .iter()
+        .map(|event| {
+            if event.all_day {
+                format!("- All day: {}", event.title)
+            } else {
+                format!(
+                    "- {}–{}: {}",
+                    clock(&event.start),
+                    clock(&event.end),
+                    event.title
+                )
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 fn local_midnight(date: NaiveDate) -> Option<DateTime<Local>> {
     Local
         .from_local_datetime(&date.and_time(NaiveTime::MIN))
@@ -42,6 +42,7 @@ pub mod mcp;
 pub mod net_stats;
 pub mod pacman;
 pub mod privacy;
+pub mod scheduler;
 pub mod secrets;
 pub mod sys_info;
 pub mod systemd_failed;
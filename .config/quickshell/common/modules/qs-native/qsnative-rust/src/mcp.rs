@@ -1,8 +1,13 @@
+mod client;
+mod http;
+mod stdio;
+
 use std::collections::BTreeMap;
 use std::ffi::CString;
 use std::os::raw::c_char;
 use std::path::{Component, Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
 use std::thread;
 use std::time::{Duration, Instant};
 
@@ -20,6 +25,11 @@ const BUILTIN_SERVER_LABEL: &str = "Leftpanel Built-ins";
 const BUILTIN_SERVER_INSTRUCTIONS: &str = "Leftpanel Built-ins provides local tools for this Quickshell configuration. Use shell_command only when the user asks you to inspect or modify local state, run project commands, or operate the local machine.";
 const EMAIL_SERVER_ID: &str = "email";
 const EMAIL_SERVER_LABEL: &str = "Email Accounts";
+const EMAIL_RESOURCE_SCHEME: &str = "email://";
+const EMAIL_RESOURCE_LIMIT: u32 = 10;
+/// How long an inbox listing serves catalog refreshes before it is re-read.
+const EMAIL_RESOURCE_TTL: Duration = Duration::from_mins(5);
+const EMAIL_RESOURCE_BODY_CHARS: usize = 20_000;
 const EMAIL_SERVER_INSTRUCTIONS: &str = "Email Accounts provides read-only mailbox tools for configured email accounts. Gmail accounts use the Gmail API with refreshable OAuth credentials. Use these tools only when the user asks about email, inbox messages, unread mail, message subjects, or reading a specific email UID or Gmail message id. Do not use email tools for Todoist tasks, projects, reminders, or general task management.";
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
@@ -86,6 +96,43 @@ pub struct Snapshot {
     pub error: String,
 }
 
+/// A prompt or resource the user picked to go along with their message.
+///
+/// `kind` is `"resource"` (read `uri`) or `"prompt"` (render `name` with
+/// `arguments`).
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ContextRef {
+    #[serde(default, rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub server_id: String,
+    #[serde(default)]
+    pub uri: String,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub arguments: Map<String, Value>,
+}
+
+/// Result of rendering a prompt or reading a resource for the UI.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ContextResult {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub error: String,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub result: Value,
+}
+
+/// Everything one server contributes to the snapshot.
+#[derive(Default)]
+struct ServerListing {
+    server: ServerSnapshot,
+    tools: Vec<ToolSnapshot>,
+    prompts: Vec<Value>,
+    resources: Vec<Value>,
+}
+
 #[expect(
     clippy::struct_excessive_bools,
     reason = "bools mirror the distinct MCP tool annotation flags (readOnly/destructive/openWorld/idempotent) and are serialized individually"
@@ -149,7 +196,7 @@ pub struct ToolResult {
 
 #[must_use]
 pub fn refresh() -> String {
-    to_json_string(&snapshot())
+    to_json_string(&snapshot(true))
 }
 
 /// Builds the flattened list of tool descriptors from the current snapshot.
@@ -159,7 +206,7 @@ pub fn refresh() -> String {
 /// Returns the snapshot error string when no tools are available and the
 /// snapshot reported a non-empty error.
 pub fn tool_descriptors() -> Result<Vec<ToolDescriptor>, String> {
-    let snapshot = snapshot();
+    let snapshot = snapshot(false);
     let server_instructions = snapshot
         .servers
         .iter()
@@ -211,12 +258,80 @@ pub fn call_tool(server_id: &str, tool_name: &str, arguments: &Map<String, Value
     if server_id == EMAIL_SERVER_ID || (server_id.is_empty() && is_email_tool(&tool_name)) {
         return call_email_tool(&tool_name, arguments);
     }
+    if let Some(config) = load_mcp_servers()
+        .unwrap_or_default()
+        .into_iter()
+        .find(|config| config.enabled && config.id == server_id)
+    {
+        return call_external_tool(&config, &tool_name, arguments);
+    }
     tool_error(
         &tool_name,
-        &format!("Unknown built-in MCP server or tool: {server_id}/{tool_name}"),
+        &format!("Unknown MCP server or tool: {server_id}/{tool_name}"),
     )
 }
 
+/// Renders a prompt via `prompts/get` on an external server.
+///
+/// # Errors
+///
+/// Returns `Err` if the server is unknown/disabled or the request fails.
+pub fn get_prompt(
+    server_id: &str,
+    name: &str,
+    arguments: &Map<String, Value>,
+) -> Result<Value, String> {
+    let config = enabled_external_server(server_id)?;
+    client::with_connection(&config, |client| client.get_prompt(name, arguments))
+}
+
+/// Reads a resource via `resources/read`, including the built-in email
+/// server's inbox resources.
+///
+/// # Errors
+///
+/// Returns `Err` if the server is unknown/disabled or the read fails.
+pub fn read_resource(server_id: &str, uri: &str) -> Result<Value, String> {
+    if server_id.trim() == EMAIL_SERVER_ID {
+        return read_email_resource(uri.trim());
+    }
+    let config = enabled_external_server(server_id)?;
+    client::with_connection(&config, |client| client.read_resource(uri))
+}
+
+/// Resolves the selected prompts/resources into one user `message` input
+/// item, or `None` when nothing was selected.
+///
+/// Prompt messages are folded into the user turn (assistant-authored prompt
+/// text is labelled as such) so the item replays like any other user input.
+///
+/// # Errors
+///
+/// Returns `Err` if a reference is malformed or cannot be resolved.
+pub fn context_input_item(refs: &[ContextRef]) -> Result<Option<Value>, String> {
+    let mut content = Vec::new();
+    for reference in refs {
+        match reference.kind.trim() {
+            "resource" => {
+                let result = read_resource(&reference.server_id, &reference.uri)?;
+                content.extend(resource_content_parts(&result));
+            }
+            "prompt" => {
+                let result =
+                    get_prompt(&reference.server_id, &reference.name, &reference.arguments)?;
+                content.extend(prompt_content_parts(&result));
+            }
+            other => return Err(format!("unknown MCP context type: {other:?}")),
+        }
+    }
+    if content.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(
+        json!({ "type": "message", "role": "user", "content": content }),
+    ))
+}
+
 pub fn tool_result_transcript_payload(result: &ToolResult) -> Map<String, Value> {
     let mut payload = Map::new();
     let mut content = result.content.clone();
@@ -266,10 +381,32 @@ pub fn tool_result_transcript_output(result: &ToolResult) -> String {
     })
 }
 
-fn snapshot() -> Snapshot {
-    let mut servers = vec![builtin_server_snapshot(), email_server_snapshot()];
+/// Builds the catalog. Prompts and resources are only listed when
+/// `with_context` is set, so per-turn tool lookups stay cheap.
+fn snapshot(with_context: bool) -> Snapshot {
+    let mut servers = vec![builtin_server_snapshot()];
     let mut tools = builtin_tool_snapshots();
+    let mut prompts = Vec::new();
+    let mut resources = Vec::new();
+    let mut email_server = email_server_snapshot();
     tools.append(&mut email_tool_snapshots());
+    if with_context {
+        resources = email_resources();
+        email_server.resource_count = resources.len();
+    }
+    servers.push(email_server);
+
+    let (configs, error) = match load_mcp_servers() {
+        Ok(configs) => (configs, String::new()),
+        Err(error) => (Vec::new(), error),
+    };
+    client::retain_connections(&configs);
+    for mut listing in external_snapshots(&configs, with_context) {
+        servers.push(listing.server);
+        tools.append(&mut listing.tools);
+        prompts.append(&mut listing.prompts);
+        resources.append(&mut listing.resources);
+    }
 
     servers
         .sort_by(|a, b| (a.label.as_str(), a.id.as_str()).cmp(&(b.label.as_str(), b.id.as_str())));
@@ -280,10 +417,135 @@ fn snapshot() -> Snapshot {
     Snapshot {
         servers,
         tools,
-        prompts: Vec::new(),
-        resources: Vec::new(),
+        prompts,
+        resources,
         status: "ready".to_owned(),
-        error: String::new(),
+        error,
+    }
+}
+
+/// Connects to every enabled external server in parallel and lists its tools
+/// (plus prompts and resources when `with_context` is set).
+fn external_snapshots(
+    configs: &[app_config::McpServerConfig],
+    with_context: bool,
+) -> Vec<ServerListing> {
+    thread::scope(|scope| {
+        let handles = configs
+            .iter()
+            .map(|config| scope.spawn(move || external_snapshot(config, with_context)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .zip(configs)
+            .map(|(handle, config)| {
+                handle.join().unwrap_or_else(|_| ServerListing {
+                    server: external_server_error(config, "snapshot worker panicked"),
+                    ..ServerListing::default()
+                })
+            })
+            .collect()
+    })
+}
+
+fn external_snapshot(config: &app_config::McpServerConfig, with_context: bool) -> ServerListing {
+    if !config.enabled {
+        return ServerListing {
+            server: ServerSnapshot {
+                status: "disabled".to_owned(),
+                ..external_server_base(config)
+            },
+            ..ServerListing::default()
+        };
+    }
+    let listed = client::with_connection(config, |client| {
+        let tools = client.list_tools()?;
+        let (prompts, resources) = if with_context {
+            (client.list_prompts()?, client.list_resources()?)
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        Ok((client.info.clone(), tools, prompts, resources))
+    });
+    let (info, tools, prompts, resources) = match listed {
+        Ok(listed) => listed,
+        Err(error) => {
+            return ServerListing {
+                server: external_server_error(config, &error),
+                ..ServerListing::default()
+            }
+        }
+    };
+    let tools = tools
+        .iter()
+        .filter_map(|tool| client::tool_snapshot(config, tool))
+        .collect::<Vec<_>>();
+    let prompts = prompts
+        .iter()
+        .filter_map(|prompt| client::prompt_snapshot(config, prompt))
+        .collect::<Vec<_>>();
+    let resources = resources
+        .iter()
+        .filter_map(|resource| client::resource_snapshot(config, resource))
+        .collect::<Vec<_>>();
+    let server = ServerSnapshot {
+        connected: true,
+        status: "connected".to_owned(),
+        server_name: info.name,
+        server_version: info.version,
+        instructions: info.instructions,
+        tool_count: tools.len(),
+        prompt_count: prompts.len(),
+        resource_count: resources.len(),
+        capabilities: info.capabilities.into_iter().collect(),
+        ..external_server_base(config)
+    };
+    ServerListing {
+        server,
+        tools,
+        prompts,
+        resources,
+    }
+}
+
+fn external_server_base(config: &app_config::McpServerConfig) -> ServerSnapshot {
+    ServerSnapshot {
+        id: config.id.clone(),
+        label: config.label.clone(),
+        url: client::server_url(config),
+        enabled: config.enabled,
+        ..ServerSnapshot::default()
+    }
+}
+
+fn external_server_error(config: &app_config::McpServerConfig, error: &str) -> ServerSnapshot {
+    ServerSnapshot {
+        status: "error".to_owned(),
+        error: error.to_owned(),
+        ..external_server_base(config)
+    }
+}
+
+fn enabled_external_server(server_id: &str) -> Result<app_config::McpServerConfig, String> {
+    let server_id = server_id.trim();
+    load_mcp_servers()?
+        .into_iter()
+        .find(|config| config.enabled && config.id == server_id)
+        .ok_or_else(|| format!("Unknown or disabled MCP server: {server_id}"))
+}
+
+fn call_external_tool(
+    config: &app_config::McpServerConfig,
+    tool_name: &str,
+    arguments: &Map<String, Value>,
+) -> ToolResult {
+    let started = Instant::now();
+    match client::with_connection(config, |client| client.call_tool(tool_name, arguments)) {
+        Ok(result) => ToolResult {
+            duration_ms: elapsed_millis_i64(started),
+            ..client::tool_result(tool_name, &result)
+        },
+        Err(error) => tool_error(tool_name, &format!("{}: {error}", config.label)),
     }
 }
 
@@ -308,6 +570,7 @@ fn email_server_snapshot() -> ServerSnapshot {
         tool_count: email_tool_snapshots().len(),
         capabilities: BTreeMap::from([
             ("tools".to_owned(), Value::Bool(true)),
+            ("resources".to_owned(), Value::Bool(true)),
             ("accounts".to_owned(), json!(accounts.len())),
         ]),
         ..ServerSnapshot::default()
@@ -778,11 +1041,215 @@ fn call_email_read(arguments: &Map<String, Value>) -> ToolResult {
     }
 }
 
+/// Inbox listings and message headers kept between catalog refreshes.
+static EMAIL_RESOURCES: OnceLock<Mutex<EmailResourceCache>> = OnceLock::new();
+
+#[derive(Default)]
+struct EmailResourceCache {
+    /// When each account's inbox was listed, and the message ids it held.
+    listings: BTreeMap<String, (Instant, Vec<String>)>,
+    /// Resource entries by URI; a message's headers never change.
+    entries: BTreeMap<String, Value>,
+}
+
+/// Lists recent inbox messages of every Gmail account as resources. Each
+/// inbox is listed at most once per [`EMAIL_RESOURCE_TTL`], and only
+/// messages not seen before have their headers fetched; bodies wait for
+/// `read_email_resource`. Accounts that cannot be reached are skipped; the
+/// tools report those errors instead.
+fn email_resources() -> Vec<Value> {
+    let mut cache = EMAIL_RESOURCES
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    let mut out = Vec::new();
+    let mut listed_accounts = Vec::new();
+    for account in load_email_accounts().unwrap_or_default() {
+        if account.provider.trim() != "gmail" {
+            continue;
+        }
+        let Ok(gmail_account) = GmailAccount::load(&account.id, account.address.trim()) else {
+            continue;
+        };
+        let client = GmailClient::new(&gmail_account);
+        let fresh = cache
+            .listings
+            .get(&account.id)
+            .filter(|(listed_at, _)| listed_at.elapsed() < EMAIL_RESOURCE_TTL)
+            .map(|(_, ids)| ids.clone());
+        let ids = if let Some(ids) = fresh {
+            ids
+        } else {
+            let Ok(list) = client.list_messages("in:inbox", EMAIL_RESOURCE_LIMIT) else {
+                continue;
+            };
+            let ids: Vec<String> = list.messages.into_iter().map(|listed| listed.id).collect();
+            cache
+                .listings
+                .insert(account.id.clone(), (Instant::now(), ids.clone()));
+            ids
+        };
+        for id in &ids {
+            let uri = email_resource_uri(&account.id, id);
+            if let Some(entry) = cache.entries.get(&uri) {
+                out.push(entry.clone());
+                continue;
+            }
+            let Ok(message) = client.get_message(id, false, 0) else {
+                continue;
+            };
+            let subject = first_non_empty([message.subject.as_str(), "(no subject)"]);
+            let entry = json!({
+                "server_id": EMAIL_SERVER_ID,
+                "server_label": EMAIL_SERVER_LABEL,
+                "uri": uri,
+                "name": subject,
+                "title": subject,
+                "description": format!("{} · {}", message.from, message.date),
+                "mime_type": "text/plain",
+            });
+            cache.entries.insert(uri, entry.clone());
+            out.push(entry);
+        }
+        listed_accounts.push(account.id);
+    }
+    // Forget accounts that are gone and messages that left the inbox.
+    cache.listings.retain(|id, _| listed_accounts.contains(id));
+    let kept: Vec<String> = out
+        .iter()
+        .filter_map(|entry| entry["uri"].as_str().map(str::to_owned))
+        .collect();
+    cache.entries.retain(|uri, _| kept.contains(uri));
+    out
+}
+
+fn email_resource_uri(account_id: &str, message_id: &str) -> String {
+    format!(
+        "{EMAIL_RESOURCE_SCHEME}{}/{}",
+        account_id.trim(),
+        message_id.trim()
+    )
+}
+
+fn read_email_resource(uri: &str) -> Result<Value, String> {
+    let (account_id, message_id) = uri
+        .strip_prefix(EMAIL_RESOURCE_SCHEME)
+        .and_then(|rest| rest.split_once('/'))
+        .filter(|(account, id)| !account.is_empty() && !id.is_empty())
+        .ok_or_else(|| format!("Not an email resource: {uri}"))?;
+    let accounts = load_email_accounts()?;
+    let account = app_config::select_account_by_id_or_address(&accounts, account_id)?;
+    if account.provider.trim() != "gmail" {
+        return Err("Rust email MCP currently supports Gmail accounts only".to_owned());
+    }
+    let gmail_account = GmailAccount::load(&account.id, account.address.trim())?;
+    let message = GmailClient::new(&gmail_account).get_message(
+        message_id,
+        true,
+        EMAIL_RESOURCE_BODY_CHARS,
+    )?;
+    let body = first_non_empty([
+        message.body_text.as_str(),
+        message.body_html.as_str(),
+        message.snippet.as_str(),
+    ]);
+    Ok(json!({
+        "contents": [{
+            "uri": uri,
+            "mimeType": "text/plain",
+            "text": format!(
+                "From: {}\nTo: {}\nDate: {}\nSubject: {}\n\n{body}",
+                message.from, message.to, message.date, message.subject
+            ),
+        }]
+    }))
+}
+
+/// Converts `resources/read` contents into Responses user content parts.
+fn resource_content_parts(result: &Value) -> Vec<Value> {
+    result
+        .get("contents")
+        .and_then(Value::as_array)
+        .map(|contents| contents.iter().filter_map(embedded_resource_part).collect())
+        .unwrap_or_default()
+}
+
+/// Converts `prompts/get` messages into Responses user content parts.
+fn prompt_content_parts(result: &Value) -> Vec<Value> {
+    let Some(messages) = result.get("messages").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+    let mut parts = Vec::new();
+    for message in messages {
+        let Some(content) = message.get("content") else {
+            continue;
+        };
+        let assistant = message.get("role").and_then(Value::as_str) == Some("assistant");
+        let part = match content.get("type").and_then(Value::as_str).unwrap_or("") {
+            "text" => content
+                .get("text")
+                .and_then(Value::as_str)
+                .map(|text| json!({ "type": "input_text", "text": text })),
+            "image" => image_part(content.get("mimeType"), content.get("data")),
+            "resource" => content.get("resource").and_then(embedded_resource_part),
+            "resource_link" => content.get("uri").and_then(Value::as_str).map(
+                |uri| json!({ "type": "input_text", "text": format!("Resource link: {uri}") }),
+            ),
+            _ => None,
+        };
+        let Some(mut part) = part else {
+            continue;
+        };
+        if assistant {
+            if let Some(text) = part.get("text").and_then(Value::as_str) {
+                part["text"] = json!(format!("[assistant] {text}"));
+            }
+        }
+        parts.push(part);
+    }
+    parts
+}
+
+fn embedded_resource_part(resource: &Value) -> Option<Value> {
+    let uri = resource.get("uri").and_then(Value::as_str).unwrap_or("");
+    if let Some(text) = resource.get("text").and_then(Value::as_str) {
+        return Some(json!({ "type": "input_text", "text": format!("Resource {uri}:\n{text}") }));
+    }
+    let mime = resource
+        .get("mimeType")
+        .and_then(Value::as_str)
+        .unwrap_or("");
+    if mime.to_ascii_lowercase().starts_with("image/") {
+        return image_part(resource.get("mimeType"), resource.get("blob"));
+    }
+    resource.get("blob").map(|_| {
+        json!({
+            "type": "input_text",
+            "text": format!("Resource {uri}: binary {mime} content omitted."),
+        })
+    })
+}
+
+fn image_part(mime: Option<&Value>, data: Option<&Value>) -> Option<Value> {
+    let mime = mime.and_then(Value::as_str)?;
+    let data = data.and_then(Value::as_str)?;
+    Some(json!({
+        "type": "input_image",
+        "image_url": format!("data:{mime};base64,{data}"),
+        "detail": "auto",
+    }))
+}
+
 fn load_email_accounts() -> Result<Vec<app_config::EmailAccount>, String> {
     let path = app_config::default_path();
     app_config::load_all_accounts(&path)
 }
 
+fn load_mcp_servers() -> Result<Vec<app_config::McpServerConfig>, String> {
+    let path = app_config::default_path();
+    app_config::load_mcp_servers(&path)
+}
+
 fn select_email_account(
     arguments: &Map<String, Value>,
 ) -> Result<app_config::EmailAccount, String> {
@@ -966,7 +1433,64 @@ fn is_zero_i64(value: &i64) -> bool {
 #[no_mangle]
 /// Refreshes the MCP server/tool snapshot. Returns a CBOR-encoded `Snapshot`.
 pub extern "C" fn QsNative_AiMcp_Refresh() -> crate::ffi::QsNativeBytes {
-    crate::ffi::into_cbor(&snapshot())
+    crate::ffi::into_cbor(&snapshot(true))
+}
+
+#[no_mangle]
+/// Renders an MCP prompt. `arguments_json` is a JSON object of prompt
+/// arguments. Returns a CBOR-encoded `ContextResult` carrying the raw
+/// `prompts/get` result.
+///
+/// # Safety
+///
+/// Pointer arguments must be null or valid NUL-terminated strings for the
+/// duration of this call. The returned buffer must be released with
+/// `QsNative_FreeBytes`.
+pub unsafe extern "C" fn QsNative_AiMcp_GetPrompt(
+    server_id: *const c_char,
+    name: *const c_char,
+    arguments_json: *const c_char,
+) -> crate::ffi::QsNativeBytes {
+    let (server_id, name, arguments_json) = unsafe {
+        (
+            crate::ffi::c_string(server_id),
+            crate::ffi::c_string(name),
+            crate::ffi::c_string(arguments_json),
+        )
+    };
+    let arguments = serde_json::from_str::<Map<String, Value>>(&arguments_json).unwrap_or_default();
+    crate::ffi::into_cbor(&context_result(get_prompt(&server_id, &name, &arguments)))
+}
+
+#[no_mangle]
+/// Reads an MCP resource. Returns a CBOR-encoded `ContextResult` carrying the
+/// raw `resources/read` result.
+///
+/// # Safety
+///
+/// Pointer arguments must be null or valid NUL-terminated strings for the
+/// duration of this call. The returned buffer must be released with
+/// `QsNative_FreeBytes`.
+pub unsafe extern "C" fn QsNative_AiMcp_ReadResource(
+    server_id: *const c_char,
+    uri: *const c_char,
+) -> crate::ffi::QsNativeBytes {
+    let (server_id, uri) = unsafe { (crate::ffi::c_string(server_id), crate::ffi::c_string(uri)) };
+    crate::ffi::into_cbor(&context_result(read_resource(&server_id, &uri)))
+}
+
+fn context_result(result: Result<Value, String>) -> ContextResult {
+    match result {
+        Ok(result) => ContextResult {
+            ok: true,
+            result,
+            ..ContextResult::default()
+        },
+        Err(error) => ContextResult {
+            error,
+            ..ContextResult::default()
+        },
+    }
 }
 
 #[no_mangle]
@@ -988,6 +1512,37 @@ pub unsafe extern "C" fn QsNative_Free(s: *mut c_char) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn prompt_and_resource_content_become_user_input_parts() {
+        let prompt = json!({
+            "messages": [
+                {"role": "user", "content": {"type": "text", "text": "Summarize"}},
+                {"role": "assistant", "content": {"type": "text", "text": "Sure"}},
+                {"role": "user", "content": {"type": "image", "mimeType": "image/png", "data": "AAAA"}},
+                {"role": "user", "content": {"type": "resource", "resource": {"uri": "file:///a.txt", "text": "alpha"}}},
+            ]
+        });
+        let parts = prompt_content_parts(&prompt);
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0]["text"], "Summarize");
+        assert_eq!(parts[1]["text"], "[assistant] Sure");
+        assert_eq!(parts[2]["image_url"], "data:image/png;base64,AAAA");
+        assert_eq!(parts[3]["text"], "Resource file:///a.txt:\nalpha");
+
+        let resource = json!({
+            "contents": [
+                {"uri": "file:///b.bin", "mimeType": "application/octet-stream", "blob": "AA=="},
+            ]
+        });
+        let parts = resource_content_parts(&resource);
+        assert_eq!(parts[0]["type"], "input_text");
+        assert!(parts[0]["text"]
+            .as_str()
+            .unwrap_or_default()
+            .contains("omitted"));
+        assert_eq!(context_input_item(&[]).expect("empty context"), None);
+    }
+
     #[test]
     fn refresh_and_descriptors_include_email_catalog() {
         let snapshot: Snapshot = serde_json::from_str(&refresh()).expect("snapshot json");
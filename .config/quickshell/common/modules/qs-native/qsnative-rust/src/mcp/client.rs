@@ -0,0 +1,539 @@
+//! JSON-RPC client for external Model Context Protocol servers.
+//!
+//! One live connection is kept per configured server id and reused across
+//! snapshots and tool calls. A connection is re-established when its config
+//! changes or the server goes away. Requests on a single connection are
+//! serialized; different servers are independent.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Map, Value};
+
+use super::http::HttpTransport;
+use super::stdio::StdioTransport;
+use super::{risk_for_tool, ToolResult, ToolSnapshot, CLIENT_VERSION};
+use crate::app_config::McpServerConfig;
+use crate::utils::first_non_empty;
+
+const PROTOCOL_VERSION: &str = "2025-06-18";
+const CLIENT_NAME: &str = "quickshell-leftpanel";
+const MAX_LIST_PAGES: usize = 64;
+
+/// Moves JSON-RPC messages to and from a server.
+pub(super) trait Transport: Send {
+    fn send(&mut self, message: &Value) -> Result<(), String>;
+    /// Returns the next message from the server, failing once `deadline` passes.
+    fn recv(&mut self, deadline: Instant) -> Result<Value, String>;
+    fn is_alive(&mut self) -> bool;
+    /// True once the server has forgotten this connection's session, meaning
+    /// the failed request can be retried on a fresh connection.
+    fn session_expired(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct ServerInfo {
+    pub(super) name: String,
+    pub(super) version: String,
+    pub(super) instructions: String,
+    pub(super) capabilities: Map<String, Value>,
+}
+
+pub(super) struct Client {
+    config: McpServerConfig,
+    transport: Box<dyn Transport>,
+    next_id: i64,
+    pub(super) info: ServerInfo,
+}
+
+type SharedClient = Arc<Mutex<Client>>;
+
+static CLIENTS: OnceLock<Mutex<BTreeMap<String, SharedClient>>> = OnceLock::new();
+
+impl Client {
+    fn connect(config: &McpServerConfig) -> Result<Self, String> {
+        let transport: Box<dyn Transport> = if config.url.is_empty() {
+            Box::new(StdioTransport::spawn(config)?)
+        } else {
+            Box::new(HttpTransport::open(config)?)
+        };
+        let mut client = Self {
+            config: config.clone(),
+            transport,
+            next_id: 1,
+            info: ServerInfo::default(),
+        };
+        let result = client.request(
+            "initialize",
+            &json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": {},
+                "clientInfo": { "name": CLIENT_NAME, "version": CLIENT_VERSION },
+            }),
+        )?;
+        client.info = ServerInfo {
+            name: str_at(&result, "/serverInfo/name"),
+            version: str_at(&result, "/serverInfo/version"),
+            instructions: str_at(&result, "/instructions"),
+            capabilities: result
+                .get("capabilities")
+                .and_then(Value::as_object)
+                .cloned()
+                .unwrap_or_default(),
+        };
+        client.notify("notifications/initialized")?;
+        Ok(client)
+    }
+
+    pub(super) fn supports(&self, capability: &str) -> bool {
+        self.info.capabilities.contains_key(capability)
+    }
+
+    pub(super) fn request(&mut self, method: &str, params: &Value) -> Result<Value, String> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.transport.send(&json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        }))?;
+        let deadline = Instant::now() + Duration::from_millis(self.config.timeout_ms);
+        loop {
+            let message = match self.transport.recv(deadline) {
+                Ok(message) => message,
+                Err(error) => {
+                    // Best effort: tell the server to stop working on a request
+                    // nobody is waiting for any more.
+                    let _ = self.transport.send(&json!({
+                        "jsonrpc": "2.0",
+                        "method": "notifications/cancelled",
+                        "params": { "requestId": id, "reason": error },
+                    }));
+                    return Err(format!("{method}: {error}"));
+                }
+            };
+            if message.get("method").is_some() {
+                self.answer_server_message(&message)?;
+                continue;
+            }
+            if message.get("id").and_then(Value::as_i64) != Some(id) {
+                continue;
+            }
+            if let Some(error) = message.get("error") {
+                return Err(format!("{method}: {}", rpc_error_text(error)));
+            }
+            return Ok(message.get("result").cloned().unwrap_or(Value::Null));
+        }
+    }
+
+    fn notify(&mut self, method: &str) -> Result<(), String> {
+        self.transport
+            .send(&json!({ "jsonrpc": "2.0", "method": method }))
+    }
+
+    /// Replies to server-initiated requests so the server is not left waiting.
+    /// Notifications (no `id`) are informational and ignored.
+    fn answer_server_message(&mut self, message: &Value) -> Result<(), String> {
+        let Some(id) = message.get("id") else {
+            return Ok(());
+        };
+        let reply = if message.get("method").and_then(Value::as_str) == Some("ping") {
+            json!({ "jsonrpc": "2.0", "id": id, "result": {} })
+        } else {
+            json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": -32601, "message": "method not supported by client" },
+            })
+        };
+        self.transport.send(&reply)
+    }
+
+    /// Collects every page of a cursor-paginated `*/list` method.
+    pub(super) fn list_all(&mut self, method: &str, key: &str) -> Result<Vec<Value>, String> {
+        let mut out = Vec::new();
+        let mut cursor = String::new();
+        for _ in 0..MAX_LIST_PAGES {
+            let params = if cursor.is_empty() {
+                json!({})
+            } else {
+                json!({ "cursor": cursor })
+            };
+            let result = self.request(method, &params)?;
+            if let Some(items) = result.get(key).and_then(Value::as_array) {
+                out.extend(items.iter().cloned());
+            }
+            cursor = str_at(&result, "/nextCursor");
+            if cursor.is_empty() {
+                break;
+            }
+        }
+        Ok(out)
+    }
+
+    pub(super) fn list_tools(&mut self) -> Result<Vec<Value>, String> {
+        if !self.supports("tools") {
+            return Ok(Vec::new());
+        }
+        self.list_all("tools/list", "tools")
+    }
+
+    pub(super) fn list_prompts(&mut self) -> Result<Vec<Value>, String> {
+        if !self.supports("prompts") {
+            return Ok(Vec::new());
+        }
+        self.list_all("prompts/list", "prompts")
+    }
+
+    pub(super) fn list_resources(&mut self) -> Result<Vec<Value>, String> {
+        if !self.supports("resources") {
+            return Ok(Vec::new());
+        }
+        self.list_all("resources/list", "resources")
+    }
+
+    pub(super) fn get_prompt(
+        &mut self,
+        name: &str,
+        arguments: &Map<String, Value>,
+    ) -> Result<Value, String> {
+        self.request(
+            "prompts/get",
+            &json!({ "name": name, "arguments": arguments }),
+        )
+    }
+
+    pub(super) fn read_resource(&mut self, uri: &str) -> Result<Value, String> {
+        self.request("resources/read", &json!({ "uri": uri }))
+    }
+
+    pub(super) fn call_tool(
+        &mut self,
+        name: &str,
+        arguments: &Map<String, Value>,
+    ) -> Result<Value, String> {
+        self.request(
+            "tools/call",
+            &json!({ "name": name, "arguments": arguments }),
+        )
+    }
+}
+
+/// Runs `f` against the live connection for `config`, connecting on demand.
+///
+/// A connection whose server died during `f` is dropped from the registry so
+/// the next call reconnects. When an HTTP session expired, `f` is retried once
+/// on a fresh connection.
+pub(super) fn with_connection<T>(
+    config: &McpServerConfig,
+    mut f: impl FnMut(&mut Client) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut retried = false;
+    loop {
+        let shared = connection(config)?;
+        let mut client = shared.lock().expect("mcp client mutex");
+        let result = f(&mut client);
+        if result.is_ok() || client.transport.is_alive() {
+            return result;
+        }
+        let expired = client.transport.session_expired();
+        drop(client);
+        {
+            let mut clients = registry().lock().expect("mcp client registry mutex");
+            if clients
+                .get(&config.id)
+                .is_some_and(|current| Arc::ptr_eq(current, &shared))
+            {
+                clients.remove(&config.id);
+            }
+        }
+        if !expired || retried {
+            return result;
+        }
+        retried = true;
+    }
+}
+
+/// Drops connections for servers that are no longer configured and enabled.
+pub(super) fn retain_connections(configs: &[McpServerConfig]) {
+    registry()
+        .lock()
+        .expect("mcp client registry mutex")
+        .retain(|id, _| {
+            configs
+                .iter()
+                .any(|config| &config.id == id && config.enabled)
+        });
+}
+
+fn connection(config: &McpServerConfig) -> Result<SharedClient, String> {
+    let existing = registry()
+        .lock()
+        .expect("mcp client registry mutex")
+        .get(&config.id)
+        .cloned();
+    if let Some(existing) = existing {
+        let reusable = {
+            let mut client = existing.lock().expect("mcp client mutex");
+            client.config == *config && client.transport.is_alive()
+        };
+        if reusable {
+            return Ok(existing);
+        }
+    }
+    // Connect without holding the registry lock so a slow server start does
+    // not stall the others.
+    let client = Arc::new(Mutex::new(Client::connect(config)?));
+    registry()
+        .lock()
+        .expect("mcp client registry mutex")
+        .insert(config.id.clone(), client.clone());
+    Ok(client)
+}
+
+fn registry() -> &'static Mutex<BTreeMap<String, SharedClient>> {
+    CLIENTS.get_or_init(|| Mutex::new(BTreeMap::new()))
+}
+
+pub(super) fn server_url(config: &McpServerConfig) -> String {
+    if !config.url.is_empty() {
+        return config.url.clone();
+    }
+    let mut url = format!("stdio://{}", config.command);
+    for arg in &config.args {
+        url.push(' ');
+        url.push_str(arg);
+    }
+    url
+}
+
+/// Converts an MCP `Tool` definition into the catalog shape. Annotation hints
+/// follow the spec defaults: tools are assumed destructive and open-world
+/// unless they say otherwise.
+pub(super) fn tool_snapshot(config: &McpServerConfig, tool: &Value) -> Option<ToolSnapshot> {
+    let name = str_at(tool, "/name");
+    if name.is_empty() {
+        return None;
+    }
+    let hint = |key: &str| {
+        tool.pointer(&format!("/annotations/{key}"))
+            .and_then(Value::as_bool)
+    };
+    let read_only = hint("readOnlyHint").unwrap_or(false);
+    let destructive = !read_only && hint("destructiveHint").unwrap_or(true);
+    Some(ToolSnapshot {
+        server_id: config.id.clone(),
+        server_label: config.label.clone(),
+        qualified_name: format!("{}__{name}", config.id),
+        title: first_non_empty([
+            str_at(tool, "/title").as_str(),
+            str_at(tool, "/annotations/title").as_str(),
+        ]),
+        description: str_at(tool, "/description"),
+        input_schema: object_map(tool.get("inputSchema")),
+        output_schema: object_map(tool.get("outputSchema")),
+        read_only,
+        destructive,
+        open_world: hint("openWorldHint").unwrap_or(true),
+        idempotent: hint("idempotentHint").unwrap_or(false),
+        risk: risk_for_tool(read_only, destructive).to_owned(),
+        name,
+    })
+}
+
+/// Converts an MCP `Prompt` definition into the catalog shape.
+pub(super) fn prompt_snapshot(config: &McpServerConfig, prompt: &Value) -> Option<Value> {
+    let name = str_at(prompt, "/name");
+    if name.is_empty() {
+        return None;
+    }
+    Some(json!({
+        "server_id": config.id,
+        "server_label": config.label,
+        "name": name,
+        "qualified_name": format!("{}__{name}", config.id),
+        "title": str_at(prompt, "/title"),
+        "description": str_at(prompt, "/description"),
+        "arguments": prompt.get("arguments").cloned().unwrap_or_else(|| json!([])),
+    }))
+}
+
+/// Converts an MCP `Resource` definition into the catalog shape.
+pub(super) fn resource_snapshot(config: &McpServerConfig, resource: &Value) -> Option<Value> {
+    let uri = str_at(resource, "/uri");
+    if uri.is_empty() {
+        return None;
+    }
+    Some(json!({
+        "server_id": config.id,
+        "server_label": config.label,
+        "uri": uri,
+        "name": str_at(resource, "/name"),
+        "title": str_at(resource, "/title"),
+        "description": str_at(resource, "/description"),
+        "mime_type": str_at(resource, "/mimeType"),
+        "size": resource.get("size").and_then(Value::as_u64),
+    }))
+}
+
+/// Converts a `tools/call` result into a `ToolResult`.
+pub(super) fn tool_result(name: &str, result: &Value) -> ToolResult {
+    let content = result
+        .get("content")
+        .and_then(Value::as_array)
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(Value::as_object)
+                .cloned()
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    let text = content
+        .iter()
+        .filter(|item| item.get("type").and_then(Value::as_str) == Some("text"))
+        .filter_map(|item| item.get("text").and_then(Value::as_str))
+        .collect::<Vec<_>>()
+        .join("\n");
+    ToolResult {
+        name: name.to_owned(),
+        text,
+        content,
+        structured_content: result
+            .get("structuredContent")
+            .and_then(Value::as_object)
+            .cloned()
+            .unwrap_or_default(),
+        meta: result
+            .get("_meta")
+            .and_then(Value::as_object)
+            .cloned()
+            .unwrap_or_default(),
+        is_error: result
+            .get("isError")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        ..ToolResult::default()
+    }
+}
+
+fn rpc_error_text(error: &Value) -> String {
+    let message = str_at(error, "/message");
+    match error.get("code").and_then(Value::as_i64) {
+        Some(code) if message.is_empty() => format!("JSON-RPC error {code}"),
+        Some(code) => format!("{message} ({code})"),
+        None => first_non_empty([message.as_str(), "JSON-RPC error"]),
+    }
+}
+
+fn object_map(value: Option<&Value>) -> BTreeMap<String, Value> {
+    value
+        .and_then(Value::as_object)
+        .map(|object| object.clone().into_iter().collect())
+        .unwrap_or_default()
+}
+
+fn str_at(value: &Value, pointer: &str) -> String {
+    value
+        .pointer(pointer)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .trim()
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A canned stdio server: replies to each request by id, in call order.
+    const FAKE_SERVER: &str = r#"
+while IFS= read -r line; do
+  id=$(printf '%s' "$line" | sed -n 's/^{"id":\([0-9]*\).*/\1/p')
+  [ -z "$id" ] && continue
+  case "$line" in
+    *'"initialize"'*) echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"protocolVersion\":\"2025-06-18\",\"capabilities\":{\"tools\":{},\"prompts\":{},\"resources\":{}},\"serverInfo\":{\"name\":\"fake\",\"version\":\"0.1\"}}}" ;;
+    *'"tools/list"'*) echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"tools\":[{\"name\":\"echo\",\"description\":\"Echo\",\"inputSchema\":{\"type\":\"object\"},\"annotations\":{\"readOnlyHint\":true}}]}}" ;;
+    *'"tools/call"'*) echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"content\":[{\"type\":\"text\",\"text\":\"pong\"}]}}" ;;
+    *'"prompts/list"'*) echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"prompts\":[{\"name\":\"review\",\"arguments\":[{\"name\":\"code\",\"required\":true}]}]}}" ;;
+    *'"prompts/get"'*) echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"messages\":[{\"role\":\"user\",\"content\":{\"type\":\"text\",\"text\":\"Review this\"}}]}}" ;;
+    *'"resources/list"'*) echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"resources\":[{\"uri\":\"file:///notes.md\",\"name\":\"notes\",\"mimeType\":\"text/markdown\"}]}}" ;;
+    *'"resources/read"'*) echo "{\"jsonrpc\":\"2.0\",\"id\":$id,\"result\":{\"contents\":[{\"uri\":\"file:///notes.md\",\"text\":\"notes body\"}]}}" ;;
+  esac
+done
+"#;
+
+    fn fake_config(id: &str) -> McpServerConfig {
+        McpServerConfig {
+            id: id.to_owned(),
+            label: "Fake".to_owned(),
+            command: "sh".to_owned(),
+            args: vec!["-c".to_owned(), FAKE_SERVER.to_owned()],
+            env: BTreeMap::new(),
+            url: String::new(),
+            headers: BTreeMap::new(),
+            bearer_token_secret: String::new(),
+            enabled: true,
+            timeout_ms: 5_000,
+        }
+    }
+
+    #[test]
+    fn stdio_client_initializes_lists_and_calls_tools() {
+        let config = fake_config("fake_stdio");
+        let (info, tools, result) = with_connection(&config, |client| {
+            let tools = client.list_tools()?;
+            let result = client.call_tool("echo", &Map::new())?;
+            Ok((client.info.clone(), tools, result))
+        })
+        .expect("fake server round trip");
+        retain_connections(&[]);
+
+        assert_eq!(info.name, "fake");
+        let snapshot = tool_snapshot(&config, &tools[0]).expect("tool snapshot");
+        assert_eq!(snapshot.qualified_name, "fake_stdio__echo");
+        assert!(snapshot.read_only);
+        assert!(!snapshot.destructive);
+        assert_eq!(snapshot.risk, "read");
+        let result = tool_result("echo", &result);
+        assert_eq!(result.text, "pong");
+        assert!(!result.is_error);
+    }
+
+    #[test]
+    fn stdio_client_lists_and_fetches_prompts_and_resources() {
+        let config = fake_config("fake_context");
+        let (prompts, resources, prompt, resource) = with_connection(&config, |client| {
+            Ok((
+                client.list_prompts()?,
+                client.list_resources()?,
+                client.get_prompt("review", &Map::new())?,
+                client.read_resource("file:///notes.md")?,
+            ))
+        })
+        .expect("fake server round trip");
+        retain_connections(&[]);
+
+        let prompt_entry = prompt_snapshot(&config, &prompts[0]).expect("prompt snapshot");
+        assert_eq!(prompt_entry["qualified_name"], "fake_context__review");
+        assert_eq!(prompt_entry["arguments"][0]["name"], "code");
+        let resource_entry = resource_snapshot(&config, &resources[0]).expect("resource snapshot");
+        assert_eq!(resource_entry["uri"], "file:///notes.md");
+        assert_eq!(resource_entry["mime_type"], "text/markdown");
+        assert_eq!(prompt["messages"][0]["content"]["text"], "Review this");
+        assert_eq!(resource["contents"][0]["text"], "notes body");
+    }
+
+    #[test]
+    fn unannotated_tools_default_to_destructive() {
+        let snapshot =
+            tool_snapshot(&fake_config("x"), &json!({ "name": "rm" })).expect("tool snapshot");
+        assert!(snapshot.destructive);
+        assert!(snapshot.open_world);
+        assert_eq!(snapshot.risk, "destructive");
+    }
+}
@@ -0,0 +1,326 @@
+//! Streamable HTTP transport: every JSON-RPC message is sent as a POST to the server
+//! URL and the reply arrives either as a JSON body or as a `text/event-stream`.
+//!
+//! The server-assigned `Mcp-Session-Id` is echoed on every later request and
+//! released with a DELETE when the transport is dropped. An SSE stream that
+//! drops before the response arrives is resumed with a GET carrying
+//! `Last-Event-ID`. A 404 on an established session marks it expired so the
+//! client reconnects.
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use super::client::Transport;
+use crate::app_config::McpServerConfig;
+
+const SESSION_HEADER: &str = "Mcp-Session-Id";
+const PROTOCOL_HEADER: &str = "MCP-Protocol-Version";
+const MAX_RESUME_ATTEMPTS: usize = 3;
+const DEFAULT_RETRY: Duration = Duration::from_millis(500);
+const MAX_RETRY: Duration = Duration::from_secs(5);
+
+pub(super) struct HttpTransport {
+    agent: ureq::Agent,
+    url: String,
+    headers: Vec<(String, String)>,
+    bearer_token: Option<String>,
+    session_id: Option<String>,
+    protocol_version: Option<String>,
+    inbox: VecDeque<Value>,
+    expired: bool,
+}
+
+/// One dispatched SSE event; `data` is the joined `data:` lines.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct SseEvent {
+    id: Option<String>,
+    data: String,
+    retry: Option<Duration>,
+}
+
+impl HttpTransport {
+    pub(super) fn open(config: &McpServerConfig) -> Result<Self, String> {
+        let bearer_token = if config.bearer_token_secret.is_empty() {
+            None
+        } else {
+            let token = crate::secrets::lookup(&config.bearer_token_secret)
+                .map(|token| token.trim().to_owned())
+                .filter(|token| !token.is_empty())
+                .ok_or_else(|| {
+                    format!("{} not found in Secret Service", config.bearer_token_secret)
+                })?;
+            Some(token)
+        };
+        Ok(Self {
+            agent: ureq::Agent::config_builder()
+                .http_status_as_error(false)
+                .timeout_global(Some(Duration::from_millis(config.timeout_ms)))
+                .build()
+                .new_agent(),
+            url: config.url.clone(),
+            headers: config
+                .headers
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            bearer_token,
+            session_id: None,
+            protocol_version: None,
+            inbox: VecDeque::new(),
+            expired: false,
+        })
+    }
+
+    fn with_headers<B>(&self, mut request: ureq::RequestBuilder<B>) -> ureq::RequestBuilder<B> {
+        for (key, value) in &self.headers {
+            request = request.header(key, value);
+        }
+        if let Some(token) = &self.bearer_token {
+            request = request.header("Authorization", format!("Bearer {token}"));
+        }
+        if let Some(session_id) = &self.session_id {
+            request = request.header(SESSION_HEADER, session_id);
+        }
+        if let Some(version) = &self.protocol_version {
+            request = request.header(PROTOCOL_HEADER, version);
+        }
+        request
+    }
+
+    fn check_status(
+        &mut self,
+        response: &mut ureq::http::Response<ureq::Body>,
+    ) -> Result<(), String> {
+        let status = response.status().as_u16();
+        if (200..300).contains(&status) {
+            return Ok(());
+        }
+        if status == 404 && self.session_id.is_some() {
+            self.expired = true;
+            return Err("session expired".to_owned());
+        }
+        if status == 401 || status == 403 {
+            return Err(format!("HTTP {status}: check the server's bearer token"));
+        }
+        let body = response
+            .body_mut()
+            .read_to_string()
+            .unwrap_or_default()
+            .chars()
+            .take(200)
+            .collect::<String>();
+        Err(format!("HTTP {status}: {}", body.trim()))
+    }
+
+    /// Reads SSE messages into the inbox until the response to `request_id`
+    /// arrives, resuming a dropped stream via `Last-Event-ID`.
+    fn drain_stream(
+        &mut self,
+        response: ureq::http::Response<ureq::Body>,
+        request_id: Option<&Value>,
+    ) -> Result<(), String> {
+        let mut reader = response.into_body().into_reader();
+        let mut last_event_id = None;
+        let mut retry = DEFAULT_RETRY;
+        for attempt in 0..=MAX_RESUME_ATTEMPTS {
+            let mut answered = false;
+            let read = read_sse(&mut reader, |event| {
+                if event.id.is_some() {
+                    last_event_id.clone_from(&event.id);
+                }
+                if let Some(delay) = event.retry {
+                    retry = delay.min(MAX_RETRY);
+                }
+                for message in parse_messages(&event.data) {
+                    answered |= request_id.is_some() && message.get("id") == request_id;
+                    self.note_protocol_version(&message);
+                    self.inbox.push_back(message);
+                }
+                !answered
+            });
+            if answered || request_id.is_none() {
+                return Ok(());
+            }
+            let Some(event_id) = last_event_id.clone() else {
+                // Without an event id the stream cannot be resumed.
+                return read.and(Err("stream ended before a response".to_owned()));
+            };
+            if attempt == MAX_RESUME_ATTEMPTS {
+                break;
+            }
+            thread::sleep(retry);
+            let mut resumed = self
+                .with_headers(self.agent.get(&self.url))
+                .header("Accept", "text/event-stream")
+                .header("Last-Event-ID", &event_id)
+                .call()
+                .map_err(|error| format!("resume stream: {error}"))?;
+            self.check_status(&mut resumed)?;
+            reader = resumed.into_body().into_reader();
+        }
+        Err("stream ended before a response".to_owned())
+    }
+
+    /// Records the negotiated version so later requests carry it as a header.
+    fn note_protocol_version(&mut self, message: &Value) {
+        if let Some(version) = message
+            .pointer("/result/protocolVersion")
+            .and_then(Value::as_str)
+        {
+            self.protocol_version = Some(version.to_owned());
+        }
+    }
+}
+
+impl Transport for HttpTransport {
+    fn send(&mut self, message: &Value) -> Result<(), String> {
+        let body = serde_json::to_vec(message).map_err(|error| error.to_string())?;
+        let mut response = self
+            .with_headers(self.agent.post(&self.url))
+            .header("Content-Type", "application/json")
+            .header("Accept", "application/json, text/event-stream")
+            .send(&body[..])
+            .map_err(|error| format!("POST {}: {error}", self.url))?;
+        self.check_status(&mut response)?;
+        if let Some(session_id) = response
+            .headers()
+            .get(SESSION_HEADER)
+            .and_then(|value| value.to_str().ok())
+        {
+            self.session_id = Some(session_id.to_owned());
+        }
+        if response.status().as_u16() == 202 {
+            return Ok(());
+        }
+        let content_type = response
+            .headers()
+            .get("content-type")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        // Only requests expect a reply; notifications and responses get 202.
+        let request_id = message
+            .get("method")
+            .and_then(|_| message.get("id"))
+            .cloned();
+        if content_type.starts_with("text/event-stream") {
+            return self.drain_stream(response, request_id.as_ref());
+        }
+        let text = response
+            .body_mut()
+            .read_to_string()
+            .map_err(|error| format!("read response: {error}"))?;
+        for message in parse_messages(&text) {
+            self.note_protocol_version(&message);
+            self.inbox.push_back(message);
+        }
+        Ok(())
+    }
+
+    fn recv(&mut self, _deadline: Instant) -> Result<Value, String> {
+        // Replies are collected synchronously by `send`, under the agent's
+        // timeout, so an empty inbox means the server never answered.
+        self.inbox
+            .pop_front()
+            .ok_or_else(|| "server sent no response".to_owned())
+    }
+
+    fn is_alive(&mut self) -> bool {
+        !self.expired
+    }
+
+    fn session_expired(&self) -> bool {
+        self.expired
+    }
+}
+
+impl Drop for HttpTransport {
+    fn drop(&mut self) {
+        if self.session_id.is_none() || self.expired {
+            return;
+        }
+        let _ = self.with_headers(self.agent.delete(&self.url)).call();
+    }
+}
+
+/// Accepts a single JSON-RPC message or a batch array; anything else is dropped.
+fn parse_messages(text: &str) -> Vec<Value> {
+    match serde_json::from_str::<Value>(text.trim()) {
+        Ok(value @ Value::Object(_)) => vec![value],
+        Ok(Value::Array(items)) => items.into_iter().filter(Value::is_object).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Parses an SSE stream, calling `on_event` per dispatched event until it
+/// returns `false` or the stream ends.
+fn read_sse(reader: impl Read, mut on_event: impl FnMut(SseEvent) -> bool) -> Result<(), String> {
+    let mut buffered = BufReader::new(reader);
+    let mut line = String::new();
+    let mut event = SseEvent::default();
+    let mut has_data = false;
+    loop {
+        line.clear();
+        let read = buffered
+            .read_line(&mut line)
+            .map_err(|error| error.to_string())?;
+        let line = line.trim_end_matches(['\r', '\n']);
+        if read == 0 || line.is_empty() {
+            let pending = std::mem::take(&mut event);
+            if has_data && !on_event(pending) {
+                return Ok(());
+            }
+            has_data = false;
+            if read == 0 {
+                return Ok(());
+            }
+            continue;
+        }
+        if line.starts_with(':') {
+            continue;
+        }
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "data" => {
+                if has_data {
+                    event.data.push('\n');
+                }
+                event.data.push_str(value);
+                has_data = true;
+            }
+            "id" => event.id = Some(value.to_owned()),
+            "retry" => event.retry = value.parse().ok().map(Duration::from_millis),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sse_parser_joins_data_lines_and_tracks_ids() {
+        let stream = ": keep-alive\nid: 7\nretry: 250\ndata: {\"a\":\ndata: 1}\n\n\
+                      event: message\ndata: [{\"id\":1},2]\n\n";
+        let mut events = Vec::new();
+        read_sse(stream.as_bytes(), |event| {
+            events.push(event);
+            true
+        })
+        .expect("parse");
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].id.as_deref(), Some("7"));
+        assert_eq!(events[0].retry, Some(Duration::from_millis(250)));
+        assert_eq!(
+            parse_messages(&events[0].data),
+            vec![serde_json::json!({"a": 1})]
+        );
+        assert_eq!(parse_messages(&events[1].data).len(), 1);
+    }
+}
@@ -0,0 +1,109 @@
+//! Newline-delimited JSON-RPC over a child process's stdin/stdout.
+//!
+//! The child's stdout is drained by a reader thread into a channel so reads can
+//! honour a deadline; stderr is inherited so server logs land in the Quickshell
+//! log. The child is killed when the transport is dropped.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Instant;
+
+use serde_json::Value;
+
+use super::client::Transport;
+use crate::app_config::McpServerConfig;
+
+pub(super) struct StdioTransport {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl StdioTransport {
+    pub(super) fn spawn(config: &McpServerConfig) -> Result<Self, String> {
+        let mut child = Command::new(&config.command)
+            .args(&config.args)
+            .envs(&config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|error| format!("spawn {}: {error}", config.command))?;
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| format!("{}: stdin unavailable", config.command))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| format!("{}: stdout unavailable", config.command))?;
+
+        let (tx, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            child,
+            stdin,
+            lines,
+        })
+    }
+}
+
+impl Transport for StdioTransport {
+    fn send(&mut self, message: &Value) -> Result<(), String> {
+        let mut line = serde_json::to_vec(message).map_err(|error| error.to_string())?;
+        line.push(b'\n');
+        self.stdin
+            .write_all(&line)
+            .and_then(|()| self.stdin.flush())
+            .map_err(|error| format!("write to server: {error}"))
+    }
+
+    fn recv(&mut self, deadline: Instant) -> Result<Value, String> {
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.lines.recv_timeout(timeout) {
+                Ok(line) => {
+                    let line = line.trim();
+                    if line.is_empty() {
+                        continue;
+                    }
+                    // Servers occasionally print banners to stdout; skip anything
+                    // that is not a JSON-RPC message rather than failing the read.
+                    if let Ok(value @ Value::Object(_)) = serde_json::from_str::<Value>(line) {
+                        return Ok(value);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => return Err("request timed out".to_owned()),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(match self.child.try_wait() {
+                        Ok(Some(status)) => format!("server exited with {status}"),
+                        _ => "server closed stdout".to_owned(),
+                    });
+                }
+            }
+        }
+    }
+
+    fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+impl Drop for StdioTransport {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,289 @@
+//! Scheduled background agent jobs.
+//!
+//! Opaque handle owned by the C++ `QsNativeAiScheduler` `QObject`. `Start`
+//! spawns a worker that re-reads `[[schedule.jobs]]` from `config.toml` on
+//! every tick, runs each job once it comes due (headless, one at a time, see
+//! `ai::run_scheduled_job`) and reports back to C++ as JSON: `job_started`
+//! with the run's cancellable session id, `job_finished` after each run and
+//! `error` when the schedule cannot be read. Runs that fell due while the
+//! shell was not running are skipped, not caught up.
+
+use std::collections::HashMap;
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Days, Local, TimeZone};
+use serde_json::json;
+
+use crate::ai::ProviderConfig;
+use crate::app_config::ScheduledJob;
+use crate::ffi::{emit_snapshot, QsNativeUpdateFn};
+
+/// How often the worker re-reads the schedule and looks for due jobs.
+const TICK: Duration = Duration::from_secs(20);
+/// How often a sleeping worker re-checks its stop flag.
+const STOP_POLL: Duration = Duration::from_millis(500);
+
+/// Callback target shared between the handle and the worker thread. Set to
+/// `None` by `Delete` while holding the mutex, which both stops any further
+/// callbacks and blocks until an in-flight callback finishes, so the C++ `ctx`
+/// is never touched after the `QObject` is torn down.
+struct Sink {
+    ctx: usize,
+    cb: QsNativeUpdateFn,
+}
+
+type Gate = Arc<Mutex<Option<Sink>>>;
+
+/// Opaque per-instance handle owned by the C++ `QsNativeAiScheduler` `QObject`.
+pub struct AiSchedulerHandle {
+    gate: Gate,
+    stop_flag: Option<Arc<AtomicBool>>,
+}
+
+#[no_mangle]
+pub extern "C" fn QsNative_AiScheduler_New() -> *mut AiSchedulerHandle {
+    Box::into_raw(Box::new(AiSchedulerHandle {
+        gate: Arc::new(Mutex::new(None)),
+        stop_flag: None,
+    }))
+}
+
+/// # Safety
+/// `handle` must be null or a pointer from `QsNative_AiScheduler_New` not yet
+/// freed. Any in-flight worker callback is drained before the handle drops;
+/// a job that is still running finishes, but reports nothing.
+///
+/// # Panics
+/// Panics if the gate mutex is poisoned (a worker thread panicked while holding it).
+#[no_mangle]
+pub unsafe extern "C" fn QsNative_AiScheduler_Delete(handle: *mut AiSchedulerHandle) {
+    if handle.is_null() {
+        return;
+    }
+    let handle = Box::from_raw(handle);
+    if let Some(flag) = &handle.stop_flag {
+        flag.store(true, Ordering::Relaxed);
+    }
+    *handle.gate.lock().expect("scheduler gate poisoned") = None;
+}
+
+/// (Re)starts the scheduler worker. `(provider_config_ptr,
+/// provider_config_len)` is the CBOR provider map the chat session streams
+/// with; jobs use it for their API keys and endpoints.
+///
+/// # Safety
+/// `handle` must be valid; `(provider_config_ptr, provider_config_len)` must
+/// describe a readable CBOR byte range for the call (or the pointer may be
+/// null); `ctx`/`cb` must stay valid until `QsNative_AiScheduler_Delete`
+/// drains the gate.
+///
+/// # Panics
+/// Panics if the gate mutex is poisoned (a worker thread panicked while holding it).
+#[no_mangle]
+pub unsafe extern "C" fn QsNative_AiScheduler_Start(
+    handle: *mut AiSchedulerHandle,
+    provider_config_ptr: *const u8,
+    provider_config_len: usize,
+    ctx: *mut c_void,
+    cb: QsNativeUpdateFn,
+) {
+    if handle.is_null() {
+        return;
+    }
+    let handle = &mut *handle;
+    stop_worker(handle);
+
+    let provider_config = crate::ffi::from_cbor::<HashMap<String, ProviderConfig>>(
+        provider_config_ptr,
+        provider_config_len,
+    )
+    .unwrap_or_default();
+    *handle.gate.lock().expect("scheduler gate poisoned") = Some(Sink {
+        ctx: ctx as usize,
+        cb,
+    });
+
+    let stop_flag = Arc::new(AtomicBool::new(false));
+    handle.stop_flag = Some(Arc::clone(&stop_flag));
+    let gate = Arc::clone(&handle.gate);
+    thread::spawn(move || run_scheduler(&provider_config, &stop_flag, &gate));
+}
+
+/// Stops the worker after the job it is running, if any.
+///
+/// # Safety
+/// `handle` must be null or a valid handle pointer.
+#[no_mangle]
+pub unsafe extern "C" fn QsNative_AiScheduler_Stop(handle: *mut AiSchedulerHandle) {
+    if handle.is_null() {
+        return;
+    }
+    stop_worker(&mut *handle);
+}
+
+fn stop_worker(handle: &mut AiSchedulerHandle) {
+    if let Some(flag) = handle.stop_flag.take() {
+        flag.store(true, Ordering::Relaxed);
+    }
+}
+
+fn run_scheduler(
+    provider_config: &HashMap<String, ProviderConfig>,
+    stop_flag: &AtomicBool,
+    gate: &Gate,
+) {
+    // Each job with the definition its next run was computed from, so an
+    // edited job is rescheduled from the moment the edit is seen.
+    let mut schedule: HashMap<String, (ScheduledJob, Option<DateTime<Local>>)> = HashMap::new();
+    let mut last_error = String::new();
+    while !stop_flag.load(Ordering::Relaxed) {
+        match crate::app_config::load_scheduled_jobs(&crate::app_config::default_path()) {
+            Ok(jobs) => {
+                last_error.clear();
+                schedule.retain(|id, _| jobs.iter().any(|job| job.id == *id));
+                for job in jobs {
+                    if stop_flag.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let now = Local::now();
+                    let due = match schedule.get(&job.id) {
+                        Some((known, due)) if *known == job => *due,
+                        _ => next_run(&job, &now),
+                    };
+                    let due = if due.is_some_and(|due| due <= now) {
+                        run_job(&job, provider_config, gate);
+                        next_run(&job, &Local::now())
+                    } else {
+                        due
+                    };
+                    schedule.insert(job.id.clone(), (job, due));
+                }
+            }
+            Err(error) => {
+                if error != last_error {
+                    emit_event(
+                        gate,
+                        &json!({ "kind": "error", "error": error }).to_string(),
+                    );
+                    last_error = error;
+                }
+            }
+        }
+        sleep_unless_stopped(stop_flag, TICK);
+    }
+}
+
+fn run_job(job: &ScheduledJob, provider_config: &HashMap<String, ProviderConfig>, gate: &Gate) {
+    let started = |session_id: i32| {
+        let event = json!({
+            "kind": "job_started",
+            "job_id": job.id,
+            "label": job.label,
+            "session_id": session_id,
+        });
+        emit_event(gate, &event.to_string());
+    };
+    let event = match crate::ai::run_scheduled_job(job, provider_config.clone(), started) {
+        Ok(run) => json!({
+            "kind": "job_finished",
+            "job_id": job.id,
+            "label": job.label,
+            "conversation_id": run.conversation_id,
+            "title": run.title,
+            "error": run.error,
+        }),
+        Err(error) => json!({
+            "kind": "job_finished",
+            "job_id": job.id,
+            "label": job.label,
+            "error": error,
+        }),
+    };
+    emit_event(gate, &event.to_string());
+}
+
+/// The first time after `after` that falls on one of the job's days at its
+/// time of day. A time skipped by a DST change skips that day's run.
+fn next_run<Tz: TimeZone>(job: &ScheduledJob, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+    let zone = after.timezone();
+    (0..=7).find_map(|offset| {
+        let date = after.date_naive().checked_add_days(Days::new(offset))?;
+        if !job.days.contains(&date.weekday()) {
+            return None;
+        }
+        let at = zone
+            .from_local_datetime(&date.and_hms_opt(job.hour, job.minute, 0)?)
+            .earliest()?;
+        (at > *after).then_some(at)
+    })
+}
+
+fn sleep_unless_stopped(stop_flag: &AtomicBool, duration: Duration) {
+    let mut left = duration;
+    while !left.is_zero() && !stop_flag.load(Ordering::Relaxed) {
+        let step = left.min(STOP_POLL);
+        thread::sleep(step);
+        left -= step;
+    }
+}
+
+/// Delivers one JSON event to C++ while holding the gate, so a concurrent
+/// `Delete` cannot free the `QObject` mid-callback.
+fn emit_event(gate: &Gate, json: &str) {
+    let guard = gate.lock().expect("scheduler gate poisoned");
+    if let Some(sink) = guard.as_ref() {
+        unsafe { emit_snapshot(sink.cb, sink.ctx as *mut c_void, json.to_owned()) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Utc, Weekday};
+
+    use super::*;
+
+    #[test]
+    fn next_run_picks_the_next_matching_day_and_time() {
+        let job = ScheduledJob {
+            id: "morning".to_owned(),
+            label: "morning".to_owned(),
+            prompt: "Summarize my inbox.".to_owned(),
+            hour: 8,
+            minute: 30,
+            days: vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+            ],
+            model: String::new(),
+            system_prompt: String::new(),
+        };
+        let at = |text: &str| text.parse::<DateTime<Utc>>().expect("timestamp");
+        // Friday after the run: Monday morning.
+        assert_eq!(
+            next_run(&job, &at("2026-10-16T09:00:00Z")),
+            Some(at("2026-10-19T08:30:00Z"))
+        );
+        // Monday before the run: later that day.
+        assert_eq!(
+            next_run(&job, &at("2026-10-19T08:00:00Z")),
+            Some(at("2026-10-19T08:30:00Z"))
+        );
+        // Exactly at the run: the next day.
+        assert_eq!(
+            next_run(&job, &at("2026-10-19T08:30:00Z")),
+            Some(at("2026-10-20T08:30:00Z"))
+        );
+        let never = ScheduledJob {
+            days: Vec::new(),
+            ..job
+        };
+        assert_eq!(next_run(&never, &at("2026-10-19T08:00:00Z")), None);
+    }
+}
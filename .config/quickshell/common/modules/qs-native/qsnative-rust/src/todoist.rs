@@ -2,6 +2,7 @@ use std::collections::BTreeMap;
 use std::fs;
 use std::os::raw::{c_char, c_void};
 use std::path::Path;
+use std::sync::{Mutex, OnceLock};
 use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -16,6 +17,9 @@ const TODOIST_SYNC_URL: &str = "https://api.todoist.com/api/v1/sync";
 const TODOIST_CACHE_VERSION: i32 = 1;
 const TODOIST_SYNC_TOKEN_FULL: &str = "*";
 
+/// The cache path of the latest refresh, for `today_digest`.
+static LAST_CACHE_PATH: OnceLock<Mutex<String>> = OnceLock::new();
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct CacheState {
     #[serde(default)]
@@ -231,6 +235,9 @@ fn refresh_todoist_result(cache_path: &str, prefer_cache: bool) -> Result<Refres
         cache_path.trim()
     ));
 
+    if let Ok(mut last) = LAST_CACHE_PATH.get_or_init(Mutex::default).lock() {
+        cache_path.trim().clone_into(&mut last);
+    }
     let cached_state = read_cache_state(cache_path).ok();
     if prefer_cache {
         if let Some(state) = cached_state.as_ref() {
@@ -584,6 +591,33 @@ fn render_list_output(state: Option<&CacheState>, using_cache: bool, error: &str
     output
 }
 
+/// Today's and overdue tasks from the on-disk cache, one line each, for the
+/// assistant's system prompt. `None` before the first refresh.
+pub(crate) fn today_digest() -> Option<String> {
+    let path = LAST_CACHE_PATH.get()?.lock().ok()?.clone();
+    let state = read_cache_state(&path).ok()?;
+    Some(render_today_digest(&render_list_output(
+        Some(&state),
+        true,
+        "",
+    )))
+}
+
+fn render_today_digest(output: &ListOutput) -> String {
+    if output.today.is_empty() {
+        return "No tasks due today.".to_owned();
+    }
+    output
+        .today
+        .iter()
+        .map(|task| match task.due_human.as_deref() {
+            Some(due) => format!("- {} ({due})", task.title),
+            None => format!("- {}", task.title),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 fn task_due(item: &TodoistItem, today: chrono::NaiveDate) -> (Option<i64>, Option<String>, bool) {
     let Some(date) = item.due.as_ref().and_then(|due| due.date.as_deref()) else {
         return (None, None, false);
@@ -52,6 +52,14 @@ pub fn is_false(value: &bool) -> bool {
     !*value
 }
 
+/// Returns `true` when `value` is zero.
+///
+/// Intended for use with `#[serde(skip_serializing_if = "crate::utils::is_zero")]`.
+#[must_use]
+pub fn is_zero(value: &i32) -> bool {
+    *value == 0
+}
+
 /// Trims `value` and returns `Some(trimmed.to_owned())` if non-empty, else `None`.
 pub(crate) fn non_empty_trimmed(value: &str) -> Option<String> {
     let trimmed = value.trim();
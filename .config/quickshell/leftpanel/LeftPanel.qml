@@ -1,6 +1,7 @@
 pragma ComponentBehavior: Bound
 import QtQuick
 import Quickshell
+import Quickshell.Hyprland
 import Quickshell.Io
 import "../common" as Common
 import "../common/JsonUtils.js" as JsonUtils
@@ -228,6 +229,14 @@ Item {
         accent: root.toolDisabled("provider_search") ? Common.Config.color.error : Common.Config.color.tertiary,
         recommended: true
       },
+      {
+        value: "image_generation",
+        label: "Image generation",
+        description: root.toolDisabled("image_generation") ? "Disabled - models will not be offered hosted image generation" : "Enabled - supported models may generate images",
+        icon: "\uf03e",
+        accent: root.toolDisabled("image_generation") ? Common.Config.color.error : Common.Config.color.tertiary,
+        recommended: true
+      },
       {
         value: "builtin",
         label: "Shell",
@@ -298,6 +307,7 @@ Item {
     system_prompt: root.moodPrompts[root.currentMood] || ""
     provider_config: root.providerConfig
     disabled_tool_servers: root.disabledToolServers
+    focused_window: Hyprland.activeToplevel ? String(Hyprland.activeToplevel.title || "") : ""
 
     onOpenModelPickerRequested: {
       root.showModelCatalogStatus()
@@ -312,6 +322,10 @@ Item {
       root.activeCommand = "resume"
       root.showCommandPicker = true
     }
+    onOpenSearchPickerRequested: {
+      root.activeCommand = "search"
+      root.showCommandPicker = true
+    }
     onOpenProviderPickerRequested: root.openProviderPicker()
     onOpenToolPickerRequested: root.openToolPicker()
     onScrollToEndRequested: panelView.scrollToEnd()
@@ -322,6 +336,26 @@ Item {
     Component.onCompleted: restoreHistory()
   }
 
+  // Runs the [[schedule.jobs]] prompts from config.toml in the background;
+  // each run lands in the resume list as a `scheduled` conversation.
+  AiScheduler {
+    id: scheduler
+    provider_config: root.providerConfig
+    onJobFinished: function (job) {
+      const label = String(job.label || job.job_id || "Scheduled job")
+      const error = String(job.error || "")
+      if (error)
+        Common.ProcessHelper.execDetached(["notify-send", "-a", "Left panel", label + " failed", error])
+      else
+        Common.ProcessHelper.execDetached(["notify-send", "-a", "Left panel", label + " finished", "Open it from /resume: " + String(job.title || "")])
+    }
+    onErrorChanged: {
+      if (error)
+        console.warn("AiScheduler error:", error)
+    }
+    Component.onCompleted: start()
+  }
+
   onModelIdChanged: {
     chatSession.resetForModelSwitch(root.modelId)
     panelView.scrollToEnd()
@@ -367,6 +401,7 @@ Item {
     onRegenerateRequested: messageId => chatSession.regenerate(messageId)
     onDeleteRequested: messageId => chatSession.deleteMessage(messageId)
     onEditRequested: (messageId, newContent) => chatSession.editMessage(messageId, newContent)
+    onBranchRequested: (messageId, newContent) => chatSession.branchFrom(messageId, newContent)
 
     onDismissCommandPickerRequested: root.showCommandPicker = false
 
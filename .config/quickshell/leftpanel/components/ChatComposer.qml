@@ -86,6 +86,16 @@ Item {
     return p.endsWith(".pdf")
   }
 
+  function isImageAttachment(a) {
+    if (!a)
+      return false
+    const mime = String(a.mime || "").trim().toLowerCase()
+    if (mime.length > 0)
+      return mime.startsWith("image/")
+    const p = String(a.path || "").trim().toLowerCase()
+    return /\.(png|jpe?g|gif|webp|bmp|svg|avif)$/.test(p)
+  }
+
   function attachmentLabel(a) {
     const p = String((a || {}).path || "").trim()
     if (!p)
@@ -365,17 +375,17 @@ Item {
                         fillMode: Image.PreserveAspectCrop
                         asynchronous: true
                         cache: false
-                        visible: !root.isPdfAttachment(attachmentDelegate.modelData)
+                        visible: root.isImageAttachment(attachmentDelegate.modelData)
                       }
 
                       Item {
                         anchors.fill: parent
-                        visible: root.isPdfAttachment(attachmentDelegate.modelData)
+                        visible: !root.isImageAttachment(attachmentDelegate.modelData)
 
                         Text {
                           anchors.horizontalCenter: parent.horizontalCenter
                           anchors.verticalCenter: parent.verticalCenter
-                          text: "\uf1c1" // file-pdf
+                          text: root.isPdfAttachment(attachmentDelegate.modelData) ? "\uf1c1" : "\uf15c" // file-pdf / file-lines
                           color: Common.Config.color.on_surface_variant
                           font.family: Common.Config.iconFontFamily
                           font.pixelSize: 22
@@ -26,8 +26,17 @@ Item {
   property string activeSelectionKey: ""
   property var metrics: ({})
   property var attachments: []
+  property string reasoning: ""
+  property bool reasoningExpanded: false
+  property var citations: []
+  // Answers to this turn when it has been regenerated: [{index, active, preview, ...}].
+  property var variants: []
+  readonly property int activeVariant: root.variants.findIndex(v => v.active)
+  property var sourceList: uniqueSources(root.citations)
 
   property var attachmentList: normalizedAttachments(root.attachments)
+  property var imageAttachments: attachmentList.filter(a => isImageAttachment(a))
+  property var documentAttachments: attachmentList.filter(a => !isImageAttachment(a))
   property Item actionTooltipTarget: null
   property Item pendingActionTooltipTarget: null
   property string actionTooltipText: ""
@@ -35,6 +44,7 @@ Item {
   property bool actionTooltipOpen: false
 
   signal regenerateRequested
+  signal variantRequested(int index)
   signal deleteRequested
   signal editSaved(string newContent)
   signal selectionActivated(string selectionKey)
@@ -92,6 +102,23 @@ Item {
     return /(^|[^\\])\$[^\s$][\s\S]*?[^\\]\$/.test(source) || /\\\([\s\S]+?\\\)/.test(source) || /\$\$[\s\S]+?\$\$/.test(source) || /\\\[[\s\S]+?\\\]/.test(source) || /\\begin\{(?:equation\*?|align\*?|gather\*?|multline\*?|matrix\*?|bmatrix|pmatrix|vmatrix|Vmatrix)\}[\s\S]+?\\end\{(?:equation\*?|align\*?|gather\*?|multline\*?|matrix\*?|bmatrix|pmatrix|vmatrix|Vmatrix)\}/.test(source)
   }
 
+  // One entry per cited URL, in order of first citation.
+  function uniqueSources(value) {
+    const seen = {}
+    const sources = []
+    for (const citation of normalizedAttachments(value)) {
+      const url = String(citation && citation.url || "")
+      if (url.length === 0 || seen[url])
+        continue
+      seen[url] = true
+      sources.push({
+        url: url,
+        title: String(citation.title || "") || url.replace(/^[a-z]+:\/\//, "").split("/")[0]
+      })
+    }
+    return sources
+  }
+
   function normalizedAttachments(value) {
     if (!value)
       return []
@@ -123,6 +150,19 @@ Item {
     root.renderMarkdown = !root.renderMarkdown
   }
 
+  function isImageAttachment(attachment) {
+    const mime = String((attachment || {}).mime || "").trim().toLowerCase()
+    if (mime.length > 0)
+      return mime.startsWith("image/")
+    const path = String((attachment || {}).path || "").trim().toLowerCase()
+    return /\.(png|jpe?g|gif|webp|bmp|svg|avif)$/.test(path)
+  }
+
+  function attachmentName(attachment) {
+    const path = String((attachment || {}).path || "").trim()
+    return path.length > 0 ? path.split("/").pop() : qsTr("Attachment")
+  }
+
   function attachmentSource(attachment) {
     if (!attachment)
       return ""
@@ -275,6 +315,36 @@ Item {
           }
         }
 
+        // Variant switcher: ‹ 2/3 ›
+        Row {
+          visible: root.variants.length > 1 && root.done
+          spacing: 2
+
+          MessageControlButton {
+            id: previousVariantButton
+            icon: "\ueab5"
+            enabled: root.activeVariant > 0
+            onClicked: root.variantRequested(root.variants[root.activeVariant - 1].index)
+            onHoveredChanged: hovered ? root.showActionTooltip(previousVariantButton, qsTr("Previous answer")) : root.hideActionTooltip(previousVariantButton)
+          }
+
+          Text {
+            anchors.verticalCenter: parent.verticalCenter
+            text: (root.activeVariant + 1) + "/" + root.variants.length
+            font.pixelSize: 10
+            font.family: Common.Config.fontFamily
+            color: Common.Config.color.on_surface_variant
+          }
+
+          MessageControlButton {
+            id: nextVariantButton
+            icon: "\ueab6"
+            enabled: root.activeVariant >= 0 && root.activeVariant < root.variants.length - 1
+            onClicked: root.variantRequested(root.variants[root.activeVariant + 1].index)
+            onHoveredChanged: hovered ? root.showActionTooltip(nextVariantButton, qsTr("Next answer")) : root.hideActionTooltip(nextVariantButton)
+          }
+        }
+
         // Action buttons (appear on hover)
         Row {
           spacing: 2
@@ -351,11 +421,75 @@ Item {
         }
       }
 
+      // Reasoning summary / thoughts, collapsed to a toggle by default.
+      ColumnLayout {
+        Layout.fillWidth: true
+        Layout.topMargin: 6
+        visible: root.isAssistant && root.reasoning.length > 0
+        spacing: 4
+
+        Item {
+          Layout.fillWidth: true
+          implicitHeight: reasoningToggle.implicitHeight
+          opacity: 0.5
+
+          MouseArea {
+            anchors.fill: parent
+            cursorShape: Qt.PointingHandCursor
+            onClicked: root.reasoningExpanded = !root.reasoningExpanded
+          }
+
+          Row {
+            id: reasoningToggle
+            spacing: 4
+
+            Text {
+              anchors.verticalCenter: parent.verticalCenter
+              text: "\uf105"
+              color: Common.Config.color.on_surface_variant
+              rotation: root.reasoningExpanded ? 90 : 0
+              font.family: Common.Config.iconFontFamily
+              font.pixelSize: 11
+
+              Behavior on rotation {
+                NumberAnimation {
+                  duration: Common.Config.motion.duration.shortMs
+                  easing.type: Easing.OutCubic
+                }
+              }
+            }
+
+            Text {
+              anchors.verticalCenter: parent.verticalCenter
+              text: root.thinking ? "THINKING" : "THOUGHTS"
+              color: Common.Config.color.on_surface_variant
+              font {
+                family: Common.Config.fontFamily
+                pixelSize: 9
+                weight: Font.Bold
+              }
+            }
+          }
+        }
+
+        Text {
+          Layout.fillWidth: true
+          visible: root.reasoningExpanded
+          text: root.reasoning
+          textFormat: Text.PlainText
+          wrapMode: Text.Wrap
+          color: Common.Config.color.on_surface_variant
+          font.family: Common.Config.fontFamily
+          font.pixelSize: 12
+          font.italic: true
+        }
+      }
+
       // In-progress assistant message (placeholder while streaming).
       Item {
         Layout.fillWidth: true
         Layout.topMargin: 6
-        visible: root.thinking
+        visible: root.thinking && root.reasoning.length === 0
 
         Column {
           width: parent.width
@@ -566,16 +700,17 @@ Item {
         }
       }
 
-      // Image attachment thumbnails (user messages with attached images)
+      // Attachment thumbnails (images the user attached or the model generated)
+      // and file chips for documents
       Flow {
         id: attachmentFlow
-        visible: root.isUser && root.attachmentList.length > 0
+        visible: root.attachmentList.length > 0
         spacing: 6
         Layout.fillWidth: true
         Layout.topMargin: 6
 
         Repeater {
-          model: root.attachmentList
+          model: root.imageAttachments
           Image {
             id: attachmentPreview
             required property var modelData
@@ -592,16 +727,90 @@ Item {
             cache: false
           }
         }
+
+        Repeater {
+          model: root.documentAttachments
+
+          Rectangle {
+            id: documentChip
+            required property var modelData
+
+            width: Math.min(documentText.implicitWidth + 12, 220)
+            height: documentText.implicitHeight + 6
+            radius: height / 2
+            color: Qt.alpha(Common.Config.color.on_surface, 0.06)
+
+            Text {
+              id: documentText
+              anchors.fill: parent
+              anchors.leftMargin: 6
+              anchors.rightMargin: 6
+              verticalAlignment: Text.AlignVCenter
+              text: root.attachmentName(documentChip.modelData)
+              color: Common.Config.color.on_surface_variant
+              elide: Text.ElideRight
+              font.family: Common.Config.fontFamily
+              font.pixelSize: 10
+            }
+          }
+        }
+      }
+
+      // Web-search sources cited by the reply
+      Flow {
+        visible: root.isAssistant && root.sourceList.length > 0
+        spacing: 4
+        Layout.fillWidth: true
+        Layout.topMargin: 6
+
+        Repeater {
+          model: root.sourceList
+
+          Rectangle {
+            id: sourceChip
+            required property var modelData
+            required property int index
+
+            width: Math.min(sourceText.implicitWidth + 12, 220)
+            height: sourceText.implicitHeight + 6
+            radius: height / 2
+            color: Qt.alpha(Common.Config.color.primary, sourceMouse.containsMouse ? 0.16 : 0.08)
+
+            Text {
+              id: sourceText
+              anchors.fill: parent
+              anchors.leftMargin: 6
+              anchors.rightMargin: 6
+              verticalAlignment: Text.AlignVCenter
+              text: (sourceChip.index + 1) + "  " + sourceChip.modelData.title
+              color: Common.Config.color.primary
+              elide: Text.ElideRight
+              font.family: Common.Config.fontFamily
+              font.pixelSize: 10
+            }
+
+            MouseArea {
+              id: sourceMouse
+              anchors.fill: parent
+              hoverEnabled: true
+              cursorShape: Qt.PointingHandCursor
+              onClicked: Qt.openUrlExternally(sourceChip.modelData.url)
+            }
+          }
+        }
       }
 
-      // Per-message stream metrics (assistant messages, shown after streaming completes)
+      // Per-message stream metrics (assistant messages; live estimates while streaming)
       Text {
         property var metricsData: root.metrics || ({})
         property int metricsTokens: metricsData.output_tokens || 0
         property int metricsTtft: metricsData.ttf_ms || 0
+        property real metricsRate: metricsData.tokens_per_sec || 0
+        // Names the model that answered when the requested one fell back.
+        property string metricsVia: (metricsData.fallbacks || []).length > 0 ? "  ·  via " + (metricsData.model || "") : ""
 
-        visible: root.isAssistant && root.done && !root.thinking && metricsTokens > 0
-        text: metricsTtft > 0 ? metricsTtft + "ms ttft  ·  " + metricsTokens + " tok" : metricsTokens + " tok"
+        visible: root.isAssistant && !root.thinking && metricsTokens > 0
+        text: (metricsTtft > 0 ? metricsTtft + "ms ttft  ·  " : "") + (root.done ? "" : "~") + metricsTokens + " tok" + (metricsRate > 0 ? "  ·  " + metricsRate.toFixed(1) + " tok/s" : "") + metricsVia
         color: Common.Config.color.on_surface_variant
         opacity: 0.45
         font.pixelSize: 10
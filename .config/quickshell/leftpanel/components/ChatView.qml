@@ -22,6 +22,8 @@ Item {
   signal regenerateRequested(string messageId)
   signal deleteRequested(string messageId)
   signal editRequested(string messageId, string newContent)
+  // An edited user message is resent in a fork, leaving the original thread intact.
+  signal branchRequested(string messageId, string newContent)
 
   function positionToEnd() {
     messageList.scrollToEnd()
@@ -312,11 +314,14 @@ Item {
             required property var attachments
             required property var tool
             required property bool showHeader
+            required property string reasoning
+            required property var citations
+            required property int variantCount
 
             width: messageColumn.width
             implicitHeight: contentLoader.loadedItem ? contentLoader.loadedItem.implicitHeight : 0
             property string _messageId: messageId
-            property bool emptyAssistantPlaceholder: kind !== "tool" && sender === "assistant" && String(body || "").trim().length === 0 && !(root.busy && index === (messageRepeater.count - 1))
+            property bool emptyAssistantPlaceholder: kind !== "tool" && sender === "assistant" && String(body || "").trim().length === 0 && reasoning.length === 0 && (attachments || []).length === 0 && !(root.busy && index === (messageRepeater.count - 1))
             readonly property Item loadedContentItem: contentLoader.loadedItem
 
             Loader {
@@ -336,6 +341,9 @@ Item {
                 content: delegateRoot.body
                 metrics: delegateRoot.metrics
                 attachments: delegateRoot.attachments
+                reasoning: delegateRoot.reasoning
+                citations: delegateRoot.citations
+                variants: delegateRoot.sender === "assistant" && delegateRoot.variantCount > 1 && !root.busy && root.chatSession ? root.chatSession.variants(delegateRoot._messageId) : []
                 activeSelectionKey: messageList.activeSelectionKey
                 modelLabel: delegateRoot.sender === "assistant" ? root.modelLabel : ""
                 moodIcon: root.moodIcon
@@ -346,8 +354,9 @@ Item {
                 done: !streaming
 
                 onRegenerateRequested: root.regenerateRequested(delegateRoot._messageId)
+                onVariantRequested: index => root.chatSession.switchVariant(delegateRoot._messageId, index)
                 onDeleteRequested: root.deleteRequested(delegateRoot._messageId)
-                onEditSaved: newContent => root.editRequested(delegateRoot._messageId, newContent)
+                onEditSaved: newContent => delegateRoot.sender === "user" ? root.branchRequested(delegateRoot._messageId, newContent) : root.editRequested(delegateRoot._messageId, newContent)
                 onSelectionActivated: selectionKey => messageList.activeSelectionKey = selectionKey
               }
             }
@@ -464,7 +473,16 @@ Item {
     anchors.left: parent.left
     anchors.right: parent.right
     anchors.bottom: parent.bottom
-    height: composer.implicitHeight + Common.Config.space.md * 2
+    height: composer.implicitHeight + Common.Config.space.md * 2 + (approvalBar.visible ? approvalBar.implicitHeight + Common.Config.space.sm : 0)
+
+    Components.ToolApprovalBar {
+      id: approvalBar
+      anchors.left: parent.left
+      anchors.right: parent.right
+      anchors.top: parent.top
+      anchors.topMargin: Common.Config.space.md
+      chatSession: root.chatSession
+    }
 
     Components.ChatComposer {
       id: composer
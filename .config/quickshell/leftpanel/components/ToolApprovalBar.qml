@@ -0,0 +1,113 @@
+pragma ComponentBehavior: Bound
+import QtQuick
+import "../../common" as Common
+import "../../common/materialkit" as MK
+
+// Shows the oldest tool call waiting on the approval gate with Allow/Deny.
+Item {
+  id: root
+
+  property var chatSession: null
+  readonly property var pending: chatSession ? (chatSession.pending_approvals || []) : []
+  readonly property var request: pending.length > 0 ? pending[0] : ({})
+  readonly property string toolCallId: String(request.tool_call_id || "")
+  readonly property string title: String(request.tool_title || request.tool_name || "tool call")
+  readonly property string serverLabel: String(request.server_label || request.server_id || "")
+  readonly property string detail: String(request.subtitle || "")
+  readonly property bool destructive: String(request.risk || "") === "destructive"
+
+  visible: pending.length > 0
+  implicitHeight: visible ? card.implicitHeight : 0
+
+  Rectangle {
+    id: card
+    anchors.left: parent.left
+    anchors.right: parent.right
+    implicitHeight: content.implicitHeight + Common.Config.space.sm * 2
+    radius: Common.Config.shape.corner.md
+    color: Common.Config.color.surface_container_high
+    border.width: 1
+    border.color: Qt.alpha(root.destructive ? Common.Config.color.error : Common.Config.color.primary, 0.4)
+
+    Row {
+      id: content
+      anchors.left: parent.left
+      anchors.right: parent.right
+      anchors.verticalCenter: parent.verticalCenter
+      anchors.leftMargin: Common.Config.space.md
+      anchors.rightMargin: Common.Config.space.sm
+      spacing: Common.Config.space.sm
+
+      Column {
+        width: content.width - actions.width - content.spacing
+        anchors.verticalCenter: parent.verticalCenter
+        spacing: 2
+
+        Text {
+          width: parent.width
+          text: root.serverLabel.length > 0 ? qsTr("Allow %1 (%2)?").arg(root.title).arg(root.serverLabel) : qsTr("Allow %1?").arg(root.title)
+          color: Common.Config.color.on_surface
+          font.family: Common.Config.fontFamily
+          font.pixelSize: Common.Config.type.labelLarge.size
+          font.weight: Common.Config.type.labelLarge.weight
+          elide: Text.ElideRight
+        }
+
+        Text {
+          width: parent.width
+          visible: root.detail.length > 0
+          text: root.detail
+          color: Common.Config.color.on_surface_variant
+          font.family: "JetBrains Mono"
+          font.pixelSize: Common.Config.type.labelSmall.size
+          elide: Text.ElideRight
+        }
+      }
+
+      Row {
+        id: actions
+        anchors.verticalCenter: parent.verticalCenter
+        spacing: Common.Config.space.xs
+
+        Repeater {
+          model: [
+            {
+              label: qsTr("Deny"),
+              allow: false
+            },
+            {
+              label: qsTr("Allow"),
+              allow: true
+            }
+          ]
+
+          delegate: MK.ClickableSurface {
+            id: choice
+            required property var modelData
+
+            width: choiceText.implicitWidth + Common.Config.space.md * 2
+            height: 28
+            radius: height / 2
+            backgroundColor: choice.modelData.allow ? Common.Config.color.primary_container : Qt.alpha(Common.Config.color.on_surface, 0.05)
+            hoverBackgroundColor: Qt.alpha(Common.Config.color.primary, 0.18)
+            pressedBackgroundColor: Qt.alpha(Common.Config.color.primary, 0.24)
+            rippleColor: Common.Config.color.primary
+            rippleStateOpacity: choice.hovered ? Common.Config.state.hoverOpacity : 0
+
+            onClicked: root.chatSession.resolveApproval(root.toolCallId, choice.modelData.allow)
+
+            Text {
+              id: choiceText
+              anchors.centerIn: parent
+              text: choice.modelData.label
+              color: choice.modelData.allow ? Common.Config.color.on_primary_container : Common.Config.color.on_surface
+              font.family: Common.Config.fontFamily
+              font.pixelSize: Common.Config.type.labelMedium.size
+              font.weight: Common.Config.type.labelMedium.weight
+            }
+          }
+        }
+      }
+    }
+  }
+}
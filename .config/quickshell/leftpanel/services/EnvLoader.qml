@@ -15,9 +15,14 @@ Item {
 
   readonly property string openaiApiKey: envVars["OPENAI_API_KEY"] || ""
   readonly property string geminiApiKey: envVars["GEMINI_API_KEY"] || ""
+  readonly property string anthropicApiKey: envVars["ANTHROPIC_API_KEY"] || ""
   readonly property string localApiKey: envVars["LOCAL_API_KEY"] || ""
   readonly property string openaiBaseUrl: envVars["OPENAI_BASE_URL"] || ""
+  readonly property string anthropicBaseUrl: envVars["ANTHROPIC_BASE_URL"] || ""
   readonly property string localBaseUrl: envVars["LOCAL_BASE_URL"] || "http://127.0.0.1:8317/v1"
+  readonly property string openaiWireApi: envVars["OPENAI_WIRE_API"] || ""
+  readonly property string localWireApi: envVars["LOCAL_WIRE_API"] || ""
+  readonly property string ollamaBaseUrl: envVars["OLLAMA_BASE_URL"] || ""
 
   function canonicalModelId(rawId) {
     const trimmed = String(rawId || "").trim()
@@ -27,20 +32,31 @@ Item {
       return trimmed
     if (trimmed.startsWith("gpt-5."))
       return "local/" + trimmed
+    if (trimmed.startsWith("claude-"))
+      return "anthropic/" + trimmed
     return trimmed.startsWith("gemini-") ? ("gemini/" + trimmed) : ("openai/" + trimmed)
   }
 
   readonly property var providerConfig: ({
       local: {
         api_key: localApiKey,
-        base_url: localBaseUrl
+        base_url: localBaseUrl,
+        api: localWireApi
       },
       openai: {
         api_key: openaiApiKey,
-        base_url: openaiBaseUrl
+        base_url: openaiBaseUrl,
+        api: openaiWireApi
       },
       gemini: {
         api_key: geminiApiKey
+      },
+      anthropic: {
+        api_key: anthropicApiKey,
+        base_url: anthropicBaseUrl
+      },
+      ollama: {
+        base_url: ollamaBaseUrl
       }
     })
 
@@ -46,6 +46,7 @@ Item {
   signal regenerateRequested(string messageId)
   signal deleteRequested(string messageId)
   signal editRequested(string messageId, string newContent)
+  signal branchRequested(string messageId, string newContent)
   signal modelSelected(string value)
   signal providerSelected(string value)
   signal providerMoved(string value, string beforeValue)
@@ -147,6 +148,7 @@ Item {
           onRegenerateRequested: messageId => root.regenerateRequested(messageId)
           onDeleteRequested: messageId => root.deleteRequested(messageId)
           onEditRequested: (messageId, newContent) => root.editRequested(messageId, newContent)
+          onBranchRequested: (messageId, newContent) => root.branchRequested(messageId, newContent)
         }
 
         Rectangle {
@@ -157,7 +159,7 @@ Item {
           readonly property bool isModelPicker: root.activeCommand === "model"
           readonly property bool isProviderPicker: root.activeCommand === "providers"
           readonly property bool isToolPicker: root.activeCommand === "tools"
-          readonly property bool isResumePicker: root.activeCommand === "resume"
+          readonly property bool isResumePicker: root.activeCommand === "resume" || root.activeCommand === "search"
 
           MouseArea {
             id: overlayDismissArea
@@ -177,7 +179,7 @@ Item {
           Components.CommandPicker {
             id: commandPicker
             anchors.centerIn: parent
-            command: parent.isModelPicker ? "/MODEL" : (parent.isProviderPicker ? "/PROVIDERS" : (parent.isToolPicker ? "/TOOLS" : (parent.isResumePicker ? "/RESUME" : "/MOOD")))
+            command: parent.isModelPicker ? "/MODEL" : (parent.isProviderPicker ? "/PROVIDERS" : (parent.isToolPicker ? "/TOOLS" : (parent.isResumePicker ? (root.activeCommand === "search" ? "/SEARCH" : "/RESUME") : "/MOOD")))
             options: parent.isModelPicker ? root.availableModels : (parent.isProviderPicker ? root.availableProviders : (parent.isToolPicker ? root.availableTools : (parent.isResumePicker ? root.resumeConversations : root.availableMoods)))
             showAllToggle: parent.isModelPicker
             reorderable: parent.isProviderPicker
@@ -194,7 +196,7 @@ Item {
                 root.providerSelected(value)
               else if (root.activeCommand === "tools")
                 root.toolToggled(value)
-              else if (root.activeCommand === "resume")
+              else if (parent.isResumePicker)
                 root.resumeSelected(value)
               else
                 root.moodSelected(value)